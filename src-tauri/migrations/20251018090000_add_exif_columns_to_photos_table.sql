@@ -0,0 +1,15 @@
+-- `Photo` has always carried file_hash, but the column was never created
+ALTER TABLE photos ADD COLUMN file_hash TEXT;
+
+-- Capture metadata extracted from EXIF during scans
+ALTER TABLE photos ADD COLUMN date_taken_offset INTEGER;
+ALTER TABLE photos ADD COLUMN camera_make TEXT;
+ALTER TABLE photos ADD COLUMN camera_model TEXT;
+ALTER TABLE photos ADD COLUMN lens_model TEXT;
+ALTER TABLE photos ADD COLUMN iso INTEGER;
+ALTER TABLE photos ADD COLUMN aperture REAL;
+ALTER TABLE photos ADD COLUMN exposure_time REAL;
+ALTER TABLE photos ADD COLUMN focal_length REAL;
+ALTER TABLE photos ADD COLUMN orientation INTEGER;
+
+CREATE INDEX idx_photos_camera_model ON photos(camera_model);
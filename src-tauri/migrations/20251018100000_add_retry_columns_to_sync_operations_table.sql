@@ -0,0 +1,7 @@
+-- Pending rows form the durable queue of operations not yet mirrored to the backup drive
+ALTER TABLE sync_operations ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sync_operations ADD COLUMN last_error TEXT;
+ALTER TABLE sync_operations ADD COLUMN last_attempt_at DATETIME;
+ALTER TABLE sync_operations ADD COLUMN completed_at DATETIME;
+
+CREATE INDEX idx_sync_operations_status ON sync_operations(status);
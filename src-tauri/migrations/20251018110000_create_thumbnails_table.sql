@@ -0,0 +1,13 @@
+-- Rows outlive their photo until ThumbnailCache::evict_orphans removes them
+-- together with any cached files no other photo shares, so there is no
+-- cascading foreign key here.
+CREATE TABLE IF NOT EXISTS thumbnails (
+    photo_id INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    content_hash TEXT NOT NULL,
+    path TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (photo_id, size)
+);
+
+CREATE INDEX idx_thumbnails_path ON thumbnails(path);
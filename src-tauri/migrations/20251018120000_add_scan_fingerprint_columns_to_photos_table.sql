@@ -0,0 +1,6 @@
+-- Modification time (milliseconds since the Unix epoch) recorded alongside
+-- file_size, so rescans can skip files that haven't changed
+ALTER TABLE photos ADD COLUMN file_mtime INTEGER;
+
+-- Set when a rescan no longer finds the photo's file, cleared when it reappears
+ALTER TABLE photos ADD COLUMN missing_since DATETIME;
@@ -0,0 +1,8 @@
+-- Videos are catalogued alongside stills: 'photo' or 'video'
+ALTER TABLE photos ADD COLUMN media_type TEXT NOT NULL DEFAULT 'photo';
+
+-- Container metadata, NULL for stills
+ALTER TABLE photos ADD COLUMN duration_ms INTEGER;
+ALTER TABLE photos ADD COLUMN video_codec TEXT;
+
+CREATE INDEX idx_photos_media_type ON photos(media_type);
@@ -0,0 +1,15 @@
+-- Files that are one logical item (RAW+JPEG pairs, Live Photo stills and
+-- videos). The gallery shows only the representative of each stack.
+CREATE TABLE IF NOT EXISTS stacks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    representative_id INTEGER REFERENCES photos (id) ON DELETE SET NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE photos ADD COLUMN stack_id INTEGER REFERENCES stacks (id) ON DELETE SET NULL;
+
+-- Apple's identifier shared by a Live Photo's still and video
+ALTER TABLE photos ADD COLUMN content_identifier TEXT;
+
+CREATE INDEX idx_photos_stack_id ON photos(stack_id);
+CREATE INDEX idx_photos_content_identifier ON photos(content_identifier);
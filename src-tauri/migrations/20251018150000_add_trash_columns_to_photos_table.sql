@@ -0,0 +1,8 @@
+-- Deleted photos go to the trash first: their file moves into the drive's
+-- .photovault/trash, `path` follows it and `trashed_from` remembers where to
+-- restore it to. The row is removed once the trash is emptied or the
+-- retention period runs out.
+ALTER TABLE photos ADD COLUMN deleted_at DATETIME;
+ALTER TABLE photos ADD COLUMN trashed_from TEXT;
+
+CREATE INDEX idx_photos_deleted_at ON photos(deleted_at);
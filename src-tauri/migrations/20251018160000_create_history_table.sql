@@ -0,0 +1,11 @@
+-- Undoable actions, newest last. `operations` redoes an action and `inverse`
+-- undoes it; both are JSON arrays of `Operation`s applied in order. Undone
+-- actions stay until a new action is recorded, so they can be redone.
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    label TEXT NOT NULL,
+    operations TEXT NOT NULL,
+    inverse TEXT NOT NULL,
+    undone BOOLEAN NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
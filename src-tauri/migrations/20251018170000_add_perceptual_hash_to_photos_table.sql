@@ -0,0 +1,4 @@
+-- A difference hash of the decoded image, as 16 hex digits. Unlike
+-- `file_hash` it stays close for resized or re-encoded copies, so near
+-- duplicates can be found by Hamming distance. Computed on demand.
+ALTER TABLE photos ADD COLUMN perceptual_hash TEXT;
@@ -0,0 +1,7 @@
+-- A hash of the first and last 64 KiB of the file, so duplicate scans only
+-- read whole files whose size and ends both match another's. Both hashes
+-- are saved as they're computed, so an interrupted scan picks up where it
+-- left off.
+ALTER TABLE photos ADD COLUMN partial_hash TEXT;
+
+CREATE INDEX idx_photos_file_hash ON photos(file_hash);
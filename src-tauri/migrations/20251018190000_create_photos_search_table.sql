@@ -0,0 +1,28 @@
+-- The EXIF ImageDescription, which most tools write captions to.
+ALTER TABLE photos ADD COLUMN caption TEXT;
+
+-- Full-text index over what a photo is found by. The rowid is the photo's
+-- id; rows are kept up to date by the sync engine as operations are applied,
+-- and trashed photos have none.
+CREATE VIRTUAL TABLE photos_search USING fts5(
+    filename,
+    folder,
+    tags,
+    albums,
+    caption,
+    camera,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+INSERT INTO photos_search (rowid, filename, folder, tags, albums, caption, camera)
+SELECT
+    p.id,
+    p.filename,
+    rtrim(p.path, replace(replace(p.path, '/', ''), '\', '')),
+    (SELECT group_concat(t.name, ' ') FROM tags t JOIN photo_tags pt ON pt.tag_id = t.id WHERE pt.photo_id = p.id),
+    (SELECT group_concat(a.name, ' ') FROM albums a JOIN photo_albums pa ON pa.album_id = a.id WHERE pa.photo_id = p.id),
+    p.caption,
+    trim(coalesce(p.camera_make, '') || ' ' || coalesce(p.camera_model, ''))
+FROM photos p
+WHERE p.deleted_at IS NULL;
@@ -0,0 +1,7 @@
+-- Albums whose photos are whichever match a filter when they're looked at.
+-- `criteria` is a JSON `FilterCriteria`.
+CREATE TABLE IF NOT EXISTS smart_albums (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    criteria TEXT NOT NULL
+);
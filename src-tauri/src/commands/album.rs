@@ -1,4 +1,5 @@
-use crate::models::album::Album;
+use crate::models::album::AlbumEntry;
+use crate::models::filter::FilterCriteria;
 use crate::services::album::AlbumService;
 use crate::AppState;
 use tauri::State;
@@ -13,7 +14,7 @@ pub async fn create_album(state: State<'_, AppState>, name: String) -> Result<()
 }
 
 #[tauri::command]
-pub async fn get_albums(state: State<'_, AppState>) -> Result<Vec<Album>, String> {
+pub async fn get_albums(state: State<'_, AppState>) -> Result<Vec<AlbumEntry>, String> {
     let album_service = AlbumService::new(&state.sync_engine);
     album_service.get_albums().await.map_err(|e| e.to_string())
 }
@@ -53,3 +54,54 @@ pub async fn get_photos_by_album(
         .await
         .map_err(|e| e.to_string())
 }
+
+#[tauri::command]
+pub async fn create_smart_album(
+    state: State<'_, AppState>,
+    name: String,
+    criteria: FilterCriteria,
+) -> Result<(), String> {
+    let album_service = AlbumService::new(&state.sync_engine);
+    album_service
+        .create_smart_album(name, criteria)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_smart_album(
+    state: State<'_, AppState>,
+    smart_album_id: i64,
+    name: String,
+    criteria: FilterCriteria,
+) -> Result<(), String> {
+    let album_service = AlbumService::new(&state.sync_engine);
+    album_service
+        .update_smart_album(smart_album_id, name, criteria)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_smart_album(
+    state: State<'_, AppState>,
+    smart_album_id: i64,
+) -> Result<(), String> {
+    let album_service = AlbumService::new(&state.sync_engine);
+    album_service
+        .delete_smart_album(smart_album_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_photos_by_smart_album(
+    state: State<'_, AppState>,
+    smart_album_id: i64,
+) -> Result<Vec<Photo>, String> {
+    let album_service = AlbumService::new(&state.sync_engine);
+    album_service
+        .get_photos_by_smart_album_id(smart_album_id)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -1,45 +1,137 @@
 use crate::{
-    models::operation::Operation,
-    services::duplicate::{DuplicateDetector, DuplicateGroup},
+    services::cross_drive::{self, CrossDriveGroup},
+    services::duplicate::{DuplicateDetector, DuplicateGroup, HashProgress, DEFAULT_MAX_DISTANCE},
+    services::keep_best::{self, KeepPolicy, KeepRecommendation, KeepSummary},
+    services::trash,
     AppState,
 };
-use std::path::PathBuf;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 
+pub const DUPLICATE_PROGRESS_EVENT: &str = "duplicate-progress";
+
+/// Groups byte-identical photos. Hashing progress is reported through
+/// `duplicate-progress` events.
+#[tauri::command]
+pub async fn find_duplicates(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    // Not held while hashing, which can take a while on large libraries
+    let pool = state
+        .db_pool
+        .lock()
+        .await
+        .clone()
+        .ok_or("Database not connected")?;
+    DuplicateDetector::find_duplicates_with_progress(&pool, |progress: &HashProgress| {
+        let _ = app.emit(DUPLICATE_PROGRESS_EVENT, progress);
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Groups photos that look alike, such as resized or re-encoded copies.
+/// `max_distance` is how many of the 64 perceptual hash bits may differ.
+#[tauri::command]
+pub async fn find_similar_photos(
+    max_distance: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    let pool = state
+        .db_pool
+        .lock()
+        .await
+        .clone()
+        .ok_or("Database not connected")?;
+    DuplicateDetector::find_similar(&pool, max_distance.unwrap_or(DEFAULT_MAX_DISTANCE))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Finds files the primary and backup drives hold under different paths,
+/// with different content, or on one drive only.
 #[tauri::command]
-pub async fn find_duplicates(state: State<'_, AppState>) -> Result<Vec<DuplicateGroup>, String> {
-    let db_pool = state.db_pool.lock().await;
-    let pool = db_pool.as_ref().ok_or("Database not connected")?;
-    DuplicateDetector::find_duplicates(pool)
+pub async fn find_cross_drive_duplicates(
+    verify_hashes: bool,
+    state: State<'_, AppState>,
+) -> Result<Vec<CrossDriveGroup>, String> {
+    cross_drive::compare_drives(&state.sync_engine, verify_hashes)
         .await
         .map_err(|e| e.to_string())
 }
 
+/// Moves the duplicates to the trash and returns the space emptying it frees.
 #[tauri::command]
 pub async fn delete_duplicates(
     photo_ids: Vec<i64>,
     state: State<'_, AppState>,
 ) -> Result<i64, String> {
+    let primary_drive = super::get_config()
+        .await?
+        .primary_drive
+        .ok_or("Primary drive not configured")?;
     let mut sync_engine_lock = state.sync_engine.lock().await;
     let sync_engine = sync_engine_lock
         .as_mut()
         .ok_or("Sync engine not initialized")?;
 
-    let mut total_space_freed = 0;
-
-    for photo_id in photo_ids {
-        let photo = sync_engine
-            .get_photo_by_id(photo_id)
-            .await
-            .map_err(|e| e.to_string())?;
-        let path = PathBuf::from(&photo.path);
-        total_space_freed += photo.file_size.unwrap_or(0);
-        let op = Operation::Delete { path };
-        sync_engine
-            .execute_operation(&op)
-            .await
-            .map_err(|e| e.to_string())?;
+    let summary = sync_engine
+        .record_action(
+            format!("Delete {} duplicates", photo_ids.len()),
+            async |sync_engine| trash::trash_photos(sync_engine, &primary_drive, &photo_ids).await,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(failure) = summary.failed.first() {
+        return Err(format!(
+            "Failed to delete {}: {}",
+            failure.path.display(),
+            failure.error
+        ));
     }
+    Ok(summary.bytes)
+}
+
+/// Picks the copy to keep from each group of photo ids, by `policy` or the
+/// default one.
+#[tauri::command]
+pub async fn recommend_keepers(
+    photo_groups: Vec<Vec<i64>>,
+    policy: Option<KeepPolicy>,
+    state: State<'_, AppState>,
+) -> Result<Vec<KeepRecommendation>, String> {
+    let pool = state
+        .sync_engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| engine.primary_db.clone())
+        .ok_or("Sync engine not initialized")?;
+    keep_best::recommend(&pool, &photo_groups, &policy.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    Ok(total_space_freed)
+/// Moves every copy but the keeper to the trash, first giving the keeper
+/// their tags and album memberships.
+#[tauri::command]
+pub async fn keep_best_copies(
+    recommendations: Vec<KeepRecommendation>,
+    state: State<'_, AppState>,
+) -> Result<KeepSummary, String> {
+    let primary_drive = super::get_config()
+        .await?
+        .primary_drive
+        .ok_or("Primary drive not configured")?;
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    sync_engine
+        .record_action(
+            format!("Keep the best of {} duplicate sets", recommendations.len()),
+            async |sync_engine| {
+                keep_best::keep_best(sync_engine, &primary_drive, &recommendations).await
+            },
+        )
+        .await
+        .map_err(|e| e.to_string())
 }
@@ -0,0 +1,56 @@
+use crate::services::config::{self, CONFIG_FILE_NAME};
+use crate::services::formats::{FormatRegistry, BUILTIN_FORMATS};
+use crate::AppState;
+use serde::Serialize;
+use tauri::State;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatInfo {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub enabled: bool,
+}
+
+/// Lists every supported format and whether scans currently pick it up.
+#[tauri::command]
+pub async fn get_formats() -> Result<Vec<FormatInfo>, String> {
+    let config_path = config::get_app_config_dir()
+        .map_err(|e| e.to_string())?
+        .join(CONFIG_FILE_NAME);
+    let config = config::load_config_from_path(&config_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    let registry = FormatRegistry::from_config(&config);
+
+    Ok(BUILTIN_FORMATS
+        .iter()
+        .map(|handler| FormatInfo {
+            name: handler.name().to_string(),
+            extensions: handler.extensions().iter().map(|e| e.to_string()).collect(),
+            enabled: registry.is_enabled(handler.name()),
+        })
+        .collect())
+}
+
+/// Saves the formats scans and the library watcher pick up.
+#[tauri::command]
+pub async fn set_enabled_formats(
+    formats: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let config_path = config::get_app_config_dir()
+        .map_err(|e| e.to_string())?
+        .join(CONFIG_FILE_NAME);
+    let mut current_config = config::load_config_from_path(&config_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    current_config.enabled_formats = Some(formats);
+    config::save_config_to_path(&current_config, &config_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(sync_engine) = state.sync_engine.lock().await.as_mut() {
+        sync_engine.set_formats(FormatRegistry::from_config(&current_config));
+    }
+    Ok(())
+}
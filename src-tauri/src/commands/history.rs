@@ -0,0 +1,40 @@
+use crate::services::history::{self, HistoryEntry};
+use crate::AppState;
+use tauri::State;
+
+/// How many actions the history view shows.
+const HISTORY_LIMIT: i64 = 100;
+
+#[tauri::command]
+pub async fn get_history(state: State<'_, AppState>) -> Result<Vec<HistoryEntry>, String> {
+    let pool = state
+        .sync_engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| engine.primary_db.clone())
+        .ok_or("Sync engine not initialized")?;
+    history::list_history(&pool, HISTORY_LIMIT)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Undoes the most recent action on both drives, returning it, or `None`
+/// when there's nothing left to undo.
+#[tauri::command]
+pub async fn undo_last(state: State<'_, AppState>) -> Result<Option<HistoryEntry>, String> {
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    history::undo_last(sync_engine)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Redoes the most recently undone action, returning it, or `None` when
+/// there's nothing to redo.
+#[tauri::command]
+pub async fn redo(state: State<'_, AppState>) -> Result<Option<HistoryEntry>, String> {
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    history::redo(sync_engine).await.map_err(|e| e.to_string())
+}
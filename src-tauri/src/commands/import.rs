@@ -0,0 +1,122 @@
+use crate::services::config::{self, CONFIG_FILE_NAME};
+use crate::services::importer::{
+    self, ImportProgress, ImportSummary, PathTemplate, DEFAULT_IMPORT_TEMPLATE,
+};
+use crate::AppState;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub const IMPORT_PROGRESS_EVENT: &str = "import-progress";
+pub const IMPORT_FINISHED_EVENT: &str = "import-finished";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportFinished {
+    pub summary: Option<ImportSummary>,
+    pub error: Option<String>,
+}
+
+/// Starts importing the photos in `source` into the primary drive in the
+/// background. Progress is reported through `import-progress` events and the
+/// outcome through `import-finished`.
+#[tauri::command]
+pub async fn import_folder(
+    source: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let config = super::get_config().await?;
+    let primary_drive = config.primary_drive.ok_or("Primary drive not configured")?;
+    let template = config
+        .import_template
+        .as_deref()
+        .unwrap_or(DEFAULT_IMPORT_TEMPLATE);
+    let template = PathTemplate::parse(template).map_err(|e| e.to_string())?;
+
+    let cancel = {
+        let mut import_job = state.import_job.lock().await;
+        if import_job.is_some() {
+            return Err("An import is already running".to_string());
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        *import_job = Some(cancel.clone());
+        cancel
+    };
+
+    tauri::async_runtime::spawn(async move {
+        // Import in a task of its own so `import_job` is cleared even if it panics
+        let import = tauri::async_runtime::spawn({
+            let app = app.clone();
+            async move {
+                let state = app.state::<AppState>();
+                importer::import_folder(
+                    &state.sync_engine,
+                    &PathBuf::from(source),
+                    &primary_drive,
+                    &template,
+                    &cancel,
+                    |progress: &ImportProgress| {
+                        let _ = app.emit(IMPORT_PROGRESS_EVENT, progress);
+                    },
+                )
+                .await
+                .map_err(|e| e.to_string())
+            }
+        });
+        let result = import
+            .await
+            .unwrap_or_else(|e| Err(format!("Import stopped unexpectedly: {}", e)));
+        let state = app.state::<AppState>();
+        *state.import_job.lock().await = None;
+
+        let finished = match result {
+            Ok(summary) => ImportFinished {
+                summary: Some(summary),
+                error: None,
+            },
+            Err(e) => ImportFinished {
+                summary: None,
+                error: Some(e),
+            },
+        };
+        if let Err(e) = app.emit(IMPORT_FINISHED_EVENT, finished) {
+            eprintln!("Failed to report import result: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// Asks the running import to stop. Returns whether an import was running.
+#[tauri::command]
+pub async fn cancel_import(state: State<'_, AppState>) -> Result<bool, String> {
+    match &*state.import_job.lock().await {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Sets where imports place files, e.g. `{year}/{month}/{date}_{original_name}`.
+/// An empty template restores the default.
+#[tauri::command]
+pub async fn set_import_template(template: String) -> Result<(), String> {
+    let template = Some(template.trim().to_string()).filter(|template| !template.is_empty());
+    if let Some(template) = &template {
+        PathTemplate::parse(template).map_err(|e| e.to_string())?;
+    }
+
+    let config_path = config::get_app_config_dir()
+        .map_err(|e| e.to_string())?
+        .join(CONFIG_FILE_NAME);
+    let mut current_config = config::load_config_from_path(&config_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    current_config.import_template = template;
+    config::save_config_to_path(&current_config, &config_path)
+        .await
+        .map_err(|e| e.to_string())
+}
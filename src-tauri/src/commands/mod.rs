@@ -1,20 +1,39 @@
 use crate::db::manager::DatabaseManager;
-use crate::models::operation::Operation;
+use crate::models::operation::{Operation, QueuedOperation};
 use crate::services::config::{self, AppConfig, CONFIG_FILE_NAME};
+use crate::services::file_mirror::FileMirror;
+use crate::services::formats::FormatRegistry;
 use crate::services::sync_engine::SyncEngine;
 use crate::services::sync_status::{self, SyncStatus};
+use crate::services::thumbnail::ThumbnailCache;
 use sqlx::{Pool, Sqlite};
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use tauri::{async_runtime::Mutex, State};
 
 pub mod album;
 pub mod duplicates;
 pub mod filter;
+pub mod formats;
+pub mod history;
+pub mod import;
+pub mod organize;
+pub mod reconcile;
+pub mod rename;
+pub mod scan;
+pub mod search;
+pub mod stack;
 pub mod tag;
+pub mod trash;
 
 pub struct AppState {
     pub db_pool: Mutex<Option<Pool<Sqlite>>>,
     pub sync_engine: Mutex<Option<SyncEngine>>,
+    /// Cancellation flag of the library scan currently running, if any.
+    pub scan_job: Mutex<Option<Arc<AtomicBool>>>,
+    /// Cancellation flag of the import currently running, if any.
+    pub import_job: Mutex<Option<Arc<AtomicBool>>>,
 }
 
 type CommandResult<T> = Result<T, String>;
@@ -28,21 +47,6 @@ pub async fn get_config() -> CommandResult<AppConfig> {
         .map_err(|e| e.to_string())
 }
 
-#[tauri::command]
-pub async fn scan_library(state: State<'_, AppState>) -> CommandResult<()> {
-    let mut sync_engine = state.sync_engine.lock().await;
-    if let Some(sync_engine) = &mut *sync_engine {
-        let config = get_config().await?;
-        if let Some(primary_drive) = config.primary_drive {
-            sync_engine
-                .scan_library(primary_drive.to_str().unwrap())
-                .await
-                .map_err(|e| e.to_string())?;
-        }
-    }
-    Ok(())
-}
-
 #[tauri::command]
 pub async fn get_photos(
     limit: i64,
@@ -60,6 +64,22 @@ pub async fn get_photos(
     Ok(Vec::new())
 }
 
+/// Catalogued photos whose file has vanished from the primary drive.
+#[tauri::command]
+pub async fn get_missing_photos(
+    state: State<'_, AppState>,
+) -> CommandResult<Vec<crate::models::photo::Photo>> {
+    let sync_engine = state.sync_engine.lock().await;
+    if let Some(sync_engine) = &*sync_engine {
+        return sync_engine
+            .get_missing_photos()
+            .await
+            .map_err(|e| e.to_string());
+    }
+    Ok(Vec::new())
+}
+
+/// Moves the photos, and the rest of their stacks, into `target_path`.
 #[tauri::command]
 pub async fn move_photos(
     photo_ids: Vec<i64>,
@@ -68,22 +88,27 @@ pub async fn move_photos(
 ) -> CommandResult<()> {
     let mut sync_engine = state.sync_engine.lock().await;
     if let Some(sync_engine) = &mut *sync_engine {
-        for photo_id in photo_ids {
-            let photo = sync_engine
-                .get_photo_by_id(photo_id)
-                .await
-                .map_err(|e| e.to_string())?;
-            let from = PathBuf::from(&photo.path);
-            let to = PathBuf::from(&target_path).join(from.file_name().unwrap());
-            tokio::fs::rename(&from, &to)
-                .await
-                .map_err(|e| e.to_string())?;
-            let op = Operation::Move { from, to };
-            sync_engine
-                .execute_operation(&op)
-                .await
-                .map_err(|e| e.to_string())?;
-        }
+        let photos = crate::services::stack::expand(&sync_engine.primary_db, &photo_ids)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Photos moved before a failure can still be undone
+        sync_engine
+            .record_action(
+                format!("Move {} photos", photos.len()),
+                async |sync_engine| {
+                    for photo in photos {
+                        let from = PathBuf::from(&photo.path);
+                        let to = PathBuf::from(&target_path).join(from.file_name().unwrap());
+                        tokio::fs::rename(&from, &to).await?;
+                        sync_engine
+                            .execute_operation(&Operation::Move { from, to })
+                            .await?;
+                    }
+                    Ok(())
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
     }
     Ok(())
 }
@@ -91,6 +116,8 @@ pub async fn move_photos(
 #[derive(serde::Serialize)]
 pub struct QueueStatus {
     pub pending_operations: u32,
+    /// Operations that kept failing and are no longer retried.
+    pub failed_operations: Vec<QueuedOperation>,
 }
 
 #[tauri::command]
@@ -99,13 +126,17 @@ pub async fn get_sync_queue_status(state: State<'_, AppState>) -> CommandResult<
     if let Some(sync_engine) = &*sync_engine {
         return Ok(QueueStatus {
             pending_operations: sync_engine.queue_len() as u32,
+            failed_operations: sync_engine.failed_operations().to_vec(),
         });
     }
     Ok(QueueStatus {
         pending_operations: 0,
+        failed_operations: Vec::new(),
     })
 }
 
+/// Renames the photo. The rest of its stack takes the same name with its
+/// own extension, e.g. `IMG_1234.CR2` follows `IMG_1234.JPG` to `Beach.CR2`.
 #[tauri::command]
 pub async fn rename_photo(
     photo_id: i64,
@@ -114,45 +145,63 @@ pub async fn rename_photo(
 ) -> CommandResult<()> {
     let mut sync_engine = state.sync_engine.lock().await;
     if let Some(sync_engine) = &mut *sync_engine {
-        let photo = sync_engine
-            .get_photo_by_id(photo_id)
-            .await
-            .map_err(|e| e.to_string())?;
-        let from = PathBuf::from(&photo.path);
-        let to = from.with_file_name(&new_name);
-        tokio::fs::rename(&from, &to)
+        let photos = crate::services::stack::members(&sync_engine.primary_db, photo_id)
             .await
             .map_err(|e| e.to_string())?;
-        let op = Operation::Rename {
-            path: from,
-            new_name,
-        };
         sync_engine
-            .execute_operation(&op)
+            .record_action(format!("Rename to {}", new_name), async |sync_engine| {
+                for photo in photos {
+                    let from = PathBuf::from(&photo.path);
+                    let new_name = if photo.id == photo_id {
+                        new_name.clone()
+                    } else {
+                        crate::services::stack::member_file_name(&from, &new_name)
+                    };
+                    let to = from.with_file_name(&new_name);
+                    tokio::fs::rename(&from, &to).await?;
+                    let op = Operation::Rename {
+                        path: from,
+                        new_name,
+                    };
+                    sync_engine.execute_operation(&op).await?;
+                }
+                Ok(())
+            })
             .await
             .map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
+/// Moves the photos along with the rest of their stacks to the trash.
 #[tauri::command]
 pub async fn delete_photos(photo_ids: Vec<i64>, state: State<'_, AppState>) -> CommandResult<()> {
+    let primary_drive = get_config()
+        .await?
+        .primary_drive
+        .ok_or("Primary drive not configured")?;
     let mut sync_engine = state.sync_engine.lock().await;
     if let Some(sync_engine) = &mut *sync_engine {
-        for photo_id in photo_ids {
-            let photo = sync_engine
-                .get_photo_by_id(photo_id)
-                .await
-                .map_err(|e| e.to_string())?;
-            let path = PathBuf::from(&photo.path);
-            tokio::fs::remove_file(&path)
-                .await
-                .map_err(|e| e.to_string())?;
-            let op = Operation::Delete { path };
-            sync_engine
-                .execute_operation(&op)
-                .await
-                .map_err(|e| e.to_string())?;
+        let photos = crate::services::stack::expand(&sync_engine.primary_db, &photo_ids)
+            .await
+            .map_err(|e| e.to_string())?;
+        let photo_ids: Vec<i64> = photos.iter().map(|photo| photo.id).collect();
+        let summary = sync_engine
+            .record_action(
+                format!("Delete {} photos", photo_ids.len()),
+                async |sync_engine| {
+                    crate::services::trash::trash_photos(sync_engine, &primary_drive, &photo_ids)
+                        .await
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(failure) = summary.failed.first() {
+            return Err(format!(
+                "Failed to delete {}: {}",
+                failure.path.display(),
+                failure.error
+            ));
         }
     }
     Ok(())
@@ -204,7 +253,11 @@ pub async fn set_drive_paths(
         .map_err(|e| e.to_string())?;
     println!("[set_drive_paths] Backup pool created.");
 
-    let engine = SyncEngine::new(primary_pool.clone(), Some(backup_pool));
+    let mut engine = SyncEngine::new(primary_pool.clone(), Some(backup_pool))
+        .with_file_mirror(FileMirror::new(primary_path.clone(), backup_path))
+        .with_thumbnail_cache(ThumbnailCache::for_library(&primary_path))
+        .with_formats(FormatRegistry::from_config(&current_config));
+    engine.load_queue().await.map_err(|e| e.to_string())?;
     println!("[set_drive_paths] SyncEngine created.");
 
     let mut sync_engine_state = state.sync_engine.lock().await;
@@ -217,14 +270,38 @@ pub async fn set_drive_paths(
 }
 
 #[tauri::command]
-pub async fn verify_sync_status() -> CommandResult<SyncStatus> {
+pub async fn verify_sync_status(state: State<'_, AppState>) -> CommandResult<SyncStatus> {
     let config_dir = config::get_app_config_dir().map_err(|e| e.to_string())?;
     let config_path = config_dir.join(CONFIG_FILE_NAME);
     let config = config::load_config_from_path(&config_path)
         .await
         .map_err(|e| e.to_string())?;
 
-    sync_status::verify_sync_status(&config)
+    let mut status = sync_status::verify_sync_status(&config)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Connected drives are only in sync once nothing is waiting to be mirrored;
+    // `reconcile_drives` performs the full comparison.
+    if let Some(sync_engine) = &*state.sync_engine.lock().await {
+        status.pending_operations = sync_engine.queue_len() as u32;
+        status.is_in_sync = status.is_in_sync && status.pending_operations == 0;
+    }
+    Ok(status)
+}
+
+/// Turns the primary drive watcher on or off. The watcher picks up the
+/// change on its next config check.
+#[tauri::command]
+pub async fn set_watch_primary_drive(enabled: bool) -> CommandResult<()> {
+    let config_path = config::get_app_config_dir()
+        .map_err(|e| e.to_string())?
+        .join(CONFIG_FILE_NAME);
+    let mut current_config = config::load_config_from_path(&config_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    current_config.watch_primary_drive = enabled;
+    config::save_config_to_path(&current_config, &config_path)
         .await
         .map_err(|e| e.to_string())
 }
@@ -0,0 +1,50 @@
+use crate::services::organizer::{self, OrganizePlan, OrganizeRule, OrganizeSummary};
+use crate::AppState;
+use tauri::State;
+
+/// Shows where `rules` would move the photos on the primary drive.
+#[tauri::command]
+pub async fn preview_organize(
+    rules: Vec<OrganizeRule>,
+    state: State<'_, AppState>,
+) -> Result<OrganizePlan, String> {
+    let primary_drive = super::get_config()
+        .await?
+        .primary_drive
+        .ok_or("Primary drive not configured")?;
+    let pool = state
+        .sync_engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| engine.primary_db.clone())
+        .ok_or("Sync engine not initialized")?;
+    organizer::plan_organize(&pool, &primary_drive, &rules)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Moves the photos on the primary drive into the folders `rules` give them.
+#[tauri::command]
+pub async fn organize_library(
+    rules: Vec<OrganizeRule>,
+    state: State<'_, AppState>,
+) -> Result<OrganizeSummary, String> {
+    let primary_drive = super::get_config()
+        .await?
+        .primary_drive
+        .ok_or("Primary drive not configured")?;
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    // Planned again under the engine lock, as the library may have changed
+    // since the preview
+    let plan = organizer::plan_organize(&sync_engine.primary_db, &primary_drive, &rules)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine
+        .record_action("Organize library", async |sync_engine| {
+            organizer::apply_organize(sync_engine, &plan).await
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
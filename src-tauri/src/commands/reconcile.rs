@@ -0,0 +1,23 @@
+use crate::services::reconcile::{self, Discrepancy, ReconciliationReport};
+use crate::AppState;
+use tauri::State;
+
+#[tauri::command]
+pub async fn reconcile_drives(
+    state: State<'_, AppState>,
+    verify_hashes: bool,
+) -> Result<ReconciliationReport, String> {
+    reconcile::reconcile(&state.sync_engine, verify_hashes)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn repair_discrepancies(
+    state: State<'_, AppState>,
+    discrepancies: Vec<Discrepancy>,
+) -> Result<(), String> {
+    reconcile::repair(&state.sync_engine, &discrepancies)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,50 @@
+use crate::services::batch_rename::{self, RenamePlan, RenameTemplate};
+use crate::AppState;
+use tauri::State;
+
+/// Shows what renaming the photos with `template` would do, e.g.
+/// `{date:%Y%m%d}_{camera}_{seq:04}{ext}`, including any name collisions.
+#[tauri::command]
+pub async fn preview_batch_rename(
+    photo_ids: Vec<i64>,
+    template: String,
+    state: State<'_, AppState>,
+) -> Result<RenamePlan, String> {
+    let template = RenameTemplate::parse(&template).map_err(|e| e.to_string())?;
+    let pool = state
+        .sync_engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| engine.primary_db.clone())
+        .ok_or("Sync engine not initialized")?;
+    batch_rename::plan_rename(&pool, &photo_ids, &template)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Renames the photos with `template`. Nothing is renamed if any of the new
+/// names collide.
+#[tauri::command]
+pub async fn batch_rename(
+    photo_ids: Vec<i64>,
+    template: String,
+    state: State<'_, AppState>,
+) -> Result<RenamePlan, String> {
+    let template = RenameTemplate::parse(&template).map_err(|e| e.to_string())?;
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    // Planned again under the engine lock, as the files may have changed
+    // since the preview
+    let plan = batch_rename::plan_rename(&sync_engine.primary_db, &photo_ids, &template)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine
+        .record_action(
+            format!("Rename {} files", plan.renames.len()),
+            async |sync_engine| batch_rename::apply_rename(sync_engine, &plan).await,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(plan)
+}
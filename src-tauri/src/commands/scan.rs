@@ -0,0 +1,87 @@
+use crate::services::scanner::{self, ScanProgress, ScanSummary};
+use crate::AppState;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub const SCAN_PROGRESS_EVENT: &str = "scan-progress";
+pub const SCAN_FINISHED_EVENT: &str = "scan-finished";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanFinished {
+    pub summary: Option<ScanSummary>,
+    pub error: Option<String>,
+}
+
+/// Starts scanning the primary drive in the background. Progress is reported
+/// through `scan-progress` events and the outcome through `scan-finished`.
+#[tauri::command]
+pub async fn scan_library(app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    let primary_drive = super::get_config()
+        .await?
+        .primary_drive
+        .ok_or("Primary drive not configured")?;
+
+    let cancel = {
+        let mut scan_job = state.scan_job.lock().await;
+        if scan_job.is_some() {
+            return Err("A library scan is already running".to_string());
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        *scan_job = Some(cancel.clone());
+        cancel
+    };
+
+    tauri::async_runtime::spawn(async move {
+        // Scan in a task of its own so `scan_job` is cleared even if it panics
+        let scan = tauri::async_runtime::spawn({
+            let app = app.clone();
+            async move {
+                let state = app.state::<AppState>();
+                scanner::scan_library(
+                    &state.sync_engine,
+                    &primary_drive,
+                    &cancel,
+                    |progress: &ScanProgress| {
+                        let _ = app.emit(SCAN_PROGRESS_EVENT, progress);
+                    },
+                )
+                .await
+                .map_err(|e| e.to_string())
+            }
+        });
+        let result = scan
+            .await
+            .unwrap_or_else(|e| Err(format!("Library scan stopped unexpectedly: {}", e)));
+        let state = app.state::<AppState>();
+        *state.scan_job.lock().await = None;
+
+        let finished = match result {
+            Ok(summary) => ScanFinished {
+                summary: Some(summary),
+                error: None,
+            },
+            Err(e) => ScanFinished {
+                summary: None,
+                error: Some(e),
+            },
+        };
+        if let Err(e) = app.emit(SCAN_FINISHED_EVENT, finished) {
+            eprintln!("Failed to report scan result: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// Asks the running scan to stop. Returns whether a scan was running.
+#[tauri::command]
+pub async fn cancel_scan(state: State<'_, AppState>) -> Result<bool, String> {
+    match &*state.scan_job.lock().await {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,64 @@
+use crate::models::photo::Photo;
+use crate::services::query::{self, ParseError, QueryError};
+use crate::services::search::{self, SearchResult};
+use crate::AppState;
+use serde::Serialize;
+use tauri::State;
+
+/// Why `query_photos` failed; syntax errors carry the part of the query to
+/// point at.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QueryPhotosError {
+    Syntax(ParseError),
+    Failed { message: String },
+}
+
+impl From<QueryError> for QueryPhotosError {
+    fn from(e: QueryError) -> Self {
+        match e {
+            QueryError::Parse(e) => QueryPhotosError::Syntax(e),
+            e => QueryPhotosError::Failed {
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+async fn primary_pool(state: &State<'_, AppState>) -> Option<sqlx::SqlitePool> {
+    state
+        .sync_engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| engine.primary_db.clone())
+}
+
+/// Full-text search over the library, best matches first.
+#[tauri::command]
+pub async fn search_library(
+    query: String,
+    limit: Option<i64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchResult>, String> {
+    let pool = primary_pool(&state)
+        .await
+        .ok_or("Sync engine not initialized")?;
+    search::search(&pool, &query, limit.unwrap_or(search::DEFAULT_LIMIT))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The photos matching a query such as `tag:beach -tag:work width>=4000`.
+#[tauri::command]
+pub async fn query_photos(
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<Photo>, QueryPhotosError> {
+    let pool = primary_pool(&state)
+        .await
+        .ok_or_else(|| QueryPhotosError::Failed {
+            message: "Sync engine not initialized".to_string(),
+        })?;
+    Ok(query::query_photos(&pool, &query).await?)
+}
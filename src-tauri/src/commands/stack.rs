@@ -0,0 +1,102 @@
+use crate::models::operation::Operation;
+use crate::models::photo::Photo;
+use crate::services::stack;
+use crate::AppState;
+use std::path::PathBuf;
+use tauri::State;
+
+/// The photo's stack, representative first, or just the photo when unstacked.
+#[tauri::command]
+pub async fn get_stack(photo_id: i64, state: State<'_, AppState>) -> Result<Vec<Photo>, String> {
+    let sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_ref().ok_or("Sync engine not initialized")?;
+    stack::members(&sync_engine.primary_db, photo_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Stacks the photos by hand, shown as whichever of them would be picked
+/// automatically.
+#[tauri::command]
+pub async fn create_stack(photo_ids: Vec<i64>, state: State<'_, AppState>) -> Result<(), String> {
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    let photos = stack::expand(&sync_engine.primary_db, &photo_ids)
+        .await
+        .map_err(|e| e.to_string())?;
+    if photos.len() < 2 {
+        return Err("A stack needs at least two photos".to_string());
+    }
+    let representative = stack::representative(&photos)
+        .map(|photo| PathBuf::from(&photo.path))
+        .ok_or("A stack needs at least two photos")?;
+    let op = Operation::CreateStack {
+        paths: photos
+            .iter()
+            .map(|photo| PathBuf::from(&photo.path))
+            .collect(),
+        representative,
+    };
+    sync_engine
+        .record_action(
+            format!("Stack {} photos", photos.len()),
+            async |sync_engine| sync_engine.execute_operation(&op).await,
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Makes the photo the one the gallery shows for its stack.
+#[tauri::command]
+pub async fn set_stack_representative(
+    photo_id: i64,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    let photos = stack::members(&sync_engine.primary_db, photo_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(photo) = photos.iter().find(|photo| photo.id == photo_id) else {
+        return Ok(());
+    };
+    if photos.len() < 2 {
+        return Err("Photo is not stacked".to_string());
+    }
+    let op = Operation::CreateStack {
+        paths: photos
+            .iter()
+            .map(|photo| PathBuf::from(&photo.path))
+            .collect(),
+        representative: PathBuf::from(&photo.path),
+    };
+    sync_engine
+        .record_action("Change stack cover", async |sync_engine| {
+            sync_engine.execute_operation(&op).await
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Splits the photo's stack back into separate photos.
+#[tauri::command]
+pub async fn unstack_photo(photo_id: i64, state: State<'_, AppState>) -> Result<(), String> {
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    let photo = sync_engine
+        .get_photo_by_id(photo_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if photo.stack_id.is_none() {
+        return Ok(());
+    }
+    let op = Operation::Unstack {
+        path: PathBuf::from(&photo.path),
+    };
+    sync_engine
+        .record_action(format!("Unstack {}", photo.filename), async |sync_engine| {
+            sync_engine.execute_operation(&op).await
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
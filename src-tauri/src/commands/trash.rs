@@ -0,0 +1,62 @@
+use crate::models::photo::Photo;
+use crate::services::config::{self, CONFIG_FILE_NAME};
+use crate::services::trash::{self, TrashSummary};
+use crate::AppState;
+use tauri::State;
+
+#[tauri::command]
+pub async fn list_trash(state: State<'_, AppState>) -> Result<Vec<Photo>, String> {
+    let pool = state
+        .sync_engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| engine.primary_db.clone())
+        .ok_or("Sync engine not initialized")?;
+    trash::list_trash(&pool).await.map_err(|e| e.to_string())
+}
+
+/// Moves trashed photos back to where they were deleted from.
+#[tauri::command]
+pub async fn restore_from_trash(
+    photo_ids: Vec<i64>,
+    state: State<'_, AppState>,
+) -> Result<TrashSummary, String> {
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    sync_engine
+        .record_action(
+            format!("Restore {} photos", photo_ids.len()),
+            async |sync_engine| trash::restore_photos(sync_engine, &photo_ids).await,
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Permanently deletes everything in the trash.
+#[tauri::command]
+pub async fn empty_trash(state: State<'_, AppState>) -> Result<TrashSummary, String> {
+    let mut sync_engine = state.sync_engine.lock().await;
+    let sync_engine = sync_engine.as_mut().ok_or("Sync engine not initialized")?;
+    trash::empty_trash(sync_engine)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Sets how many days deleted photos are kept. `None` restores the default.
+#[tauri::command]
+pub async fn set_trash_retention_days(days: Option<u32>) -> Result<(), String> {
+    if days == Some(0) {
+        return Err("Photos must be kept in the trash for at least a day".to_string());
+    }
+    let config_path = config::get_app_config_dir()
+        .map_err(|e| e.to_string())?
+        .join(CONFIG_FILE_NAME);
+    let mut current_config = config::load_config_from_path(&config_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    current_config.trash_retention_days = days;
+    config::save_config_to_path(&current_config, &config_path)
+        .await
+        .map_err(|e| e.to_string())
+}
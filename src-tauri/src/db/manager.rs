@@ -19,11 +19,13 @@ impl DatabaseManager {
     }
 
     pub async fn get_photos(&self, limit: i64, offset: i64) -> Result<Vec<Photo>> {
-        let photos = sqlx::query_as::<_, Photo>("SELECT * FROM photos LIMIT ? OFFSET ?")
-            .bind(limit)
-            .bind(offset)
-            .fetch_all(&self.primary_pool)
-            .await?;
+        let photos = sqlx::query_as::<_, Photo>(
+            "SELECT * FROM photos WHERE deleted_at IS NULL LIMIT ? OFFSET ?",
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.primary_pool)
+        .await?;
         Ok(photos)
     }
 
@@ -4,17 +4,39 @@ pub mod models;
 pub mod services;
 
 use commands::album::{
-    add_photos_to_album, create_album, delete_album, get_albums, get_photos_by_album,
+    add_photos_to_album, create_album, create_smart_album, delete_album, delete_smart_album,
+    get_albums, get_photos_by_album, get_photos_by_smart_album, update_smart_album,
+};
+use commands::duplicates::{
+    delete_duplicates, find_cross_drive_duplicates, find_duplicates, find_similar_photos,
+    keep_best_copies, recommend_keepers,
 };
-use commands::duplicates::{delete_duplicates, find_duplicates};
 use commands::filter::{filter_photos_command, search_photos_command};
+use commands::formats::{get_formats, set_enabled_formats};
+use commands::history::{get_history, redo, undo_last};
+use commands::import::{cancel_import, import_folder, set_import_template};
+use commands::organize::{organize_library, preview_organize};
+use commands::reconcile::{reconcile_drives, repair_discrepancies};
+use commands::rename::{batch_rename, preview_batch_rename};
+use commands::scan::{cancel_scan, scan_library};
+use commands::search::{query_photos, search_library};
+use commands::stack::{create_stack, get_stack, set_stack_representative, unstack_photo};
 use commands::tag::{add_tag, get_all_tags};
+use commands::trash::{empty_trash, list_trash, restore_from_trash, set_trash_retention_days};
 use commands::{
-    delete_photos, get_config, get_photos, get_sync_queue_status, move_photos, rename_photo,
-    scan_library, set_drive_paths, verify_sync_status, AppState,
+    delete_photos, get_config, get_missing_photos, get_photos, get_sync_queue_status, move_photos,
+    rename_photo, set_drive_paths, set_watch_primary_drive, verify_sync_status, AppState,
 };
 use db::manager::DatabaseManager;
+use services::backup_monitor;
 use services::config::{self, CONFIG_FILE_NAME};
+use services::file_mirror::FileMirror;
+use services::formats::FormatRegistry;
+use services::library_watcher;
+use services::media_protocol;
+use services::thumbnail::ThumbnailCache;
+use services::trash;
+use tauri::http::StatusCode;
 use tauri::{async_runtime::Mutex, Manager};
 
 use crate::services::sync_engine::SyncEngine;
@@ -29,35 +51,93 @@ pub fn run() {
     let initial_config =
         tauri::async_runtime::block_on(config::load_config_from_path(&config_path))
             .unwrap_or_default();
+    let formats = FormatRegistry::from_config(&initial_config);
 
     let state = AppState {
         db_pool: Mutex::new(None),
         sync_engine: Mutex::new(None),
+        scan_job: Mutex::new(None),
+        import_job: Mutex::new(None),
     };
 
     tauri::Builder::default()
         .manage(state)
+        .register_asynchronous_uri_scheme_protocol(
+            media_protocol::SCHEME,
+            |ctx, request, responder| {
+                let app = ctx.app_handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    let state = app.state::<AppState>();
+                    // Release the engine lock before reading any files
+                    let source = state.sync_engine.lock().await.as_ref().map(|engine| {
+                        (engine.primary_db.clone(), engine.thumbnail_cache().cloned())
+                    });
+                    let response = match source {
+                        Some((pool, thumbnails)) => {
+                            media_protocol::respond(&pool, thumbnails.as_ref(), &request).await
+                        }
+                        None => media_protocol::empty_response(StatusCode::SERVICE_UNAVAILABLE),
+                    };
+                    responder.respond(response);
+                });
+            },
+        )
         .invoke_handler(tauri::generate_handler![
             get_config,
             set_drive_paths,
+            set_watch_primary_drive,
             verify_sync_status,
             move_photos,
             delete_photos,
+            list_trash,
+            restore_from_trash,
+            empty_trash,
+            set_trash_retention_days,
+            get_history,
+            undo_last,
+            redo,
             rename_photo,
+            preview_batch_rename,
+            batch_rename,
+            get_stack,
+            create_stack,
+            set_stack_representative,
+            unstack_photo,
             get_sync_queue_status,
             scan_library,
+            get_formats,
+            set_enabled_formats,
+            cancel_scan,
+            import_folder,
+            cancel_import,
+            set_import_template,
+            preview_organize,
+            organize_library,
             get_photos,
+            get_missing_photos,
             create_album,
             add_photos_to_album,
             get_albums,
             delete_album,
             get_photos_by_album,
+            create_smart_album,
+            update_smart_album,
+            delete_smart_album,
+            get_photos_by_smart_album,
             add_tag,
             get_all_tags,
             filter_photos_command,
             search_photos_command,
+            search_library,
+            query_photos,
             find_duplicates,
+            find_similar_photos,
+            find_cross_drive_duplicates,
+            recommend_keepers,
+            keep_best_copies,
             delete_duplicates,
+            reconcile_drives,
+            repair_discrepancies,
         ])
         .setup(move |app| {
             let handle = app.handle();
@@ -70,9 +150,20 @@ pub fn run() {
                 let result = tauri::async_runtime::block_on(async {
                     let primary_pool =
                         DatabaseManager::create_pool(&primary_path.join("photovault.db")).await?;
-                    let backup_pool =
-                        DatabaseManager::create_pool(&backup_path.join("photovault.db")).await?;
-                    let engine = SyncEngine::new(primary_pool.clone(), Some(backup_pool));
+                    // An unplugged backup drive is picked up later by the backup monitor
+                    let backup_pool = if backup_path.is_dir() {
+                        Some(
+                            DatabaseManager::create_pool(&backup_path.join("photovault.db"))
+                                .await?,
+                        )
+                    } else {
+                        None
+                    };
+                    let mut engine = SyncEngine::new(primary_pool.clone(), backup_pool)
+                        .with_file_mirror(FileMirror::new(primary_path.clone(), backup_path))
+                        .with_thumbnail_cache(ThumbnailCache::for_library(&primary_path))
+                        .with_formats(formats);
+                    engine.load_queue().await?;
                     Ok::<_, anyhow::Error>((primary_pool, engine))
                 });
 
@@ -84,6 +175,10 @@ pub fn run() {
                 }
             }
 
+            backup_monitor::spawn(handle.clone());
+            library_watcher::spawn(handle.clone());
+            trash::spawn(handle.clone());
+
             Ok(())
         })
         .run(tauri::generate_context!())
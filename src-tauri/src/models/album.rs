@@ -1,3 +1,4 @@
+use crate::models::filter::FilterCriteria;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -5,4 +6,26 @@ use sqlx::FromRow;
 pub struct Album {
     pub id: i64,
     pub name: String,
+    /// How many photos in the library the album holds right now.
+    #[sqlx(default)]
+    pub photo_count: i64,
+}
+
+/// An album holding whichever photos match its filter, rather than photos
+/// added to it. Smart albums are numbered separately from albums.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartAlbum {
+    pub id: i64,
+    pub name: String,
+    pub criteria: FilterCriteria,
+    /// How many photos in the library match `criteria` right now.
+    pub photo_count: i64,
+}
+
+/// One entry in the album list, which holds both kinds of album.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "album", rename_all = "snake_case")]
+pub enum AlbumEntry {
+    Album(Album),
+    Smart(SmartAlbum),
 }
@@ -1,7 +1,9 @@
+use crate::models::photo::MediaType;
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct FilterCriteria {
     pub date_from: Option<DateTime<Utc>>,
     pub date_to: Option<DateTime<Utc>>,
@@ -10,4 +12,8 @@ pub struct FilterCriteria {
     pub tags: Option<Vec<i64>>,
     pub albums: Option<Vec<i64>>,
     pub query: Option<String>,
+    pub media_type: Option<MediaType>,
+    /// A query in the language of `services::query` the photos must also
+    /// match, e.g. `tag:beach -tag:work`.
+    pub expression: Option<String>,
 }
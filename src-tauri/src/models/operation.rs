@@ -1,15 +1,115 @@
+use super::filter::FilterCriteria;
 use super::photo::Photo;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Operation {
-    AddPhoto { photo: Photo },
-    Move { from: PathBuf, to: PathBuf },
-    Delete { path: PathBuf },
-    Rename { path: PathBuf, new_name: String },
-    CreateAlbum { name: String },
-    DeleteAlbum { album_id: i64 },
-    AddToAlbum { photo_id: i64, album_id: i64 },
-    AddTag { photo_id: i64, tag_name: String },
+    AddPhoto {
+        photo: Box<Photo>,
+    },
+    SyncPhoto {
+        photo: Box<Photo>,
+    },
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Permanently removes the photo at `path` from the catalog.
+    Delete {
+        path: PathBuf,
+    },
+    /// Moves the photo at `path` into the trash at `trash_path`, keeping its
+    /// catalog row until the trash is emptied.
+    Trash {
+        path: PathBuf,
+        trash_path: PathBuf,
+    },
+    /// Moves a trashed photo from `trash_path` back to `path`.
+    Restore {
+        trash_path: PathBuf,
+        path: PathBuf,
+    },
+    Rename {
+        path: PathBuf,
+        new_name: String,
+    },
+    CreateAlbum {
+        name: String,
+    },
+    /// Deletes the album with `album_id`. When `name` is given, the delete is
+    /// refused if that album has since been replaced by one with another
+    /// name, as on a backup whose albums are numbered differently.
+    DeleteAlbum {
+        album_id: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    /// Recreates a deleted album under its old id, with the photos it held,
+    /// numbering its name if another album has taken it since.
+    RestoreAlbum {
+        album_id: i64,
+        name: String,
+        photo_ids: Vec<i64>,
+    },
+    /// Saves a filter as an album holding the photos that match it.
+    CreateSmartAlbum {
+        name: String,
+        criteria: FilterCriteria,
+    },
+    UpdateSmartAlbum {
+        smart_album_id: i64,
+        name: String,
+        criteria: FilterCriteria,
+    },
+    /// Deletes the smart album with `smart_album_id`, refused like
+    /// `DeleteAlbum` when it no longer has `name`.
+    DeleteSmartAlbum {
+        smart_album_id: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    /// Recreates a deleted smart album under its old id, numbering its name
+    /// if another smart album has taken it since.
+    RestoreSmartAlbum {
+        smart_album_id: i64,
+        name: String,
+        criteria: FilterCriteria,
+    },
+    AddToAlbum {
+        photo_id: i64,
+        album_id: i64,
+    },
+    RemoveFromAlbum {
+        photo_id: i64,
+        album_id: i64,
+    },
+    AddTag {
+        photo_id: i64,
+        tag_name: String,
+    },
+    /// Untags the photo, dropping the tag once no photo has it.
+    RemoveTag {
+        photo_id: i64,
+        tag_name: String,
+    },
+    /// Groups the photos at `paths` into one stack shown as `representative`,
+    /// taking them out of any stack they were in.
+    CreateStack {
+        paths: Vec<PathBuf>,
+        representative: PathBuf,
+    },
+    /// Dissolves the stack containing the photo at `path`.
+    Unstack {
+        path: PathBuf,
+    },
+}
+
+/// An operation recorded in `sync_operations` that still has to reach the backup drive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedOperation {
+    pub id: i64,
+    pub operation: Operation,
+    pub attempts: i64,
+    pub last_error: Option<String>,
 }
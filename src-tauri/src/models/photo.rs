@@ -1,43 +1,124 @@
-use anyhow::Result;
+use crate::services::exif;
+use crate::services::formats;
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
-use image::{GenericImageView, ImageFormat};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum MediaType {
+    #[default]
+    Photo,
+    Video,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
 pub struct Photo {
     #[sqlx(default)]
     pub id: i64,
     pub path: String,
     pub filename: String,
     pub file_hash: Option<String>,
+    /// Hex difference hash of the decoded image, for finding near duplicates.
+    pub perceptual_hash: Option<String>,
     pub file_size: Option<i64>,
     pub date_taken: Option<DateTime<Utc>>,
     pub width: Option<i64>,
     pub height: Option<i64>,
     pub format: String,
+    pub date_taken_offset: Option<i64>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    pub iso: Option<i64>,
+    pub aperture: Option<f64>,
+    pub exposure_time: Option<f64>,
+    pub focal_length: Option<f64>,
+    pub orientation: Option<i64>,
+    pub file_mtime: Option<i64>,
+    pub missing_since: Option<DateTime<Utc>>,
+    pub media_type: MediaType,
+    pub duration_ms: Option<i64>,
+    pub video_codec: Option<String>,
+    pub stack_id: Option<i64>,
+    pub content_identifier: Option<String>,
+    pub caption: Option<String>,
+    /// When the photo was moved to the trash; `None` for photos in the library.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Where a trashed photo is restored to.
+    pub trashed_from: Option<String>,
 }
 
 impl Photo {
     pub fn new_from_path(path: PathBuf) -> Result<Self> {
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| anyhow!("File path is not valid UTF-8: {}", path.display()))?
+            .to_string();
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("File has no name: {}", path.display()))?
+            .to_string();
         let metadata = std::fs::metadata(&path)?;
         let file_size = metadata.len() as i64;
 
-        let img = image::open(&path)?;
-        let (width, height) = img.dimensions();
-        let format = format!("{:?}", ImageFormat::from_path(&path)?);
+        let handler = formats::handler_for(&path)
+            .ok_or_else(|| anyhow!("Unsupported file type: {}", path.display()))?;
+        let format = handler.name().to_string();
+        let video = handler.video_info(&path)?;
+        let (width, height) = match &video {
+            Some(video) => (video.width, video.height),
+            None => handler.dimensions(&path)?,
+        };
+        // Videos carry their capture time in the container rather than EXIF
+        let (media_type, exif) = match &video {
+            Some(_) => (MediaType::Video, exif::ExifData::default()),
+            None => (MediaType::Photo, exif::read_exif(&path)?),
+        };
+        let video = video.unwrap_or_default();
 
         Ok(Self {
             id: 0,
-            path: path.to_str().unwrap().to_string(),
-            filename: path.file_name().unwrap().to_str().unwrap().to_string(),
+            path: path_str,
+            filename,
             file_hash: None,
+            perceptual_hash: None,
             file_size: Some(file_size),
-            date_taken: None, // TODO: Read from EXIF
+            date_taken: exif.date_taken.or(video.created),
             width: Some(width as i64),
             height: Some(height as i64),
             format,
+            date_taken_offset: exif.date_taken_offset,
+            camera_make: exif.camera_make,
+            camera_model: exif.camera_model,
+            lens_model: exif.lens_model,
+            iso: exif.iso,
+            aperture: exif.aperture,
+            exposure_time: exif.exposure_time,
+            focal_length: exif.focal_length,
+            orientation: exif.orientation,
+            file_mtime: modified_millis(&metadata),
+            missing_since: None,
+            media_type,
+            duration_ms: video.duration_ms,
+            video_codec: video.codec,
+            stack_id: None,
+            content_identifier: exif.content_identifier.or(video.content_identifier),
+            caption: exif.caption,
+            deleted_at: None,
+            trashed_from: None,
         })
     }
 }
+
+/// A file's modification time in milliseconds since the Unix epoch, as stored
+/// in `photos.file_mtime`.
+pub fn modified_millis(metadata: &std::fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_millis() as i64)
+}
@@ -1,5 +1,7 @@
-use crate::models::album::Album;
+use crate::models::album::AlbumEntry;
+use crate::models::filter::FilterCriteria;
 use crate::models::operation::Operation;
+use crate::services::filter;
 use crate::services::sync_engine::SyncEngine;
 use anyhow::{anyhow, Result};
 use tauri::async_runtime::Mutex;
@@ -14,48 +16,60 @@ impl<'a> AlbumService<'a> {
     }
 
     pub async fn create_album(&self, name: String) -> Result<()> {
+        let label = format!("Create album {}", name);
         let op = Operation::CreateAlbum { name };
-        let mut sync_engine = self.sync_engine.lock().await;
-        if let Some(sync_engine) = &mut *sync_engine {
-            sync_engine.execute_operation(&op).await
-        } else {
-            Err(anyhow!("Sync engine not initialized"))
-        }
+        self.record(label, &op).await
     }
 
     pub async fn add_photos_to_album(&self, photo_ids: Vec<i64>, album_id: i64) -> Result<()> {
         let mut sync_engine = self.sync_engine.lock().await;
         if let Some(sync_engine) = &mut *sync_engine {
-            for photo_id in photo_ids {
-                let op = Operation::AddToAlbum { photo_id, album_id };
-                sync_engine.execute_operation(&op).await?;
-            }
-            Ok(())
+            sync_engine
+                .record_action(
+                    format!("Add {} photos to album", photo_ids.len()),
+                    async |sync_engine| {
+                        for photo_id in photo_ids {
+                            let op = Operation::AddToAlbum { photo_id, album_id };
+                            sync_engine.execute_operation(&op).await?;
+                        }
+                        Ok(())
+                    },
+                )
+                .await
         } else {
             Err(anyhow!("Sync engine not initialized"))
         }
     }
 
-    pub async fn get_albums(&self) -> Result<Vec<Album>> {
+    /// The albums, then the smart albums, each with its live photo count.
+    pub async fn get_albums(&self) -> Result<Vec<AlbumEntry>> {
         let sync_engine = self.sync_engine.lock().await;
         if let Some(sync_engine) = &*sync_engine {
-            let albums = sqlx::query_as::<_, Album>("SELECT * FROM albums")
-                .fetch_all(&sync_engine.primary_db)
-                .await?;
-            Ok(albums)
+            let mut entries: Vec<AlbumEntry> = sync_engine
+                .get_albums()
+                .await?
+                .into_iter()
+                .map(AlbumEntry::Album)
+                .collect();
+            entries.extend(
+                sync_engine
+                    .get_smart_albums()
+                    .await?
+                    .into_iter()
+                    .map(AlbumEntry::Smart),
+            );
+            Ok(entries)
         } else {
             Err(anyhow!("Sync engine not initialized"))
         }
     }
 
     pub async fn delete_album(&self, album_id: i64) -> Result<()> {
-        let op = Operation::DeleteAlbum { album_id };
-        let mut sync_engine = self.sync_engine.lock().await;
-        if let Some(sync_engine) = &mut *sync_engine {
-            sync_engine.execute_operation(&op).await
-        } else {
-            Err(anyhow!("Sync engine not initialized"))
-        }
+        let op = Operation::DeleteAlbum {
+            album_id,
+            name: None,
+        };
+        self.record("Delete album", &op).await
     }
 
     pub async fn get_photos_by_album_id(
@@ -69,4 +83,63 @@ impl<'a> AlbumService<'a> {
             Err(anyhow!("Sync engine not initialized"))
         }
     }
+
+    pub async fn create_smart_album(&self, name: String, criteria: FilterCriteria) -> Result<()> {
+        filter::validate(&criteria)?;
+        let label = format!("Create smart album {}", name);
+        let op = Operation::CreateSmartAlbum { name, criteria };
+        self.record(label, &op).await
+    }
+
+    pub async fn update_smart_album(
+        &self,
+        smart_album_id: i64,
+        name: String,
+        criteria: FilterCriteria,
+    ) -> Result<()> {
+        filter::validate(&criteria)?;
+        let label = format!("Edit smart album {}", name);
+        let op = Operation::UpdateSmartAlbum {
+            smart_album_id,
+            name,
+            criteria,
+        };
+        self.record(label, &op).await
+    }
+
+    pub async fn delete_smart_album(&self, smart_album_id: i64) -> Result<()> {
+        let op = Operation::DeleteSmartAlbum {
+            smart_album_id,
+            name: None,
+        };
+        self.record("Delete smart album", &op).await
+    }
+
+    pub async fn get_photos_by_smart_album_id(
+        &self,
+        smart_album_id: i64,
+    ) -> Result<Vec<crate::models::photo::Photo>> {
+        let sync_engine = self.sync_engine.lock().await;
+        if let Some(sync_engine) = &*sync_engine {
+            sync_engine
+                .get_photos_by_smart_album_id(smart_album_id)
+                .await
+        } else {
+            Err(anyhow!("Sync engine not initialized"))
+        }
+    }
+
+    /// Executes `op` as an undoable action called `label`.
+    async fn record(&self, label: impl Into<String>, op: &Operation) -> Result<()> {
+        let mut sync_engine = self.sync_engine.lock().await;
+        if let Some(sync_engine) = &mut *sync_engine {
+            sync_engine
+                .record_action(label, async |sync_engine| {
+                    sync_engine.execute_operation(op).await
+                })
+                .await
+        } else {
+            Err(anyhow!("Sync engine not initialized"))
+        }
+    }
 }
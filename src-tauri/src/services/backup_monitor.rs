@@ -0,0 +1,168 @@
+use crate::db::manager::DatabaseManager;
+use crate::services::config::{self, CONFIG_FILE_NAME};
+use crate::services::sync_engine::SyncEngine;
+use crate::services::sync_status;
+use crate::AppState;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
+
+/// How often the backup drive's availability is checked.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How long to wait before retrying a queue flush that failed while the drive stayed connected.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+pub const BACKUP_CONNECTED_EVENT: &str = "backup-connected";
+pub const BACKUP_DISCONNECTED_EVENT: &str = "backup-disconnected";
+pub const SYNC_PROGRESS_EVENT: &str = "sync-progress";
+pub const SYNC_RESULT_EVENT: &str = "sync-result";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncProgress {
+    pub flushed: u32,
+    pub remaining: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncResult {
+    pub flushed: u32,
+    pub remaining: u32,
+    pub error: Option<String>,
+}
+
+/// How `update_backup` changed the engine's backup catalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupChange {
+    Reconnected,
+    Disconnected,
+    Unchanged,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    last_failure: Option<Instant>,
+}
+
+/// Attaches the catalog on `backup_drive` once the drive is `connected` again,
+/// or detaches it when the drive is gone.
+pub async fn update_backup(
+    engine: &mut SyncEngine,
+    backup_drive: Option<&Path>,
+    connected: bool,
+) -> Result<BackupChange> {
+    match (connected, backup_drive, engine.has_backup()) {
+        (true, Some(backup_drive), false) => {
+            let pool = DatabaseManager::create_pool(&backup_drive.join("photovault.db")).await?;
+            engine.set_backup_db(Some(pool));
+            Ok(BackupChange::Reconnected)
+        }
+        (false, _, true) => {
+            engine.set_backup_db(None);
+            Ok(BackupChange::Disconnected)
+        }
+        _ => Ok(BackupChange::Unchanged),
+    }
+}
+
+/// Drains the queue one operation at a time, releasing the engine lock between
+/// operations so commands issued from the UI aren't blocked by a long flush.
+///
+/// Stops at the first operation that fails, leaving it at the head of the queue.
+pub async fn drain_queue(
+    engine: &Mutex<Option<SyncEngine>>,
+    mut on_progress: impl FnMut(&SyncProgress),
+) -> SyncResult {
+    let mut flushed = 0;
+
+    let (remaining, error) = loop {
+        let mut sync_engine = engine.lock().await;
+        let Some(sync_engine) = sync_engine.as_mut() else {
+            break (0, None);
+        };
+        if !sync_engine.has_backup() {
+            break (sync_engine.queue_len() as u32, None);
+        }
+
+        let failed = sync_engine.failed_operations().len();
+        match sync_engine.flush_next().await {
+            Ok(true) => {
+                // An operation given up on is set aside rather than copied
+                if sync_engine.failed_operations().len() == failed {
+                    flushed += 1;
+                }
+                on_progress(&SyncProgress {
+                    flushed,
+                    remaining: sync_engine.queue_len() as u32,
+                });
+            }
+            Ok(false) => break (0, None),
+            Err(e) => break (sync_engine.queue_len() as u32, Some(e.to_string())),
+        }
+    };
+
+    SyncResult {
+        flushed,
+        remaining,
+        error,
+    }
+}
+
+/// Starts a background task that watches `AppConfig::backup_drive`, re-attaches
+/// the backup catalog when the drive reappears and flushes the pending queue.
+pub fn spawn(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut monitor = MonitorState::default();
+        loop {
+            if let Err(e) = poll(&app, &mut monitor).await {
+                eprintln!("Backup monitor error: {}", e);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+async fn poll(app: &AppHandle, monitor: &mut MonitorState) -> Result<()> {
+    let config_path = config::get_app_config_dir()?.join(CONFIG_FILE_NAME);
+    let config = config::load_config_from_path(&config_path).await?;
+    let status = sync_status::verify_sync_status(&config).await?;
+    let state = app.state::<AppState>();
+
+    let flush_due = {
+        let mut sync_engine = state.sync_engine.lock().await;
+        let Some(sync_engine) = sync_engine.as_mut() else {
+            return Ok(());
+        };
+
+        let change = update_backup(
+            sync_engine,
+            config.backup_drive.as_deref(),
+            status.backup_connected,
+        )
+        .await?;
+        match change {
+            BackupChange::Reconnected => app.emit(BACKUP_CONNECTED_EVENT, ())?,
+            BackupChange::Disconnected => app.emit(BACKUP_DISCONNECTED_EVENT, ())?,
+            BackupChange::Unchanged => {}
+        }
+
+        let retry_due = monitor
+            .last_failure
+            .is_none_or(|failed_at| failed_at.elapsed() >= RETRY_INTERVAL);
+        sync_engine.has_backup()
+            && sync_engine.queue_len() > 0
+            && (change == BackupChange::Reconnected || retry_due)
+    };
+
+    if flush_due {
+        let result = drain_queue(&state.sync_engine, |progress| {
+            let _ = app.emit(SYNC_PROGRESS_EVENT, progress);
+        })
+        .await;
+        monitor.last_failure = result.error.as_ref().map(|_| Instant::now());
+        app.emit(SYNC_RESULT_EVENT, result)?;
+    }
+    Ok(())
+}
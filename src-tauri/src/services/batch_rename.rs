@@ -0,0 +1,338 @@
+use crate::models::operation::Operation;
+use crate::models::photo::Photo;
+use crate::services::importer::capture_time;
+use crate::services::stack;
+use crate::services::sync_engine::{AlreadyCommitted, SyncEngine};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Format of `{date}` without a format of its own.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+/// Stands in for `{camera}` on photos without camera metadata.
+const UNKNOWN_CAMERA: &str = "Unknown";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    /// Capture time with a strftime format.
+    Date(String),
+    Camera,
+    /// Position in the batch, zero-padded to the width.
+    Seq(usize),
+    /// The file's current name without its extension.
+    Name,
+    /// The file's current extension with its dot, or nothing.
+    Ext,
+}
+
+/// A file name template such as `{date:%Y%m%d}_{camera}_{seq:04}{ext}`.
+///
+/// `{date}` is the capture time on the camera's clock, formatted with
+/// `%Y-%m-%d` or the strftime format after the colon. `{camera}` is the
+/// camera model, `{seq}` the photo's 1-based position in the batch (padded
+/// with zeros to the width after the colon), `{name}` the current name
+/// without its extension and `{ext}` the current extension, dot included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameTemplate {
+    tokens: Vec<Token>,
+}
+
+impl RenameTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if rest[..start].contains('}') {
+                bail!("Unmatched '}}' in rename template");
+            }
+            if start > 0 {
+                tokens.push(Token::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow!("Unclosed '{{' in rename template"))?;
+            tokens.push(Self::parse_token(&rest[start + 1..end])?);
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            bail!("Unmatched '}}' in rename template");
+        }
+        if !rest.is_empty() {
+            tokens.push(Token::Literal(rest.to_string()));
+        }
+
+        let has_separator = tokens.iter().any(|token| match token {
+            Token::Literal(text) => text.contains(['/', '\\']),
+            _ => false,
+        });
+        if has_separator {
+            bail!("Rename template can't contain path separators");
+        }
+        if tokens.is_empty() {
+            bail!("Rename template is empty");
+        }
+        Ok(Self { tokens })
+    }
+
+    fn parse_token(token: &str) -> Result<Token> {
+        let (name, spec) = match token.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (token, None),
+        };
+        match (name, spec) {
+            ("date", spec) => {
+                let format = spec.unwrap_or(DEFAULT_DATE_FORMAT);
+                if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    bail!("Invalid date format '{}' in rename template", format);
+                }
+                Ok(Token::Date(format.to_string()))
+            }
+            ("seq", None) => Ok(Token::Seq(0)),
+            ("seq", Some(width)) => width
+                .parse()
+                .map(Token::Seq)
+                .map_err(|_| anyhow!("Invalid sequence width '{}' in rename template", width)),
+            ("camera", None) => Ok(Token::Camera),
+            ("name", None) => Ok(Token::Name),
+            ("ext", None) => Ok(Token::Ext),
+            _ => bail!("Unknown placeholder {{{}}} in rename template", token),
+        }
+    }
+
+    /// The new file name for `photo` as the `seq`th file of the batch.
+    pub fn render(&self, photo: &Photo, seq: usize) -> String {
+        let path = Path::new(&photo.path);
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Literal(text) => text.clone(),
+                Token::Date(format) => sanitize(&capture_time(photo).format(format).to_string()),
+                Token::Camera => sanitize(
+                    photo
+                        .camera_model
+                        .as_deref()
+                        .or(photo.camera_make.as_deref())
+                        .unwrap_or(UNKNOWN_CAMERA)
+                        .trim(),
+                ),
+                Token::Seq(width) => format!("{:0width$}", seq, width = width),
+                Token::Name => path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                Token::Ext => path
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+/// Replaces path separators in metadata, e.g. a camera model containing `/`.
+fn sanitize(value: &str) -> String {
+    value.replace(['/', '\\'], "-")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedRename {
+    pub photo_id: i64,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Files that can't all take the name `target`: several files in the batch
+/// render to it, or it belongs to a file that isn't being renamed away.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenameCollision {
+    pub target: PathBuf,
+    pub sources: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenamePlan {
+    /// The renames in the order they have to happen, so a file only takes a
+    /// name once its previous owner has moved on. Files whose name doesn't
+    /// change are left out.
+    pub renames: Vec<PlannedRename>,
+    pub collisions: Vec<RenameCollision>,
+}
+
+/// Works out the new names of the photos with `photo_ids` without touching
+/// any files.
+///
+/// Photos are numbered for `{seq}` in order of capture time. The rest of a
+/// photo's stack takes the same name with its own extension, as with a
+/// single rename.
+pub async fn plan_rename(
+    pool: &SqlitePool,
+    photo_ids: &[i64],
+    template: &RenameTemplate,
+) -> Result<RenamePlan> {
+    let mut selected = Vec::new();
+    for &photo_id in photo_ids {
+        let photo = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE id = ?")
+            .bind(photo_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| anyhow!("Photo {} not found", photo_id))?;
+        selected.push(photo);
+    }
+    selected.sort_by(|a, b| (capture_time(a), &a.path).cmp(&(capture_time(b), &b.path)));
+
+    let mut seen = HashSet::new();
+    let mut renames = Vec::new();
+    let mut seq = 0;
+    for photo in selected {
+        if seen.contains(&photo.id) {
+            continue;
+        }
+        seq += 1;
+        let new_name = template.render(&photo, seq);
+        if matches!(new_name.as_str(), "" | "." | "..") {
+            bail!("Rename template gives {} an empty name", photo.filename);
+        }
+        for member in stack::members(pool, photo.id).await? {
+            if !seen.insert(member.id) {
+                continue;
+            }
+            let from = PathBuf::from(&member.path);
+            let to = if member.id == photo.id {
+                from.with_file_name(&new_name)
+            } else {
+                from.with_file_name(stack::member_file_name(&from, &new_name))
+            };
+            if to != from {
+                renames.push(PlannedRename {
+                    photo_id: member.id,
+                    from,
+                    to,
+                });
+            }
+        }
+    }
+    Ok(order_renames(renames))
+}
+
+/// Orders `renames` so each target is free when its turn comes and reports
+/// the ones that can't happen.
+fn order_renames(renames: Vec<PlannedRename>) -> RenamePlan {
+    let mut by_target: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for rename in &renames {
+        by_target
+            .entry(rename.to.clone())
+            .or_default()
+            .push(rename.from.clone());
+    }
+    let mut collisions: Vec<RenameCollision> = by_target
+        .into_iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .map(|(target, sources)| RenameCollision { target, sources })
+        .collect();
+    let pending: Vec<PlannedRename> = renames
+        .into_iter()
+        .filter(|rename| !collisions.iter().any(|c| c.target == rename.to))
+        .collect();
+
+    // Names held by files in the batch free up once those files move on
+    let moving: HashSet<PathBuf> = pending.iter().map(|rename| rename.from.clone()).collect();
+    let (pending, taken): (Vec<_>, Vec<_>) = pending
+        .into_iter()
+        .partition(|rename| moving.contains(&rename.to) || !rename.to.exists());
+    collisions.extend(taken.into_iter().map(collision));
+
+    // A rename waits while its target still holds an earlier name; whatever
+    // is left when nothing can proceed is a cycle, like two files swapping
+    let mut renames = Vec::new();
+    let mut pending = pending;
+    loop {
+        let held: HashSet<PathBuf> = pending.iter().map(|rename| rename.from.clone()).collect();
+        let (ready, blocked): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|rename| !held.contains(&rename.to));
+        pending = blocked;
+        if ready.is_empty() {
+            break;
+        }
+        renames.extend(ready);
+    }
+    collisions.extend(pending.into_iter().map(collision));
+    collisions.sort_by(|a, b| a.target.cmp(&b.target));
+    RenamePlan {
+        renames,
+        collisions,
+    }
+}
+
+fn collision(rename: PlannedRename) -> RenameCollision {
+    RenameCollision {
+        target: rename.to,
+        sources: vec![rename.from],
+    }
+}
+
+/// Renames the files in `plan` and catalogs the renames as one
+/// `Operation::Rename` per file.
+///
+/// A plan with collisions is refused, and if a file can't be renamed or the
+/// catalog update fails, the files already renamed are put back, so nothing
+/// changes. The exception is a failure after the catalog committed the
+/// renames, e.g. while mirroring them: the renames then stand and the error
+/// is still returned.
+pub async fn apply_rename(engine: &mut SyncEngine, plan: &RenamePlan) -> Result<()> {
+    if !plan.collisions.is_empty() {
+        bail!(
+            "{} files would collide with existing names",
+            plan.collisions.len()
+        );
+    }
+
+    let mut renamed: Vec<&PlannedRename> = Vec::new();
+    let mut result = Ok(());
+    for rename in &plan.renames {
+        // `rename` replaces existing files on Unix, so check again
+        if rename.to.exists() {
+            result = Err(anyhow!("{} already exists", rename.to.display()));
+            break;
+        }
+        if let Err(e) = tokio::fs::rename(&rename.from, &rename.to).await {
+            result = Err(e).with_context(|| format!("Failed to rename {}", rename.from.display()));
+            break;
+        }
+        renamed.push(rename);
+    }
+    if result.is_ok() {
+        let ops: Vec<Operation> = plan
+            .renames
+            .iter()
+            .map(|rename| Operation::Rename {
+                path: rename.from.clone(),
+                new_name: rename
+                    .to
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        result = engine.execute_batch(&ops).await;
+    }
+
+    if result.as_ref().is_err_and(|e| !e.is::<AlreadyCommitted>()) {
+        for rename in renamed.into_iter().rev() {
+            if let Err(e) = tokio::fs::rename(&rename.to, &rename.from).await {
+                eprintln!(
+                    "Failed to restore {} to {}: {}",
+                    rename.to.display(),
+                    rename.from.display(),
+                    e
+                );
+            }
+        }
+    }
+    result
+}
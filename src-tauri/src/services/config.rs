@@ -11,6 +11,21 @@ pub const CONFIG_FILE_NAME: &str = "config.json";
 pub struct AppConfig {
     pub primary_drive: Option<PathBuf>,
     pub backup_drive: Option<PathBuf>,
+    /// Keep the catalog current by watching the primary drive for changes.
+    #[serde(default)]
+    pub watch_primary_drive: bool,
+    /// Names of the formats scans pick up (see `FormatRegistry`); every
+    /// supported format when unset.
+    #[serde(default)]
+    pub enabled_formats: Option<Vec<String>>,
+    /// Where imports place files inside the primary drive (see
+    /// `PathTemplate`); `DEFAULT_IMPORT_TEMPLATE` when unset.
+    #[serde(default)]
+    pub import_template: Option<String>,
+    /// Days a deleted photo stays in the trash before it's removed for good;
+    /// `DEFAULT_TRASH_RETENTION_DAYS` when unset.
+    #[serde(default)]
+    pub trash_retention_days: Option<u32>,
 }
 
 /// Returns the path to the application's config directory.
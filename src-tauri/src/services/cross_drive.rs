@@ -0,0 +1,365 @@
+use crate::models::photo::modified_millis;
+use crate::services::duplicate::{hash_file_blocking, HashPool, HASH_BATCH_SIZE};
+use crate::services::file_mirror::FileMirror;
+use crate::services::sync_engine::SyncEngine;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Drive {
+    Primary,
+    Backup,
+}
+
+/// A file on one of the drives.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DriveCopy {
+    pub drive: Drive,
+    /// Where the file is on its drive.
+    pub path: PathBuf,
+    pub size: u64,
+    /// The primary catalog's photo for copies on the primary drive.
+    pub photo_id: Option<i64>,
+}
+
+/// How the copies in a `CrossDriveGroup` relate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossDriveKind {
+    /// The same content under different paths on each drive, e.g. a folder
+    /// renamed on one drive only.
+    Relocated,
+    /// Both drives hold the path, but with different content.
+    Diverged,
+    /// Content only one drive holds, under a path the other drive doesn't
+    /// have. Several copies of it on that drive are grouped together.
+    Orphaned,
+}
+
+/// Files that differ between the drives, like `DuplicateGroup` but with the
+/// drive each copy lives on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrossDriveGroup {
+    pub kind: CrossDriveKind,
+    /// The content hash the copies share; `None` for diverged copies and for
+    /// orphans no other file could match, which aren't hashed.
+    pub hash: Option<String>,
+    pub copies: Vec<DriveCopy>,
+    pub size: u64,
+}
+
+/// What a catalog says about a photo's file.
+#[derive(sqlx::FromRow)]
+struct CatalogFile {
+    id: i64,
+    path: String,
+    file_size: Option<i64>,
+    file_mtime: Option<i64>,
+    file_hash: Option<String>,
+}
+
+/// A catalogued photo's file as found on its drive.
+struct DriveFile {
+    id: i64,
+    /// Where the file is on its drive.
+    location: PathBuf,
+    size: u64,
+    mtime: Option<i64>,
+    /// Starts as the catalog's hash if the file hasn't changed since it was
+    /// taken, and is filled in for files that need comparing.
+    hash: Option<String>,
+}
+
+/// Compares the primary and backup catalogs by content hash.
+///
+/// Both catalogs list photos by their primary drive path. Photos both
+/// catalogs list are diverged when their files' sizes or hashes differ.
+/// Photos only one catalog lists at their path are matched by hash against
+/// the other catalog's unpaired photos of the same size: matches were
+/// relocated, the rest are orphaned. Photos whose file is gone from its
+/// drive are left to `reconcile`.
+///
+/// The catalogs' hashes are used where the file's size and modification time
+/// still match the catalog; only files another file could match and whose
+/// hash is missing or stale are read, and the new hashes are saved. With
+/// `verify_hashes` every file that could match is read again instead. The
+/// engine is only locked long enough to look up the drives.
+pub async fn compare_drives(
+    engine: &Mutex<Option<SyncEngine>>,
+    verify_hashes: bool,
+) -> Result<Vec<CrossDriveGroup>> {
+    let (primary_db, backup_db, mirror) = {
+        let engine = engine.lock().await;
+        let engine = engine
+            .as_ref()
+            .ok_or_else(|| anyhow!("Sync engine not initialized"))?;
+        let backup_db = engine
+            .backup_db()
+            .ok_or_else(|| anyhow!("Backup drive not connected"))?;
+        let mirror = engine
+            .file_mirror()
+            .ok_or_else(|| anyhow!("Backup drive not configured"))?;
+        (engine.primary_db.clone(), backup_db.clone(), mirror.clone())
+    };
+    let mut primary_files =
+        drive_files(&primary_db, &mirror, Drive::Primary, verify_hashes).await?;
+    let mut backup_files = drive_files(&backup_db, &mirror, Drive::Backup, verify_hashes).await?;
+    let copy = |drive: Drive, file: &DriveFile| DriveCopy {
+        drive,
+        path: file.location.clone(),
+        size: file.size,
+        photo_id: match drive {
+            Drive::Primary => Some(file.id),
+            Drive::Backup => None,
+        },
+    };
+    let mut groups = Vec::new();
+
+    // Photos both catalogs list, diverged if their sizes differ and hashed
+    // otherwise
+    let mut primary_jobs = Vec::new();
+    let mut backup_jobs = Vec::new();
+    for (path, primary) in &primary_files {
+        let Some(backup) = backup_files.get(path) else {
+            continue;
+        };
+        if primary.size != backup.size {
+            groups.push(diverged(
+                copy(Drive::Primary, primary),
+                copy(Drive::Backup, backup),
+            ));
+        } else {
+            primary_jobs.push(path.clone());
+            backup_jobs.push(path.clone());
+        }
+    }
+
+    // Photos only one catalog lists at their path, hashed if another
+    // unpaired photo has the same size
+    let unpaired: Vec<(Drive, PathBuf, u64)> = primary_files
+        .iter()
+        .filter(|(path, _)| !backup_files.contains_key(*path))
+        .map(|(path, file)| (Drive::Primary, path.clone(), file.size))
+        .chain(
+            backup_files
+                .iter()
+                .filter(|(path, _)| !primary_files.contains_key(*path))
+                .map(|(path, file)| (Drive::Backup, path.clone(), file.size)),
+        )
+        .collect();
+    let mut by_size: BTreeMap<u64, Vec<(Drive, &PathBuf)>> = BTreeMap::new();
+    for (drive, path, size) in &unpaired {
+        by_size.entry(*size).or_default().push((*drive, path));
+    }
+    for (size, files) in &by_size {
+        if files.len() == 1 {
+            let (drive, path) = files[0];
+            let file = match drive {
+                Drive::Primary => &primary_files[path],
+                Drive::Backup => &backup_files[path],
+            };
+            groups.push(CrossDriveGroup {
+                kind: CrossDriveKind::Orphaned,
+                hash: None,
+                copies: vec![copy(drive, file)],
+                size: *size,
+            });
+            continue;
+        }
+        for &(drive, path) in files {
+            match drive {
+                Drive::Primary => primary_jobs.push(path.clone()),
+                Drive::Backup => backup_jobs.push(path.clone()),
+            }
+        }
+    }
+
+    fill_hashes(
+        &primary_db,
+        Drive::Primary,
+        &mut primary_files,
+        primary_jobs,
+    )
+    .await?;
+    fill_hashes(&backup_db, Drive::Backup, &mut backup_files, backup_jobs).await?;
+
+    for (path, primary) in &primary_files {
+        let Some(backup) = backup_files.get(path) else {
+            continue;
+        };
+        if let (Some(primary_hash), Some(backup_hash)) = (&primary.hash, &backup.hash) {
+            if primary.size == backup.size && primary_hash != backup_hash {
+                groups.push(diverged(
+                    copy(Drive::Primary, primary),
+                    copy(Drive::Backup, backup),
+                ));
+            }
+        }
+    }
+
+    let mut by_hash: BTreeMap<(u64, &String), Vec<DriveCopy>> = BTreeMap::new();
+    for (drive, path, size) in &unpaired {
+        // Already reported, as nothing could match
+        if by_size[size].len() == 1 {
+            continue;
+        }
+        let file = match drive {
+            Drive::Primary => &primary_files[path],
+            Drive::Backup => &backup_files[path],
+        };
+        if let Some(hash) = &file.hash {
+            by_hash
+                .entry((*size, hash))
+                .or_default()
+                .push(copy(*drive, file));
+        }
+    }
+    for ((size, hash), copies) in by_hash {
+        let on_both = copies.iter().any(|copy| copy.drive == Drive::Primary)
+            && copies.iter().any(|copy| copy.drive == Drive::Backup);
+        groups.push(CrossDriveGroup {
+            kind: if on_both {
+                CrossDriveKind::Relocated
+            } else {
+                CrossDriveKind::Orphaned
+            },
+            hash: Some(hash.clone()),
+            size: size * copies.len() as u64,
+            copies,
+        });
+    }
+    Ok(groups)
+}
+
+fn diverged(primary: DriveCopy, backup: DriveCopy) -> CrossDriveGroup {
+    CrossDriveGroup {
+        kind: CrossDriveKind::Diverged,
+        hash: None,
+        size: primary.size + backup.size,
+        copies: vec![primary, backup],
+    }
+}
+
+/// The photos in the catalog on `pool` whose files are on `drive`, by their
+/// primary drive path. Catalog hashes are dropped when the file changed
+/// since, or when `verify_hashes` asks for every file to be read again.
+async fn drive_files(
+    pool: &SqlitePool,
+    mirror: &FileMirror,
+    drive: Drive,
+    verify_hashes: bool,
+) -> Result<BTreeMap<PathBuf, DriveFile>> {
+    let rows = sqlx::query_as::<_, CatalogFile>(
+        "SELECT id, path, file_size, file_mtime, file_hash FROM photos WHERE deleted_at IS NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mirror = mirror.clone();
+    let files = tokio::task::spawn_blocking(move || {
+        let mut files = BTreeMap::new();
+        for row in rows {
+            let path = PathBuf::from(&row.path);
+            let location = match drive {
+                Drive::Primary => path.clone(),
+                Drive::Backup => match mirror.backup_path(&path) {
+                    Ok(location) => location,
+                    Err(_) => continue,
+                },
+            };
+            let Ok(metadata) = std::fs::metadata(&location) else {
+                continue;
+            };
+            let size = metadata.len();
+            let mtime = modified_millis(&metadata);
+            let unchanged = row.file_size == Some(size as i64) && row.file_mtime == mtime;
+            files.insert(
+                path,
+                DriveFile {
+                    id: row.id,
+                    location,
+                    size,
+                    mtime,
+                    hash: row.file_hash.filter(|_| unchanged && !verify_hashes),
+                },
+            );
+        }
+        files
+    })
+    .await?;
+    Ok(files)
+}
+
+/// Hashes the files at `paths` that have no hash yet and saves the hashes in
+/// `drive`'s catalog on `pool`. Files that can't be read are left unhashed.
+///
+/// A primary catalog hash is only saved while the file still matches the
+/// catalog's size and modification time, as rescans rely on those to spot
+/// changed files. Backup copies don't keep their modification times, so the
+/// backup catalog takes the copy's own along with the hash.
+async fn fill_hashes(
+    pool: &SqlitePool,
+    drive: Drive,
+    files: &mut BTreeMap<PathBuf, DriveFile>,
+    paths: Vec<PathBuf>,
+) -> Result<()> {
+    let jobs = paths
+        .into_iter()
+        .filter(|path| files[path].hash.is_none())
+        .map(|path| {
+            let location = files[&path].location.clone();
+            (path, location)
+        })
+        .collect();
+    let mut running = HashPool::new(jobs, hash_file_blocking);
+    // Saved in batches so the catalog isn't locked while files are hashed
+    let mut batch = Vec::new();
+    while let Some((path, location, result)) = running.next().await? {
+        match result {
+            Ok(hash) => batch.push((path, hash)),
+            Err(e) => eprintln!("Failed to hash {}: {}", location.display(), e),
+        }
+        if batch.len() >= HASH_BATCH_SIZE {
+            save_hashes(pool, drive, files, &mut batch).await?;
+        }
+    }
+    save_hashes(pool, drive, files, &mut batch).await
+}
+
+async fn save_hashes(
+    pool: &SqlitePool,
+    drive: Drive,
+    files: &mut BTreeMap<PathBuf, DriveFile>,
+    batch: &mut Vec<(PathBuf, String)>,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for (path, hash) in batch.iter() {
+        let file = &files[path];
+        let update = match drive {
+            Drive::Primary => sqlx::query(
+                "UPDATE photos SET file_hash = ? WHERE id = ? AND file_size = ? AND file_mtime IS ?",
+            )
+            .bind(hash)
+            .bind(file.id)
+            .bind(file.size as i64)
+            .bind(file.mtime),
+            Drive::Backup => {
+                sqlx::query("UPDATE photos SET file_hash = ?, file_size = ?, file_mtime = ? WHERE id = ?")
+                    .bind(hash)
+                    .bind(file.size as i64)
+                    .bind(file.mtime)
+                    .bind(file.id)
+            }
+        };
+        update.execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    for (path, hash) in batch.drain(..) {
+        files.get_mut(&path).unwrap().hash = Some(hash);
+    }
+    Ok(())
+}
@@ -1,18 +1,74 @@
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::path::Path;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::task::JoinSet;
 
 use crate::models::photo::Photo;
+use crate::services::{formats, thumbnail};
+use anyhow::anyhow;
 use sqlx::{Pool, Sqlite};
 use std::collections::HashMap;
 
+/// Hamming distance between perceptual hashes up to which photos count as
+/// near duplicates, unless the caller asks for another.
+pub const DEFAULT_MAX_DISTANCE: u32 = 10;
+
+/// Bits in a perceptual hash, for turning distances into similarity scores.
+const HASH_BITS: u32 = 64;
+/// Longest edge images are shrunk to before their perceptual hash is taken,
+/// the size of the smallest thumbnail.
+const HASH_IMAGE_SIZE: u32 = thumbnail::THUMBNAIL_SIZES[0];
+
+/// Bytes read from each end of a file for its partial hash.
+pub const PARTIAL_HASH_BYTES: u64 = 64 * 1024;
+/// Read size when hashing whole files.
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+/// Most files hashed at once. Hashing is mostly waiting on the disk, so a
+/// few more than this rarely helps and makes spinning drives seek.
+const MAX_HASH_WORKERS: usize = 8;
+/// Hashes saved per transaction, and so how often progress is reported.
+pub(crate) const HASH_BATCH_SIZE: usize = 200;
+
+/// Which pass of a duplicate scan is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashStage {
+    /// Hashing the ends of files that share their size with another.
+    Partial,
+    /// Hashing whole files whose size and ends both match another's.
+    Full,
+}
+
+/// Reported after each batch of files is hashed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashProgress {
+    pub stage: HashStage,
+    pub hashed: u32,
+    /// Files this stage has to hash, not counting ones hashed by earlier runs.
+    pub total: u32,
+}
+
+/// What a duplicate scan knows about a photo before reading it.
+#[derive(sqlx::FromRow)]
+struct HashCandidate {
+    id: i64,
+    path: String,
+    file_size: i64,
+    partial_hash: Option<String>,
+    file_hash: Option<String>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DuplicateError {
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("Hashing task failed: {0}")]
+    TaskError(#[from] tokio::task::JoinError),
 }
 
 pub struct DuplicateDetector;
@@ -21,55 +77,223 @@ impl DuplicateDetector {
     pub async fn find_duplicates(
         pool: &Pool<Sqlite>,
     ) -> Result<Vec<DuplicateGroup>, DuplicateError> {
-        let mut photos = sqlx::query_as::<_, Photo>("SELECT * FROM photos")
-            .fetch_all(pool)
-            .await?;
+        Self::find_duplicates_with_progress(pool, |_| {}).await
+    }
 
-        for photo in &mut photos {
-            if photo.file_hash.is_none() {
-                let path = Path::new(&photo.path);
-                if path.exists() {
-                    let hash = Self::hash_file(path).await?;
-                    Self::cache_hash(pool, photo.id, hash.clone()).await?;
-                    photo.file_hash = Some(hash);
-                }
+    /// Groups byte-identical photos, reading as little as possible.
+    ///
+    /// Only files sharing their size with another can be duplicates, so only
+    /// those are opened. Their first and last `PARTIAL_HASH_BYTES` are hashed
+    /// first, and only files whose size and partial hash both collide are
+    /// hashed in full. Files are hashed on a bounded pool of blocking workers
+    /// and the hashes saved in batches, so a run that's interrupted resumes
+    /// from the files it hadn't reached. Files that can't be read are skipped.
+    pub async fn find_duplicates_with_progress(
+        pool: &Pool<Sqlite>,
+        mut on_progress: impl FnMut(&HashProgress),
+    ) -> Result<Vec<DuplicateGroup>, DuplicateError> {
+        let mut candidates = sqlx::query_as::<_, HashCandidate>(
+            "SELECT id, path, file_size, partial_hash, file_hash FROM photos
+             WHERE deleted_at IS NULL AND file_size IN (
+                 SELECT file_size FROM photos WHERE deleted_at IS NULL
+                 GROUP BY file_size HAVING COUNT(*) > 1
+             )",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let jobs = candidates
+            .iter()
+            .filter(|candidate| candidate.partial_hash.is_none())
+            .map(|candidate| (candidate.id, PathBuf::from(&candidate.path)))
+            .collect();
+        let partial_hashes = hash_files(
+            pool,
+            jobs,
+            HashStage::Partial,
+            partial_hash_file,
+            "UPDATE photos SET partial_hash = ? WHERE id = ?",
+            &mut on_progress,
+        )
+        .await?;
+        let mut collisions: HashMap<(i64, String), Vec<usize>> = HashMap::new();
+        for (index, candidate) in candidates.iter_mut().enumerate() {
+            if let Some(hash) = partial_hashes.get(&candidate.id) {
+                candidate.partial_hash = Some(hash.clone());
+            }
+            if let Some(hash) = &candidate.partial_hash {
+                collisions
+                    .entry((candidate.file_size, hash.clone()))
+                    .or_default()
+                    .push(index);
             }
         }
 
-        let mut groups: HashMap<String, Vec<Photo>> = HashMap::new();
+        let jobs = collisions
+            .into_values()
+            .filter(|indexes| indexes.len() > 1)
+            .flatten()
+            .map(|index| &candidates[index])
+            .filter(|candidate| candidate.file_hash.is_none())
+            .map(|candidate| (candidate.id, PathBuf::from(&candidate.path)))
+            .collect();
+        hash_files(
+            pool,
+            jobs,
+            HashStage::Full,
+            hash_file_blocking,
+            "UPDATE photos SET file_hash = ? WHERE id = ?",
+            &mut on_progress,
+        )
+        .await?;
+
+        // Files hashed elsewhere, e.g. on import, are grouped as well
+        let photos = sqlx::query_as::<_, Photo>(
+            "SELECT * FROM photos WHERE deleted_at IS NULL AND file_hash IN (
+                 SELECT file_hash FROM photos WHERE deleted_at IS NULL
+                 GROUP BY file_hash HAVING COUNT(*) > 1
+             ) ORDER BY file_hash, id",
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
         for photo in photos {
-            if let Some(hash) = &photo.file_hash {
-                groups.entry(hash.clone()).or_default().push(photo);
+            let hash = photo.file_hash.clone().unwrap_or_default();
+            match groups.last_mut() {
+                Some(group) if group.hash == hash => {
+                    group.size += photo.file_size.unwrap_or(0);
+                    group.photos.push(photo);
+                }
+                _ => groups.push(DuplicateGroup {
+                    hash,
+                    size: photo.file_size.unwrap_or(0),
+                    photos: vec![photo],
+                    similarity: 1.0,
+                }),
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Groups photos whose perceptual hashes are within `max_distance` bits of
+    /// each other, so resized or re-encoded copies are found too. Photos join
+    /// a group through any member, and a group's similarity reflects the
+    /// loosest match that joined it. Photos in the same stack, like a RAW and
+    /// its JPEG, aren't matched with each other. Videos and undecodable files
+    /// are skipped.
+    ///
+    /// Missing hashes are worked out on a pool of blocking workers, from the
+    /// smallest cached thumbnail where there is one.
+    pub async fn find_similar(
+        pool: &Pool<Sqlite>,
+        max_distance: u32,
+    ) -> Result<Vec<DuplicateGroup>, DuplicateError> {
+        let mut photos = sqlx::query_as::<_, Photo>(
+            "SELECT * FROM photos WHERE deleted_at IS NULL AND media_type = 'photo' ORDER BY id",
+        )
+        .fetch_all(pool)
+        .await?;
+        let thumbnails: HashMap<i64, String> =
+            sqlx::query_as("SELECT photo_id, path FROM thumbnails WHERE size = ?")
+                .bind(HASH_IMAGE_SIZE)
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .collect();
+
+        let mut jobs = photos
+            .iter()
+            .enumerate()
+            .filter(|(_, photo)| photo.perceptual_hash.is_none())
+            .filter_map(|(index, photo)| {
+                // Thumbnails are already upright
+                let thumbnail = thumbnails.get(&photo.id).map(PathBuf::from);
+                match thumbnail.filter(|thumbnail| thumbnail.exists()) {
+                    Some(thumbnail) => Some((index, thumbnail, None)),
+                    None => {
+                        let path = PathBuf::from(&photo.path);
+                        path.exists().then_some((index, path, photo.orientation))
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+        // Decoding keeps the CPU rather than the disk busy
+        let workers = std::thread::available_parallelism().map_or(1, |workers| workers.get());
+        let mut running = JoinSet::new();
+        loop {
+            while running.len() < workers {
+                let Some((index, source, orientation)) = jobs.next() else {
+                    break;
+                };
+                running.spawn_blocking(move || (index, perceptual_hash(&source, orientation)));
+            }
+            let Some(finished) = running.join_next().await else {
+                break;
+            };
+            let (index, result) = finished?;
+            let photo = &mut photos[index];
+            match result {
+                Ok(hash) => {
+                    let hash = format!("{:016x}", hash);
+                    Self::cache_perceptual_hash(pool, photo.id, hash.clone()).await?;
+                    photo.perceptual_hash = Some(hash);
+                }
+                Err(e) => eprintln!("Failed to hash {}: {}", photo.path, e),
             }
         }
 
-        let duplicate_groups = groups
+        let photos: Vec<(u64, Photo)> = photos
+            .into_iter()
+            .filter_map(|photo| {
+                let hash = u64::from_str_radix(photo.perceptual_hash.as_deref()?, 16).ok()?;
+                Some((hash, photo))
+            })
+            .collect();
+        let hashes: Vec<u64> = photos.iter().map(|(hash, _)| *hash).collect();
+        let stacks: Vec<Option<i64>> = photos.iter().map(|(_, photo)| photo.stack_id).collect();
+        let mut photos: Vec<Option<Photo>> =
+            photos.into_iter().map(|(_, photo)| Some(photo)).collect();
+
+        let duplicate_groups = cluster(&hashes, &stacks, max_distance)
             .into_iter()
-            .filter(|(_, photos)| photos.len() > 1)
-            .map(|(hash, photos)| {
+            .map(|(members, distance)| {
+                let photos: Vec<Photo> = members
+                    .iter()
+                    .filter_map(|&member| photos[member].take())
+                    .collect();
                 let size = photos.iter().map(|p| p.file_size.unwrap_or(0)).sum();
-                DuplicateGroup { hash, photos, size }
+                DuplicateGroup {
+                    hash: format!("{:016x}", hashes[members[0]]),
+                    photos,
+                    size,
+                    similarity: 1.0 - distance as f64 / HASH_BITS as f64,
+                }
             })
             .collect();
 
         Ok(duplicate_groups)
     }
 
-    pub async fn hash_file(path: &Path) -> Result<String, std::io::Error> {
-        let mut file = File::open(path).await?;
-        let mut hasher = Sha256::new();
-        let mut buffer = [0; 1024];
-
-        loop {
-            let n = file.read(&mut buffer).await?;
-            if n == 0 {
-                break;
-            }
-            hasher.update(&buffer[..n]);
-        }
+    pub async fn cache_perceptual_hash(
+        pool: &Pool<Sqlite>,
+        photo_id: i64,
+        hash: String,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE photos SET perceptual_hash = ? WHERE id = ?")
+            .bind(hash)
+            .bind(photo_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
 
-        let hash = hasher.finalize();
-        Ok(hex::encode(hash))
+    /// The SHA-256 of the file at `path`, read on a blocking thread.
+    pub async fn hash_file(path: &Path) -> Result<String, std::io::Error> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || hash_file_blocking(&path))
+            .await
+            .map_err(std::io::Error::other)?
     }
 
     pub async fn cache_hash(
@@ -91,4 +315,280 @@ pub struct DuplicateGroup {
     pub hash: String,
     pub photos: Vec<Photo>,
     pub size: i64,
+    /// 1.0 for identical files or images, lower the further apart the
+    /// group's perceptual hashes are.
+    pub similarity: f64,
+}
+
+/// Hashes files on up to `MAX_HASH_WORKERS` blocking threads, handing back
+/// each result as it's ready along with the key it was queued under.
+pub(crate) struct HashPool<K> {
+    jobs: std::vec::IntoIter<(K, PathBuf)>,
+    running: JoinSet<(K, PathBuf, std::io::Result<String>)>,
+    hash: fn(&Path) -> std::io::Result<String>,
+    workers: usize,
+}
+
+impl<K: Send + 'static> HashPool<K> {
+    pub(crate) fn new(jobs: Vec<(K, PathBuf)>, hash: fn(&Path) -> std::io::Result<String>) -> Self {
+        let workers = std::thread::available_parallelism()
+            .map_or(1, |workers| workers.get())
+            .min(MAX_HASH_WORKERS);
+        Self {
+            jobs: jobs.into_iter(),
+            running: JoinSet::new(),
+            hash,
+            workers,
+        }
+    }
+
+    /// The next finished file, or `None` once every file has been hashed.
+    pub(crate) async fn next(
+        &mut self,
+    ) -> Result<Option<(K, PathBuf, std::io::Result<String>)>, tokio::task::JoinError> {
+        while self.running.len() < self.workers {
+            let Some((key, path)) = self.jobs.next() else {
+                break;
+            };
+            let hash = self.hash;
+            self.running.spawn_blocking(move || {
+                let result = hash(&path);
+                (key, path, result)
+            });
+        }
+        self.running.join_next().await.transpose()
+    }
+}
+
+/// Hashes the files in `jobs` through a `HashPool`, saving each batch of
+/// hashes with `update` as it completes. Returns the hashes by photo id.
+async fn hash_files(
+    pool: &Pool<Sqlite>,
+    jobs: Vec<(i64, PathBuf)>,
+    stage: HashStage,
+    hash: fn(&Path) -> std::io::Result<String>,
+    update: &'static str,
+    on_progress: &mut impl FnMut(&HashProgress),
+) -> Result<HashMap<i64, String>, DuplicateError> {
+    let mut progress = HashProgress {
+        stage,
+        hashed: 0,
+        total: jobs.len() as u32,
+    };
+    let mut hashes = HashMap::new();
+    let mut batch: Vec<(i64, String)> = Vec::new();
+    let mut running = HashPool::new(jobs, hash);
+    while let Some((photo_id, path, result)) = running.next().await? {
+        progress.hashed += 1;
+        match result {
+            Ok(hash) => batch.push((photo_id, hash)),
+            // Gone since it was catalogued; reconcile deals with those
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Failed to hash {}: {}", path.display(), e),
+        }
+        if batch.len() >= HASH_BATCH_SIZE {
+            save_hashes(pool, update, &mut batch, &mut hashes).await?;
+            on_progress(&progress);
+        }
+    }
+    save_hashes(pool, update, &mut batch, &mut hashes).await?;
+    on_progress(&progress);
+    Ok(hashes)
+}
+
+async fn save_hashes(
+    pool: &Pool<Sqlite>,
+    update: &'static str,
+    batch: &mut Vec<(i64, String)>,
+    hashes: &mut HashMap<i64, String>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (photo_id, hash) in batch.iter() {
+        sqlx::query(update)
+            .bind(hash)
+            .bind(photo_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    hashes.extend(batch.drain(..));
+    Ok(())
+}
+
+/// The SHA-256 of the file at `path`, for blocking threads.
+pub(crate) fn hash_file_blocking(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hashes the first and last `PARTIAL_HASH_BYTES` of the file, or all of it
+/// when it's no longer than that twice over.
+pub(crate) fn partial_hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    if size <= 2 * PARTIAL_HASH_BYTES {
+        return hash_file_blocking(path);
+    }
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; PARTIAL_HASH_BYTES as usize];
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+    file.seek(SeekFrom::End(-(PARTIAL_HASH_BYTES as i64)))?;
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// The difference hash of the image at `path`, turned upright first so
+/// copies that had their EXIF rotation applied still match, and shrunk to
+/// `HASH_IMAGE_SIZE` so it doesn't matter whether it was read from the
+/// original or a thumbnail.
+fn perceptual_hash(path: &Path, orientation: Option<i64>) -> anyhow::Result<u64> {
+    let handler = formats::handler_for(path)
+        .ok_or_else(|| anyhow!("Unsupported file type: {}", path.display()))?;
+    let mut image = thumbnail::apply_orientation(handler.preview(path)?, orientation);
+    if image.width().max(image.height()) > HASH_IMAGE_SIZE {
+        image = image.thumbnail(HASH_IMAGE_SIZE, HASH_IMAGE_SIZE);
+    }
+    Ok(difference_hash(&image))
+}
+
+/// A 64-bit dHash: the image shrunk to 9x8 grey pixels, one bit per pair of
+/// horizontal neighbours, set where brightness increases to the right.
+/// Scaling and recompression barely move it, while different pictures land
+/// about 32 bits apart.
+pub fn difference_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Groups the indexes of `hashes` that are linked through hashes at most
+/// `max_distance` bits apart, with the largest distance each group needed.
+/// Hashes in the same one of `stacks` aren't linked. Only groups of two or
+/// more are returned.
+fn cluster(hashes: &[u64], stacks: &[Option<i64>], max_distance: u32) -> Vec<(Vec<usize>, u32)> {
+    fn root(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+
+    let mut tree = BkTree::default();
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    let mut widest = vec![0; hashes.len()];
+    for (index, &hash) in hashes.iter().enumerate() {
+        for (other, distance) in tree.within(hash, max_distance) {
+            if stacks[index].is_some() && stacks[index] == stacks[other] {
+                continue;
+            }
+            let (a, b) = (root(&mut parents, index), root(&mut parents, other));
+            if a != b {
+                parents[b] = a;
+                widest[a] = widest[a].max(widest[b]).max(distance);
+            }
+        }
+        tree.insert(hash, index);
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..hashes.len() {
+        let root = root(&mut parents, index);
+        groups.entry(root).or_default().push(index);
+    }
+    let mut groups: Vec<(Vec<usize>, u32)> = groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(root, members)| (members, widest[root]))
+        .collect();
+    groups.sort_by_key(|(members, _)| members[0]);
+    groups
+}
+
+/// A BK-tree over Hamming distance, so hashes near a query are found without
+/// comparing against every one.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    hash: u64,
+    index: usize,
+    /// Child nodes by their distance from this one.
+    children: Vec<(u32, usize)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, index: usize) {
+        let new = self.nodes.len();
+        if new > 0 {
+            let mut node = 0;
+            loop {
+                let distance = (self.nodes[node].hash ^ hash).count_ones();
+                match self.nodes[node]
+                    .children
+                    .iter()
+                    .find(|(d, _)| *d == distance)
+                {
+                    Some(&(_, child)) => node = child,
+                    None => {
+                        self.nodes[node].children.push((distance, new));
+                        break;
+                    }
+                }
+            }
+        }
+        self.nodes.push(BkNode {
+            hash,
+            index,
+            children: Vec::new(),
+        });
+    }
+
+    /// The indexes of hashes at most `max_distance` from `hash`, with their
+    /// distances.
+    fn within(&self, hash: u64, max_distance: u32) -> Vec<(usize, u32)> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(node) = pending.pop() {
+            let node = &self.nodes[node];
+            let distance = (node.hash ^ hash).count_ones();
+            if distance <= max_distance {
+                found.push((node.index, distance));
+            }
+            // By the triangle inequality, only children this far from the
+            // node can be close enough
+            pending.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| d.abs_diff(distance) <= max_distance)
+                    .map(|&(_, child)| child),
+            );
+        }
+        found
+    }
 }
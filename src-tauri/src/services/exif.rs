@@ -0,0 +1,178 @@
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use exif::{Exif, In, Reader, Tag, Value};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Capture metadata read from the EXIF block of an image file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExifData {
+    pub date_taken: Option<DateTime<Utc>>,
+    /// Offset from UTC in minutes recorded alongside `DateTimeOriginal`.
+    pub date_taken_offset: Option<i64>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    pub iso: Option<i64>,
+    pub aperture: Option<f64>,
+    /// Exposure time in seconds.
+    pub exposure_time: Option<f64>,
+    /// Focal length in millimetres.
+    pub focal_length: Option<f64>,
+    pub orientation: Option<i64>,
+    /// Apple's identifier linking a Live Photo's still to its video.
+    pub content_identifier: Option<String>,
+    /// The `ImageDescription`, which is where most tools write captions.
+    pub caption: Option<String>,
+}
+
+/// Reads EXIF metadata from the file at `path`.
+///
+/// Files without an EXIF block (or with one the parser can't make sense of)
+/// yield an empty `ExifData`; only I/O failures are reported as errors.
+pub fn read_exif(path: &Path) -> Result<ExifData> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    match Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => Ok(ExifData::from_exif(&exif)),
+        Err(exif::Error::Io(e)) => Err(e.into()),
+        Err(_) => Ok(ExifData::default()),
+    }
+}
+
+impl ExifData {
+    pub fn from_exif(exif: &Exif) -> Self {
+        let (date_taken, date_taken_offset) = capture_time(exif);
+
+        Self {
+            date_taken,
+            date_taken_offset,
+            camera_make: ascii_field(exif, Tag::Make),
+            camera_model: ascii_field(exif, Tag::Model),
+            lens_model: ascii_field(exif, Tag::LensModel),
+            iso: uint_field(exif, Tag::PhotographicSensitivity),
+            aperture: rational_field(exif, Tag::FNumber),
+            exposure_time: rational_field(exif, Tag::ExposureTime),
+            focal_length: rational_field(exif, Tag::FocalLength),
+            orientation: uint_field(exif, Tag::Orientation),
+            content_identifier: apple_content_identifier(exif),
+            caption: ascii_field(exif, Tag::ImageDescription),
+        }
+    }
+}
+
+/// Resolves `DateTimeOriginal` (falling back to `DateTime`) to UTC.
+///
+/// When the camera recorded `OffsetTimeOriginal` the local time is shifted by
+/// it; otherwise the wall-clock time is stored as if it were UTC so that
+/// photos from the same camera still sort correctly.
+fn capture_time(exif: &Exif) -> (Option<DateTime<Utc>>, Option<i64>) {
+    let field = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+        .or_else(|| exif.get_field(Tag::DateTime, In::PRIMARY));
+    let Some(Value::Ascii(values)) = field.map(|f| &f.value) else {
+        return (None, None);
+    };
+    let Some(Ok(mut dt)) = values.first().map(|v| exif::DateTime::from_ascii(v)) else {
+        return (None, None);
+    };
+
+    if let Some(Value::Ascii(subsec)) = exif
+        .get_field(Tag::SubSecTimeOriginal, In::PRIMARY)
+        .map(|f| &f.value)
+    {
+        if let Some(v) = subsec.first() {
+            let _ = dt.parse_subsec(v);
+        }
+    }
+    if let Some(Value::Ascii(offset)) = exif
+        .get_field(Tag::OffsetTimeOriginal, In::PRIMARY)
+        .map(|f| &f.value)
+    {
+        if let Some(v) = offset.first() {
+            let _ = dt.parse_offset(v);
+        }
+    }
+
+    let naive =
+        NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32).and_then(|d| {
+            d.and_hms_nano_opt(
+                dt.hour as u32,
+                dt.minute as u32,
+                dt.second as u32,
+                dt.nanosecond.unwrap_or(0),
+            )
+        });
+    let Some(naive) = naive else {
+        return (None, None);
+    };
+
+    match dt
+        .offset
+        .and_then(|minutes| FixedOffset::east_opt(minutes as i32 * 60))
+    {
+        Some(offset) => {
+            let utc = offset
+                .from_local_datetime(&naive)
+                .single()
+                .map(|local| local.with_timezone(&Utc));
+            (utc, dt.offset.map(i64::from))
+        }
+        None => (Some(Utc.from_utc_datetime(&naive)), None),
+    }
+}
+
+/// Reads `ContentIdentifier` (tag 0x11) from an Apple MakerNote: a
+/// `Apple iOS\0` header, a version, a byte order mark and then a big-endian
+/// IFD whose offsets are relative to the start of the MakerNote.
+fn apple_content_identifier(exif: &Exif) -> Option<String> {
+    let Value::Undefined(note, _) = &exif.get_field(Tag::MakerNote, In::PRIMARY)?.value else {
+        return None;
+    };
+    if !note.starts_with(b"Apple iOS\0") || note.get(12..14) != Some(&b"MM"[..]) {
+        return None;
+    }
+    let be16 = |at: usize| Some(u16::from_be_bytes(note.get(at..at + 2)?.try_into().ok()?));
+    let be32 = |at: usize| Some(u32::from_be_bytes(note.get(at..at + 4)?.try_into().ok()?));
+
+    let count = be16(14)? as usize;
+    (0..count).find_map(|index| {
+        let entry = 16 + index * 12;
+        // An ASCII UUID, always too long to be stored inline
+        if be16(entry)? != 0x11 || be16(entry + 2)? != 2 {
+            return None;
+        }
+        let len = be32(entry + 4)? as usize;
+        let offset = be32(entry + 8)? as usize;
+        let value = String::from_utf8_lossy(note.get(offset..offset.checked_add(len)?)?);
+        let value = value.trim_end_matches('\0');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => {
+            let value = String::from_utf8_lossy(values.first()?);
+            let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            (!value.is_empty()).then(|| value.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn uint_field(exif: &Exif, tag: Tag) -> Option<i64> {
+    exif.get_field(tag, In::PRIMARY)?
+        .value
+        .get_uint(0)
+        .map(i64::from)
+}
+
+fn rational_field(exif: &Exif, tag: Tag) -> Option<f64> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(values) => values.first().filter(|r| r.denom != 0).map(|r| r.to_f64()),
+        Value::SRational(values) => values.first().filter(|r| r.denom != 0).map(|r| r.to_f64()),
+        _ => None,
+    }
+}
@@ -0,0 +1,171 @@
+use crate::models::operation::Operation;
+use crate::services::duplicate::DuplicateDetector;
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Suffix used for in-flight copies so a half-written file never sits at its final path.
+pub(crate) const PARTIAL_SUFFIX: &str = ".photovault-partial";
+
+/// Mirrors file system changes made on the primary drive onto the backup drive,
+/// preserving each file's path relative to the drive root.
+#[derive(Debug, Clone)]
+pub struct FileMirror {
+    primary_root: PathBuf,
+    backup_root: PathBuf,
+}
+
+impl FileMirror {
+    pub fn new(primary_root: PathBuf, backup_root: PathBuf) -> Self {
+        Self {
+            primary_root,
+            backup_root,
+        }
+    }
+
+    pub fn primary_root(&self) -> &Path {
+        &self.primary_root
+    }
+
+    pub fn backup_root(&self) -> &Path {
+        &self.backup_root
+    }
+
+    /// Maps a path on the primary drive to the same relative location on the backup drive.
+    pub fn backup_path(&self, primary_path: &Path) -> Result<PathBuf> {
+        let relative = primary_path.strip_prefix(&self.primary_root).map_err(|_| {
+            anyhow!(
+                "{} is outside the primary drive {}",
+                primary_path.display(),
+                self.primary_root.display()
+            )
+        })?;
+        Ok(self.backup_root.join(relative))
+    }
+
+    /// Applies the file system side of `op` to the backup drive.
+    ///
+    /// Catalog-only operations (albums, tags, stacks) are a no-op.
+    pub async fn apply(&self, op: &Operation) -> Result<()> {
+        match op {
+            Operation::AddPhoto { photo } | Operation::SyncPhoto { photo } => {
+                let source = PathBuf::from(&photo.path);
+                let target = self.backup_path(&source)?;
+                self.copy_verified(&source, &target).await
+            }
+            Operation::Move { from, to } => self.relocate(from, to).await,
+            Operation::Rename { path, new_name } => {
+                self.relocate(path, &path.with_file_name(new_name)).await
+            }
+            Operation::Trash { path, trash_path } => self.relocate(path, trash_path).await,
+            Operation::Restore { trash_path, path } => self.relocate(trash_path, path).await,
+            Operation::Delete { path } => {
+                let target = self.backup_path(path)?;
+                match fs::remove_file(&target).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        Err(e).with_context(|| format!("Failed to delete {}", target.display()))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Operation::CreateAlbum { .. }
+            | Operation::DeleteAlbum { .. }
+            | Operation::RestoreAlbum { .. }
+            | Operation::CreateSmartAlbum { .. }
+            | Operation::UpdateSmartAlbum { .. }
+            | Operation::DeleteSmartAlbum { .. }
+            | Operation::RestoreSmartAlbum { .. }
+            | Operation::AddToAlbum { .. }
+            | Operation::RemoveFromAlbum { .. }
+            | Operation::AddTag { .. }
+            | Operation::RemoveTag { .. }
+            | Operation::CreateStack { .. }
+            | Operation::Unstack { .. } => Ok(()),
+        }
+    }
+
+    /// Copies the backup copy of `primary_path` back onto the primary drive.
+    pub async fn restore(&self, primary_path: &Path) -> Result<()> {
+        let backup = self.backup_path(primary_path)?;
+        self.copy_verified(&backup, primary_path).await
+    }
+
+    /// Moves the backup copy of `from` to the backup location of `to`. If the
+    /// backup copy is missing, the file is copied fresh from its new primary location.
+    async fn relocate(&self, from: &Path, to: &Path) -> Result<()> {
+        let backup_from = self.backup_path(from)?;
+        let backup_to = self.backup_path(to)?;
+
+        if !fs::try_exists(&backup_from).await? {
+            return self.copy_verified(to, &backup_to).await;
+        }
+
+        create_parent_dirs(&backup_to).await?;
+        fs::rename(&backup_from, &backup_to)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to move {} to {}",
+                    backup_from.display(),
+                    backup_to.display()
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Copies `source` to `target` and checks the SHA-256 of both before the
+    /// copy is moved into place. An identical existing target is left untouched.
+    async fn copy_verified(&self, source: &Path, target: &Path) -> Result<()> {
+        let source_hash = DuplicateDetector::hash_file(source)
+            .await
+            .with_context(|| format!("Failed to read {}", source.display()))?;
+        Self::copy_checked(source, target, &source_hash).await
+    }
+
+    /// `copy_verified` for a source whose SHA-256 is already known, e.g. to
+    /// copy an imported file into the library.
+    pub(crate) async fn copy_checked(
+        source: &Path,
+        target: &Path,
+        source_hash: &str,
+    ) -> Result<()> {
+        if fs::try_exists(target).await?
+            && DuplicateDetector::hash_file(target).await? == source_hash
+        {
+            return Ok(());
+        }
+
+        create_parent_dirs(target).await?;
+        let mut partial = target.as_os_str().to_owned();
+        partial.push(PARTIAL_SUFFIX);
+        let partial = PathBuf::from(partial);
+
+        fs::copy(source, &partial).await.with_context(|| {
+            format!(
+                "Failed to copy {} to {}",
+                source.display(),
+                target.display()
+            )
+        })?;
+
+        let copied_hash = DuplicateDetector::hash_file(&partial).await?;
+        if copied_hash != source_hash {
+            let _ = fs::remove_file(&partial).await;
+            return Err(anyhow!(
+                "Checksum mismatch after copying {} to {}",
+                source.display(),
+                target.display()
+            ));
+        }
+
+        fs::rename(&partial, target).await?;
+        Ok(())
+    }
+}
+
+async fn create_parent_dirs(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    Ok(())
+}
@@ -3,97 +3,97 @@ use sqlx::{QueryBuilder, Sqlite, SqlitePool};
 
 pub use crate::models::filter::FilterCriteria;
 use crate::models::photo::Photo;
+use crate::services::query::{self, ParseError};
+use crate::services::search;
 
 pub async fn filter_photos(pool: &SqlitePool, criteria: FilterCriteria) -> Result<Vec<Photo>> {
     let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT p.* FROM photos p ");
+    push_conditions(&mut query_builder, &criteria)?;
+    let photos = query_builder.build_query_as().fetch_all(pool).await?;
+    Ok(photos)
+}
 
-    if let Some(tags) = &criteria.tags {
-        if !tags.is_empty() {
-            query_builder.push("JOIN photo_tags pt ON p.id = pt.photo_id ");
-        }
-    }
-
-    if let Some(albums) = &criteria.albums {
-        if !albums.is_empty() {
-            query_builder.push("JOIN album_photos ap ON p.id = ap.photo_id ");
-        }
-    }
-
-    let mut conditions = Vec::new();
-
-    if let Some(_) = criteria.date_from {
-        conditions.push("p.date_taken >= ?".to_string());
-    }
-    if let Some(_) = criteria.date_to {
-        conditions.push("p.date_taken <= ?".to_string());
-    }
-    if let Some(_) = criteria.min_width {
-        conditions.push("p.width >= ?".to_string());
-    }
-    if let Some(_) = criteria.min_height {
-        conditions.push("p.height >= ?".to_string());
-    }
-    if let Some(query) = &criteria.query {
-        if !query.is_empty() {
-            conditions.push("p.filename LIKE ?".to_string());
-        }
-    }
-    if let Some(tags) = &criteria.tags {
-        if !tags.is_empty() {
-            let placeholders = tags.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
-            conditions.push(format!("pt.tag_id IN ({})", placeholders));
-        }
-    }
-    if let Some(albums) = &criteria.albums {
-        if !albums.is_empty() {
-            let placeholders = albums.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
-            conditions.push(format!("ap.album_id IN ({})", placeholders));
-        }
-    }
+/// How many photos in the library match `criteria`.
+pub async fn count_photos(pool: &SqlitePool, criteria: &FilterCriteria) -> Result<i64> {
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT COUNT(*) FROM photos p ");
+    push_conditions(&mut query_builder, criteria)?;
+    let count = query_builder.build_query_scalar().fetch_one(pool).await?;
+    Ok(count)
+}
 
-    if !conditions.is_empty() {
-        query_builder.push("WHERE ");
-        query_builder.push(conditions.join(" AND "));
+/// Checks that `criteria` can be evaluated, i.e. that its expression parses.
+pub fn validate(criteria: &FilterCriteria) -> Result<(), ParseError> {
+    match &criteria.expression {
+        Some(expression) if !expression.trim().is_empty() => query::parse(expression).map(|_| ()),
+        _ => Ok(()),
     }
+}
 
-    let mut query = query_builder.build_query_as();
+/// Appends the `WHERE` clause photos matching `criteria` meet, with its
+/// values bound.
+fn push_conditions(
+    query_builder: &mut QueryBuilder<Sqlite>,
+    criteria: &FilterCriteria,
+) -> Result<()> {
+    query_builder.push("WHERE p.deleted_at IS NULL AND p.missing_since IS NULL");
 
     if let Some(date_from) = criteria.date_from {
-        query = query.bind(date_from);
+        query_builder.push(" AND p.date_taken >= ");
+        query_builder.push_bind(date_from);
     }
     if let Some(date_to) = criteria.date_to {
-        query = query.bind(date_to);
+        query_builder.push(" AND p.date_taken <= ");
+        query_builder.push_bind(date_to);
     }
     if let Some(min_width) = criteria.min_width {
-        query = query.bind(min_width);
+        query_builder.push(" AND p.width >= ");
+        query_builder.push_bind(min_width);
     }
     if let Some(min_height) = criteria.min_height {
-        query = query.bind(min_height);
-    }
-    if let Some(query_str) = &criteria.query {
-        if !query_str.is_empty() {
-            query = query.bind(format!("%{}%", query_str));
-        }
-    }
-    if let Some(tags) = &criteria.tags {
+        query_builder.push(" AND p.height >= ");
+        query_builder.push_bind(min_height);
+    }
+    if let Some(text_match) = criteria.query.as_deref().and_then(search::match_expression) {
+        query_builder
+            .push(" AND p.id IN (SELECT rowid FROM photos_search WHERE photos_search MATCH ");
+        query_builder.push_bind(text_match);
+        query_builder.push(")");
+    }
+    if let Some(media_type) = criteria.media_type {
+        query_builder.push(" AND p.media_type = ");
+        query_builder.push_bind(media_type);
+    }
+    // Photos with any of the tags, and in any of the albums
+    if let Some(tags) = criteria.tags.as_ref().filter(|tags| !tags.is_empty()) {
+        query_builder.push(" AND p.id IN (SELECT photo_id FROM photo_tags WHERE tag_id IN (");
+        let mut ids = query_builder.separated(", ");
         for tag_id in tags {
-            query = query.bind(tag_id);
+            ids.push_bind(*tag_id);
         }
+        query_builder.push("))");
     }
-    if let Some(albums) = &criteria.albums {
+    if let Some(albums) = criteria.albums.as_ref().filter(|albums| !albums.is_empty()) {
+        query_builder.push(" AND p.id IN (SELECT photo_id FROM photo_albums WHERE album_id IN (");
+        let mut ids = query_builder.separated(", ");
         for album_id in albums {
-            query = query.bind(album_id);
+            ids.push_bind(*album_id);
         }
+        query_builder.push("))");
     }
-
-    let photos = query.fetch_all(pool).await?;
-    Ok(photos)
+    if let Some(expression) = criteria
+        .expression
+        .as_deref()
+        .filter(|e| !e.trim().is_empty())
+    {
+        query_builder.push(" AND ");
+        query::parse(expression)?.push_condition(query_builder);
+    }
+    Ok(())
 }
 
+/// The photos matching `query`, best matches first. See `search::search`.
 pub async fn search_photos(pool: &SqlitePool, query: String) -> Result<Vec<Photo>> {
-    let criteria = FilterCriteria {
-        query: Some(query),
-        ..Default::default()
-    };
-    filter_photos(pool, criteria).await
+    let results = search::search(pool, &query, search::DEFAULT_LIMIT).await?;
+    Ok(results.into_iter().map(|result| result.photo).collect())
 }
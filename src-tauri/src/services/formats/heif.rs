@@ -0,0 +1,230 @@
+//! Just enough ISO base media file format parsing to find the size of a HEIF
+//! file's primary image: `meta/pitm` names the primary item, `meta/iprp/ipma`
+//! associates it with properties in `meta/iprp/ipco`, one of which is `ispe`.
+//!
+//! HEVC-coded pixels can't be decoded here, but many files also carry a JPEG
+//! thumbnail item or an EXIF block with one; `meta/iinf` gives each item's
+//! type and `meta/iloc` where its bytes are.
+
+use super::isobmff::{find, parse_boxes, read_top_level_box, read_u16, read_u32, read_u64};
+use super::tiff;
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, ImageFormat};
+use std::cmp::Reverse;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/// Largest item we'll load looking for a preview; thumbnails are a few
+/// hundred kilobytes at most.
+const MAX_ITEM_SIZE: u64 = 16 * 1024 * 1024;
+
+/// `(file offset, length)` of each run of an item's bytes.
+type Extents = Vec<(u64, u64)>;
+
+pub fn primary_dimensions<R: Read + Seek>(reader: &mut R) -> Result<(u32, u32)> {
+    let meta = read_top_level_box(reader, b"meta")?;
+    // `meta` is a full box: skip version and flags
+    let children = parse_boxes(meta.get(4..).ok_or_else(|| anyhow!("Truncated meta box"))?)?;
+
+    let pitm = find(&children, b"pitm").ok_or_else(|| anyhow!("No primary item"))?;
+    let primary_item = match pitm.first() {
+        Some(0) => read_u16(pitm, 4)? as u32,
+        _ => read_u32(pitm, 4)?,
+    };
+
+    let iprp = parse_boxes(find(&children, b"iprp").ok_or_else(|| anyhow!("No item properties"))?)?;
+    let ipco = parse_boxes(find(&iprp, b"ipco").ok_or_else(|| anyhow!("No item properties"))?)?;
+    let ipma = find(&iprp, b"ipma").ok_or_else(|| anyhow!("No property associations"))?;
+
+    for index in associated_properties(ipma, primary_item)? {
+        // Property indices are 1-based; 0 means "no property"
+        let Some((kind, body)) = index.checked_sub(1).and_then(|i| ipco.get(i as usize)) else {
+            continue;
+        };
+        if kind == b"ispe" {
+            return Ok((read_u32(body, 4)?, read_u32(body, 8)?));
+        }
+    }
+    bail!("Primary item has no image size")
+}
+
+/// Property indices `ipma` associates with `item_id`.
+fn associated_properties(ipma: &[u8], item_id: u32) -> Result<Vec<u16>> {
+    let version = *ipma.first().ok_or_else(|| anyhow!("Truncated ipma box"))?;
+    let wide_indices = read_u32(ipma, 0)? & 1 == 1;
+    let entry_count = read_u32(ipma, 4)?;
+
+    let mut pos = 8;
+    for _ in 0..entry_count {
+        let id = if version < 1 {
+            pos += 2;
+            read_u16(ipma, pos - 2)? as u32
+        } else {
+            pos += 4;
+            read_u32(ipma, pos - 4)?
+        };
+        let count = *ipma.get(pos).ok_or_else(|| anyhow!("Truncated ipma box"))? as usize;
+        pos += 1;
+
+        let mut indices = Vec::with_capacity(count);
+        for _ in 0..count {
+            // The top bit flags the property as essential
+            if wide_indices {
+                indices.push(read_u16(ipma, pos)? & 0x7fff);
+                pos += 2;
+            } else {
+                let byte = *ipma.get(pos).ok_or_else(|| anyhow!("Truncated ipma box"))?;
+                indices.push((byte & 0x7f) as u16);
+                pos += 1;
+            }
+        }
+        if id == item_id {
+            return Ok(indices);
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Decodes the largest JPEG thumbnail item, falling back to the thumbnail in
+/// the file's EXIF block.
+pub fn embedded_preview<R: Read + Seek>(reader: &mut R) -> Result<Option<DynamicImage>> {
+    let meta = read_top_level_box(reader, b"meta")?;
+    let children = parse_boxes(meta.get(4..).ok_or_else(|| anyhow!("Truncated meta box"))?)?;
+    let (Some(iinf), Some(iloc)) = (find(&children, b"iinf"), find(&children, b"iloc")) else {
+        return Ok(None);
+    };
+    let types = item_types(iinf)?;
+    let locations = item_locations(iloc)?;
+    // Extents of every item of type `kind`, largest item first
+    let extents_of = |kind: &[u8; 4]| {
+        let mut found: Vec<&[(u64, u64)]> = locations
+            .iter()
+            .filter(|(id, _)| types.contains(&(*id, *kind)))
+            .map(|(_, extents)| extents.as_slice())
+            .collect();
+        found.sort_by_key(|extents| Reverse(extents.iter().map(|&(_, len)| len).sum::<u64>()));
+        found
+    };
+
+    for extents in extents_of(b"jpeg") {
+        let Ok(data) = read_item(reader, extents) else {
+            continue;
+        };
+        if let Ok(image) = image::load_from_memory_with_format(&data, ImageFormat::Jpeg) {
+            return Ok(Some(image));
+        }
+    }
+    for extents in extents_of(b"Exif") {
+        let Ok(data) = read_item(reader, extents) else {
+            continue;
+        };
+        // EXIF items start with the offset of the TIFF header
+        let Some(tiff_data) = read_u32(&data, 0)
+            .ok()
+            .and_then(|skip| data.get(4 + skip as usize..))
+        else {
+            continue;
+        };
+        let mut exif = Cursor::new(tiff_data);
+        let Ok(layout) = tiff::inspect(&mut exif) else {
+            continue;
+        };
+        if let Some(image) = tiff::largest_preview(&mut exif, &layout)? {
+            return Ok(Some(image));
+        }
+    }
+    Ok(None)
+}
+
+/// `(item id, item type)` for each entry in `iinf`.
+fn item_types(iinf: &[u8]) -> Result<Vec<(u32, [u8; 4])>> {
+    let version = *iinf.first().ok_or_else(|| anyhow!("Truncated iinf box"))?;
+    let entries = if version == 0 { 6 } else { 8 };
+    let mut items = Vec::new();
+    for (kind, infe) in parse_boxes(iinf.get(entries..).unwrap_or_default())? {
+        // Item types were added in version 2 of `infe`
+        if &kind != b"infe" || infe.first().is_none_or(|&version| version < 2) {
+            continue;
+        }
+        let (id, type_at) = match infe[0] {
+            2 => (read_u16(infe, 4)? as u32, 8),
+            _ => (read_u32(infe, 4)?, 10),
+        };
+        let item_type = infe
+            .get(type_at..type_at + 4)
+            .ok_or_else(|| anyhow!("Truncated infe box"))?;
+        items.push((id, item_type.try_into()?));
+    }
+    Ok(items)
+}
+
+/// The extents of each item `iloc` stores in the file itself. Items built
+/// from other items or stored elsewhere are skipped.
+fn item_locations(iloc: &[u8]) -> Result<Vec<(u32, Extents)>> {
+    let version = *iloc.first().ok_or_else(|| anyhow!("Truncated iloc box"))?;
+    let sizes = read_u16(iloc, 4)?;
+    let offset_size = (sizes >> 12) as usize;
+    let length_size = (sizes >> 8 & 0xf) as usize;
+    let base_offset_size = (sizes >> 4 & 0xf) as usize;
+    let index_size = match version {
+        1 | 2 => (sizes & 0xf) as usize,
+        _ => 0,
+    };
+    // Item ids and the item count are 16-bit before version 2
+    let id_size = if version < 2 { 2 } else { 4 };
+
+    let mut pos = 6;
+    let item_count = take(iloc, &mut pos, id_size)?;
+    let mut items = Vec::new();
+    for _ in 0..item_count {
+        let id = take(iloc, &mut pos, id_size)? as u32;
+        let construction_method = match version {
+            1 | 2 => take(iloc, &mut pos, 2)? & 0xf,
+            _ => 0,
+        };
+        let data_reference_index = take(iloc, &mut pos, 2)?;
+        let base_offset = take(iloc, &mut pos, base_offset_size)?;
+        let extent_count = take(iloc, &mut pos, 2)?;
+
+        let mut extents = Vec::with_capacity(extent_count as usize);
+        for _ in 0..extent_count {
+            take(iloc, &mut pos, index_size)?;
+            let offset = take(iloc, &mut pos, offset_size)?;
+            let length = take(iloc, &mut pos, length_size)?;
+            let offset = base_offset
+                .checked_add(offset)
+                .ok_or_else(|| anyhow!("Bad iloc extent"))?;
+            extents.push((offset, length));
+        }
+        // Method 0 is plain file offsets; reference 0 means this file
+        if construction_method == 0 && data_reference_index == 0 {
+            items.push((id, extents));
+        }
+    }
+    Ok(items)
+}
+
+/// Reads a `size`-byte big-endian field at `pos` and moves past it.
+fn take(data: &[u8], pos: &mut usize, size: usize) -> Result<u64> {
+    let value = match size {
+        0 => 0,
+        2 => read_u16(data, *pos)? as u64,
+        4 => read_u32(data, *pos)? as u64,
+        8 => read_u64(data, *pos)?,
+        _ => bail!("Unsupported iloc field size {}", size),
+    };
+    *pos += size;
+    Ok(value)
+}
+
+fn read_item<R: Read + Seek>(reader: &mut R, extents: &[(u64, u64)]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for &(offset, length) in extents {
+        // A zero length runs to the end of the file
+        if length == 0 || length > MAX_ITEM_SIZE - data.len() as u64 {
+            bail!("Item too large to preview");
+        }
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.by_ref().take(length).read_to_end(&mut data)?;
+    }
+    Ok(data)
+}
@@ -0,0 +1,96 @@
+//! Box parsing shared by the ISO base media file formats: HEIF stills and
+//! MP4/QuickTime video.
+
+use anyhow::{anyhow, bail, Result};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Largest metadata box we're willing to load; real `meta` boxes are a few
+/// kilobytes and `moov` boxes a few megabytes.
+const MAX_BOX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Scans the file's top-level boxes and returns the body of the first `kind`.
+pub fn read_top_level_box<R: Read + Seek>(reader: &mut R, kind: &[u8; 4]) -> Result<Vec<u8>> {
+    let mut offset = 0u64;
+    loop {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let mut size = u32::from_be_bytes(header[0..4].try_into()?) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        }
+
+        if &header[4..8] == kind {
+            let body_len = match size {
+                0 => MAX_BOX_SIZE,
+                size => size
+                    .checked_sub(header_len)
+                    .ok_or_else(|| anyhow!("Bad box size"))?,
+            };
+            if body_len > MAX_BOX_SIZE {
+                bail!("{} box too large", String::from_utf8_lossy(kind));
+            }
+            let mut body = Vec::new();
+            reader.take(body_len).read_to_end(&mut body)?;
+            return Ok(body);
+        }
+        if size < header_len {
+            bail!("No {} box", String::from_utf8_lossy(kind));
+        }
+        offset = offset
+            .checked_add(size)
+            .ok_or_else(|| anyhow!("Bad box size"))?;
+    }
+}
+
+/// Splits a run of boxes into `(type, body)` pairs.
+pub fn parse_boxes(mut data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[0..4].try_into()?) as usize;
+        let kind: [u8; 4] = data[4..8].try_into()?;
+        let (header_len, size) = match size {
+            0 => (8, data.len()),
+            1 => (16, read_u64(data, 8)? as usize),
+            size => (8, size),
+        };
+        if size < header_len || size > data.len() {
+            bail!("Bad {} box size", String::from_utf8_lossy(&kind));
+        }
+        boxes.push((kind, &data[header_len..size]));
+        data = &data[size..];
+    }
+    Ok(boxes)
+}
+
+pub fn find<'a>(boxes: &[([u8; 4], &'a [u8])], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes
+        .iter()
+        .find(|(found, _)| found == kind)
+        .map(|(_, body)| *body)
+}
+
+pub fn read_u16(data: &[u8], at: usize) -> Result<u16> {
+    let bytes = data
+        .get(at..at + 2)
+        .ok_or_else(|| anyhow!("Truncated box"))?;
+    Ok(u16::from_be_bytes(bytes.try_into()?))
+}
+
+pub fn read_u32(data: &[u8], at: usize) -> Result<u32> {
+    let bytes = data
+        .get(at..at + 4)
+        .ok_or_else(|| anyhow!("Truncated box"))?;
+    Ok(u32::from_be_bytes(bytes.try_into()?))
+}
+
+pub fn read_u64(data: &[u8], at: usize) -> Result<u64> {
+    let bytes = data
+        .get(at..at + 8)
+        .ok_or_else(|| anyhow!("Truncated box"))?;
+    Ok(u64::from_be_bytes(bytes.try_into()?))
+}
@@ -0,0 +1,315 @@
+mod heif;
+mod isobmff;
+mod tiff;
+mod video;
+
+use crate::services::config::AppConfig;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use image::DynamicImage;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Reads one family of image files. Handlers are looked up by file extension.
+pub trait FormatHandler: Send + Sync {
+    /// Stored in `photos.format` and matched (case-insensitively) against
+    /// `AppConfig::enabled_formats`.
+    fn name(&self) -> &'static str;
+    /// Lowercase extensions, without the dot.
+    fn extensions(&self) -> &'static [&'static str];
+    fn mime_type(&self) -> &'static str;
+    /// Width and height of the full-size image, read without decoding it.
+    fn dimensions(&self, path: &Path) -> Result<(u32, u32)>;
+    /// Decodes the image, or the largest preview embedded in it, for thumbnails.
+    fn preview(&self, path: &Path) -> Result<DynamicImage>;
+    /// Container metadata for video formats; `None` for stills.
+    fn video_info(&self, _path: &Path) -> Result<Option<VideoInfo>> {
+        Ok(None)
+    }
+}
+
+/// What a video's container records about it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoInfo {
+    /// Display size, after any rotation the container applies.
+    pub width: u32,
+    pub height: u32,
+    pub duration_ms: Option<i64>,
+    pub codec: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    /// Apple's identifier linking a Live Photo's video to its still.
+    pub content_identifier: Option<String>,
+}
+
+/// Returned by `FormatHandler::preview` when a file holds no image that can
+/// be decoded here, as opposed to one that couldn't be read.
+#[derive(Debug)]
+pub struct NoPreview(pub PathBuf);
+
+impl fmt::Display for NoPreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No decodable preview in {}", self.0.display())
+    }
+}
+
+impl std::error::Error for NoPreview {}
+
+/// Formats the `image` crate decodes directly.
+struct Raster {
+    name: &'static str,
+    extensions: &'static [&'static str],
+    mime_type: &'static str,
+}
+
+impl FormatHandler for Raster {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        self.extensions
+    }
+
+    fn mime_type(&self) -> &'static str {
+        self.mime_type
+    }
+
+    fn dimensions(&self, path: &Path) -> Result<(u32, u32)> {
+        Ok(image::image_dimensions(path)?)
+    }
+
+    fn preview(&self, path: &Path) -> Result<DynamicImage> {
+        Ok(image::open(path)?)
+    }
+}
+
+/// HEIF/HEIC stills. Their HEVC-coded pixels can't be decoded here, so
+/// previews come from an embedded JPEG thumbnail when the file has one.
+struct Heif;
+
+impl FormatHandler for Heif {
+    fn name(&self) -> &'static str {
+        "Heic"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["heic", "heif"]
+    }
+
+    fn mime_type(&self) -> &'static str {
+        "image/heic"
+    }
+
+    fn dimensions(&self, path: &Path) -> Result<(u32, u32)> {
+        heif::primary_dimensions(&mut BufReader::new(File::open(path)?))
+    }
+
+    fn preview(&self, path: &Path) -> Result<DynamicImage> {
+        heif::embedded_preview(&mut BufReader::new(File::open(path)?))?
+            .ok_or_else(|| NoPreview(path.to_path_buf()).into())
+    }
+}
+
+/// Camera RAW files built on TIFF, previewed through their embedded JPEGs.
+struct TiffRaw {
+    name: &'static str,
+    extensions: &'static [&'static str],
+    mime_type: &'static str,
+}
+
+impl FormatHandler for TiffRaw {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        self.extensions
+    }
+
+    fn mime_type(&self) -> &'static str {
+        self.mime_type
+    }
+
+    fn dimensions(&self, path: &Path) -> Result<(u32, u32)> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let layout = tiff::inspect(&mut reader)?;
+        if let Some(dimensions) = layout.dimensions {
+            return Ok(dimensions);
+        }
+        let preview = tiff::largest_preview(&mut reader, &layout)?
+            .ok_or_else(|| anyhow!("No image dimensions in {}", path.display()))?;
+        Ok((preview.width(), preview.height()))
+    }
+
+    fn preview(&self, path: &Path) -> Result<DynamicImage> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let layout = tiff::inspect(&mut reader)?;
+        tiff::largest_preview(&mut reader, &layout)?
+            .ok_or_else(|| NoPreview(path.to_path_buf()).into())
+    }
+}
+
+/// MP4 and QuickTime movies. Frames can't be decoded here, so previews come
+/// from embedded cover art or, for Motion JPEG, the first frame.
+struct Video {
+    name: &'static str,
+    extensions: &'static [&'static str],
+    mime_type: &'static str,
+}
+
+impl FormatHandler for Video {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        self.extensions
+    }
+
+    fn mime_type(&self) -> &'static str {
+        self.mime_type
+    }
+
+    fn dimensions(&self, path: &Path) -> Result<(u32, u32)> {
+        let info = video::inspect(&mut BufReader::new(File::open(path)?))?.info;
+        Ok((info.width, info.height))
+    }
+
+    fn preview(&self, path: &Path) -> Result<DynamicImage> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let layout = video::inspect(&mut reader)?;
+        video::poster(&mut reader, &layout)?.ok_or_else(|| NoPreview(path.to_path_buf()).into())
+    }
+
+    fn video_info(&self, path: &Path) -> Result<Option<VideoInfo>> {
+        let layout = video::inspect(&mut BufReader::new(File::open(path)?))?;
+        Ok(Some(layout.info))
+    }
+}
+
+static JPEG: Raster = Raster {
+    name: "Jpeg",
+    extensions: &["jpg", "jpeg"],
+    mime_type: "image/jpeg",
+};
+static PNG: Raster = Raster {
+    name: "Png",
+    extensions: &["png"],
+    mime_type: "image/png",
+};
+static GIF: Raster = Raster {
+    name: "Gif",
+    extensions: &["gif"],
+    mime_type: "image/gif",
+};
+static WEBP: Raster = Raster {
+    name: "WebP",
+    extensions: &["webp"],
+    mime_type: "image/webp",
+};
+static TIFF: Raster = Raster {
+    name: "Tiff",
+    extensions: &["tif", "tiff"],
+    mime_type: "image/tiff",
+};
+static HEIF: Heif = Heif;
+static CR2: TiffRaw = TiffRaw {
+    name: "Cr2",
+    extensions: &["cr2"],
+    mime_type: "image/x-canon-cr2",
+};
+static NEF: TiffRaw = TiffRaw {
+    name: "Nef",
+    extensions: &["nef"],
+    mime_type: "image/x-nikon-nef",
+};
+static ARW: TiffRaw = TiffRaw {
+    name: "Arw",
+    extensions: &["arw"],
+    mime_type: "image/x-sony-arw",
+};
+static DNG: TiffRaw = TiffRaw {
+    name: "Dng",
+    extensions: &["dng"],
+    mime_type: "image/x-adobe-dng",
+};
+static MP4: Video = Video {
+    name: "Mp4",
+    extensions: &["mp4", "m4v"],
+    mime_type: "video/mp4",
+};
+static MOV: Video = Video {
+    name: "Mov",
+    extensions: &["mov", "qt"],
+    mime_type: "video/quicktime",
+};
+
+/// Every format PhotoVault can read.
+pub static BUILTIN_FORMATS: &[&dyn FormatHandler] = &[
+    &JPEG, &PNG, &GIF, &WEBP, &TIFF, &HEIF, &CR2, &NEF, &ARW, &DNG, &MP4, &MOV,
+];
+
+/// The builtin handler for `path`'s extension, whether or not it's enabled.
+pub fn handler_for(path: &Path) -> Option<&'static dyn FormatHandler> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    BUILTIN_FORMATS
+        .iter()
+        .copied()
+        .find(|handler| handler.extensions().contains(&extension.as_str()))
+}
+
+/// The formats scans and the library watcher pick up.
+#[derive(Clone)]
+pub struct FormatRegistry {
+    handlers: Vec<&'static dyn FormatHandler>,
+}
+
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        Self {
+            handlers: BUILTIN_FORMATS.to_vec(),
+        }
+    }
+}
+
+impl FormatRegistry {
+    /// Enables the named formats, or every builtin format for `None`.
+    /// Unknown names are ignored.
+    pub fn new(enabled: Option<&[String]>) -> Self {
+        let Some(enabled) = enabled else {
+            return Self::default();
+        };
+        Self {
+            handlers: BUILTIN_FORMATS
+                .iter()
+                .copied()
+                .filter(|handler| {
+                    enabled
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(handler.name()))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::new(config.enabled_formats.as_deref())
+    }
+
+    pub fn handlers(&self) -> &[&'static dyn FormatHandler] {
+        &self.handlers
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.handlers
+            .iter()
+            .any(|handler| handler.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Whether `path` has the extension of an enabled format.
+    pub fn is_supported(&self, path: &Path) -> bool {
+        handler_for(path).is_some_and(|handler| self.is_enabled(handler.name()))
+    }
+}
@@ -0,0 +1,270 @@
+//! Walks the IFDs of TIFF-based camera RAW files (CR2, NEF, ARW, DNG) to find
+//! the full image size and the JPEG previews cameras embed alongside the
+//! sensor data.
+
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, ImageFormat};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom};
+
+const NEW_SUBFILE_TYPE: u16 = 0x00fe;
+const IMAGE_WIDTH: u16 = 0x0100;
+const IMAGE_LENGTH: u16 = 0x0101;
+const COMPRESSION: u16 = 0x0103;
+const STRIP_OFFSETS: u16 = 0x0111;
+const STRIP_BYTE_COUNTS: u16 = 0x0117;
+const SUB_IFDS: u16 = 0x014a;
+const JPEG_OFFSET: u16 = 0x0201;
+const JPEG_LENGTH: u16 = 0x0202;
+const EXIF_IFD: u16 = 0x8769;
+const PIXEL_X_DIMENSION: u16 = 0xa002;
+const PIXEL_Y_DIMENSION: u16 = 0xa003;
+
+/// Guards against corrupt files with IFD cycles or absurd entry counts.
+const MAX_IFDS: usize = 64;
+const MAX_VALUES: u32 = 4096;
+/// Bytes read to check a preview's JPEG header before loading all of it.
+const PROBE_LEN: u64 = 64 * 1024;
+
+#[derive(Debug, Default)]
+pub struct TiffLayout {
+    /// Size of the full-resolution image, when the file records one.
+    pub dimensions: Option<(u32, u32)>,
+    /// `(offset, length)` of every embedded JPEG stream, largest first.
+    pub jpegs: Vec<(u64, u64)>,
+}
+
+#[derive(Default)]
+struct Ifd {
+    subfile_type: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    compression: Option<u32>,
+    strip_offsets: Vec<u32>,
+    strip_byte_counts: Vec<u32>,
+    jpeg_offset: Option<u32>,
+    jpeg_length: Option<u32>,
+    pixel_x: Option<u32>,
+    pixel_y: Option<u32>,
+}
+
+#[derive(Clone, Copy)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: [u8; 2]) -> u16 {
+        match self {
+            Self::Little => u16::from_le_bytes(bytes),
+            Self::Big => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Self::Little => u32::from_le_bytes(bytes),
+            Self::Big => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+pub fn inspect<R: Read + Seek>(reader: &mut R) -> Result<TiffLayout> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    let order = match &header[0..2] {
+        b"II" => ByteOrder::Little,
+        b"MM" => ByteOrder::Big,
+        _ => bail!("Not a TIFF file"),
+    };
+    if order.u16([header[2], header[3]]) != 42 {
+        bail!("Not a TIFF file");
+    }
+
+    let mut pending = vec![order.u32(header[4..8].try_into()?)];
+    let mut visited = HashSet::new();
+    let mut ifds = Vec::new();
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || !visited.insert(offset) || visited.len() > MAX_IFDS {
+            continue;
+        }
+        let (ifd, next, children) = read_ifd(reader, order, offset)?;
+        ifds.push(ifd);
+        pending.push(next);
+        pending.extend(children);
+    }
+
+    let mut layout = TiffLayout::default();
+    for ifd in &ifds {
+        if let (Some(offset), Some(length)) = (ifd.jpeg_offset, ifd.jpeg_length) {
+            layout.jpegs.push((offset as u64, length as u64));
+        }
+        // Compression 6 and 7 are JPEG; single-strip ones can be previews
+        if matches!(ifd.compression, Some(6 | 7))
+            && ifd.strip_offsets.len() == 1
+            && ifd.strip_byte_counts.len() == 1
+        {
+            layout
+                .jpegs
+                .push((ifd.strip_offsets[0] as u64, ifd.strip_byte_counts[0] as u64));
+        }
+    }
+    layout.jpegs.sort_by_key(|&(_, length)| Reverse(length));
+    layout.jpegs.dedup();
+
+    let area = |ifd: &&Ifd| {
+        ifd.width
+            .zip(ifd.height)
+            .map_or(0, |(w, h)| w as u64 * h as u64)
+    };
+    layout.dimensions = ifds
+        .iter()
+        .filter(|ifd| ifd.subfile_type.unwrap_or(0) == 0)
+        .max_by_key(area)
+        .and_then(|ifd| ifd.width.zip(ifd.height))
+        .or_else(|| ifds.iter().find_map(|ifd| ifd.pixel_x.zip(ifd.pixel_y)));
+    Ok(layout)
+}
+
+/// Decodes the largest embedded JPEG a standard decoder can handle. RAW
+/// sensor data stored as lossless JPEG is skipped.
+pub fn largest_preview<R: Read + Seek>(
+    reader: &mut R,
+    layout: &TiffLayout,
+) -> Result<Option<DynamicImage>> {
+    for &(offset, length) in &layout.jpegs {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut probe = Vec::new();
+        reader
+            .by_ref()
+            .take(length.min(PROBE_LEN))
+            .read_to_end(&mut probe)?;
+        if !is_displayable_jpeg(&probe) {
+            continue;
+        }
+
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::new();
+        reader.by_ref().take(length).read_to_end(&mut data)?;
+        if let Ok(image) = image::load_from_memory_with_format(&data, ImageFormat::Jpeg) {
+            return Ok(Some(image));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the JPEG stream in `data` is baseline or progressive, rather than
+/// the lossless (or arithmetic-coded) variants RAW files use for sensor data.
+fn is_displayable_jpeg(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return false;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xff {
+            return false;
+        }
+        let marker = data[pos + 1];
+        match marker {
+            0xc0..=0xc2 => return true,
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return false,
+            // Fill bytes before a marker
+            0xff => {
+                pos += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        pos += 2 + length;
+    }
+    false
+}
+
+/// Reads the IFD at `offset`, returning it with the offset of the next IFD in
+/// the chain and the offsets of any child IFDs (SubIFDs and the EXIF IFD).
+fn read_ifd<R: Read + Seek>(
+    reader: &mut R,
+    order: ByteOrder,
+    offset: u32,
+) -> Result<(Ifd, u32, Vec<u32>)> {
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    let mut count = [0u8; 2];
+    reader.read_exact(&mut count)?;
+    let count = order.u16(count) as usize;
+
+    let mut entries = vec![0u8; count * 12];
+    reader.read_exact(&mut entries)?;
+    let mut next = [0u8; 4];
+    // Some writers end the last IFD without a next-IFD pointer
+    let next = match reader.read_exact(&mut next) {
+        Ok(()) => order.u32(next),
+        Err(_) => 0,
+    };
+
+    let mut ifd = Ifd::default();
+    let mut children = Vec::new();
+    for entry in entries.chunks_exact(12) {
+        let tag = order.u16([entry[0], entry[1]]);
+        let kind = order.u16([entry[2], entry[3]]);
+        let count = order.u32(entry[4..8].try_into()?);
+        let mut values = || read_values(reader, order, kind, count, entry[8..12].try_into()?);
+        match tag {
+            NEW_SUBFILE_TYPE => ifd.subfile_type = values()?.first().copied(),
+            IMAGE_WIDTH => ifd.width = values()?.first().copied(),
+            IMAGE_LENGTH => ifd.height = values()?.first().copied(),
+            COMPRESSION => ifd.compression = values()?.first().copied(),
+            STRIP_OFFSETS => ifd.strip_offsets = values()?,
+            STRIP_BYTE_COUNTS => ifd.strip_byte_counts = values()?,
+            JPEG_OFFSET => ifd.jpeg_offset = values()?.first().copied(),
+            JPEG_LENGTH => ifd.jpeg_length = values()?.first().copied(),
+            PIXEL_X_DIMENSION => ifd.pixel_x = values()?.first().copied(),
+            PIXEL_Y_DIMENSION => ifd.pixel_y = values()?.first().copied(),
+            SUB_IFDS | EXIF_IFD => children.extend(values()?),
+            _ => {}
+        }
+    }
+    Ok((ifd, next, children))
+}
+
+/// Reads an entry's SHORT, LONG or IFD values, which live inline in the
+/// entry when they fit in four bytes and at an offset otherwise.
+fn read_values<R: Read + Seek>(
+    reader: &mut R,
+    order: ByteOrder,
+    kind: u16,
+    count: u32,
+    inline: [u8; 4],
+) -> Result<Vec<u32>> {
+    let width = match kind {
+        3 => 2,
+        4 | 13 => 4,
+        _ => return Ok(Vec::new()),
+    };
+    if count > MAX_VALUES {
+        return Err(anyhow!("Too many values in TIFF entry"));
+    }
+
+    let len = width * count as usize;
+    let bytes = if len <= 4 {
+        inline[..len].to_vec()
+    } else {
+        let position = reader.stream_position()?;
+        reader.seek(SeekFrom::Start(order.u32(inline) as u64))?;
+        let mut bytes = vec![0u8; len];
+        reader.read_exact(&mut bytes)?;
+        reader.seek(SeekFrom::Start(position))?;
+        bytes
+    };
+
+    Ok(bytes
+        .chunks_exact(width)
+        .map(|value| match width {
+            2 => order.u16([value[0], value[1]]) as u32,
+            _ => order.u32([value[0], value[1], value[2], value[3]]),
+        })
+        .collect())
+}
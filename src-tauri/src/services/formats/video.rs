@@ -0,0 +1,225 @@
+//! Reads MP4 and QuickTime movie metadata from the `moov` box: duration and
+//! creation time from `mvhd`, and resolution and codec from the first video
+//! track's `tkhd` and `stsd`.
+
+use super::isobmff::{find, parse_boxes, read_top_level_box, read_u32, read_u64};
+use super::VideoInfo;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use image::{DynamicImage, ImageFormat};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Seconds between the QuickTime epoch (1904-01-01) and the Unix epoch.
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+/// QuickTime metadata key linking a Live Photo's video to its still.
+const CONTENT_IDENTIFIER_KEY: &[u8] = b"com.apple.quicktime.content.identifier";
+/// Largest Motion JPEG frame we'll load for a poster.
+const MAX_FRAME_SIZE: u64 = 32 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub struct MovieLayout {
+    pub info: VideoInfo,
+    /// Cover art from `moov/udta/meta/ilst/covr`, as stored.
+    pub artwork: Option<Vec<u8>>,
+    /// `(offset, length)` of the first video sample, when it's a JPEG frame.
+    pub jpeg_frame: Option<(u64, u64)>,
+}
+
+pub fn inspect<R: Read + Seek>(reader: &mut R) -> Result<MovieLayout> {
+    let moov = read_top_level_box(reader, b"moov")?;
+    let children = parse_boxes(&moov)?;
+    let mut layout = MovieLayout::default();
+
+    if let Some(mvhd) = find(&children, b"mvhd") {
+        let (created, timescale, duration) = match mvhd.first() {
+            Some(1) => (
+                read_u64(mvhd, 4)?,
+                read_u32(mvhd, 20)?,
+                Some(read_u64(mvhd, 24)?).filter(|&d| d != u64::MAX),
+            ),
+            _ => (
+                read_u32(mvhd, 4)? as u64,
+                read_u32(mvhd, 12)?,
+                Some(read_u32(mvhd, 16)?)
+                    .filter(|&d| d != u32::MAX)
+                    .map(u64::from),
+            ),
+        };
+        layout.info.duration_ms = duration
+            .filter(|_| timescale > 0)
+            .map(|d| (d as u128 * 1000 / timescale as u128) as i64);
+        layout.info.created = quicktime_time(created);
+    }
+
+    let video_track = children
+        .iter()
+        .filter(|(kind, _)| kind == b"trak")
+        .find_map(|(_, trak)| parse_boxes(trak).ok().filter(|trak| is_video_track(trak)));
+    if let Some(trak) = video_track {
+        read_video_track(&trak, &mut layout)?;
+    }
+
+    layout.artwork = cover_art(&children);
+    layout.info.content_identifier = quicktime_metadata(&children, CONTENT_IDENTIFIER_KEY)
+        .map(|value| String::from_utf8_lossy(value).into_owned());
+    Ok(layout)
+}
+
+/// The cover art, or for Motion JPEG movies the first frame, decoded.
+pub fn poster<R: Read + Seek>(
+    reader: &mut R,
+    layout: &MovieLayout,
+) -> Result<Option<DynamicImage>> {
+    if let Some(artwork) = &layout.artwork {
+        if let Ok(image) = image::load_from_memory(artwork) {
+            return Ok(Some(image));
+        }
+    }
+    let Some((offset, length)) = layout.jpeg_frame.filter(|&(_, len)| len <= MAX_FRAME_SIZE) else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut frame = Vec::new();
+    reader.take(length).read_to_end(&mut frame)?;
+    Ok(image::load_from_memory_with_format(&frame, ImageFormat::Jpeg).ok())
+}
+
+fn quicktime_time(seconds: u64) -> Option<DateTime<Utc>> {
+    // Zero means the writer didn't record a time
+    if seconds == 0 {
+        return None;
+    }
+    let unix = i64::try_from(seconds).ok()? - QUICKTIME_EPOCH_OFFSET;
+    DateTime::from_timestamp(unix, 0)
+}
+
+fn is_video_track(trak: &[([u8; 4], &[u8])]) -> bool {
+    let handler = find(trak, b"mdia")
+        .and_then(|mdia| parse_boxes(mdia).ok())
+        .and_then(|mdia| find(&mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)));
+    handler == Some(&b"vide"[..])
+}
+
+fn read_video_track(trak: &[([u8; 4], &[u8])], layout: &mut MovieLayout) -> Result<()> {
+    if let Some(tkhd) = find(trak, b"tkhd") {
+        // The transformation matrix and 16.16 fixed-point display size sit
+        // after the version-dependent times and duration
+        let matrix = if tkhd.first() == Some(&1) { 52 } else { 40 };
+        let width = read_u32(tkhd, matrix + 36)? >> 16;
+        let height = read_u32(tkhd, matrix + 40)? >> 16;
+        // A zero `a` with a non-zero `b` is a quarter turn, as phones use
+        // for portrait video
+        let quarter_turn = read_u32(tkhd, matrix)? == 0 && read_u32(tkhd, matrix + 4)? != 0;
+        if width > 0 && height > 0 {
+            layout.info.width = width;
+            layout.info.height = height;
+        }
+        if quarter_turn {
+            std::mem::swap(&mut layout.info.width, &mut layout.info.height);
+        }
+    }
+
+    let stbl = find(trak, b"mdia")
+        .map(parse_boxes)
+        .transpose()?
+        .and_then(|mdia| find(&mdia, b"minf").map(parse_boxes))
+        .transpose()?
+        .and_then(|minf| find(&minf, b"stbl").map(parse_boxes))
+        .transpose()?
+        .ok_or_else(|| anyhow!("Video track has no sample table"))?;
+
+    // `stsd` is a full box with an entry count, then one box per sample entry
+    let entries = find(&stbl, b"stsd")
+        .and_then(|stsd| stsd.get(8..))
+        .map(parse_boxes)
+        .transpose()?
+        .unwrap_or_default();
+    let Some(&(fourcc, entry)) = entries.first() else {
+        return Ok(());
+    };
+    layout.info.codec = Some(codec_name(&fourcc));
+    if layout.info.width == 0 || layout.info.height == 0 {
+        // The visual sample entry's coded width and height, 16 bits each
+        let size = read_u32(entry, 24)?;
+        layout.info.width = size >> 16;
+        layout.info.height = size & 0xffff;
+    }
+
+    if matches!(&fourcc, b"jpeg" | b"mjpa") {
+        layout.jpeg_frame = first_sample(&stbl)?;
+    }
+    Ok(())
+}
+
+fn first_sample(stbl: &[([u8; 4], &[u8])]) -> Result<Option<(u64, u64)>> {
+    let Some(stsz) = find(stbl, b"stsz") else {
+        return Ok(None);
+    };
+    let length = match read_u32(stsz, 4)? {
+        0 => read_u32(stsz, 12)?,
+        fixed => fixed,
+    } as u64;
+    let offset = match (find(stbl, b"stco"), find(stbl, b"co64")) {
+        (Some(stco), _) => read_u32(stco, 8)? as u64,
+        (None, Some(co64)) => read_u64(co64, 8)?,
+        (None, None) => return Ok(None),
+    };
+    Ok(Some((offset, length)))
+}
+
+/// The children of a `meta` box. QuickTime writes `meta` as a plain box and
+/// MP4 as a full box, so both layouts are accepted.
+fn meta_children(meta: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    let meta = if meta.get(4..8) == Some(&b"hdlr"[..]) {
+        meta
+    } else {
+        meta.get(4..)?
+    };
+    parse_boxes(meta).ok()
+}
+
+/// The payload of an `ilst` item's `data` box, after its type indicator and
+/// locale.
+fn item_value(item: &[u8]) -> Option<&[u8]> {
+    find(&parse_boxes(item).ok()?, b"data")?.get(8..)
+}
+
+/// iTunes-style cover art from `moov/udta/meta/ilst/covr`.
+fn cover_art(moov: &[([u8; 4], &[u8])]) -> Option<Vec<u8>> {
+    let udta = parse_boxes(find(moov, b"udta")?).ok()?;
+    let meta = meta_children(find(&udta, b"meta")?)?;
+    let ilst = parse_boxes(find(&meta, b"ilst")?).ok()?;
+    Some(item_value(find(&ilst, b"covr")?)?.to_vec())
+}
+
+/// A QuickTime metadata value from `moov/meta`, where `keys` lists the key
+/// names and each `ilst` item's box type is a 1-based index into it.
+fn quicktime_metadata<'a>(moov: &[([u8; 4], &'a [u8])], key: &[u8]) -> Option<&'a [u8]> {
+    let meta = meta_children(find(moov, b"meta")?)?;
+    // `keys` is a full box with an entry count, then size, namespace and name
+    let mut entries = find(&meta, b"keys")?.get(8..)?;
+    let mut index = 0u32;
+    loop {
+        index += 1;
+        let size = u32::from_be_bytes(entries.get(0..4)?.try_into().ok()?) as usize;
+        if entries.get(8..size.max(8))? == key {
+            break;
+        }
+        entries = entries.get(size.max(8)..)?;
+    }
+    let ilst = parse_boxes(find(&meta, b"ilst")?).ok()?;
+    item_value(find(&ilst, &index.to_be_bytes())?)
+}
+
+fn codec_name(fourcc: &[u8; 4]) -> String {
+    match fourcc {
+        b"avc1" | b"avc3" => "H.264".to_string(),
+        b"hvc1" | b"hev1" => "HEVC".to_string(),
+        b"av01" => "AV1".to_string(),
+        b"vp09" => "VP9".to_string(),
+        b"mp4v" => "MPEG-4".to_string(),
+        b"jpeg" | b"mjpa" | b"mjpb" => "Motion JPEG".to_string(),
+        b"apcn" | b"apch" | b"apcs" | b"apco" | b"ap4h" | b"ap4x" => "ProRes".to_string(),
+        other => String::from_utf8_lossy(other).trim().to_string(),
+    }
+}
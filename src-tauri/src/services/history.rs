@@ -0,0 +1,467 @@
+use crate::models::filter::FilterCriteria;
+use crate::models::operation::Operation;
+use crate::services::organizer::move_file;
+use crate::services::sync_engine::{AlreadyCommitted, SyncEngine};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// An action in the undo history, as shown to the user.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub label: String,
+    /// Undone actions can be redone until a new action is recorded.
+    pub undone: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The operations of an action being recorded by `SyncEngine::record_action`,
+/// with the operations that undo them.
+#[derive(Debug, Clone)]
+pub struct PendingAction {
+    label: String,
+    operations: Vec<Operation>,
+    inverse: Vec<Operation>,
+    /// Cleared once an operation that can't be undone is recorded.
+    reversible: bool,
+}
+
+impl PendingAction {
+    pub(crate) fn new(label: String) -> Self {
+        Self {
+            label,
+            operations: Vec::new(),
+            inverse: Vec::new(),
+            reversible: true,
+        }
+    }
+
+    /// Adds `op`, undone by `inverse` (see `inverse`), to the action.
+    pub(crate) fn record(&mut self, op: &Operation, inverse: Option<Vec<Operation>>) {
+        match inverse {
+            Some(inverse) => {
+                self.operations.push(op.clone());
+                // Later operations are undone first
+                self.inverse.splice(0..0, inverse);
+            }
+            None => self.reversible = false,
+        }
+    }
+}
+
+/// The operations that undo a create `op` just applied on `conn`: deleting
+/// the row its insert made, which `inverse` can't know the id of beforehand.
+/// Empty for other operations.
+///
+/// The backup replays the delete by the same id. Its albums are numbered
+/// alike while both catalogs apply the same operations in order, but not
+/// once a create is set aside as failed there, so the delete also carries
+/// the album's name and is refused where the id holds another album.
+pub(crate) async fn inverse_of_insert(
+    conn: &mut SqliteConnection,
+    op: &Operation,
+) -> Result<Vec<Operation>> {
+    let name = match op {
+        Operation::CreateAlbum { name } | Operation::CreateSmartAlbum { name, .. } => {
+            Some(name.clone())
+        }
+        _ => return Ok(Vec::new()),
+    };
+    // The create's insert is the last one `execute_on_db` made on `conn`
+    let id: i64 = sqlx::query_scalar("SELECT last_insert_rowid()")
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(vec![match op {
+        Operation::CreateAlbum { .. } => Operation::DeleteAlbum { album_id: id, name },
+        _ => Operation::DeleteSmartAlbum {
+            smart_album_id: id,
+            name,
+        },
+    }])
+}
+
+/// The operations that undo `op`, worked out from the catalog on `conn` just
+/// before `op` is applied to it. `None` when `op` can't be undone: adding a
+/// photo comes from a file that's already there, and a permanent delete
+/// leaves nothing to bring back.
+pub(crate) async fn inverse(
+    conn: &mut SqliteConnection,
+    op: &Operation,
+) -> Result<Option<Vec<Operation>>> {
+    let inverse = match op {
+        Operation::AddPhoto { .. } | Operation::SyncPhoto { .. } | Operation::Delete { .. } => {
+            return Ok(None)
+        }
+        Operation::Move { from, to } => vec![Operation::Move {
+            from: to.clone(),
+            to: from.clone(),
+        }],
+        Operation::Rename { path, new_name } => {
+            let old_name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| anyhow!("{} has no file name", path.display()))?;
+            vec![Operation::Rename {
+                path: path.with_file_name(new_name),
+                new_name: old_name.to_string(),
+            }]
+        }
+        Operation::Trash { path, trash_path } => {
+            // Trashing takes the photo out of its stack
+            let mut inverse = vec![Operation::Restore {
+                trash_path: trash_path.clone(),
+                path: path.clone(),
+            }];
+            inverse.extend(stack_of(conn, path).await?.map(create_stack));
+            inverse
+        }
+        Operation::Restore { trash_path, path } => vec![Operation::Trash {
+            path: path.clone(),
+            trash_path: trash_path.clone(),
+        }],
+        // Undone by deleting the row they insert, see `inverse_of_insert`
+        Operation::CreateAlbum { .. } | Operation::CreateSmartAlbum { .. } => Vec::new(),
+        Operation::DeleteAlbum { album_id, .. } => {
+            let name: Option<String> = sqlx::query_scalar("SELECT name FROM albums WHERE id = ?")
+                .bind(album_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+            let photo_ids: Vec<i64> = sqlx::query_scalar(
+                "SELECT photo_id FROM photo_albums WHERE album_id = ? ORDER BY photo_id",
+            )
+            .bind(album_id)
+            .fetch_all(&mut *conn)
+            .await?;
+            name.map(|name| Operation::RestoreAlbum {
+                album_id: *album_id,
+                name,
+                photo_ids,
+            })
+            .into_iter()
+            .collect()
+        }
+        Operation::RestoreAlbum { album_id, .. } => vec![Operation::DeleteAlbum {
+            album_id: *album_id,
+            name: None,
+        }],
+        Operation::UpdateSmartAlbum { smart_album_id, .. } => smart_album(conn, *smart_album_id)
+            .await?
+            .map(|(name, criteria)| Operation::UpdateSmartAlbum {
+                smart_album_id: *smart_album_id,
+                name,
+                criteria,
+            })
+            .into_iter()
+            .collect(),
+        Operation::DeleteSmartAlbum { smart_album_id, .. } => smart_album(conn, *smart_album_id)
+            .await?
+            .map(|(name, criteria)| Operation::RestoreSmartAlbum {
+                smart_album_id: *smart_album_id,
+                name,
+                criteria,
+            })
+            .into_iter()
+            .collect(),
+        Operation::RestoreSmartAlbum { smart_album_id, .. } => {
+            vec![Operation::DeleteSmartAlbum {
+                smart_album_id: *smart_album_id,
+                name: None,
+            }]
+        }
+        Operation::AddToAlbum { photo_id, album_id } => vec![Operation::RemoveFromAlbum {
+            photo_id: *photo_id,
+            album_id: *album_id,
+        }],
+        Operation::RemoveFromAlbum { photo_id, album_id } => {
+            let member: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM photo_albums WHERE photo_id = ? AND album_id = ?",
+            )
+            .bind(photo_id)
+            .bind(album_id)
+            .fetch_one(&mut *conn)
+            .await?;
+            (member > 0)
+                .then_some(Operation::AddToAlbum {
+                    photo_id: *photo_id,
+                    album_id: *album_id,
+                })
+                .into_iter()
+                .collect()
+        }
+        Operation::AddTag { photo_id, tag_name } => vec![Operation::RemoveTag {
+            photo_id: *photo_id,
+            tag_name: tag_name.clone(),
+        }],
+        Operation::RemoveTag { photo_id, tag_name } => {
+            let tagged: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM photo_tags pt JOIN tags t ON t.id = pt.tag_id
+                 WHERE pt.photo_id = ? AND t.name = ?",
+            )
+            .bind(photo_id)
+            .bind(tag_name)
+            .fetch_one(&mut *conn)
+            .await?;
+            (tagged > 0)
+                .then_some(Operation::AddTag {
+                    photo_id: *photo_id,
+                    tag_name: tag_name.clone(),
+                })
+                .into_iter()
+                .collect()
+        }
+        Operation::CreateStack {
+            paths,
+            representative,
+        } => {
+            // Dissolve the new stack, then rebuild the ones its photos came from
+            let mut inverse = vec![Operation::Unstack {
+                path: representative.clone(),
+            }];
+            let mut seen = HashSet::new();
+            for path in paths {
+                if let Some(stack) = stack_of(conn, path).await? {
+                    if seen.insert(stack.0.clone()) {
+                        inverse.push(create_stack(stack));
+                    }
+                }
+            }
+            inverse
+        }
+        Operation::Unstack { path } => stack_of(conn, path)
+            .await?
+            .map(create_stack)
+            .into_iter()
+            .collect(),
+    };
+    Ok(Some(inverse))
+}
+
+/// The name and criteria of a smart album, if it exists.
+async fn smart_album(
+    conn: &mut SqliteConnection,
+    smart_album_id: i64,
+) -> Result<Option<(String, FilterCriteria)>> {
+    let row: Option<(String, String)> =
+        sqlx::query_as("SELECT name, criteria FROM smart_albums WHERE id = ?")
+            .bind(smart_album_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    row.map(|(name, criteria)| Ok((name, serde_json::from_str(&criteria)?)))
+        .transpose()
+}
+
+/// The paths in the stack the photo at `path` is in, and its representative.
+async fn stack_of(
+    conn: &mut SqliteConnection,
+    path: &Path,
+) -> Result<Option<(Vec<PathBuf>, PathBuf)>> {
+    let members: Vec<(String, bool)> = sqlx::query_as(
+        "SELECT p.path, p.id = s.representative_id FROM photos p JOIN stacks s ON s.id = p.stack_id
+         WHERE p.stack_id = (SELECT stack_id FROM photos WHERE path = ?) ORDER BY p.path",
+    )
+    .bind(path.to_str())
+    .fetch_all(&mut *conn)
+    .await?;
+    let Some((representative, _)) = members.iter().find(|(_, representative)| *representative)
+    else {
+        return Ok(None);
+    };
+    let paths = members
+        .iter()
+        .map(|(path, _)| PathBuf::from(path))
+        .collect();
+    Ok(Some((paths, PathBuf::from(representative))))
+}
+
+fn create_stack((paths, representative): (Vec<PathBuf>, PathBuf)) -> Operation {
+    Operation::CreateStack {
+        paths,
+        representative,
+    }
+}
+
+/// Saves a recorded action as the newest entry in the history, discarding
+/// anything that was undone before it. Actions that can't be undone, or
+/// that changed nothing, aren't saved.
+pub(crate) async fn record(pool: &SqlitePool, action: PendingAction) -> Result<()> {
+    if !action.reversible {
+        println!(
+            "\"{}\" can't be undone, leaving it out of the history",
+            action.label
+        );
+        return Ok(());
+    }
+    if action.operations.is_empty() {
+        return Ok(());
+    }
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM history WHERE undone = 1")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO history (label, operations, inverse) VALUES (?, ?, ?)")
+        .bind(&action.label)
+        .bind(serde_json::to_string(&action.operations)?)
+        .bind(serde_json::to_string(&action.inverse)?)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The most recent `limit` entries, newest first.
+pub async fn list_history(pool: &SqlitePool, limit: i64) -> Result<Vec<HistoryEntry>> {
+    let entries = sqlx::query_as::<_, HistoryEntry>(
+        "SELECT id, label, undone, created_at FROM history ORDER BY id DESC LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(entries)
+}
+
+/// Undoes the newest action that hasn't been undone, on both drives.
+/// Returns `None` when there's nothing to undo.
+///
+/// Actions whose files are gone, e.g. deletions whose trash has since been
+/// emptied, can no longer be undone and are dropped from the history so the
+/// ones before them still can be.
+pub async fn undo_last(engine: &mut SyncEngine) -> Result<Option<HistoryEntry>> {
+    loop {
+        let row: Option<(i64, String, String)> = sqlx::query_as(
+            "SELECT id, label, inverse FROM history WHERE undone = 0 ORDER BY id DESC LIMIT 1",
+        )
+        .fetch_optional(&engine.primary_db)
+        .await?;
+        let Some((id, label, inverse)) = row else {
+            return Ok(None);
+        };
+        let inverse: Vec<Operation> = serde_json::from_str(&inverse)?;
+        if !files_exist(&inverse).await {
+            println!("\"{}\" can no longer be undone, dropping it", label);
+            sqlx::query("DELETE FROM history WHERE id = ?")
+                .bind(id)
+                .execute(&engine.primary_db)
+                .await?;
+            continue;
+        }
+        let operations = replay(engine, &inverse).await?;
+        sqlx::query("UPDATE history SET undone = 1, operations = ? WHERE id = ?")
+            .bind(serde_json::to_string(&operations)?)
+            .bind(id)
+            .execute(&engine.primary_db)
+            .await?;
+        return entry(&engine.primary_db, id).await.map(Some);
+    }
+}
+
+/// Redoes the action undone most recently. Returns `None` when there's
+/// nothing to redo.
+pub async fn redo(engine: &mut SyncEngine) -> Result<Option<HistoryEntry>> {
+    let row: Option<(i64, String)> =
+        sqlx::query_as("SELECT id, operations FROM history WHERE undone = 1 ORDER BY id LIMIT 1")
+            .fetch_optional(&engine.primary_db)
+            .await?;
+    let Some((id, operations)) = row else {
+        return Ok(None);
+    };
+    let operations: Vec<Operation> = serde_json::from_str(&operations)?;
+    let inverse = replay(engine, &operations).await?;
+    sqlx::query("UPDATE history SET undone = 0, inverse = ? WHERE id = ?")
+        .bind(serde_json::to_string(&inverse)?)
+        .bind(id)
+        .execute(&engine.primary_db)
+        .await?;
+    entry(&engine.primary_db, id).await.map(Some)
+}
+
+async fn entry(pool: &SqlitePool, id: i64) -> Result<HistoryEntry> {
+    let entry = sqlx::query_as::<_, HistoryEntry>(
+        "SELECT id, label, undone, created_at FROM history WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+    Ok(entry)
+}
+
+/// Applies `ops` to the primary drive's files and, through `engine`, to both
+/// catalogs and the backup drive. Returns the operations that reverse them,
+/// which are recomputed rather than reused since replaying can change ids.
+///
+/// Nothing changes unless every file can be moved: files already moved are
+/// put back if a later one fails or the catalog can't be updated. Once the
+/// catalog has committed the moves they stand, even if mirroring them fails.
+async fn replay(engine: &mut SyncEngine, ops: &[Operation]) -> Result<Vec<Operation>> {
+    let mut moved: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut result = Ok(());
+    for (from, to) in ops.iter().filter_map(file_move) {
+        if let Err(e) = move_file(&from, &to).await {
+            result = Err(e);
+            break;
+        }
+        moved.push((from, to));
+    }
+    if result.is_ok() {
+        // Recorded only to work out the reverse; the history entry is updated
+        // by the caller
+        engine.begin_action("replay");
+        result = engine.execute_batch(ops).await;
+    }
+    let action = engine.take_action();
+
+    if let Err(e) = &result {
+        if e.is::<AlreadyCommitted>() {
+            eprintln!("Replayed the action, but then: {}", e);
+            result = Ok(());
+        }
+    }
+    if let Err(e) = result {
+        for (from, to) in moved.into_iter().rev() {
+            if let Err(e) = tokio::fs::rename(&to, &from).await {
+                eprintln!(
+                    "Failed to restore {} to {}: {}",
+                    to.display(),
+                    from.display(),
+                    e
+                );
+            }
+        }
+        return Err(e);
+    }
+    // Drop the folders restored files were kept in, if now empty
+    for op in ops {
+        if let Operation::Restore { trash_path, .. } = op {
+            if let Some(folder) = trash_path.parent() {
+                let _ = tokio::fs::remove_dir(folder).await;
+            }
+        }
+    }
+    // Everything in the history has an inverse, and so do the inverses
+    Ok(action.map(|action| action.inverse).unwrap_or_default())
+}
+
+/// Whether every file `ops` move on the primary drive is still there.
+async fn files_exist(ops: &[Operation]) -> bool {
+    for (from, _) in ops.iter().filter_map(file_move) {
+        if !tokio::fs::try_exists(&from).await.unwrap_or(false) {
+            return false;
+        }
+    }
+    true
+}
+
+/// The file an operation moves on the primary drive, which the engine leaves
+/// to its caller.
+fn file_move(op: &Operation) -> Option<(PathBuf, PathBuf)> {
+    match op {
+        Operation::Move { from, to } => Some((from.clone(), to.clone())),
+        Operation::Rename { path, new_name } => Some((path.clone(), path.with_file_name(new_name))),
+        Operation::Trash { path, trash_path } => Some((path.clone(), trash_path.clone())),
+        Operation::Restore { trash_path, path } => Some((trash_path.clone(), path.clone())),
+        _ => None,
+    }
+}
@@ -0,0 +1,441 @@
+use crate::models::operation::Operation;
+use crate::models::photo::{modified_millis, MediaType, Photo};
+use crate::services::config::CONFIG_DIR_NAME;
+use crate::services::duplicate::DuplicateDetector;
+use crate::services::file_mirror::FileMirror;
+use crate::services::scanner::{apply_batch, ScanFailure, PROGRESS_INTERVAL, SCAN_BATCH_SIZE};
+use crate::services::stack;
+use crate::services::sync_engine::{AlreadyCommitted, SyncEngine};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+/// Where imported files go when `AppConfig::import_template` is unset.
+pub const DEFAULT_IMPORT_TEMPLATE: &str = "{year}/{month}/{date}_{original_name}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Year,
+    Month,
+    Day,
+    Date,
+    OriginalName,
+}
+
+impl Placeholder {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "year" => Some(Self::Year),
+            "month" => Some(Self::Month),
+            "day" => Some(Self::Day),
+            "date" => Some(Self::Date),
+            "original_name" => Some(Self::OriginalName),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A library-relative destination such as `{year}/{month}/{date}_{original_name}`.
+///
+/// Dates are the capture time as the camera's clock showed it: `{year}`,
+/// `{month}` and `{day}` are zero-padded numbers and `{date}` is
+/// `YYYY-MM-DD`. `{original_name}` is the source file's name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if rest[..start].contains('}') {
+                bail!("Unmatched '}}' in import template");
+            }
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow!("Unclosed '{{' in import template"))?;
+            let name = &rest[start + 1..end];
+            let placeholder = Placeholder::parse(name)
+                .ok_or_else(|| anyhow!("Unknown placeholder {{{}}} in import template", name))?;
+            parts.push(Part::Placeholder(placeholder));
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            bail!("Unmatched '}}' in import template");
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        let template = Self { parts };
+        // Every file must land inside the library
+        let sample = template.render(NaiveDateTime::default(), "IMG_0001.JPG");
+        let inside_library = sample
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if !inside_library || sample.file_name().is_none() {
+            bail!("Import template must be a relative path inside the library");
+        }
+        Ok(template)
+    }
+
+    pub fn render(&self, captured: NaiveDateTime, original_name: &str) -> PathBuf {
+        let date: NaiveDate = captured.date();
+        let rendered: String = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(text) => text.clone(),
+                Part::Placeholder(Placeholder::Year) => date.format("%Y").to_string(),
+                Part::Placeholder(Placeholder::Month) => date.format("%m").to_string(),
+                Part::Placeholder(Placeholder::Day) => date.format("%d").to_string(),
+                Part::Placeholder(Placeholder::Date) => date.format("%Y-%m-%d").to_string(),
+                Part::Placeholder(Placeholder::OriginalName) => original_name.to_string(),
+            })
+            .collect();
+        PathBuf::from(rendered)
+    }
+}
+
+/// When the photo was taken on the camera's clock, falling back to the file's
+/// modification time in the local time zone.
+pub fn capture_time(photo: &Photo) -> NaiveDateTime {
+    match (photo.date_taken, photo.date_taken_offset) {
+        (Some(taken), Some(minutes)) => (taken + Duration::minutes(minutes)).naive_utc(),
+        // Video creation times are UTC
+        (Some(taken), None) if photo.media_type == MediaType::Video => {
+            taken.with_timezone(&Local).naive_local()
+        }
+        // Stills without an offset store their wall-clock time
+        (Some(taken), None) => taken.naive_utc(),
+        (None, _) => photo
+            .file_mtime
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|mtime| mtime.with_timezone(&Local).naive_local())
+            .unwrap_or_default(),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportProgress {
+    /// Supported files found in the source so far.
+    pub discovered: u32,
+    pub imported: u32,
+    pub duplicates: u32,
+    pub failed: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportSummary {
+    /// Library paths of the copies, in the order they were made.
+    pub imported: Vec<PathBuf>,
+    /// Source files left behind because the library already has their content.
+    pub duplicates: Vec<PathBuf>,
+    pub failed: Vec<ScanFailure>,
+    pub stacked: u32,
+    pub cancelled: bool,
+}
+
+/// Catalogued SHA-256 hashes, filled in lazily for photos that were never
+/// hashed: those are only read when a source file has the same size.
+struct LibraryHashes {
+    known: HashSet<String>,
+    unhashed: HashMap<i64, Vec<(i64, String)>>,
+}
+
+impl LibraryHashes {
+    async fn load(pool: &sqlx::SqlitePool) -> Result<Self> {
+        let rows: Vec<(i64, String, Option<String>, Option<i64>)> = sqlx::query_as(
+            "SELECT id, path, file_hash, file_size FROM photos WHERE deleted_at IS NULL",
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut hashes = Self {
+            known: HashSet::new(),
+            unhashed: HashMap::new(),
+        };
+        for (id, path, hash, size) in rows {
+            match (hash, size) {
+                (Some(hash), _) => {
+                    hashes.known.insert(hash);
+                }
+                (None, Some(size)) => hashes.unhashed.entry(size).or_default().push((id, path)),
+                (None, None) => {}
+            }
+        }
+        Ok(hashes)
+    }
+
+    async fn contains(&mut self, pool: &sqlx::SqlitePool, hash: &str, size: i64) -> Result<bool> {
+        if self.known.contains(hash) {
+            return Ok(true);
+        }
+        for (id, path) in self.unhashed.remove(&size).unwrap_or_default() {
+            // Missing or unreadable library files can't be compared
+            if let Ok(library_hash) = DuplicateDetector::hash_file(Path::new(&path)).await {
+                DuplicateDetector::cache_hash(pool, id, library_hash.clone()).await?;
+                self.known.insert(library_hash);
+            }
+        }
+        Ok(self.known.contains(hash))
+    }
+}
+
+/// Copies the supported files under `source` into the library at `root`,
+/// placed by `template`, and catalogs the copies.
+///
+/// Files whose SHA-256 matches a catalogued photo, or a file imported earlier
+/// in the same run, are skipped. A copy whose destination is taken gets a
+/// numeric suffix rather than overwriting it. Copies are catalogued as
+/// `SyncPhoto` operations in batches of `SCAN_BATCH_SIZE`, so the backup drive
+/// receives them too, and are then paired into stacks like scanned files. A
+/// batch that can't be catalogued is deleted and its files reported as failed.
+///
+/// Setting `cancel` stops the import after the current file; files already
+/// copied stay in the library.
+pub async fn import_folder(
+    engine: &Mutex<Option<SyncEngine>>,
+    source: &Path,
+    root: &Path,
+    template: &PathTemplate,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&ImportProgress),
+) -> Result<ImportSummary> {
+    if !source.is_dir() {
+        bail!("{} is not a folder", source.display());
+    }
+    if source.starts_with(root) || root.starts_with(source) {
+        bail!("Can't import from a folder inside the library");
+    }
+    let (pool, formats) = engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| (engine.primary_db.clone(), engine.formats().clone()))
+        .ok_or_else(|| anyhow!("Sync engine not initialized"))?;
+    let mut library = LibraryHashes::load(&pool).await?;
+
+    let mut summary = ImportSummary::default();
+    let mut progress = ImportProgress::default();
+    let mut last_report = Instant::now();
+    let mut batch = Vec::new();
+
+    let entries = walkdir::WalkDir::new(source)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != CONFIG_DIR_NAME);
+    for entry in entries {
+        if cancel.load(Ordering::Relaxed) {
+            summary.cancelled = true;
+            break;
+        }
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            on_progress(&progress);
+            last_report = Instant::now();
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().map(Path::to_path_buf).unwrap_or_default();
+                summary.failed.push(ScanFailure {
+                    path,
+                    error: e.to_string(),
+                });
+                progress.failed += 1;
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() || !formats.is_supported(entry.path()) {
+            continue;
+        }
+        progress.discovered += 1;
+
+        let path = entry.path().to_path_buf();
+        match import_file(&pool, &mut library, &path, root, template).await {
+            Ok(Some(photo)) => {
+                summary.imported.push(PathBuf::from(&photo.path));
+                batch.push((path, photo));
+                progress.imported += 1;
+            }
+            Ok(None) => {
+                summary.duplicates.push(path);
+                progress.duplicates += 1;
+            }
+            Err(e) => {
+                summary.failed.push(ScanFailure {
+                    path,
+                    error: e.to_string(),
+                });
+                progress.failed += 1;
+            }
+        }
+
+        if batch.len() >= SCAN_BATCH_SIZE {
+            catalog_copies(
+                engine,
+                &mut batch,
+                &mut library,
+                &mut summary,
+                &mut progress,
+            )
+            .await;
+        }
+    }
+    catalog_copies(
+        engine,
+        &mut batch,
+        &mut library,
+        &mut summary,
+        &mut progress,
+    )
+    .await;
+
+    let stacks = stack::plan_stacks(&pool, &summary.imported).await?;
+    apply_batch(engine, &stacks).await?;
+    summary.stacked = stacks.len() as u32;
+
+    on_progress(&progress);
+    Ok(summary)
+}
+
+/// Catalogs the copies in `batch`, made from the source files paired with
+/// them, and empties it. If that fails before the catalog commits, the copies
+/// are deleted again and their sources reported as failures, so no
+/// uncatalogued copies are left behind. Once committed the copies are kept,
+/// as the catalog now records them.
+async fn catalog_copies(
+    engine: &Mutex<Option<SyncEngine>>,
+    batch: &mut Vec<(PathBuf, Photo)>,
+    library: &mut LibraryHashes,
+    summary: &mut ImportSummary,
+    progress: &mut ImportProgress,
+) {
+    // An upsert, as the library watcher may catalog a copy first
+    let ops: Vec<Operation> = batch
+        .iter()
+        .map(|(_, photo)| Operation::SyncPhoto {
+            photo: Box::new(photo.clone()),
+        })
+        .collect();
+    match apply_batch(engine, &ops).await {
+        Ok(()) => {}
+        Err(e) if e.is::<AlreadyCommitted>() => {
+            eprintln!("Imported files catalogued, but then: {}", e);
+        }
+        Err(e) => {
+            for (source, photo) in batch.iter() {
+                let copy = PathBuf::from(&photo.path);
+                let _ = tokio::fs::remove_file(&copy).await;
+                summary.imported.retain(|imported| imported != &copy);
+                if let Some(hash) = &photo.file_hash {
+                    library.known.remove(hash);
+                }
+                summary.failed.push(ScanFailure {
+                    path: source.clone(),
+                    error: format!("Failed to catalog {}: {}", copy.display(), e),
+                });
+                progress.imported -= 1;
+                progress.failed += 1;
+            }
+        }
+    }
+    batch.clear();
+}
+
+/// Copies one file into the library and returns its catalog entry, or `None`
+/// when the library already has its content.
+async fn import_file(
+    pool: &sqlx::SqlitePool,
+    library: &mut LibraryHashes,
+    source: &Path,
+    root: &Path,
+    template: &PathTemplate,
+) -> Result<Option<Photo>> {
+    let hash = DuplicateDetector::hash_file(source).await?;
+    let size = tokio::fs::metadata(source).await?.len() as i64;
+    if library.contains(pool, &hash, size).await? {
+        return Ok(None);
+    }
+
+    let mut photo = tokio::task::spawn_blocking({
+        let source = source.to_path_buf();
+        move || Photo::new_from_path(source)
+    })
+    .await??;
+    let original_name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("File name is not valid UTF-8"))?;
+    let target = unused_path(root.join(template.render(capture_time(&photo), original_name)));
+    copy_into_library(source, &target, &hash).await?;
+
+    let metadata = tokio::fs::metadata(&target).await?;
+    photo.path = target
+        .to_str()
+        .ok_or_else(|| anyhow!("Library path is not valid UTF-8"))?
+        .to_string();
+    photo.filename = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    photo.file_size = Some(metadata.len() as i64);
+    photo.file_mtime = modified_millis(&metadata);
+    // The copy was checked against this hash before it was moved into place
+    photo.file_hash = Some(hash.clone());
+    library.known.insert(hash);
+    Ok(Some(photo))
+}
+
+/// `path`, or the first of `name_1.ext`, `name_2.ext`, … that doesn't exist.
+fn unused_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{}_{}{}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+/// Copies `source` to `target` through `FileMirror::copy_checked`, keeping
+/// its modification time. A failed copy is removed.
+async fn copy_into_library(source: &Path, target: &Path, hash: &str) -> Result<()> {
+    FileMirror::copy_checked(source, target, hash).await?;
+    let preserved = async {
+        let modified = tokio::fs::metadata(source).await?.modified()?;
+        let file = std::fs::File::options().write(true).open(target)?;
+        file.set_modified(modified)
+    }
+    .await;
+    if let Err(e) = preserved {
+        let _ = tokio::fs::remove_file(target).await;
+        return Err(e).with_context(|| format!("Failed to copy to {}", target.display()));
+    }
+    Ok(())
+}
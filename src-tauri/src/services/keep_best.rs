@@ -0,0 +1,302 @@
+use crate::models::operation::Operation;
+use crate::models::photo::Photo;
+use crate::services::stack;
+use crate::services::sync_engine::SyncEngine;
+use crate::services::trash::{self, TrashSummary};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// What copies of a duplicate are compared on, in the order a `KeepPolicy`
+/// lists them. Later criteria only break ties left by earlier ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepCriterion {
+    /// Copies in `prefer_paths` first, copies in `avoid_paths` last.
+    Path,
+    /// Formats earlier in `formats` first.
+    Format,
+    /// More pixels first.
+    Resolution,
+    /// Larger files first, as they're usually less compressed.
+    FileSize,
+    /// Copies with more camera metadata first.
+    Metadata,
+    /// Copies in more albums and with more tags first.
+    Organization,
+}
+
+/// How the copy to keep is picked from a group of duplicates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeepPolicy {
+    pub criteria: Vec<KeepCriterion>,
+    /// Format names (see `FormatRegistry`), best first. Formats not listed
+    /// come after those that are.
+    pub formats: Vec<String>,
+    /// Copies whose path contains one of these, ignoring case, are kept over
+    /// others, e.g. the folder of an edited library.
+    pub prefer_paths: Vec<String>,
+    /// Copies whose path contains one of these, ignoring case, are removed
+    /// first, e.g. `WhatsApp`.
+    pub avoid_paths: Vec<String>,
+}
+
+impl Default for KeepPolicy {
+    fn default() -> Self {
+        Self {
+            criteria: vec![
+                KeepCriterion::Path,
+                KeepCriterion::Format,
+                KeepCriterion::Resolution,
+                KeepCriterion::FileSize,
+                KeepCriterion::Metadata,
+                KeepCriterion::Organization,
+            ],
+            formats: [
+                "Dng", "Cr2", "Nef", "Arw", "Tiff", "Png", "Heic", "WebP", "Jpeg",
+            ]
+            .map(String::from)
+            .to_vec(),
+            prefer_paths: Vec::new(),
+            avoid_paths: Vec::new(),
+        }
+    }
+}
+
+/// The copy of a duplicate to keep, and the ones to remove.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeepRecommendation {
+    pub keeper_id: i64,
+    pub remove_ids: Vec<i64>,
+    /// The criterion that put the keeper ahead of the runner-up; `None` when
+    /// they tied on all of them and the older catalog entry was picked.
+    pub decided_by: Option<KeepCriterion>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeepSummary {
+    /// Tags and album memberships moved onto keepers.
+    pub tags_merged: u32,
+    pub albums_merged: u32,
+    /// The removed copies, which are moved to the trash.
+    pub removed: TrashSummary,
+}
+
+/// A group member with what it's ranked on besides the photo itself.
+struct Candidate {
+    photo: Photo,
+    memberships: i64,
+}
+
+impl KeepPolicy {
+    /// How well `candidate` does on `criterion`; higher is better.
+    fn score(&self, criterion: KeepCriterion, candidate: &Candidate) -> i64 {
+        let photo = &candidate.photo;
+        match criterion {
+            KeepCriterion::Path => {
+                let path = photo.path.to_lowercase();
+                let contains = |patterns: &[String]| {
+                    patterns
+                        .iter()
+                        .any(|pattern| path.contains(&pattern.to_lowercase()))
+                };
+                contains(&self.prefer_paths) as i64 - contains(&self.avoid_paths) as i64
+            }
+            KeepCriterion::Format => {
+                let rank = self
+                    .formats
+                    .iter()
+                    .position(|format| format.eq_ignore_ascii_case(&photo.format))
+                    .unwrap_or(self.formats.len());
+                -(rank as i64)
+            }
+            KeepCriterion::Resolution => photo.width.unwrap_or(0) * photo.height.unwrap_or(0),
+            KeepCriterion::FileSize => photo.file_size.unwrap_or(0),
+            KeepCriterion::Metadata => [
+                photo.date_taken.is_some(),
+                photo.camera_make.is_some(),
+                photo.camera_model.is_some(),
+                photo.lens_model.is_some(),
+                photo.iso.is_some(),
+                photo.aperture.is_some(),
+                photo.exposure_time.is_some(),
+                photo.focal_length.is_some(),
+            ]
+            .into_iter()
+            .filter(|&present| present)
+            .count() as i64,
+            KeepCriterion::Organization => candidate.memberships,
+        }
+    }
+
+    /// Orders `a` before `b` when it's the better copy to keep, returning the
+    /// criterion that decided it.
+    fn compare(&self, a: &Candidate, b: &Candidate) -> (Ordering, Option<KeepCriterion>) {
+        for &criterion in &self.criteria {
+            let ordering = self.score(criterion, b).cmp(&self.score(criterion, a));
+            if ordering != Ordering::Equal {
+                return (ordering, Some(criterion));
+            }
+        }
+        (a.photo.id.cmp(&b.photo.id), None)
+    }
+}
+
+/// Picks the copy to keep from each group of photo ids according to
+/// `policy`. Photos no longer in the library are left out, and groups with
+/// fewer than two left are skipped.
+pub async fn recommend(
+    pool: &SqlitePool,
+    groups: &[Vec<i64>],
+    policy: &KeepPolicy,
+) -> Result<Vec<KeepRecommendation>> {
+    let mut recommendations = Vec::new();
+    for group in groups {
+        let mut candidates = candidates(pool, group).await?;
+        if candidates.len() < 2 {
+            continue;
+        }
+        candidates.sort_by(|a, b| policy.compare(a, b).0);
+        let (_, decided_by) = policy.compare(&candidates[0], &candidates[1]);
+        recommendations.push(KeepRecommendation {
+            keeper_id: candidates[0].photo.id,
+            remove_ids: candidates[1..]
+                .iter()
+                .map(|candidate| candidate.photo.id)
+                .collect(),
+            decided_by,
+        });
+    }
+    Ok(recommendations)
+}
+
+async fn candidates(pool: &SqlitePool, photo_ids: &[i64]) -> Result<Vec<Candidate>> {
+    if photo_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut query: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT * FROM photos WHERE deleted_at IS NULL AND id IN (");
+    let mut ids = query.separated(", ");
+    for photo_id in photo_ids {
+        ids.push_bind(photo_id);
+    }
+    query.push(")");
+    let photos: Vec<Photo> = query.build_query_as().fetch_all(pool).await?;
+
+    let mut candidates = Vec::new();
+    for photo in photos {
+        let memberships: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM photo_albums WHERE photo_id = ?)
+                  + (SELECT COUNT(*) FROM photo_tags WHERE photo_id = ?)",
+        )
+        .bind(photo.id)
+        .bind(photo.id)
+        .fetch_one(pool)
+        .await?;
+        candidates.push(Candidate { photo, memberships });
+    }
+    Ok(candidates)
+}
+
+/// Keeps each recommendation's keeper and moves the other copies to the
+/// trash of the drive at `root`, after copying their tags and album
+/// memberships onto the keeper.
+///
+/// Recommendations come from the frontend, so they're checked against the
+/// catalog first: stacks are kept or removed whole, keepers must still be in
+/// the library, removed photos must be under `root`, and nothing one
+/// recommendation keeps may be removed by another. Nothing changes if any
+/// check fails.
+pub async fn keep_best(
+    engine: &mut SyncEngine,
+    root: &Path,
+    recommendations: &[KeepRecommendation],
+) -> Result<KeepSummary> {
+    let mut kept = HashSet::new();
+    for recommendation in recommendations {
+        for photo in stack::members(&engine.primary_db, recommendation.keeper_id).await? {
+            if photo.id == recommendation.keeper_id && photo.deleted_at.is_some() {
+                return Err(anyhow!("Photo {} is in the trash", photo.id));
+            }
+            kept.insert(photo.id);
+        }
+    }
+    let mut removals = Vec::new();
+    for recommendation in recommendations {
+        let photos = stack::expand(&engine.primary_db, &recommendation.remove_ids).await?;
+        if let Some(photo) = photos.iter().find(|photo| kept.contains(&photo.id)) {
+            return Err(anyhow!("Photo {} is both kept and removed", photo.id));
+        }
+        let photos: Vec<&Photo> = photos
+            .iter()
+            .filter(|photo| photo.deleted_at.is_none())
+            .collect();
+        // Checked here as well as by the trash, which runs after the merge
+        if let Some(photo) = photos
+            .iter()
+            .find(|photo| !Path::new(&photo.path).starts_with(root))
+        {
+            return Err(anyhow!("{} is outside the library", photo.path));
+        }
+        let remove_ids: Vec<i64> = photos.iter().map(|photo| photo.id).collect();
+        removals.push((recommendation.keeper_id, remove_ids));
+    }
+
+    let mut summary = KeepSummary::default();
+    let mut ops = Vec::new();
+    let mut remove_ids = Vec::new();
+    for (keeper_id, photo_ids) in removals {
+        let mut tags = tags_of(&engine.primary_db, keeper_id).await?;
+        let mut albums = albums_of(&engine.primary_db, keeper_id).await?;
+        for photo_id in photo_ids {
+            for tag_name in tags_of(&engine.primary_db, photo_id).await? {
+                if tags.insert(tag_name.clone()) {
+                    ops.push(Operation::AddTag {
+                        photo_id: keeper_id,
+                        tag_name,
+                    });
+                    summary.tags_merged += 1;
+                }
+            }
+            for album_id in albums_of(&engine.primary_db, photo_id).await? {
+                if albums.insert(album_id) {
+                    ops.push(Operation::AddToAlbum {
+                        photo_id: keeper_id,
+                        album_id,
+                    });
+                    summary.albums_merged += 1;
+                }
+            }
+            if !remove_ids.contains(&photo_id) {
+                remove_ids.push(photo_id);
+            }
+        }
+    }
+
+    engine.execute_batch(&ops).await?;
+    summary.removed = trash::trash_photos(engine, root, &remove_ids).await?;
+    Ok(summary)
+}
+
+async fn tags_of(pool: &SqlitePool, photo_id: i64) -> Result<HashSet<String>> {
+    let tags: Vec<String> = sqlx::query_scalar(
+        "SELECT t.name FROM tags t JOIN photo_tags pt ON pt.tag_id = t.id WHERE pt.photo_id = ?",
+    )
+    .bind(photo_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(tags.into_iter().collect())
+}
+
+async fn albums_of(pool: &SqlitePool, photo_id: i64) -> Result<HashSet<i64>> {
+    let albums: Vec<i64> =
+        sqlx::query_scalar("SELECT album_id FROM photo_albums WHERE photo_id = ?")
+            .bind(photo_id)
+            .fetch_all(pool)
+            .await?;
+    Ok(albums.into_iter().collect())
+}
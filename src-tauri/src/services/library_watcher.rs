@@ -0,0 +1,473 @@
+use crate::models::operation::Operation;
+use crate::models::photo::{modified_millis, Photo};
+use crate::services::config::{self, CONFIG_DIR_NAME, CONFIG_FILE_NAME};
+use crate::services::formats::FormatRegistry;
+use crate::services::scanner;
+use crate::services::stack;
+use crate::services::sync_engine::SyncEngine;
+use crate::AppState;
+use anyhow::Result;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
+
+/// How long the primary drive has to be quiet before pending changes are applied.
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+/// How often `AppConfig` is re-read to start, stop or retarget the watcher.
+pub const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub const LIBRARY_CHANGED_EVENT: &str = "library-changed";
+
+/// A coalesced change to the primary drive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FileChange {
+    /// Something happened at `path`; whether it was created, rewritten or
+    /// removed is decided by looking at the file system when applying it.
+    Changed(PathBuf),
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+/// Collects raw watcher events until the drive goes quiet.
+#[derive(Debug, Default)]
+pub struct PendingChanges {
+    changes: Vec<FileChange>,
+    /// Rename sources waiting for their destination, keyed by tracker id.
+    rename_sources: HashMap<Option<usize>, PathBuf>,
+    needs_rescan: bool,
+    last_event: Option<Instant>,
+}
+
+impl PendingChanges {
+    pub fn record(&mut self, event: &Event) {
+        self.last_event = Some(Instant::now());
+        if event.need_rescan() {
+            self.needs_rescan = true;
+        }
+
+        let paths: Vec<&PathBuf> = event.paths.iter().filter(|p| !is_internal(p)).collect();
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                self.changes.push(FileChange::Renamed {
+                    from: paths[0].clone(),
+                    to: paths[1].clone(),
+                });
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                for path in paths {
+                    if let Some(unpaired) = self
+                        .rename_sources
+                        .insert(event.attrs.tracker(), path.clone())
+                    {
+                        self.changes.push(FileChange::Changed(unpaired));
+                    }
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                for path in paths {
+                    match self.rename_sources.remove(&event.attrs.tracker()) {
+                        Some(from) => self.changes.push(FileChange::Renamed {
+                            from,
+                            to: path.clone(),
+                        }),
+                        None => self.changes.push(FileChange::Changed(path.clone())),
+                    }
+                }
+            }
+            EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Data(_))
+            | EventKind::Modify(ModifyKind::Name(_))
+            | EventKind::Modify(ModifyKind::Any) => {
+                for path in paths {
+                    self.changes.push(FileChange::Changed(path.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_event.is_none()
+    }
+
+    /// When the pending changes may be applied, if there are any.
+    pub fn ready_at(&self) -> Option<Instant> {
+        self.last_event.map(|last| last + DEBOUNCE)
+    }
+
+    /// Whether the watcher dropped events and a full rescan is needed.
+    pub fn needs_rescan(&self) -> bool {
+        self.needs_rescan
+    }
+
+    /// Drains the pending changes in the order they happened, keeping only the
+    /// last occurrence of repeated changes to the same path. Rename sources
+    /// that never saw a destination were moved out of the library.
+    pub fn take(&mut self) -> Vec<FileChange> {
+        let mut changes = std::mem::take(&mut self.changes);
+        changes.extend(
+            self.rename_sources
+                .drain()
+                .map(|(_, from)| FileChange::Changed(from)),
+        );
+        self.needs_rescan = false;
+        self.last_event = None;
+
+        let last_seen: HashMap<&FileChange, usize> = changes
+            .iter()
+            .enumerate()
+            .map(|(index, change)| (change, index))
+            .collect();
+        let keep: Vec<bool> = changes
+            .iter()
+            .enumerate()
+            .map(|(index, change)| last_seen[change] == index)
+            .collect();
+        changes
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(change, keep)| keep.then_some(change))
+            .collect()
+    }
+}
+
+/// Whether `path` is inside a PhotoVault data directory, e.g. the thumbnail cache.
+fn is_internal(path: &Path) -> bool {
+    path.components()
+        .any(|component| component == Component::Normal(CONFIG_DIR_NAME.as_ref()))
+}
+
+/// What a set of file system changes means for the catalog.
+#[derive(Debug, Clone, Default)]
+pub struct LibraryPlan {
+    /// The `SyncEngine` operations that bring the catalog in line.
+    pub operations: Vec<Operation>,
+    /// Catalogued photos whose file has vanished. They're flagged with
+    /// `missing_since`, as a rescan would, rather than deleted: the backup
+    /// copy is what recovers a file removed by mistake. Flagged photos drop
+    /// out of the gallery and searches and are listed by
+    /// `SyncEngine::get_missing_photos` instead.
+    pub missing: Vec<PathBuf>,
+    /// Catalogued photos flagged missing whose file is back unchanged.
+    pub found: Vec<PathBuf>,
+}
+
+/// Works out what file system changes mean for the catalog.
+///
+/// Changes PhotoVault made itself (a move through `move_photos`, say) are
+/// already in the catalog by the time they're seen here and produce nothing.
+pub async fn plan_operations(
+    pool: &SqlitePool,
+    formats: &FormatRegistry,
+    changes: &[FileChange],
+) -> Result<LibraryPlan> {
+    let mut plan = LibraryPlan::default();
+    for change in changes {
+        match change {
+            FileChange::Changed(path) => plan_change(pool, formats, path, &mut plan).await?,
+            FileChange::Renamed { from, to } => {
+                if is_catalogued(pool, from).await? {
+                    if formats.is_supported(to) {
+                        plan.operations.push(Operation::Move {
+                            from: from.clone(),
+                            to: to.clone(),
+                        });
+                    } else {
+                        plan.missing.push(from.clone());
+                    }
+                    continue;
+                }
+
+                let moved = catalogued_under(pool, from).await?;
+                if moved.is_empty() {
+                    plan_change(pool, formats, to, &mut plan).await?;
+                }
+                for path in moved {
+                    let relative = path.strip_prefix(from)?;
+                    plan.operations.push(Operation::Move {
+                        to: to.join(relative),
+                        from: path,
+                    });
+                }
+            }
+        }
+    }
+
+    // A folder copied in is reported both as a folder and file by file
+    let mut added = HashSet::new();
+    plan.operations.retain(|op| match op {
+        Operation::AddPhoto { photo } | Operation::SyncPhoto { photo } => {
+            added.insert(photo.path.clone())
+        }
+        _ => true,
+    });
+    Ok(plan)
+}
+
+async fn plan_change(
+    pool: &SqlitePool,
+    formats: &FormatRegistry,
+    path: &Path,
+    plan: &mut LibraryPlan,
+) -> Result<()> {
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            // A removed folder takes its photos with it
+            plan.missing.extend(still_present_at(pool, path).await?);
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if metadata.is_dir() {
+        // A folder copied or moved in from outside the library
+        let files: Vec<PathBuf> = walkdir::WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != CONFIG_DIR_NAME)
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file() && formats.is_supported(entry.path()))
+            .map(|entry| entry.into_path())
+            .collect();
+        for file in files {
+            if !is_catalogued(pool, &file).await? {
+                push_photo(&file, false, &mut plan.operations).await;
+            }
+        }
+        return Ok(());
+    }
+
+    if !metadata.is_file() || !formats.is_supported(path) {
+        return Ok(());
+    }
+    let fingerprint: Option<(Option<i64>, Option<i64>, bool)> = sqlx::query_as(
+        "SELECT file_size, file_mtime, missing_since IS NOT NULL FROM photos WHERE path = ?",
+    )
+    .bind(path.to_str())
+    .fetch_optional(pool)
+    .await?;
+    match fingerprint {
+        Some((size, mtime, missing))
+            if (size, mtime) == (Some(metadata.len() as i64), modified_millis(&metadata)) =>
+        {
+            if missing {
+                plan.found.push(path.to_path_buf());
+            }
+        }
+        // Syncing the photo clears `missing_since` too
+        Some(_) => push_photo(path, true, &mut plan.operations).await,
+        None => push_photo(path, false, &mut plan.operations).await,
+    }
+    Ok(())
+}
+
+/// Reads `path` and queues it for cataloguing. Unreadable files (usually
+/// ones still being written) are logged and picked up by their next event.
+async fn push_photo(path: &Path, catalogued: bool, ops: &mut Vec<Operation>) {
+    let read = tokio::task::spawn_blocking({
+        let path = path.to_path_buf();
+        move || Photo::new_from_path(path)
+    })
+    .await;
+    match read {
+        Ok(Ok(photo)) => {
+            let photo = Box::new(photo);
+            ops.push(if catalogued {
+                Operation::SyncPhoto { photo }
+            } else {
+                Operation::AddPhoto { photo }
+            });
+        }
+        Ok(Err(e)) => eprintln!("Failed to read {}: {}", path.display(), e),
+        Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
+    }
+}
+
+async fn is_catalogued(pool: &SqlitePool, path: &Path) -> Result<bool> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM photos WHERE path = ?")
+        .bind(path.to_str())
+        .fetch_one(pool)
+        .await?;
+    Ok(count > 0)
+}
+
+/// Catalogued photos inside the directory `dir`.
+async fn catalogued_under(pool: &SqlitePool, dir: &Path) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}{}", dir.display(), std::path::MAIN_SEPARATOR);
+    let paths: Vec<String> =
+        sqlx::query_scalar("SELECT path FROM photos WHERE substr(path, 1, length(?1)) = ?1")
+            .bind(prefix)
+            .fetch_all(pool)
+            .await?;
+    Ok(paths.into_iter().map(PathBuf::from).collect())
+}
+
+/// Catalogued photos at or inside `path` not yet flagged missing.
+async fn still_present_at(pool: &SqlitePool, path: &Path) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}{}", path.display(), std::path::MAIN_SEPARATOR);
+    let paths: Vec<String> = sqlx::query_scalar(
+        "SELECT path FROM photos WHERE (path = ?1 OR substr(path, 1, length(?2)) = ?2) AND missing_since IS NULL",
+    )
+    .bind(path.to_str())
+    .bind(prefix)
+    .fetch_all(pool)
+    .await?;
+    Ok(paths.into_iter().map(PathBuf::from).collect())
+}
+
+struct ActiveWatch {
+    root: PathBuf,
+    _watcher: RecommendedWatcher,
+}
+
+/// Starts a background task that watches `AppConfig::primary_drive` while
+/// `AppConfig::watch_primary_drive` is set, applying changes through
+/// `SyncEngine` once the drive has been quiet for `DEBOUNCE`.
+pub fn spawn(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let (sender, mut events) = mpsc::unbounded_channel();
+        let mut active: Option<ActiveWatch> = None;
+        let mut pending = PendingChanges::default();
+        let mut config_poll = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+        loop {
+            let ready_at = pending.ready_at();
+            tokio::select! {
+                _ = config_poll.tick() => {
+                    if let Err(e) = update_watch(&mut active, &sender).await {
+                        eprintln!("Library watcher error: {}", e);
+                    }
+                }
+                Some(event) = events.recv() => match event {
+                    Ok(event) => pending.record(&event),
+                    Err(e) => eprintln!("Library watcher error: {}", e),
+                },
+                _ = tokio::time::sleep_until(ready_at.unwrap_or_else(Instant::now)), if ready_at.is_some() => {
+                    let Some(root) = active.as_ref().map(|active| active.root.clone()) else {
+                        pending.take();
+                        continue;
+                    };
+                    if let Err(e) = apply(&app, &root, &mut pending).await {
+                        eprintln!("Failed to apply library changes: {}", e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Starts, stops or retargets the watcher to match the current config.
+async fn update_watch(
+    active: &mut Option<ActiveWatch>,
+    sender: &mpsc::UnboundedSender<notify::Result<Event>>,
+) -> Result<()> {
+    let config_path = config::get_app_config_dir()?.join(CONFIG_FILE_NAME);
+    let config = config::load_config_from_path(&config_path).await?;
+    let root = config
+        .primary_drive
+        .filter(|root| config.watch_primary_drive && root.is_dir());
+
+    if active.as_ref().map(|active| &active.root) == root.as_ref() {
+        return Ok(());
+    }
+    *active = None;
+    if let Some(root) = root {
+        let sender = sender.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        println!("Watching {} for changes", root.display());
+        *active = Some(ActiveWatch {
+            root,
+            _watcher: watcher,
+        });
+    }
+    Ok(())
+}
+
+async fn apply(app: &AppHandle, root: &Path, pending: &mut PendingChanges) -> Result<()> {
+    let state = app.state::<AppState>();
+    let needs_rescan = pending.needs_rescan();
+    let changes = pending.take();
+
+    if needs_rescan {
+        eprintln!(
+            "Library watcher missed events, rescanning {}",
+            root.display()
+        );
+        scanner::scan_library(&state.sync_engine, root, &AtomicBool::new(false), |_| {}).await?;
+        app.emit(LIBRARY_CHANGED_EVENT, ())?;
+        return Ok(());
+    }
+
+    if apply_changes(&state.sync_engine, &changes).await? {
+        app.emit(LIBRARY_CHANGED_EVENT, ())?;
+    }
+    Ok(())
+}
+
+/// Brings the catalog in line with `changes`, returning whether anything in
+/// it changed. Operations that fail are logged and the rest still applied.
+pub async fn apply_changes(
+    engine: &Mutex<Option<SyncEngine>>,
+    changes: &[FileChange],
+) -> Result<bool> {
+    let Some((pool, formats)) = engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| (engine.primary_db.clone(), engine.formats().clone()))
+    else {
+        return Ok(false);
+    };
+    // Files are read before taking the engine lock
+    let plan = plan_operations(&pool, &formats, changes).await?;
+
+    let mut tx = pool.begin().await?;
+    for path in &plan.missing {
+        scanner::set_missing(&mut tx, &path.to_string_lossy(), true).await?;
+    }
+    for path in &plan.found {
+        scanner::set_missing(&mut tx, &path.to_string_lossy(), false).await?;
+    }
+    tx.commit().await?;
+    if plan.operations.is_empty() {
+        return Ok(!plan.missing.is_empty() || !plan.found.is_empty());
+    }
+
+    let mut sync_engine = engine.lock().await;
+    let Some(sync_engine) = sync_engine.as_mut() else {
+        return Ok(false);
+    };
+    for op in &plan.operations {
+        if let Err(e) = sync_engine.execute_operation(op).await {
+            eprintln!("Failed to apply library change {:?}: {}", op, e);
+        }
+    }
+
+    let added: Vec<PathBuf> = plan
+        .operations
+        .iter()
+        .filter_map(|op| match op {
+            Operation::AddPhoto { photo } => Some(PathBuf::from(&photo.path)),
+            _ => None,
+        })
+        .collect();
+    for op in stack::plan_stacks(&pool, &added).await? {
+        if let Err(e) = sync_engine.execute_operation(&op).await {
+            eprintln!("Failed to stack {:?}: {}", op, e);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,287 @@
+use crate::models::photo::Photo;
+use crate::services::formats::{self, NoPreview};
+use crate::services::thumbnail::{ThumbnailCache, THUMBNAIL_SIZES};
+use anyhow::Result;
+use sqlx::SqlitePool;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tauri::http::{header, Method, Request, Response, StatusCode, Uri};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// URI scheme the webview uses to load photos, e.g. `photovault://thumb/42/256`
+/// or `photovault://original/42`.
+pub const SCHEME: &str = "photovault";
+
+/// Responses are revalidated against their ETag on every use, so a photo that
+/// is edited in place never shows a stale image.
+const CACHE_CONTROL: &str = "no-cache";
+
+/// The most bytes read for one response. Longer ranges are cut short with a
+/// `206`, and players request the rest as they need it.
+pub const MAX_RANGE_LEN: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaRequest {
+    Thumbnail { photo_id: i64, size: u32 },
+    Original { photo_id: i64 },
+}
+
+impl MediaRequest {
+    /// Parses `thumb/<id>/<size>` or `original/<id>` out of a request URI.
+    ///
+    /// Besides `photovault://thumb/...`, this accepts the
+    /// `photovault://localhost/...` and `http://photovault.localhost/...` forms
+    /// produced by `convertFileSrc`, including a percent-encoded path.
+    pub fn parse(uri: &Uri) -> Option<Self> {
+        let path = percent_encoding::percent_decode_str(uri.path())
+            .decode_utf8()
+            .ok()?;
+        let mut segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if let Some(host) = uri.host() {
+            if host != "localhost" && host != format!("{}.localhost", SCHEME) {
+                segments.insert(0, host);
+            }
+        }
+
+        match segments.as_slice() {
+            ["thumb", id, size] => Some(Self::Thumbnail {
+                photo_id: id.parse().ok()?,
+                size: size.parse().ok()?,
+            }),
+            ["original", id] => Some(Self::Original {
+                photo_id: id.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An inclusive byte range resolved against a file's length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    Satisfiable { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+/// Resolves a `Range` header against a file of `len` bytes.
+///
+/// Returns `None` when the header should be ignored and the whole file served:
+/// malformed headers, non-byte units and multi-range requests.
+pub fn parse_range(header: &str, len: u64) -> Option<ByteRange> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(ByteRange::Unsatisfiable);
+        }
+        return Some(ByteRange::Satisfiable {
+            start: len.saturating_sub(suffix),
+            end: len - 1,
+        });
+    }
+
+    let start: u64 = start.parse().ok()?;
+    let end = match end {
+        "" => None,
+        end => Some(end.parse::<u64>().ok()?),
+    };
+    if end.is_some_and(|end| end < start) {
+        return None;
+    }
+    if start >= len {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Satisfiable {
+        start,
+        end: end.map_or(len - 1, |end| end.min(len - 1)),
+    })
+}
+
+pub fn empty_response(status: StatusCode) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .body(Vec::new())
+        .expect("a status-only response is always valid")
+}
+
+/// Serves a `photovault://` request from the primary catalog. Thumbnails
+/// missing from the cache are generated on demand.
+pub async fn respond(
+    pool: &SqlitePool,
+    thumbnails: Option<&ThumbnailCache>,
+    request: &Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    match serve(pool, thumbnails, request).await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Failed to serve {}: {}", request.uri(), e);
+            empty_response(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+struct MediaFile {
+    path: PathBuf,
+    content_type: &'static str,
+}
+
+async fn serve(
+    pool: &SqlitePool,
+    thumbnails: Option<&ThumbnailCache>,
+    request: &Request<Vec<u8>>,
+) -> Result<Response<Vec<u8>>> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return Ok(empty_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let Some(media) = MediaRequest::parse(request.uri()) else {
+        return Ok(empty_response(StatusCode::BAD_REQUEST));
+    };
+    let file = match resolve(pool, thumbnails, media).await {
+        Ok(Some(file)) => file,
+        Ok(None) => return Ok(empty_response(StatusCode::NOT_FOUND)),
+        // The webview shows its own placeholder for images it can't load
+        Err(e) if e.is::<NoPreview>() => {
+            return Ok(empty_response(StatusCode::UNSUPPORTED_MEDIA_TYPE))
+        }
+        Err(e) => return Err(e),
+    };
+    let metadata = match tokio::fs::metadata(&file.path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(empty_response(StatusCode::NOT_FOUND))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let len = metadata.len();
+    // The catalog hash isn't refreshed until the next scan, so an edited file
+    // is only noticed through its modification time and size
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    let etag = format!("\"{}-{}\"", modified, len);
+
+    let response = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, CACHE_CONTROL)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    let if_none_match = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag)) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).body(Vec::new())?);
+    }
+
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_range(value, len))
+        .or_else(|| {
+            // Videos are fetched in chunks rather than read whole
+            (file.content_type.starts_with("video/") && len > MAX_RANGE_LEN).then_some(
+                ByteRange::Satisfiable {
+                    start: 0,
+                    end: len - 1,
+                },
+            )
+        });
+    let (status, start, end, response) = match range {
+        Some(ByteRange::Unsatisfiable) => {
+            return Ok(response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Vec::new())?);
+        }
+        Some(ByteRange::Satisfiable { start, end }) => {
+            let end = end.min(start + MAX_RANGE_LEN - 1);
+            (
+                StatusCode::PARTIAL_CONTENT,
+                start,
+                end,
+                response.header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, len),
+                ),
+            )
+        }
+        None => (StatusCode::OK, 0, len.saturating_sub(1), response),
+    };
+    let content_length = if len == 0 { 0 } else { end - start + 1 };
+
+    let body = if request.method() == Method::HEAD || content_length == 0 {
+        Vec::new()
+    } else {
+        read_range(&file.path, start, content_length).await?
+    };
+
+    Ok(response
+        .status(status)
+        .header(header::CONTENT_TYPE, file.content_type)
+        .header(header::CONTENT_LENGTH, content_length)
+        .body(body)?)
+}
+
+async fn resolve(
+    pool: &SqlitePool,
+    thumbnails: Option<&ThumbnailCache>,
+    media: MediaRequest,
+) -> Result<Option<MediaFile>> {
+    let photo_id = match media {
+        MediaRequest::Thumbnail { photo_id, .. } | MediaRequest::Original { photo_id } => photo_id,
+    };
+    let Some(photo) = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE id = ?")
+        .bind(photo_id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    match media {
+        MediaRequest::Thumbnail { size, .. } => {
+            let Some(cache) = thumbnails.filter(|_| THUMBNAIL_SIZES.contains(&size)) else {
+                return Ok(None);
+            };
+            let path = match cache.get(pool, photo.id, size).await? {
+                Some(path) => Some(path),
+                None => {
+                    cache.generate(pool, &photo).await?;
+                    cache.get(pool, photo.id, size).await?
+                }
+            };
+            Ok(path.map(|path| MediaFile {
+                path,
+                content_type: "image/jpeg",
+            }))
+        }
+        MediaRequest::Original { .. } => {
+            let path = PathBuf::from(&photo.path);
+            Ok(Some(MediaFile {
+                content_type: content_type(&path),
+                path,
+            }))
+        }
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    formats::handler_for(path).map_or("application/octet-stream", |handler| handler.mime_type())
+}
+
+async fn read_range(path: &Path, start: u64, len: u64) -> Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut body = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut body).await?;
+    Ok(body)
+}
@@ -1,7 +1,26 @@
 pub mod album;
+pub mod backup_monitor;
+pub mod batch_rename;
 pub mod config;
+pub mod cross_drive;
 pub mod duplicate;
+pub mod exif;
+pub mod file_mirror;
 pub mod filter;
+pub mod formats;
+pub mod history;
+pub mod importer;
+pub mod keep_best;
+pub mod library_watcher;
+pub mod media_protocol;
+pub mod organizer;
+pub mod query;
+pub mod reconcile;
+pub mod scanner;
+pub mod search;
+pub mod stack;
 pub mod sync_engine;
 pub mod sync_status;
 pub mod tag;
+pub mod thumbnail;
+pub mod trash;
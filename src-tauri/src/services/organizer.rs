@@ -0,0 +1,325 @@
+use crate::models::operation::Operation;
+use crate::models::photo::Photo;
+use crate::services::importer::{capture_time, PathTemplate};
+use crate::services::scanner::{ScanFailure, SCAN_BATCH_SIZE};
+use crate::services::sync_engine::{AlreadyCommitted, SyncEngine};
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Which photos an `OrganizeRule` applies to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleMatch {
+    /// Every photo, usually as the last rule.
+    All,
+    /// Photos taken between `from` and `to`, both inclusive.
+    Taken {
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
+    /// Photos whose camera make or model contains `camera`, ignoring case.
+    Camera {
+        camera: String,
+    },
+    Tag {
+        name: String,
+    },
+    Album {
+        album_id: i64,
+    },
+    /// Photos in the format named `format` (see `FormatRegistry`), e.g. `Png`.
+    Format {
+        format: String,
+    },
+}
+
+/// Sends the photos `matches` selects to `folder`, a library-relative
+/// `PathTemplate` such as `Screenshots` or `{year}/{month}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrganizeRule {
+    pub matches: RuleMatch,
+    pub folder: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedMove {
+    pub photo_id: i64,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Index of the rule that placed the photo.
+    pub rule: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrganizePlan {
+    pub moves: Vec<PlannedMove>,
+    /// Photos already where their rule puts them.
+    pub unchanged: u32,
+    /// Photos no rule applies to, which stay where they are.
+    pub unmatched: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrganizeSummary {
+    pub moved: u32,
+    pub failed: Vec<ScanFailure>,
+}
+
+/// What rules are matched against besides the photo itself.
+struct Memberships {
+    tags: HashMap<i64, HashSet<String>>,
+    albums: HashMap<i64, HashSet<i64>>,
+}
+
+impl RuleMatch {
+    fn matches(&self, photo: &Photo, memberships: &Memberships) -> bool {
+        match self {
+            RuleMatch::All => true,
+            RuleMatch::Taken { from, to } => {
+                let taken = capture_time(photo).date();
+                from.is_none_or(|from| taken >= from) && to.is_none_or(|to| taken <= to)
+            }
+            RuleMatch::Camera { camera } => {
+                let camera = camera.to_lowercase();
+                [&photo.camera_make, &photo.camera_model]
+                    .into_iter()
+                    .flatten()
+                    .any(|value| value.to_lowercase().contains(&camera))
+            }
+            RuleMatch::Tag { name } => memberships
+                .tags
+                .get(&photo.id)
+                .is_some_and(|tags| tags.contains(name)),
+            RuleMatch::Album { album_id } => memberships
+                .albums
+                .get(&photo.id)
+                .is_some_and(|albums| albums.contains(album_id)),
+            RuleMatch::Format { format } => photo.format.eq_ignore_ascii_case(format),
+        }
+    }
+}
+
+/// Works out where the rules put each photo under `root` without moving
+/// anything.
+///
+/// The first rule that matches a photo decides its folder; photos no rule
+/// matches stay put. A stack is placed by its representative and moves as a
+/// whole. A name that's already taken, on disk or by an earlier move in the
+/// plan, gets a numeric suffix, the same one for every file in a stack.
+pub async fn plan_organize(
+    pool: &SqlitePool,
+    root: &Path,
+    rules: &[OrganizeRule],
+) -> Result<OrganizePlan> {
+    let templates = rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            let folder = rule.folder.trim_end_matches(['/', '\\']);
+            PathTemplate::parse(&format!("{}/{{original_name}}", folder))
+                .with_context(|| format!("Rule {}", index + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let photos: Vec<Photo> = sqlx::query_as(
+        "SELECT * FROM photos WHERE missing_since IS NULL AND deleted_at IS NULL ORDER BY path",
+    )
+    .fetch_all(pool)
+    .await?;
+    let memberships = Memberships::load(pool).await?;
+    let representatives: HashMap<i64, i64> =
+        sqlx::query_as::<_, (i64, Option<i64>)>("SELECT id, representative_id FROM stacks")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .filter_map(|(stack_id, representative)| Some((stack_id, representative?)))
+            .collect();
+
+    // Each unit moves together, its representative first
+    let mut units: Vec<Vec<Photo>> = Vec::new();
+    let mut stacks: HashMap<i64, usize> = HashMap::new();
+    for photo in photos {
+        if !Path::new(&photo.path).starts_with(root) {
+            continue;
+        }
+        let Some(stack_id) = photo.stack_id else {
+            units.push(vec![photo]);
+            continue;
+        };
+        let index = *stacks.entry(stack_id).or_insert_with(|| {
+            units.push(Vec::new());
+            units.len() - 1
+        });
+        if representatives.get(&stack_id) == Some(&photo.id) {
+            units[index].insert(0, photo);
+        } else {
+            units[index].push(photo);
+        }
+    }
+
+    let mut plan = OrganizePlan::default();
+    let mut reserved = HashSet::new();
+    for unit in units {
+        let lead = &unit[0];
+        let Some(rule) = rules
+            .iter()
+            .position(|rule| rule.matches.matches(lead, &memberships))
+        else {
+            plan.unmatched += unit.len() as u32;
+            continue;
+        };
+        let target = root.join(templates[rule].render(capture_time(lead), &lead.filename));
+        let folder = target.parent().unwrap_or(root);
+
+        let sources: Vec<PathBuf> = unit
+            .iter()
+            .map(|photo| PathBuf::from(&photo.path))
+            .collect();
+        let targets = (0..)
+            .map(|n| {
+                unit.iter()
+                    .map(|photo| suffixed(&folder.join(&photo.filename), n))
+                    .collect::<Vec<_>>()
+            })
+            .find(|targets| {
+                targets
+                    .iter()
+                    .zip(&sources)
+                    .all(|(to, from)| to == from || (!to.exists() && !reserved.contains(to)))
+            })
+            .unwrap();
+
+        for ((photo, from), to) in unit.iter().zip(sources).zip(targets) {
+            if to == from {
+                plan.unchanged += 1;
+                continue;
+            }
+            reserved.insert(to.clone());
+            plan.moves.push(PlannedMove {
+                photo_id: photo.id,
+                from,
+                to,
+                rule,
+            });
+        }
+    }
+    Ok(plan)
+}
+
+impl Memberships {
+    async fn load(pool: &SqlitePool) -> Result<Self> {
+        let mut memberships = Self {
+            tags: HashMap::new(),
+            albums: HashMap::new(),
+        };
+        let tags: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT pt.photo_id, t.name FROM photo_tags pt JOIN tags t ON t.id = pt.tag_id",
+        )
+        .fetch_all(pool)
+        .await?;
+        for (photo_id, name) in tags {
+            memberships.tags.entry(photo_id).or_default().insert(name);
+        }
+        let albums: Vec<(i64, i64)> = sqlx::query_as("SELECT photo_id, album_id FROM photo_albums")
+            .fetch_all(pool)
+            .await?;
+        for (photo_id, album_id) in albums {
+            memberships
+                .albums
+                .entry(photo_id)
+                .or_default()
+                .insert(album_id);
+        }
+        Ok(memberships)
+    }
+}
+
+/// `path` with `_n` after its stem, or `path` itself for zero.
+fn suffixed(path: &Path, n: u32) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{}_{}.{}", stem, n, extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{}_{}", stem, n)),
+    }
+}
+
+/// Moves the files in `plan`, creating folders as needed, and catalogs the
+/// moves as `Operation::Move`s so the backup drive is rearranged the same way.
+///
+/// Moves are applied in batches of `SCAN_BATCH_SIZE`. A file that can't be
+/// moved, say because something took its target since the plan was made, is
+/// reported and skipped. If cataloguing a batch fails, the error is returned
+/// and earlier batches stay organized. That batch's files are moved back
+/// unless the catalog had already committed their moves.
+pub async fn apply_organize(
+    engine: &mut SyncEngine,
+    plan: &OrganizePlan,
+) -> Result<OrganizeSummary> {
+    let mut summary = OrganizeSummary::default();
+    for batch in plan.moves.chunks(SCAN_BATCH_SIZE) {
+        let mut moved = Vec::new();
+        for planned in batch {
+            match move_file(&planned.from, &planned.to).await {
+                Ok(()) => moved.push(planned),
+                Err(e) => summary.failed.push(ScanFailure {
+                    path: planned.from.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        let ops: Vec<Operation> = moved
+            .iter()
+            .map(|planned| Operation::Move {
+                from: planned.from.clone(),
+                to: planned.to.clone(),
+            })
+            .collect();
+        if let Err(e) = engine.execute_batch(&ops).await {
+            // Once committed, the catalog already points at the new paths
+            if !e.is::<AlreadyCommitted>() {
+                for planned in moved.into_iter().rev() {
+                    if let Err(e) = move_file(&planned.to, &planned.from).await {
+                        eprintln!(
+                            "Failed to restore {} to {}: {}",
+                            planned.to.display(),
+                            planned.from.display(),
+                            e
+                        );
+                    }
+                }
+            }
+            return Err(e);
+        }
+        summary.moved += moved.len() as u32;
+    }
+    Ok(summary)
+}
+
+/// Moves `from` to `to`, creating `to`'s folder, but never over an existing file.
+pub(crate) async fn move_file(from: &Path, to: &Path) -> Result<()> {
+    // `rename` replaces existing files on Unix
+    if tokio::fs::try_exists(to).await? {
+        return Err(anyhow!("{} already exists", to.display()));
+    }
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    tokio::fs::rename(from, to)
+        .await
+        .with_context(|| format!("Failed to move {}", from.display()))
+}
@@ -0,0 +1,676 @@
+use crate::models::photo::{MediaType, Photo};
+use crate::services::search;
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+/// The fields a query can filter on.
+const FIELDS: [&str; 12] = [
+    "tag", "album", "camera", "lens", "format", "type", "path", "filename", "date", "width",
+    "height", "iso",
+];
+
+/// Fields that can be compared with `<`, `>=` and the like, not just `:`.
+const ORDERED_FIELDS: [&str; 4] = ["date", "width", "height", "iso"];
+
+/// A problem with a query and the characters of it that caused it, counted
+/// from 0 with `end` exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, thiserror::Error)]
+#[error("{message} (at {start}..{end})")]
+pub struct ParseError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// A parsed query. Terms next to each other must all match, `OR` matches
+/// either side and binds looser than that, `-` or `NOT` inverts a term, and
+/// parentheses group.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// Words looked up in the full-text index, e.g. `beach`.
+    Text(String),
+    /// `tag:beach`, ignoring case.
+    Tag(String),
+    /// `album:Trips`, ignoring case.
+    Album(String),
+    /// `camera:x100`, part of the make or model.
+    Camera(String),
+    /// `lens:35mm`, part of the lens model.
+    Lens(String),
+    /// `format:dng`, see `FormatRegistry`.
+    Format(String),
+    /// `type:video`.
+    MediaType(MediaType),
+    /// `path:2023/`, part of the full path.
+    Path(String),
+    /// `filename:IMG_`, part of the file name.
+    Filename(String),
+    /// `date:2023-06..2023-08`, `date>=2023`; capture times from `from` up
+    /// to but not including `until`.
+    Date {
+        from: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    /// `width>=4000`, `iso:100..400`; values from `min` to `max` inclusive.
+    Number {
+        column: &'static str,
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Colon,
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    Text(String),
+    Field {
+        name: String,
+        op: Op,
+        /// Where the operator starts and ends.
+        op_start: usize,
+        op_end: usize,
+        value: String,
+        /// Where the value starts, after any opening quote.
+        value_start: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+fn error(message: impl Into<String>, start: usize, end: usize) -> ParseError {
+    ParseError {
+        message: message.into(),
+        start,
+        end,
+    }
+}
+
+/// Parses a query such as
+/// `tag:beach -tag:work camera:"X100V" date:2023-06..2023-08 width>=4000 (album:Trips OR album:Family)`.
+pub fn parse(input: &str) -> Result<Expr, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let tokens = tokenize(&chars)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+    };
+    let expr = parser.or(None)?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(error("Unmatched ')'", token.start, token.end)),
+    }
+}
+
+fn tokenize(chars: &[char]) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let kind = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::Open
+            }
+            ')' => {
+                i += 1;
+                TokenKind::Close
+            }
+            '-' => {
+                i += 1;
+                TokenKind::Not
+            }
+            '"' => {
+                let (text, end) = quoted(chars, i)?;
+                i = end;
+                TokenKind::Text(text)
+            }
+            _ => {
+                let (kind, end) = word(chars, i)?;
+                i = end;
+                kind
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: chars[start..i].iter().collect(),
+            start,
+            end: i,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Reads the quoted string starting at `start`, returning its contents and
+/// where it ends.
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize), ParseError> {
+    match chars[start + 1..].iter().position(|&c| c == '"') {
+        Some(length) => {
+            let end = start + 1 + length;
+            Ok((chars[start + 1..end].iter().collect(), end + 1))
+        }
+        None => Err(error("Unclosed quote", start, chars.len())),
+    }
+}
+
+/// Reads a bare word or a `field:value` term starting at `start`.
+fn word(chars: &[char], start: usize) -> Result<(TokenKind, usize), ParseError> {
+    let is_end = |c: char| c.is_whitespace() || c == '(' || c == ')';
+    let mut i = start;
+    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+        i += 1;
+    }
+    let op = match chars.get(i..) {
+        _ if i == start || !chars[start].is_alphabetic() => None,
+        Some(['>', '=', ..]) => Some((Op::Ge, 2)),
+        Some(['<', '=', ..]) => Some((Op::Le, 2)),
+        Some(['>', ..]) => Some((Op::Gt, 1)),
+        Some(['<', ..]) => Some((Op::Lt, 1)),
+        Some(['=', ..]) => Some((Op::Eq, 1)),
+        Some([':', ..]) => Some((Op::Colon, 1)),
+        _ => None,
+    };
+    let Some((op, op_length)) = op else {
+        while i < chars.len() && !is_end(chars[i]) {
+            i += 1;
+        }
+        let text: String = chars[start..i].iter().collect();
+        let kind = match text.as_str() {
+            "OR" => TokenKind::Or,
+            "AND" => TokenKind::And,
+            "NOT" => TokenKind::Not,
+            _ => TokenKind::Text(text),
+        };
+        return Ok((kind, i));
+    };
+
+    let name = chars[start..i].iter().collect();
+    let op_start = i;
+    let value_start = i + op_length;
+    let (value, value_start, end) = if chars.get(value_start) == Some(&'"') {
+        let (value, end) = quoted(chars, value_start)?;
+        (value, value_start + 1, end)
+    } else {
+        let mut end = value_start;
+        while end < chars.len() && !is_end(chars[end]) {
+            end += 1;
+        }
+        (chars[value_start..end].iter().collect(), value_start, end)
+    };
+    Ok((
+        TokenKind::Field {
+            name,
+            op,
+            op_start,
+            op_end: op_start + op_length,
+            value,
+            value_start,
+        },
+        end,
+    ))
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_is(&self, kind: &TokenKind) -> bool {
+        self.peek().is_some_and(|token| token.kind == *kind)
+    }
+
+    /// Terms separated by `OR`. `after` is the token before, for errors.
+    fn or(&mut self, after: Option<&Token>) -> Result<Expr, ParseError> {
+        let mut alternatives = vec![self.and(after)?];
+        while self.next_is(&TokenKind::Or) {
+            let or = self.tokens[self.pos].clone();
+            self.pos += 1;
+            alternatives.push(self.and(Some(&or))?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.remove(0),
+            _ => Expr::Or(alternatives),
+        })
+    }
+
+    /// Terms next to each other, optionally separated by `AND`.
+    fn and(&mut self, after: Option<&Token>) -> Result<Expr, ParseError> {
+        let mut terms = vec![self.unary(after)?];
+        loop {
+            match self.peek().map(|token| &token.kind) {
+                None | Some(TokenKind::Close) | Some(TokenKind::Or) => break,
+                Some(TokenKind::And) => {
+                    let and = self.tokens[self.pos].clone();
+                    self.pos += 1;
+                    terms.push(self.unary(Some(&and))?);
+                }
+                Some(_) => terms.push(self.unary(None)?),
+            }
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => Expr::And(terms),
+        })
+    }
+
+    fn unary(&mut self, after: Option<&Token>) -> Result<Expr, ParseError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(match after {
+                Some(after) => missing_term(after),
+                None => error("Nothing to search for", 0, 0),
+            });
+        };
+        match &token.kind {
+            TokenKind::Close | TokenKind::Or | TokenKind::And => Err(match after {
+                Some(after) => missing_term(after),
+                None if token.kind == TokenKind::Close => {
+                    error("Unmatched ')'", token.start, token.end)
+                }
+                None => error(
+                    format!("Expected a term before '{}'", token.text),
+                    token.start,
+                    token.end,
+                ),
+            }),
+            TokenKind::Not => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary(Some(&token))?)))
+            }
+            TokenKind::Open => {
+                self.pos += 1;
+                if let Some(close) = self.peek().filter(|next| next.kind == TokenKind::Close) {
+                    return Err(error("Empty parentheses", token.start, close.end));
+                }
+                let expr = self.or(Some(&token))?;
+                if !self.next_is(&TokenKind::Close) {
+                    return Err(error("Unclosed '('", token.start, token.end));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            TokenKind::Text(text) => {
+                self.pos += 1;
+                if search::match_expression(text).is_none() {
+                    return Err(error("Nothing to search for", token.start, token.end));
+                }
+                Ok(Expr::Term(Term::Text(text.clone())))
+            }
+            TokenKind::Field {
+                name,
+                op,
+                op_start,
+                op_end,
+                value,
+                value_start,
+            } => {
+                self.pos += 1;
+                let field = Field {
+                    name,
+                    op: *op,
+                    op_start: *op_start,
+                    op_end: *op_end,
+                    value,
+                    value_start: *value_start,
+                    token: &token,
+                };
+                Ok(Expr::Term(field.term()?))
+            }
+        }
+    }
+}
+
+fn missing_term(after: &Token) -> ParseError {
+    error(
+        format!("Expected a term after '{}'", after.text),
+        after.start,
+        after.end,
+    )
+}
+
+/// A `field:value` token being turned into a `Term`.
+struct Field<'a> {
+    name: &'a str,
+    op: Op,
+    op_start: usize,
+    op_end: usize,
+    value: &'a str,
+    value_start: usize,
+    token: &'a Token,
+}
+
+impl Field<'_> {
+    fn term(&self) -> Result<Term, ParseError> {
+        let name = self.name.to_lowercase();
+        if !FIELDS.contains(&name.as_str()) {
+            return Err(error(
+                format!(
+                    "Unknown field '{}'; expected one of {}",
+                    self.name,
+                    FIELDS.join(", ")
+                ),
+                self.token.start,
+                self.op_start,
+            ));
+        }
+        if !ORDERED_FIELDS.contains(&name.as_str()) && !matches!(self.op, Op::Colon | Op::Eq) {
+            return Err(error(
+                format!("'{}' can only be matched with ':'", self.name),
+                self.op_start,
+                self.op_end,
+            ));
+        }
+        if self.value.is_empty() {
+            return Err(error(
+                format!("Expected a value after '{}'", self.token.text),
+                self.token.start,
+                self.token.end,
+            ));
+        }
+        let value = self.value.to_string();
+        Ok(match name.as_str() {
+            "tag" => Term::Tag(value),
+            "album" => Term::Album(value),
+            "camera" => Term::Camera(value),
+            "lens" => Term::Lens(value),
+            "format" => Term::Format(value),
+            "path" => Term::Path(value),
+            "filename" => Term::Filename(value),
+            "type" => match value.to_lowercase().as_str() {
+                "photo" => Term::MediaType(MediaType::Photo),
+                "video" => Term::MediaType(MediaType::Video),
+                _ => return Err(self.value_error("Expected 'photo' or 'video'")),
+            },
+            "date" => self.date()?,
+            "width" => self.number("width")?,
+            "height" => self.number("height")?,
+            _ => self.number("iso")?,
+        })
+    }
+
+    fn value_error(&self, message: &str) -> ParseError {
+        error(
+            message,
+            self.value_start,
+            self.value_start + self.value.chars().count(),
+        )
+    }
+
+    /// Splits `a..b`, either side of which may be left out, from a single
+    /// value. Ranges are only allowed after `:` or `=`.
+    fn bounds(&self) -> Result<(Option<&str>, Option<&str>), ParseError> {
+        match self.value.split_once("..") {
+            Some(_) if !matches!(self.op, Op::Colon | Op::Eq) => {
+                Err(self.value_error("Ranges can only follow ':' or '='"))
+            }
+            Some(("", "")) => Err(self.value_error("Expected a value on one side of '..'")),
+            Some((low, high)) => Ok((
+                (!low.is_empty()).then_some(low),
+                (!high.is_empty()).then_some(high),
+            )),
+            None => Ok((Some(self.value), Some(self.value))),
+        }
+    }
+
+    fn date(&self) -> Result<Term, ParseError> {
+        let period = |value: &str| {
+            period(value)
+                .ok_or_else(|| self.value_error("Expected a date like 2023, 2023-06 or 2023-06-15"))
+        };
+        let (low, high) = self.bounds()?;
+        let low = low.map(period).transpose()?;
+        let high = high.map(period).transpose()?;
+        let (from, until) = match self.op {
+            Op::Colon | Op::Eq => (low.map(|(start, _)| start), high.map(|(_, end)| end)),
+            Op::Ge => (low.map(|(start, _)| start), None),
+            Op::Gt => (low.map(|(_, end)| end), None),
+            Op::Le => (None, high.map(|(_, end)| end)),
+            Op::Lt => (None, high.map(|(start, _)| start)),
+        };
+        if let (Some(from), Some(until)) = (from, until) {
+            if from >= until {
+                return Err(self.value_error("The range ends before it starts"));
+            }
+        }
+        Ok(Term::Date { from, until })
+    }
+
+    fn number(&self, column: &'static str) -> Result<Term, ParseError> {
+        let number = |value: &str| {
+            value
+                .parse::<i64>()
+                .map_err(|_| self.value_error("Expected a whole number"))
+        };
+        let (low, high) = self.bounds()?;
+        let low = low.map(number).transpose()?;
+        let high = high.map(number).transpose()?;
+        let out_of_range = || self.value_error("Number out of range");
+        let (min, max) = match self.op {
+            Op::Colon | Op::Eq => (low, high),
+            Op::Ge => (low, None),
+            Op::Gt => (
+                low.map(|low| low.checked_add(1).ok_or_else(out_of_range))
+                    .transpose()?,
+                None,
+            ),
+            Op::Le => (None, high),
+            Op::Lt => (
+                None,
+                high.map(|high| high.checked_sub(1).ok_or_else(out_of_range))
+                    .transpose()?,
+            ),
+        };
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(self.value_error("The range ends before it starts"));
+            }
+        }
+        Ok(Term::Number { column, min, max })
+    }
+}
+
+/// The start of the year, month or day `value` names and the start of the
+/// next one, e.g. `2023-06` is June 2023.
+fn period(value: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let number = |index: usize, digits: usize| -> Option<u32> {
+        let part = parts.get(index)?;
+        (part.len() == digits && part.chars().all(|c| c.is_ascii_digit()))
+            .then(|| part.parse().ok())
+            .flatten()
+    };
+    let year = number(0, 4)? as i32;
+    let (start, end) = match parts.len() {
+        1 => {
+            let start = NaiveDate::from_ymd_opt(year, 1, 1)?;
+            (start, start.checked_add_months(Months::new(12))?)
+        }
+        2 => {
+            let start = NaiveDate::from_ymd_opt(year, number(1, 2)?, 1)?;
+            (start, start.checked_add_months(Months::new(1))?)
+        }
+        3 => {
+            let start = NaiveDate::from_ymd_opt(year, number(1, 2)?, number(2, 2)?)?;
+            (start, start.succ_opt()?)
+        }
+        _ => return None,
+    };
+    let midnight = |date: NaiveDate| date.and_hms_opt(0, 0, 0).map(|time| time.and_utc());
+    Some((midnight(start)?, midnight(end)?))
+}
+
+/// Escapes `value` for a `LIKE ... ESCAPE '\'` pattern matching it anywhere.
+fn contains(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl Expr {
+    /// Appends the condition photos matching the query meet, with its values
+    /// bound. Every term is true or false for each photo, never NULL, so a
+    /// negated term matches exactly the photos the term doesn't, such as ones
+    /// without a width for `-width>=4000`.
+    pub fn push_condition(&self, query: &mut QueryBuilder<'_, Sqlite>) {
+        match self {
+            Expr::Term(term) => term.push_condition(query),
+            Expr::Not(expr) => {
+                query.push("NOT (");
+                expr.push_condition(query);
+                query.push(")");
+            }
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                let separator = match self {
+                    Expr::And(_) => " AND ",
+                    _ => " OR ",
+                };
+                query.push("(");
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        query.push(separator);
+                    }
+                    expr.push_condition(query);
+                }
+                query.push(")");
+            }
+        }
+    }
+}
+
+impl Term {
+    fn push_condition(&self, query: &mut QueryBuilder<'_, Sqlite>) {
+        match self {
+            Term::Text(text) => {
+                query.push("p.id IN (SELECT rowid FROM photos_search WHERE photos_search MATCH ");
+                query.push_bind(search::match_expression(text).unwrap_or_default());
+                query.push(")");
+            }
+            Term::Tag(name) => {
+                query.push(
+                    "EXISTS (SELECT 1 FROM photo_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.photo_id = p.id AND t.name = ",
+                );
+                query.push_bind(name.clone());
+                query.push(" COLLATE NOCASE)");
+            }
+            Term::Album(name) => {
+                query.push(
+                    "EXISTS (SELECT 1 FROM photo_albums pa JOIN albums a ON a.id = pa.album_id WHERE pa.photo_id = p.id AND a.name = ",
+                );
+                query.push_bind(name.clone());
+                query.push(" COLLATE NOCASE)");
+            }
+            Term::Camera(camera) => {
+                query.push(
+                    "(COALESCE(p.camera_make, '') || ' ' || COALESCE(p.camera_model, '')) LIKE ",
+                );
+                query.push_bind(contains(camera));
+                query.push(" ESCAPE '\\'");
+            }
+            Term::Lens(lens) => {
+                query.push("COALESCE(p.lens_model, '') LIKE ");
+                query.push_bind(contains(lens));
+                query.push(" ESCAPE '\\'");
+            }
+            Term::Format(format) => {
+                query.push("p.format = ");
+                query.push_bind(format.clone());
+                query.push(" COLLATE NOCASE");
+            }
+            Term::MediaType(media_type) => {
+                query.push("p.media_type = ");
+                query.push_bind(*media_type);
+            }
+            Term::Path(path) => {
+                query.push("p.path LIKE ");
+                query.push_bind(contains(path));
+                query.push(" ESCAPE '\\'");
+            }
+            Term::Filename(filename) => {
+                query.push("p.filename LIKE ");
+                query.push_bind(contains(filename));
+                query.push(" ESCAPE '\\'");
+            }
+            Term::Date { from, until } => {
+                query.push("(p.date_taken IS NOT NULL");
+                if let Some(from) = from {
+                    query.push(" AND p.date_taken >= ");
+                    query.push_bind(*from);
+                }
+                if let Some(until) = until {
+                    query.push(" AND p.date_taken < ");
+                    query.push_bind(*until);
+                }
+                query.push(")");
+            }
+            Term::Number { column, min, max } => {
+                query.push(format!("(p.{} IS NOT NULL", column));
+                if let Some(min) = min {
+                    query.push(format!(" AND p.{} >= ", column));
+                    query.push_bind(*min);
+                }
+                if let Some(max) = max {
+                    query.push(format!(" AND p.{} <= ", column));
+                    query.push_bind(*max);
+                }
+                query.push(")");
+            }
+        }
+    }
+}
+
+/// The photos in the library matching `input`, oldest first.
+pub async fn query_photos(pool: &SqlitePool, input: &str) -> Result<Vec<Photo>, QueryError> {
+    let expr = parse(input)?;
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT p.* FROM photos p WHERE p.deleted_at IS NULL AND p.missing_since IS NULL AND ",
+    );
+    expr.push_condition(&mut query);
+    query.push(" ORDER BY p.date_taken, p.id");
+    Ok(query.build_query_as().fetch_all(pool).await?)
+}
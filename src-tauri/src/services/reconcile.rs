@@ -0,0 +1,252 @@
+use crate::models::operation::Operation;
+use crate::models::photo::Photo;
+use crate::services::config::CONFIG_DIR_NAME;
+use crate::services::duplicate::DuplicateDetector;
+use crate::services::formats::FormatRegistry;
+use crate::services::sync_engine::SyncEngine;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+/// A single difference between the primary and backup drives. Every path is
+/// the photo's location on the primary drive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Discrepancy {
+    /// The file exists on the primary drive but not on the backup drive.
+    MissingOnBackup { path: PathBuf },
+    /// The backup drive holds a file the primary drive doesn't.
+    ExtraOnBackup { path: PathBuf },
+    /// Both drives hold the file but their sizes or hashes differ.
+    ContentMismatch {
+        path: PathBuf,
+        primary_size: u64,
+        backup_size: u64,
+    },
+    /// The primary catalog lists the photo but its file is gone.
+    MissingOnPrimary { path: PathBuf, on_backup: bool },
+    /// The primary drive holds a photo the primary catalog doesn't list.
+    Uncatalogued { path: PathBuf },
+    /// The primary catalog lists the photo but the backup catalog doesn't.
+    MissingFromBackupCatalog { path: PathBuf },
+    /// The backup catalog lists a photo the primary catalog doesn't.
+    ExtraInBackupCatalog { path: PathBuf },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    pub files_checked: u32,
+    pub discrepancies: Vec<Discrepancy>,
+    pub is_in_sync: bool,
+}
+
+/// Compares both catalogs and both drives' files.
+///
+/// Files are compared by size; `verify_hashes` additionally compares the
+/// SHA-256 of every file whose size matches, which reads the whole library.
+/// The engine is only locked long enough to look up the drives, so other
+/// commands keep working while they are walked and hashed.
+pub async fn reconcile(
+    engine: &Mutex<Option<SyncEngine>>,
+    verify_hashes: bool,
+) -> Result<ReconciliationReport> {
+    let (primary_db, backup_db, mirror, formats) = {
+        let engine = engine.lock().await;
+        let engine = engine
+            .as_ref()
+            .ok_or_else(|| anyhow!("Sync engine not initialized"))?;
+        let backup_db = engine
+            .backup_db()
+            .ok_or_else(|| anyhow!("Backup drive not connected"))?;
+        let mirror = engine
+            .file_mirror()
+            .ok_or_else(|| anyhow!("Backup drive not configured"))?;
+        (
+            engine.primary_db.clone(),
+            backup_db.clone(),
+            mirror.clone(),
+            engine.formats().clone(),
+        )
+    };
+
+    let primary_catalog = catalog_paths(&primary_db).await?;
+    let backup_catalog = catalog_paths(&backup_db).await?;
+    let (primary_files, backup_files) = tokio::task::spawn_blocking({
+        let mirror = mirror.clone();
+        move || {
+            (
+                list_files(mirror.primary_root(), &formats),
+                list_files(mirror.backup_root(), &formats),
+            )
+        }
+    })
+    .await?;
+
+    let mut discrepancies = Vec::new();
+
+    for (relative, primary_size) in &primary_files {
+        let path = mirror.primary_root().join(relative);
+        // Cataloguing an unknown file also copies it to the backup drive
+        if !primary_catalog.contains(&path) {
+            discrepancies.push(Discrepancy::Uncatalogued { path });
+            continue;
+        }
+        match backup_files.get(relative) {
+            None => discrepancies.push(Discrepancy::MissingOnBackup { path }),
+            Some(&backup_size) => {
+                let differs = primary_size != &backup_size
+                    || (verify_hashes
+                        && DuplicateDetector::hash_file(&path).await?
+                            != DuplicateDetector::hash_file(&mirror.backup_path(&path)?).await?);
+                if differs {
+                    discrepancies.push(Discrepancy::ContentMismatch {
+                        path,
+                        primary_size: *primary_size,
+                        backup_size,
+                    });
+                }
+            }
+        }
+    }
+
+    for relative in backup_files.keys() {
+        let path = mirror.primary_root().join(relative);
+        if !primary_files.contains_key(relative) && !primary_catalog.contains(&path) {
+            discrepancies.push(Discrepancy::ExtraOnBackup { path });
+        }
+    }
+
+    for path in &primary_catalog {
+        if let Ok(relative) = path.strip_prefix(mirror.primary_root()) {
+            if !primary_files.contains_key(relative) {
+                discrepancies.push(Discrepancy::MissingOnPrimary {
+                    path: path.clone(),
+                    on_backup: backup_files.contains_key(relative),
+                });
+                continue;
+            }
+        }
+        if !backup_catalog.contains(path) {
+            discrepancies.push(Discrepancy::MissingFromBackupCatalog { path: path.clone() });
+        }
+    }
+
+    for path in backup_catalog.difference(&primary_catalog) {
+        // A file still on the primary drive is reported as uncatalogued, and
+        // cataloguing it replaces the backup's row
+        let on_primary = path
+            .strip_prefix(mirror.primary_root())
+            .is_ok_and(|relative| primary_files.contains_key(relative));
+        if on_primary {
+            continue;
+        }
+        discrepancies.push(Discrepancy::ExtraInBackupCatalog { path: path.clone() });
+    }
+
+    Ok(ReconciliationReport {
+        files_checked: (primary_files.len() + backup_files.len()) as u32,
+        is_in_sync: discrepancies.is_empty(),
+        discrepancies,
+    })
+}
+
+/// Resolves each discrepancy through `SyncEngine` operations, treating the
+/// primary drive as the source of truth. Photos whose primary file is missing
+/// are restored from the backup copy when one exists.
+///
+/// Files are read and restored before the engine is locked; the resulting
+/// operations are then applied as one batch.
+pub async fn repair(
+    engine: &Mutex<Option<SyncEngine>>,
+    discrepancies: &[Discrepancy],
+) -> Result<()> {
+    let (primary_db, mirror) = {
+        let engine = engine.lock().await;
+        let engine = engine
+            .as_ref()
+            .ok_or_else(|| anyhow!("Sync engine not initialized"))?;
+        (engine.primary_db.clone(), engine.file_mirror().cloned())
+    };
+
+    let mut ops = Vec::with_capacity(discrepancies.len());
+    for discrepancy in discrepancies {
+        let op = match discrepancy {
+            Discrepancy::MissingOnBackup { path }
+            | Discrepancy::ContentMismatch { path, .. }
+            | Discrepancy::MissingFromBackupCatalog { path } => Operation::SyncPhoto {
+                photo: Box::new(catalog_photo(&primary_db, path).await?),
+            },
+            // The backup catalog may still list the photo, so its row is
+            // updated rather than inserted
+            Discrepancy::Uncatalogued { path } => {
+                let path = path.clone();
+                let photo =
+                    tokio::task::spawn_blocking(move || Photo::new_from_path(path)).await??;
+                Operation::SyncPhoto {
+                    photo: Box::new(photo),
+                }
+            }
+            Discrepancy::ExtraOnBackup { path } | Discrepancy::ExtraInBackupCatalog { path } => {
+                Operation::Delete { path: path.clone() }
+            }
+            Discrepancy::MissingOnPrimary {
+                path,
+                on_backup: true,
+            } => {
+                let mirror = mirror
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Backup drive not configured"))?;
+                mirror.restore(path).await?;
+                Operation::SyncPhoto {
+                    photo: Box::new(catalog_photo(&primary_db, path).await?),
+                }
+            }
+            Discrepancy::MissingOnPrimary {
+                path,
+                on_backup: false,
+            } => Operation::Delete { path: path.clone() },
+        };
+        ops.push(op);
+    }
+
+    let mut engine = engine.lock().await;
+    let engine = engine
+        .as_mut()
+        .ok_or_else(|| anyhow!("Sync engine not initialized"))?;
+    engine.execute_batch(&ops).await
+}
+
+async fn catalog_photo(pool: &SqlitePool, path: &Path) -> Result<Photo> {
+    let photo = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE path = ?")
+        .bind(path.to_str())
+        .fetch_one(pool)
+        .await?;
+    Ok(photo)
+}
+
+async fn catalog_paths(pool: &SqlitePool) -> Result<BTreeSet<PathBuf>> {
+    let paths: Vec<String> = sqlx::query_scalar("SELECT path FROM photos WHERE deleted_at IS NULL")
+        .fetch_all(pool)
+        .await?;
+    Ok(paths.into_iter().map(PathBuf::from).collect())
+}
+
+/// Lists supported photo files under `root`, keyed by their relative path,
+/// skipping PhotoVault's own `.photovault` data directory.
+pub(crate) fn list_files(root: &Path, formats: &FormatRegistry) -> BTreeMap<PathBuf, u64> {
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != CONFIG_DIR_NAME)
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| formats.is_supported(entry.path()))
+        .filter_map(|entry| {
+            let size = entry.metadata().ok()?.len();
+            let relative = entry.path().strip_prefix(root).ok()?.to_path_buf();
+            Some((relative, size))
+        })
+        .collect()
+}
@@ -0,0 +1,269 @@
+use crate::models::operation::Operation;
+use crate::models::photo::{modified_millis, Photo};
+use crate::services::config::CONFIG_DIR_NAME;
+use crate::services::stack;
+use crate::services::sync_engine::SyncEngine;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How many new or modified photos a scan applies per catalog transaction.
+pub const SCAN_BATCH_SIZE: usize = 200;
+/// Minimum time between progress callbacks.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Running totals reported while a scan is in progress.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanProgress {
+    /// Supported files found so far.
+    pub discovered: u32,
+    /// Files read because they were new or had changed.
+    pub processed: u32,
+    /// Files left alone because their fingerprint matched the catalog.
+    pub skipped: u32,
+    pub failed: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanFailure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanSummary {
+    pub added: u32,
+    pub updated: u32,
+    pub unchanged: u32,
+    /// Catalogued photos under the library root whose files are gone. Not
+    /// computed for cancelled scans, which only saw part of the tree.
+    pub missing: Vec<PathBuf>,
+    pub failed: Vec<ScanFailure>,
+    /// Stacks formed from new files, e.g. RAW+JPEG pairs and Live Photos.
+    pub stacked: u32,
+    pub cancelled: bool,
+}
+
+/// The catalog's record of a file, compared against the file system on rescans.
+#[derive(sqlx::FromRow)]
+struct Fingerprint {
+    path: String,
+    file_size: Option<i64>,
+    file_mtime: Option<i64>,
+    missing_since: Option<DateTime<Utc>>,
+}
+
+/// Brings the catalog in line with the files under `root`.
+///
+/// Files whose size and modification time match their catalog row are
+/// skipped without being opened. New and modified files are read and applied
+/// in batches of `SCAN_BATCH_SIZE`; the engine is only locked while a batch is
+/// applied, so other commands keep working during long scans. Catalogued
+/// photos whose file has vanished are flagged with `missing_since`.
+///
+/// Setting `cancel` stops the scan after the current file. Files that can't be
+/// read are reported in `ScanSummary::failed` rather than aborting the scan.
+pub async fn scan_library(
+    engine: &Mutex<Option<SyncEngine>>,
+    root: &Path,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&ScanProgress),
+) -> Result<ScanSummary> {
+    let (pool, formats) = engine
+        .lock()
+        .await
+        .as_ref()
+        .map(|engine| (engine.primary_db.clone(), engine.formats().clone()))
+        .ok_or_else(|| anyhow!("Sync engine not initialized"))?;
+
+    let known: HashMap<String, Fingerprint> = sqlx::query_as::<_, Fingerprint>(
+        "SELECT path, file_size, file_mtime, missing_since FROM photos WHERE deleted_at IS NULL",
+    )
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(|fingerprint| (fingerprint.path.clone(), fingerprint))
+    .collect();
+
+    let mut summary = ScanSummary::default();
+    let mut progress = ScanProgress::default();
+    let mut last_report = Instant::now();
+    let mut seen = HashSet::new();
+    let mut batch = Vec::new();
+    let mut backfilled = Vec::new();
+    let mut added = Vec::new();
+
+    let entries = walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != CONFIG_DIR_NAME);
+    for entry in entries {
+        if cancel.load(Ordering::Relaxed) {
+            summary.cancelled = true;
+            break;
+        }
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            on_progress(&progress);
+            last_report = Instant::now();
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().map(Path::to_path_buf).unwrap_or_default();
+                summary.failed.push(ScanFailure {
+                    path,
+                    error: e.to_string(),
+                });
+                progress.failed += 1;
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() || !formats.is_supported(entry.path()) {
+            continue;
+        }
+        progress.discovered += 1;
+
+        let path = entry.path().to_path_buf();
+        // The catalog stores paths as text, so it can't hold this one
+        let Some(path_str) = path.to_str().map(str::to_string) else {
+            summary.failed.push(ScanFailure {
+                path,
+                error: "File path is not valid UTF-8".to_string(),
+            });
+            progress.failed += 1;
+            continue;
+        };
+        // Unreadable files still count as seen so their rows aren't flagged missing
+        seen.insert(path_str.clone());
+
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                summary.failed.push(ScanFailure {
+                    path,
+                    error: e.to_string(),
+                });
+                progress.failed += 1;
+                continue;
+            }
+        };
+        let size = Some(metadata.len() as i64);
+        let mtime = modified_millis(&metadata);
+
+        let existing = known.get(&path_str);
+        match existing {
+            Some(row) if row.file_size == size && row.file_mtime == mtime => {
+                summary.unchanged += 1;
+                progress.skipped += 1;
+                continue;
+            }
+            // Catalogued before mtimes were recorded
+            Some(row) if row.file_size == size && row.file_mtime.is_none() => {
+                backfilled.push((path_str, mtime));
+                summary.unchanged += 1;
+                progress.skipped += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let read = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || Photo::new_from_path(path)
+        })
+        .await?;
+        let photo = match read {
+            Ok(photo) => Box::new(photo),
+            Err(e) => {
+                summary.failed.push(ScanFailure {
+                    path,
+                    error: e.to_string(),
+                });
+                progress.failed += 1;
+                continue;
+            }
+        };
+        if existing.is_some() {
+            batch.push(Operation::SyncPhoto { photo });
+            summary.updated += 1;
+        } else {
+            added.push(path);
+            batch.push(Operation::AddPhoto { photo });
+            summary.added += 1;
+        }
+        progress.processed += 1;
+
+        if batch.len() >= SCAN_BATCH_SIZE {
+            apply_batch(engine, &batch).await?;
+            batch.clear();
+        }
+    }
+    apply_batch(engine, &batch).await?;
+
+    let mut tx = pool.begin().await?;
+    for (path, mtime) in &backfilled {
+        sqlx::query("UPDATE photos SET file_mtime = ? WHERE path = ?")
+            .bind(mtime)
+            .bind(path)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for (path, row) in &known {
+        if seen.contains(path) {
+            if row.missing_since.is_some() {
+                set_missing(&mut tx, path, false).await?;
+            }
+        } else if !summary.cancelled && Path::new(path).starts_with(root) {
+            if row.missing_since.is_none() {
+                set_missing(&mut tx, path, true).await?;
+            }
+            summary.missing.push(PathBuf::from(path));
+        }
+    }
+    tx.commit().await?;
+
+    let stacks = stack::plan_stacks(&pool, &added).await?;
+    apply_batch(engine, &stacks).await?;
+    summary.stacked = stacks.len() as u32;
+
+    on_progress(&progress);
+    summary.missing.sort();
+    Ok(summary)
+}
+
+/// Flags the catalogued photo at `path` as having lost its file, or clears the
+/// flag once the file is back. Its row and backup copy are left alone, so a
+/// file deleted or moved away outside PhotoVault can still be recovered.
+pub(crate) async fn set_missing(
+    conn: &mut SqliteConnection,
+    path: &str,
+    missing: bool,
+) -> Result<()> {
+    let sql = if missing {
+        "UPDATE photos SET missing_since = CURRENT_TIMESTAMP WHERE path = ? AND missing_since IS NULL"
+    } else {
+        "UPDATE photos SET missing_since = NULL WHERE path = ?"
+    };
+    sqlx::query(sql).bind(path).execute(conn).await?;
+    Ok(())
+}
+
+pub(crate) async fn apply_batch(
+    engine: &Mutex<Option<SyncEngine>>,
+    batch: &[Operation],
+) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let mut engine = engine.lock().await;
+    let engine = engine
+        .as_mut()
+        .ok_or_else(|| anyhow!("Sync engine not initialized"))?;
+    engine.execute_batch(batch).await
+}
@@ -0,0 +1,170 @@
+use crate::models::photo::Photo;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+
+/// How many results a search returns when no limit is given.
+pub const DEFAULT_LIMIT: i64 = 200;
+
+/// `bm25` weights for the `photos_search` columns, in table order: filename,
+/// folder, tags, albums, caption, camera. A hit in a filename or tag counts
+/// for more than one in a folder name every photo in it shares.
+const COLUMN_WEIGHTS: &str = "10.0, 4.0, 8.0, 6.0, 5.0, 3.0";
+
+/// Marks `snippet()` puts around matched terms. Control characters can't
+/// appear in the indexed text, unlike `<b>`, so they're safe to split on.
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+/// A run of snippet text, highlighted when it matched the query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnippetPart {
+    pub text: String,
+    pub matched: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub photo: Photo,
+    /// The part of the best matching field around the matched terms.
+    pub snippet: Vec<SnippetPart>,
+    /// The `bm25` score; lower is a better match.
+    pub score: f64,
+}
+
+#[derive(FromRow)]
+struct Hit {
+    #[sqlx(flatten)]
+    photo: Photo,
+    snippet: String,
+    score: f64,
+}
+
+/// Turns what the user typed into an FTS5 query matching photos with every
+/// word as a prefix, e.g. `bea jun` finds "beach" in June's folder. Quotes
+/// keep FTS5 syntax like `OR` or `-` from being interpreted. Returns `None`
+/// when there's nothing to search for.
+pub fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Finds the photos in the library matching `query`, best matches first.
+pub async fn search(pool: &SqlitePool, query: &str, limit: i64) -> Result<Vec<SearchResult>> {
+    let Some(expression) = match_expression(query) else {
+        return Ok(Vec::new());
+    };
+    let hits: Vec<Hit> = sqlx::query_as(&format!(
+        "SELECT p.*,
+                snippet(photos_search, -1, char(2), char(3), '…', 10) AS snippet,
+                bm25(photos_search, {}) AS score
+         FROM photos_search JOIN photos p ON p.id = photos_search.rowid
+         WHERE photos_search MATCH ? AND p.deleted_at IS NULL AND p.missing_since IS NULL
+         ORDER BY score, p.id
+         LIMIT ?",
+        COLUMN_WEIGHTS
+    ))
+    .bind(expression)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(hits
+        .into_iter()
+        .map(|hit| SearchResult {
+            photo: hit.photo,
+            snippet: snippet_parts(&hit.snippet),
+            score: hit.score,
+        })
+        .collect())
+}
+
+/// Splits a `snippet()` result on its match marks.
+pub fn snippet_parts(snippet: &str) -> Vec<SnippetPart> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut matched = false;
+    for c in snippet.chars() {
+        if c == MATCH_START || c == MATCH_END {
+            if !text.is_empty() {
+                parts.push(SnippetPart {
+                    text: std::mem::take(&mut text),
+                    matched,
+                });
+            }
+            matched = c == MATCH_START;
+        } else {
+            text.push(c);
+        }
+    }
+    if !text.is_empty() {
+        parts.push(SnippetPart { text, matched });
+    }
+    parts
+}
+
+/// Brings the search index up to date for the photo at `path`, e.g. after
+/// it's added, moved or trashed.
+pub async fn reindex_path(conn: &mut SqliteConnection, path: &str) -> Result<()> {
+    let photo_id: Option<i64> = sqlx::query_scalar("SELECT id FROM photos WHERE path = ?")
+        .bind(path)
+        .fetch_optional(&mut *conn)
+        .await?;
+    match photo_id {
+        Some(photo_id) => reindex_photo(conn, photo_id).await,
+        None => Ok(()),
+    }
+}
+
+/// Brings the search index up to date for the photos in an album.
+pub async fn reindex_album(conn: &mut SqliteConnection, album_id: i64) -> Result<()> {
+    let photo_ids: Vec<i64> =
+        sqlx::query_scalar("SELECT photo_id FROM photo_albums WHERE album_id = ?")
+            .bind(album_id)
+            .fetch_all(&mut *conn)
+            .await?;
+    for photo_id in photo_ids {
+        reindex_photo(conn, photo_id).await?;
+    }
+    Ok(())
+}
+
+/// Replaces a photo's row in the search index, e.g. after its tags or
+/// albums change. Trashed photos are left without one. Keep in step with the
+/// backfill in the migration creating `photos_search`.
+pub async fn reindex_photo(conn: &mut SqliteConnection, photo_id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM photos_search WHERE rowid = ?")
+        .bind(photo_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "INSERT INTO photos_search (rowid, filename, folder, tags, albums, caption, camera)
+         SELECT
+             p.id,
+             p.filename,
+             rtrim(p.path, replace(replace(p.path, '/', ''), '\\', '')),
+             (SELECT group_concat(t.name, ' ') FROM tags t JOIN photo_tags pt ON pt.tag_id = t.id WHERE pt.photo_id = p.id),
+             (SELECT group_concat(a.name, ' ') FROM albums a JOIN photo_albums pa ON pa.album_id = a.id WHERE pa.photo_id = p.id),
+             p.caption,
+             trim(coalesce(p.camera_make, '') || ' ' || coalesce(p.camera_model, ''))
+         FROM photos p
+         WHERE p.id = ? AND p.deleted_at IS NULL",
+    )
+    .bind(photo_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Drops the photo at `path` from the search index before it's deleted.
+pub async fn unindex_path(conn: &mut SqliteConnection, path: &str) -> Result<()> {
+    sqlx::query("DELETE FROM photos_search WHERE rowid IN (SELECT id FROM photos WHERE path = ?)")
+        .bind(path)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
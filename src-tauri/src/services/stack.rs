@@ -0,0 +1,261 @@
+use crate::models::operation::Operation;
+use crate::models::photo::{MediaType, Photo};
+use anyhow::{anyhow, Result};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Largest gap between two files' capture times for them to be paired by
+/// basename. Live Photo videos start a little before the still is taken.
+const MAX_CAPTURE_GAP_MS: i64 = 3_000;
+
+/// Formats written alongside a JPEG (or HEIC) by cameras shooting RAW+JPEG.
+const RAW_FORMATS: &[&str] = &["Cr2", "Nef", "Arw", "Dng"];
+
+/// What a file contributes to a stack, in order of preference for the
+/// stack's representative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Role {
+    Still,
+    Raw,
+    Video,
+}
+
+fn role(photo: &Photo) -> Role {
+    if photo.media_type == MediaType::Video {
+        Role::Video
+    } else if RAW_FORMATS.contains(&photo.format.as_str()) {
+        Role::Raw
+    } else {
+        Role::Still
+    }
+}
+
+/// The member the gallery shows for a stack: the displayable still when
+/// there is one, then the RAW, then the video.
+pub fn representative(photos: &[Photo]) -> Option<&Photo> {
+    photos
+        .iter()
+        .min_by(|a, b| (role(a), &a.path).cmp(&(role(b), &b.path)))
+}
+
+/// Plans the stacks newly catalogued files at `added` belong to.
+///
+/// A Live Photo's still and video are paired by their shared content
+/// identifier. Otherwise files in the same folder with the same name but
+/// different roles (a RAW and its JPEG, a still and its video) are paired
+/// when their capture times agree. A stack holds at most one file per role.
+/// Files already stacked together aren't planned again, so a stack the user
+/// dissolved is only rebuilt when one of its files is re-added.
+pub async fn plan_stacks(pool: &SqlitePool, added: &[PathBuf]) -> Result<Vec<Operation>> {
+    let added: HashSet<&str> = added.iter().filter_map(|path| path.to_str()).collect();
+    if added.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut candidates: HashMap<String, Photo> = HashMap::new();
+    let folders: HashSet<&Path> = added
+        .iter()
+        .filter_map(|path| Path::new(path).parent())
+        .collect();
+    for folder in folders {
+        for photo in photos_in(pool, folder).await? {
+            candidates.insert(photo.path.clone(), photo);
+        }
+    }
+    let identifiers: HashSet<String> = candidates
+        .values()
+        .filter(|photo| added.contains(photo.path.as_str()))
+        .filter_map(|photo| photo.content_identifier.clone())
+        .collect();
+    for identifier in identifiers {
+        let photos = sqlx::query_as::<_, Photo>(
+            "SELECT * FROM photos WHERE content_identifier = ? AND deleted_at IS NULL",
+        )
+        .bind(identifier)
+        .fetch_all(pool)
+        .await?;
+        for photo in photos {
+            candidates.insert(photo.path.clone(), photo);
+        }
+    }
+    let mut candidates: Vec<Photo> = candidates.into_values().collect();
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut by_identifier: HashMap<String, Vec<Photo>> = HashMap::new();
+    let mut by_name: HashMap<(PathBuf, String), Vec<Photo>> = HashMap::new();
+    for photo in candidates {
+        match &photo.content_identifier {
+            Some(identifier) => by_identifier
+                .entry(identifier.clone())
+                .or_default()
+                .push(photo),
+            None => by_name.entry(name_key(&photo)).or_default().push(photo),
+        }
+    }
+    let mut groups = Vec::new();
+    for group in by_identifier.into_values() {
+        if group.len() > 1 {
+            groups.push(group);
+        } else {
+            // A file with an identifier can still pair by name with one without
+            for photo in group {
+                by_name.entry(name_key(&photo)).or_default().push(photo);
+            }
+        }
+    }
+    groups.extend(by_name.into_values());
+    // Candidates were sorted, so each group starts with its first path
+    groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+
+    let mut ops = Vec::new();
+    for group in groups {
+        if !group
+            .iter()
+            .any(|photo| added.contains(photo.path.as_str()))
+        {
+            continue;
+        }
+        let Some(lead) = representative(&group) else {
+            continue;
+        };
+        let mut roles = HashSet::from([role(lead)]);
+        let mut members = vec![lead];
+        for photo in &group {
+            if roles.contains(&role(photo)) || !belong_together(lead, photo) {
+                continue;
+            }
+            roles.insert(role(photo));
+            members.push(photo);
+        }
+        if members.len() < 2 {
+            continue;
+        }
+        let already_stacked =
+            lead.stack_id.is_some() && members.iter().all(|photo| photo.stack_id == lead.stack_id);
+        if already_stacked {
+            continue;
+        }
+        ops.push(Operation::CreateStack {
+            paths: members
+                .iter()
+                .map(|photo| PathBuf::from(&photo.path))
+                .collect(),
+            representative: PathBuf::from(&lead.path),
+        });
+    }
+    Ok(ops)
+}
+
+/// Folder and lowercase file stem, e.g. `(/photos, "img_1234")`.
+fn name_key(photo: &Photo) -> (PathBuf, String) {
+    let path = Path::new(&photo.path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    (path.parent().unwrap_or(Path::new("")).to_path_buf(), stem)
+}
+
+fn belong_together(a: &Photo, b: &Photo) -> bool {
+    if let (Some(x), Some(y)) = (&a.content_identifier, &b.content_identifier) {
+        return x == y;
+    }
+    let (Some(x), Some(y)) = (a.date_taken, b.date_taken) else {
+        return true;
+    };
+    // A video's creation time is UTC, but a still without a recorded offset
+    // stores its local wall-clock time, so the two can't be compared
+    let still_without_offset = [a, b]
+        .iter()
+        .any(|photo| photo.media_type == MediaType::Photo && photo.date_taken_offset.is_none());
+    if a.media_type != b.media_type && still_without_offset {
+        return true;
+    }
+    (x - y).num_milliseconds().abs() <= MAX_CAPTURE_GAP_MS
+}
+
+/// Catalogued photos directly inside `folder`.
+async fn photos_in(pool: &SqlitePool, folder: &Path) -> Result<Vec<Photo>> {
+    let prefix = format!("{}{}", folder.display(), std::path::MAIN_SEPARATOR);
+    let photos =
+        sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE substr(path, 1, length(?1)) = ?1")
+            .bind(prefix)
+            .fetch_all(pool)
+            .await?;
+    Ok(photos
+        .into_iter()
+        .filter(|photo| Path::new(&photo.path).parent() == Some(folder))
+        .collect())
+}
+
+/// The photo with `photo_id` and the rest of its stack, representative first.
+pub async fn members(pool: &SqlitePool, photo_id: i64) -> Result<Vec<Photo>> {
+    let photo = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE id = ?")
+        .bind(photo_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| anyhow!("Photo {} not found", photo_id))?;
+    let Some(stack_id) = photo.stack_id else {
+        return Ok(vec![photo]);
+    };
+    let photos = sqlx::query_as::<_, Photo>(
+        "SELECT p.* FROM photos p JOIN stacks s ON p.stack_id = s.id
+         WHERE s.id = ? ORDER BY p.id = s.representative_id DESC, p.path",
+    )
+    .bind(stack_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(photos)
+}
+
+/// The photos with `photo_ids` and everything stacked with them, each once,
+/// so a move or delete takes whole stacks along.
+pub async fn expand(pool: &SqlitePool, photo_ids: &[i64]) -> Result<Vec<Photo>> {
+    let mut seen = HashSet::new();
+    let mut photos = Vec::new();
+    for &photo_id in photo_ids {
+        for photo in members(pool, photo_id).await? {
+            if seen.insert(photo.id) {
+                photos.push(photo);
+            }
+        }
+    }
+    Ok(photos)
+}
+
+/// The name a stack member takes when its stack is renamed to `new_name`:
+/// the new stem with the member's own extension.
+pub fn member_file_name(path: &Path, new_name: &str) -> String {
+    let stem = Path::new(new_name)
+        .file_stem()
+        .map_or(new_name.into(), |stem| stem.to_string_lossy());
+    match path.extension() {
+        Some(extension) => format!("{}.{}", stem, extension.to_string_lossy()),
+        None => stem.into_owned(),
+    }
+}
+
+/// Dissolves stacks left with fewer than two photos and re-points stacks
+/// whose representative is no longer a member.
+pub async fn prune(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query(
+        "UPDATE photos SET stack_id = NULL WHERE stack_id IN
+         (SELECT stack_id FROM photos WHERE stack_id IS NOT NULL GROUP BY stack_id HAVING COUNT(*) < 2)",
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "DELETE FROM stacks WHERE id NOT IN (SELECT stack_id FROM photos WHERE stack_id IS NOT NULL)",
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "UPDATE stacks SET representative_id = (SELECT MIN(id) FROM photos WHERE stack_id = stacks.id)
+         WHERE representative_id IS NULL
+            OR representative_id NOT IN (SELECT id FROM photos WHERE stack_id = stacks.id)",
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
@@ -1,12 +1,46 @@
-use crate::models::operation::Operation;
+use crate::models::album::{Album, SmartAlbum};
+use crate::models::filter::FilterCriteria;
+use crate::models::operation::{Operation, QueuedOperation};
 use crate::models::photo::Photo;
-use anyhow::Result;
+use crate::services::file_mirror::FileMirror;
+use crate::services::filter;
+use crate::services::formats::FormatRegistry;
+use crate::services::history::{self, PendingAction};
+use crate::services::search;
+use crate::services::stack;
+use crate::services::thumbnail::{ThumbnailCache, ThumbnailUpdater};
+use anyhow::{anyhow, Result};
 use sqlx::SqlitePool;
+use std::fmt;
+
+/// How many times an operation is tried against the backup drive before it is
+/// set aside as failed, so one bad operation can't hold up the rest of the queue.
+pub const MAX_SYNC_ATTEMPTS: i64 = 5;
+
+/// Returned by `SyncEngine::execute_operation` and `execute_batch` when they
+/// fail after the primary catalog committed the change. Callers mustn't undo
+/// the file side of such a change, as the catalog now records it.
+#[derive(Debug)]
+pub struct AlreadyCommitted(pub anyhow::Error);
+
+impl fmt::Display for AlreadyCommitted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for AlreadyCommitted {}
 
 pub struct SyncEngine {
     pub primary_db: SqlitePool,
     backup_db: Option<SqlitePool>,
-    operation_queue: Vec<Operation>,
+    file_mirror: Option<FileMirror>,
+    thumbnails: Option<ThumbnailUpdater>,
+    formats: FormatRegistry,
+    operation_queue: Vec<QueuedOperation>,
+    /// Operations given up on after `MAX_SYNC_ATTEMPTS`, oldest first.
+    failed_operations: Vec<QueuedOperation>,
+    action: Option<PendingAction>,
 }
 
 impl SyncEngine {
@@ -14,28 +48,294 @@ impl SyncEngine {
         Self {
             primary_db,
             backup_db,
+            file_mirror: None,
+            thumbnails: None,
+            formats: FormatRegistry::default(),
             operation_queue: Vec::new(),
+            failed_operations: Vec::new(),
+            action: None,
+        }
+    }
+
+    /// Attaches or detaches the backup catalog, e.g. when the backup drive is
+    /// plugged in or removed. While detached, operations are queued.
+    pub fn set_backup_db(&mut self, backup_db: Option<SqlitePool>) {
+        self.backup_db = backup_db;
+    }
+
+    pub fn has_backup(&self) -> bool {
+        self.backup_db.is_some()
+    }
+
+    pub fn backup_db(&self) -> Option<&SqlitePool> {
+        self.backup_db.as_ref()
+    }
+
+    pub fn file_mirror(&self) -> Option<&FileMirror> {
+        self.file_mirror.as_ref()
+    }
+
+    /// Mirrors file changes to the backup drive in addition to the backup catalog.
+    pub fn with_file_mirror(mut self, file_mirror: FileMirror) -> Self {
+        self.file_mirror = Some(file_mirror);
+        self
+    }
+
+    /// Keeps `thumbnails` up to date as photos are added, moved and deleted.
+    /// Thumbnails are rendered in the background, after the engine is done.
+    pub fn with_thumbnail_cache(mut self, thumbnails: ThumbnailCache) -> Self {
+        self.thumbnails = Some(ThumbnailUpdater::new(thumbnails));
+        self
+    }
+
+    pub fn thumbnail_cache(&self) -> Option<&ThumbnailCache> {
+        self.thumbnails.as_ref().map(ThumbnailUpdater::cache)
+    }
+
+    /// Waits for thumbnails of the operations executed so far to be updated.
+    pub async fn wait_for_thumbnails(&mut self) {
+        if let Some(thumbnails) = &mut self.thumbnails {
+            thumbnails.flush(&self.primary_db).await;
+        }
+    }
+
+    /// Restricts scans and the library watcher to `formats`.
+    pub fn with_formats(mut self, formats: FormatRegistry) -> Self {
+        self.formats = formats;
+        self
+    }
+
+    pub fn set_formats(&mut self, formats: FormatRegistry) {
+        self.formats = formats;
+    }
+
+    pub fn formats(&self) -> &FormatRegistry {
+        &self.formats
+    }
+
+    /// Runs `f`, recording the operations it executes as one undoable action
+    /// called `label`, e.g. "Move 3 photos". Operations executed before `f`
+    /// fails are recorded too, so they can still be undone.
+    pub async fn record_action<T>(
+        &mut self,
+        label: impl Into<String>,
+        f: impl AsyncFnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.begin_action(label);
+        let result = f(self).await;
+        if let Some(action) = self.take_action() {
+            history::record(&self.primary_db, action).await?;
         }
+        result
     }
 
+    /// Starts recording the operations executed from now on, until
+    /// `take_action`.
+    pub(crate) fn begin_action(&mut self, label: impl Into<String>) {
+        self.action = Some(PendingAction::new(label.into()));
+    }
+
+    /// Stops recording and hands back what was recorded without saving it.
+    pub(crate) fn take_action(&mut self) -> Option<PendingAction> {
+        self.action.take()
+    }
+
+    /// Applies `op` to the primary catalog, then mirrors it to the backup drive.
+    ///
+    /// The primary change is committed before mirroring starts, so the catalog
+    /// isn't locked while files are copied to the backup. Its `sync_operations`
+    /// row is only marked completed once the backup file and catalog are both
+    /// up to date; otherwise the operation is queued. While older operations
+    /// are still queued, `op` joins the back of the queue rather than reaching
+    /// the backup ahead of them.
     pub async fn execute_operation(&mut self, op: &Operation) -> Result<()> {
         let mut tx = self.primary_db.begin().await?;
-        self.execute_on_db(&mut tx, op).await?;
+        let (op_id, inverse) = self.apply(&mut tx, op).await?;
+        tx.commit().await?;
+        self.record_in_action(op, inverse);
 
-        if let Some(backup_db) = &self.backup_db {
-            let mut backup_tx = backup_db.begin().await?;
-            let backup_result = self.execute_on_db(&mut backup_tx, op).await;
-            if backup_result.is_err() {
-                tx.rollback().await?;
-                backup_tx.rollback().await?;
-                self.operation_queue.push(op.clone());
-                return backup_result;
-            }
-            backup_tx.commit().await?;
-        } else {
-            self.operation_queue.push(op.clone());
+        self.mirror_committed(vec![op_id], std::slice::from_ref(op))
+            .await
+            .map_err(|e| AlreadyCommitted(e).into())
+    }
+
+    /// Applies `ops` to the primary catalog in a single transaction, then
+    /// mirrors them to the backup drive in order.
+    ///
+    /// Like `execute_operation`, the batch waits behind any operations already
+    /// queued, and after the first mirroring failure the rest are queued
+    /// without being attempted, so they still reach the backup in order.
+    pub async fn execute_batch(&mut self, ops: &[Operation]) -> Result<()> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.primary_db.begin().await?;
+        let mut op_ids = Vec::with_capacity(ops.len());
+        let mut inverses = Vec::new();
+        for op in ops {
+            // Each inverse is read after the operations ahead of it
+            let (op_id, inverse) = self.apply(&mut tx, op).await?;
+            op_ids.push(op_id);
+            inverses.push(inverse);
         }
         tx.commit().await?;
+        for (op, inverse) in ops.iter().zip(inverses) {
+            self.record_in_action(op, inverse);
+        }
+
+        self.mirror_committed(op_ids, ops)
+            .await
+            .map_err(|e| AlreadyCommitted(e).into())
+    }
+
+    /// Mirrors `ops`, already committed to the primary catalog under `op_ids`,
+    /// to the backup drive, or queues them.
+    async fn mirror_committed(&mut self, op_ids: Vec<i64>, ops: &[Operation]) -> Result<()> {
+        let mut mirroring = self.backup_db.is_some() && self.operation_queue.is_empty();
+        for (op_id, op) in op_ids.into_iter().zip(ops) {
+            let mut queued = QueuedOperation {
+                id: op_id,
+                operation: op.clone(),
+                attempts: 0,
+                last_error: None,
+            };
+
+            if mirroring {
+                match self.mirror_to_backup(op).await {
+                    Ok(()) => Self::mark_completed(&self.primary_db, op_id).await?,
+                    Err(e) => {
+                        eprintln!("Failed to mirror operation to backup, queueing: {}", e);
+                        let error = e.to_string();
+                        Self::record_failure(&self.primary_db, op_id, &error).await?;
+                        queued.attempts = 1;
+                        queued.last_error = Some(error);
+                        mirroring = false;
+                    }
+                }
+            }
+            if !mirroring {
+                self.operation_queue.push(queued);
+            }
+            self.update_thumbnails(op);
+        }
+        Ok(())
+    }
+
+    /// Applies `op` to the catalog in `tx`, returning its `sync_operations` id
+    /// and, while an action is being recorded, the operations that undo it.
+    async fn apply(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        op: &Operation,
+    ) -> Result<(i64, Option<Option<Vec<Operation>>>)> {
+        if self.action.is_none() {
+            return Ok((self.execute_on_db(tx, op).await?, None));
+        }
+        let mut inverse = history::inverse(tx, op).await?;
+        let op_id = self.execute_on_db(tx, op).await?;
+        if let Some(inverse) = &mut inverse {
+            inverse.extend(history::inverse_of_insert(tx, op).await?);
+        }
+        Ok((op_id, Some(inverse)))
+    }
+
+    /// Adds a committed `op` to the action being recorded, if any. `inverse`
+    /// is what `apply` made of it.
+    fn record_in_action(&mut self, op: &Operation, inverse: Option<Option<Vec<Operation>>>) {
+        if let (Some(action), Some(inverse)) = (&mut self.action, inverse) {
+            action.record(op, inverse);
+        }
+    }
+
+    fn update_thumbnails(&mut self, op: &Operation) {
+        if let Some(thumbnails) = &mut self.thumbnails {
+            thumbnails.queue(&self.primary_db, op);
+        }
+    }
+
+    /// Reloads operations left pending by a previous session, oldest first,
+    /// along with those it gave up on.
+    pub async fn load_queue(&mut self) -> Result<()> {
+        let rows: Vec<(i64, String, String, i64, Option<String>)> = sqlx::query_as(
+            "SELECT id, operation, status, attempts, last_error FROM sync_operations WHERE status IN ('pending', 'failed') ORDER BY id",
+        )
+        .fetch_all(&self.primary_db)
+        .await?;
+
+        self.operation_queue.clear();
+        self.failed_operations.clear();
+        for (id, operation, status, attempts, last_error) in rows {
+            let queued = QueuedOperation {
+                id,
+                operation: serde_json::from_str(&operation)?,
+                attempts,
+                last_error,
+            };
+            match status.as_str() {
+                "failed" => self.failed_operations.push(queued),
+                _ => self.operation_queue.push(queued),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the file side of `op` to the backup drive and, once verified,
+    /// replays it against the backup catalog.
+    async fn mirror_to_backup(&self, op: &Operation) -> Result<()> {
+        let backup_db = self
+            .backup_db
+            .as_ref()
+            .ok_or_else(|| anyhow!("Backup database not connected"))?;
+
+        if let Some(file_mirror) = &self.file_mirror {
+            file_mirror.apply(op).await?;
+        }
+
+        let mut backup_tx = backup_db.begin().await?;
+        let op_id = self.execute_on_db(&mut backup_tx, op).await?;
+        Self::mark_completed(&mut *backup_tx, op_id).await?;
+        backup_tx.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_completed<'e, E>(executor: E, op_id: i64) -> Result<()>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
+        sqlx::query!(
+            "UPDATE sync_operations SET status = 'completed', completed_at = CURRENT_TIMESTAMP WHERE id = ?",
+            op_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    async fn mark_failed<'e, E>(executor: E, op_id: i64) -> Result<()>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
+        sqlx::query!(
+            "UPDATE sync_operations SET status = 'failed' WHERE id = ?",
+            op_id
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_failure<'e, E>(executor: E, op_id: i64, error: &str) -> Result<()>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
+        sqlx::query!(
+            "UPDATE sync_operations SET attempts = attempts + 1, last_error = ?, last_attempt_at = CURRENT_TIMESTAMP WHERE id = ?",
+            error,
+            op_id
+        )
+        .execute(executor)
+        .await?;
         Ok(())
     }
 
@@ -43,7 +343,7 @@ impl SyncEngine {
         &self,
         tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
         op: &Operation,
-    ) -> Result<()> {
+    ) -> Result<i64> {
         let op_json = serde_json::to_string(op)?;
         let op_id = sqlx::query!(
             "INSERT INTO sync_operations (operation, status) VALUES (?, 'pending')",
@@ -56,51 +356,152 @@ impl SyncEngine {
         match op {
             Operation::AddPhoto { photo } => {
                 sqlx::query!(
-                    "INSERT INTO photos (path, filename, file_size, date_taken, width, height, format) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                    "INSERT INTO photos (path, filename, file_hash, file_size, file_mtime, date_taken, width, height, format, date_taken_offset, camera_make, camera_model, lens_model, iso, aperture, exposure_time, focal_length, orientation, media_type, duration_ms, video_codec, content_identifier, caption) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
                     photo.path,
                     photo.filename,
+                    photo.file_hash,
                     photo.file_size,
+                    photo.file_mtime,
                     photo.date_taken,
                     photo.width,
                     photo.height,
-                    photo.format
+                    photo.format,
+                    photo.date_taken_offset,
+                    photo.camera_make,
+                    photo.camera_model,
+                    photo.lens_model,
+                    photo.iso,
+                    photo.aperture,
+                    photo.exposure_time,
+                    photo.focal_length,
+                    photo.orientation,
+                    photo.media_type,
+                    photo.duration_ms,
+                    photo.video_codec,
+                    photo.content_identifier,
+                    photo.caption
                 )
                 .execute(&mut **tx)
                 .await?;
+                search::reindex_path(tx, &photo.path).await?;
+            }
+            Operation::SyncPhoto { photo } => {
+                sqlx::query!(
+                    "INSERT INTO photos (path, filename, file_hash, file_size, file_mtime, date_taken, width, height, format, date_taken_offset, camera_make, camera_model, lens_model, iso, aperture, exposure_time, focal_length, orientation, media_type, duration_ms, video_codec, content_identifier, caption) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                     ON CONFLICT(path) DO UPDATE SET filename = excluded.filename, file_hash = excluded.file_hash, file_size = excluded.file_size, file_mtime = excluded.file_mtime, missing_since = NULL, date_taken = excluded.date_taken, width = excluded.width, height = excluded.height, format = excluded.format, date_taken_offset = excluded.date_taken_offset, camera_make = excluded.camera_make, camera_model = excluded.camera_model, lens_model = excluded.lens_model, iso = excluded.iso, aperture = excluded.aperture, exposure_time = excluded.exposure_time, focal_length = excluded.focal_length, orientation = excluded.orientation, media_type = excluded.media_type, duration_ms = excluded.duration_ms, video_codec = excluded.video_codec, content_identifier = excluded.content_identifier, caption = excluded.caption, perceptual_hash = NULL, partial_hash = NULL",
+                    photo.path,
+                    photo.filename,
+                    photo.file_hash,
+                    photo.file_size,
+                    photo.file_mtime,
+                    photo.date_taken,
+                    photo.width,
+                    photo.height,
+                    photo.format,
+                    photo.date_taken_offset,
+                    photo.camera_make,
+                    photo.camera_model,
+                    photo.lens_model,
+                    photo.iso,
+                    photo.aperture,
+                    photo.exposure_time,
+                    photo.focal_length,
+                    photo.orientation,
+                    photo.media_type,
+                    photo.duration_ms,
+                    photo.video_codec,
+                    photo.content_identifier,
+                    photo.caption
+                )
+                .execute(&mut **tx)
+                .await?;
+                search::reindex_path(tx, &photo.path).await?;
             }
             Operation::Move { from, to } => {
                 let from_str = from.to_str().unwrap();
                 let to_str = to.to_str().unwrap();
+                // A move can change the name too, e.g. a rename in a file manager
+                let filename = to.file_name().and_then(|name| name.to_str());
                 sqlx::query!(
-                    "UPDATE photos SET path = ? WHERE path = ?",
+                    "UPDATE photos SET path = ?, filename = COALESCE(?, filename) WHERE path = ?",
                     to_str,
+                    filename,
                     from_str
                 )
                 .execute(&mut **tx)
                 .await?;
+                search::reindex_path(tx, to_str).await?;
             }
             Operation::Delete { path } => {
                 let path_str = path.to_str().unwrap();
+                search::unindex_path(tx, path_str).await?;
+                // Album memberships don't cascade
+                sqlx::query!(
+                    "DELETE FROM photo_albums WHERE photo_id IN (SELECT id FROM photos WHERE path = ?)",
+                    path_str
+                )
+                .execute(&mut **tx)
+                .await?;
                 sqlx::query!("DELETE FROM photos WHERE path = ?", path_str)
                     .execute(&mut **tx)
                     .await?;
+                stack::prune(tx).await?;
+            }
+            Operation::Trash { path, trash_path } => {
+                let path_str = path.to_str().unwrap();
+                let trash_path_str = trash_path.to_str().unwrap();
+                // A trashed file leaves its stack; restoring it stacks it again
+                sqlx::query!(
+                    "UPDATE photos SET path = ?, trashed_from = path, deleted_at = CURRENT_TIMESTAMP, stack_id = NULL WHERE path = ?",
+                    trash_path_str,
+                    path_str
+                )
+                .execute(&mut **tx)
+                .await?;
+                search::reindex_path(tx, trash_path_str).await?;
+                stack::prune(tx).await?;
+            }
+            Operation::Restore { trash_path, path } => {
+                let trash_path_str = trash_path.to_str().unwrap();
+                let path_str = path.to_str().unwrap();
+                sqlx::query!(
+                    "UPDATE photos SET path = ?, trashed_from = NULL, deleted_at = NULL WHERE path = ?",
+                    path_str,
+                    trash_path_str
+                )
+                .execute(&mut **tx)
+                .await?;
+                search::reindex_path(tx, path_str).await?;
             }
             Operation::Rename { path, new_name } => {
                 let path_str = path.to_str().unwrap();
+                let new_path = path.with_file_name(new_name);
+                let new_path_str = new_path.to_str().unwrap();
                 sqlx::query!(
-                    "UPDATE photos SET filename = ? WHERE path = ?",
+                    "UPDATE photos SET filename = ?, path = ? WHERE path = ?",
                     new_name,
+                    new_path_str,
                     path_str
                 )
                 .execute(&mut **tx)
                 .await?;
+                search::reindex_path(tx, new_path_str).await?;
             }
             Operation::CreateAlbum { name } => {
                 sqlx::query!("INSERT INTO albums (name) VALUES (?)", name)
                     .execute(&mut **tx)
                     .await?;
             }
-            Operation::DeleteAlbum { album_id } => {
+            Operation::DeleteAlbum { album_id, name } => {
+                if let Some(name) = name {
+                    check_name(tx, "albums", *album_id, name).await?;
+                }
+                let photo_ids = sqlx::query_scalar!(
+                    "SELECT photo_id FROM photo_albums WHERE album_id = ?",
+                    album_id
+                )
+                .fetch_all(&mut **tx)
+                .await?;
                 // First, delete associations in photo_albums
                 sqlx::query!("DELETE FROM photo_albums WHERE album_id = ?", album_id)
                     .execute(&mut **tx)
@@ -109,6 +510,85 @@ impl SyncEngine {
                 sqlx::query!("DELETE FROM albums WHERE id = ?", album_id)
                     .execute(&mut **tx)
                     .await?;
+                for photo_id in photo_ids {
+                    search::reindex_photo(tx, photo_id).await?;
+                }
+            }
+            Operation::RestoreAlbum {
+                album_id,
+                name,
+                photo_ids,
+            } => {
+                let name = free_name(tx, "albums", name).await?;
+                sqlx::query!(
+                    "INSERT INTO albums (id, name) VALUES (?, ?)",
+                    album_id,
+                    name
+                )
+                .execute(&mut **tx)
+                .await?;
+                for photo_id in photo_ids {
+                    sqlx::query!(
+                        "INSERT INTO photo_albums (photo_id, album_id) VALUES (?, ?)",
+                        photo_id,
+                        album_id
+                    )
+                    .execute(&mut **tx)
+                    .await?;
+                }
+                search::reindex_album(tx, *album_id).await?;
+            }
+            Operation::CreateSmartAlbum { name, criteria } => {
+                let criteria = serde_json::to_string(criteria)?;
+                sqlx::query!(
+                    "INSERT INTO smart_albums (name, criteria) VALUES (?, ?)",
+                    name,
+                    criteria
+                )
+                .execute(&mut **tx)
+                .await?;
+            }
+            Operation::UpdateSmartAlbum {
+                smart_album_id,
+                name,
+                criteria,
+            } => {
+                let criteria = serde_json::to_string(criteria)?;
+                sqlx::query!(
+                    "UPDATE smart_albums SET name = ?, criteria = ? WHERE id = ?",
+                    name,
+                    criteria,
+                    smart_album_id
+                )
+                .execute(&mut **tx)
+                .await?;
+            }
+            Operation::DeleteSmartAlbum {
+                smart_album_id,
+                name,
+            } => {
+                if let Some(name) = name {
+                    check_name(tx, "smart_albums", *smart_album_id, name).await?;
+                }
+                sqlx::query!("DELETE FROM smart_albums WHERE id = ?", smart_album_id)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+            Operation::RestoreSmartAlbum {
+                smart_album_id,
+                name,
+                criteria,
+            } => {
+                let criteria = serde_json::to_string(criteria)?;
+                let name = free_name(tx, "smart_albums", name).await?;
+                sqlx::query!(
+                    "INSERT INTO smart_albums (id, name, criteria) VALUES (?, ?, ?)",
+                    smart_album_id,
+                    name,
+                    criteria
+                )
+                .execute(&mut **tx)
+                .await?;
             }
             Operation::AddToAlbum { photo_id, album_id } => {
                 sqlx::query!(
@@ -118,6 +598,17 @@ impl SyncEngine {
                 )
                 .execute(&mut **tx)
                 .await?;
+                search::reindex_photo(tx, *photo_id).await?;
+            }
+            Operation::RemoveFromAlbum { photo_id, album_id } => {
+                sqlx::query!(
+                    "DELETE FROM photo_albums WHERE photo_id = ? AND album_id = ?",
+                    photo_id,
+                    album_id
+                )
+                .execute(&mut **tx)
+                .await?;
+                search::reindex_photo(tx, *photo_id).await?;
             }
             Operation::AddTag { photo_id, tag_name } => {
                 let tag = sqlx::query!("SELECT id FROM tags WHERE name = ?", tag_name)
@@ -137,41 +628,134 @@ impl SyncEngine {
                 )
                 .execute(&mut **tx)
                 .await?;
+                search::reindex_photo(tx, *photo_id).await?;
+            }
+            Operation::RemoveTag { photo_id, tag_name } => {
+                sqlx::query!(
+                    "DELETE FROM photo_tags WHERE photo_id = ? AND tag_id = (SELECT id FROM tags WHERE name = ?)",
+                    photo_id,
+                    tag_name
+                )
+                .execute(&mut **tx)
+                .await?;
+                sqlx::query!(
+                    "DELETE FROM tags WHERE name = ? AND id NOT IN (SELECT tag_id FROM photo_tags)",
+                    tag_name
+                )
+                .execute(&mut **tx)
+                .await?;
+                search::reindex_photo(tx, *photo_id).await?;
+            }
+            Operation::CreateStack {
+                paths,
+                representative,
+            } => {
+                let stack_id = sqlx::query!("INSERT INTO stacks DEFAULT VALUES")
+                    .execute(&mut **tx)
+                    .await?
+                    .last_insert_rowid();
+                for path in paths {
+                    let path_str = path.to_str().unwrap();
+                    sqlx::query!(
+                        "UPDATE photos SET stack_id = ? WHERE path = ?",
+                        stack_id,
+                        path_str
+                    )
+                    .execute(&mut **tx)
+                    .await?;
+                }
+                let representative_str = representative.to_str().unwrap();
+                sqlx::query!(
+                    "UPDATE stacks SET representative_id = (SELECT id FROM photos WHERE path = ?) WHERE id = ?",
+                    representative_str,
+                    stack_id
+                )
+                .execute(&mut **tx)
+                .await?;
+                // Also clears stacks the photos were taken out of
+                stack::prune(tx).await?;
+            }
+            Operation::Unstack { path } => {
+                let path_str = path.to_str().unwrap();
+                sqlx::query!(
+                    "UPDATE photos SET stack_id = NULL WHERE stack_id = (SELECT stack_id FROM photos WHERE path = ?)",
+                    path_str
+                )
+                .execute(&mut **tx)
+                .await?;
+                stack::prune(tx).await?;
             }
         }
-        sqlx::query!(
-            "UPDATE sync_operations SET status = 'completed' WHERE id = ?",
-            op_id
-        )
-        .execute(&mut **tx)
-        .await?;
-        Ok(())
+        Ok(op_id)
     }
 
+    /// Replays queued operations against the backup drive in their original order.
+    ///
+    /// Flushing stops at the first failure so later operations (a move after an
+    /// add, say) are never applied ahead of the ones they depend on. Only an
+    /// operation that has failed `MAX_SYNC_ATTEMPTS` times is skipped, by
+    /// setting it aside as failed.
     pub async fn flush_queue(&mut self) -> Result<()> {
         if self.backup_db.is_none() {
             return Ok(());
         }
+        while self.flush_next().await? {}
+        Ok(())
+    }
 
-        let ops_to_flush = self.operation_queue.clone();
-        self.operation_queue.clear();
+    /// Replays the oldest queued operation against the backup drive.
+    ///
+    /// Returns `Ok(false)` once the queue is empty. A failed operation stays at
+    /// the head of the queue with its attempt count and error recorded, until
+    /// its last attempt: then it moves to `failed_operations` and `Ok(true)` is
+    /// returned so the rest of the queue can follow.
+    pub async fn flush_next(&mut self) -> Result<bool> {
+        let Some(queued) = self.operation_queue.first() else {
+            return Ok(false);
+        };
+        let op_id = queued.id;
 
-        for op in ops_to_flush {
-            let mut backup_tx = self.backup_db.as_mut().unwrap().begin().await?;
-            if self.execute_on_db(&mut backup_tx, &op).await.is_err() {
-                self.operation_queue.push(op);
-                backup_tx.rollback().await?;
-            } else {
-                backup_tx.commit().await?;
+        match self.mirror_to_backup(&queued.operation).await {
+            Ok(()) => {
+                Self::mark_completed(&self.primary_db, op_id).await?;
+                self.operation_queue.remove(0);
+                Ok(true)
+            }
+            Err(e) => {
+                let error = e.to_string();
+                Self::record_failure(&self.primary_db, op_id, &error).await?;
+                let queued = &mut self.operation_queue[0];
+                queued.attempts += 1;
+                queued.last_error = Some(error);
+                if queued.attempts < MAX_SYNC_ATTEMPTS {
+                    return Err(e);
+                }
+
+                eprintln!(
+                    "Giving up on mirroring operation {} after {} attempts: {}",
+                    op_id, queued.attempts, e
+                );
+                Self::mark_failed(&self.primary_db, op_id).await?;
+                let failed = self.operation_queue.remove(0);
+                self.failed_operations.push(failed);
+                Ok(true)
             }
         }
-        Ok(())
+    }
+
+    pub fn queued_operations(&self) -> &[QueuedOperation] {
+        &self.operation_queue
     }
 
     pub fn queue_len(&self) -> usize {
         self.operation_queue.len()
     }
 
+    /// Operations that never reached the backup drive and are no longer retried.
+    pub fn failed_operations(&self) -> &[QueuedOperation] {
+        &self.failed_operations
+    }
+
     pub async fn get_photo_by_id(&self, photo_id: i64) -> Result<Photo> {
         let photo = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE id = ?")
             .bind(photo_id)
@@ -181,17 +765,32 @@ impl SyncEngine {
     }
 
     pub async fn get_photos(&self, limit: i64, offset: i64) -> Result<Vec<Photo>> {
-        let photos = sqlx::query_as::<_, Photo>("SELECT * FROM photos LIMIT ? OFFSET ?")
-            .bind(limit)
-            .bind(offset)
-            .fetch_all(&self.primary_db)
-            .await?;
+        // Stacks are shown as their representative
+        let photos = sqlx::query_as::<_, Photo>(
+            "SELECT * FROM photos WHERE deleted_at IS NULL AND missing_since IS NULL AND (stack_id IS NULL OR id IN (SELECT representative_id FROM stacks)) LIMIT ? OFFSET ?",
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.primary_db)
+        .await?;
+        Ok(photos)
+    }
+
+    /// Catalogued photos whose file has vanished from the primary drive, most
+    /// recently missed first. They're left out of the gallery, albums and
+    /// searches until their file is back.
+    pub async fn get_missing_photos(&self) -> Result<Vec<Photo>> {
+        let photos = sqlx::query_as::<_, Photo>(
+            "SELECT * FROM photos WHERE deleted_at IS NULL AND missing_since IS NOT NULL ORDER BY missing_since DESC, path",
+        )
+        .fetch_all(&self.primary_db)
+        .await?;
         Ok(photos)
     }
 
     pub async fn get_photos_by_album_id(&self, album_id: i64) -> Result<Vec<Photo>> {
         let photos = sqlx::query_as::<_, Photo>(
-            "SELECT p.* FROM photos p JOIN photo_albums pa ON p.id = pa.photo_id WHERE pa.album_id = ?",
+            "SELECT p.* FROM photos p JOIN photo_albums pa ON p.id = pa.photo_id WHERE pa.album_id = ? AND p.deleted_at IS NULL AND p.missing_since IS NULL",
         )
         .bind(album_id)
         .fetch_all(&self.primary_db)
@@ -199,31 +798,97 @@ impl SyncEngine {
         Ok(photos)
     }
 
+    /// The albums, each with how many photos it holds.
+    pub async fn get_albums(&self) -> Result<Vec<Album>> {
+        let albums = sqlx::query_as::<_, Album>(
+            "SELECT a.id, a.name, (SELECT COUNT(*) FROM photo_albums pa JOIN photos p ON p.id = pa.photo_id
+                                   WHERE pa.album_id = a.id AND p.deleted_at IS NULL AND p.missing_since IS NULL) AS photo_count
+             FROM albums a ORDER BY a.name",
+        )
+        .fetch_all(&self.primary_db)
+        .await?;
+        Ok(albums)
+    }
+
+    /// The smart albums, each with how many photos match it.
+    pub async fn get_smart_albums(&self) -> Result<Vec<SmartAlbum>> {
+        let rows: Vec<(i64, String, String)> =
+            sqlx::query_as("SELECT id, name, criteria FROM smart_albums ORDER BY name")
+                .fetch_all(&self.primary_db)
+                .await?;
+        let mut smart_albums = Vec::with_capacity(rows.len());
+        for (id, name, criteria) in rows {
+            let criteria: FilterCriteria = serde_json::from_str(&criteria)?;
+            smart_albums.push(SmartAlbum {
+                id,
+                name,
+                photo_count: filter::count_photos(&self.primary_db, &criteria).await?,
+                criteria,
+            });
+        }
+        Ok(smart_albums)
+    }
+
+    /// The photos in the library matching a smart album's filter.
+    pub async fn get_photos_by_smart_album_id(&self, smart_album_id: i64) -> Result<Vec<Photo>> {
+        let criteria: String = sqlx::query_scalar("SELECT criteria FROM smart_albums WHERE id = ?")
+            .bind(smart_album_id)
+            .fetch_optional(&self.primary_db)
+            .await?
+            .ok_or_else(|| anyhow!("Smart album {} not found", smart_album_id))?;
+        filter::filter_photos(&self.primary_db, serde_json::from_str(&criteria)?).await
+    }
+
     pub async fn add_photo(&mut self, photo: Photo) -> Result<()> {
-        let op = Operation::AddPhoto { photo };
+        let op = Operation::AddPhoto {
+            photo: Box::new(photo),
+        };
         self.execute_operation(&op).await
     }
+}
 
-    pub async fn scan_library(&mut self, library_path: &str) -> Result<()> {
-        for entry in walkdir::WalkDir::new(library_path) {
-            let entry = entry?;
-            if entry.file_type().is_file() {
-                let path = entry.path();
-                let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("");
-                if ["jpg", "jpeg", "png", "gif"].contains(&extension.to_lowercase().as_str()) {
-                    let path_str = path.to_str().unwrap().to_string();
-                    let existing_photo = sqlx::query("SELECT id FROM photos WHERE path = ?")
-                        .bind(&path_str)
-                        .fetch_optional(&self.primary_db)
-                        .await?;
-
-                    if existing_photo.is_none() {
-                        let photo = Photo::new_from_path(path.to_path_buf())?;
-                        self.add_photo(photo).await?;
-                    }
-                }
-            }
-        }
-        Ok(())
+/// `name`, numbered `name (2)`, `name (3)`… if an album in `table` created
+/// since has taken it, for bringing back a deleted album.
+async fn free_name(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    table: &'static str,
+    name: &str,
+) -> Result<String> {
+    let taken = format!("SELECT COUNT(*) FROM {} WHERE name = ?", table);
+    let mut free_name = name.to_string();
+    let mut copy = 1;
+    while sqlx::query_scalar::<_, i64>(&taken)
+        .bind(&free_name)
+        .fetch_one(&mut **tx)
+        .await?
+        > 0
+    {
+        copy += 1;
+        free_name = format!("{} ({})", name, copy);
+    }
+    Ok(free_name)
+}
+
+/// Fails if the row with `id` in `table` is there under a name other than
+/// `name`, so a delete by id can't remove an album other than the one meant.
+async fn check_name(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    table: &'static str,
+    id: i64,
+    name: &str,
+) -> Result<()> {
+    let query = format!("SELECT name FROM {} WHERE id = ?", table);
+    let current: Option<String> = sqlx::query_scalar(&query)
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    match current {
+        Some(current) if current != name => Err(anyhow!(
+            "Expected album {} to be {}, found {}",
+            id,
+            name,
+            current
+        )),
+        _ => Ok(()),
     }
 }
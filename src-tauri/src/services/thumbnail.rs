@@ -0,0 +1,262 @@
+use crate::models::operation::Operation;
+use crate::models::photo::Photo;
+use crate::services::config::CONFIG_DIR_NAME;
+use crate::services::duplicate::DuplicateDetector;
+use crate::services::file_mirror::PARTIAL_SUFFIX;
+use crate::services::formats;
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, ImageOutputFormat};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use tokio::sync::{mpsc, oneshot};
+
+/// Longest-edge sizes, in pixels, generated for every photo.
+pub const THUMBNAIL_SIZES: &[u32] = &[256, 1024];
+
+const THUMBNAIL_QUALITY: u8 = 85;
+
+/// Content-addressed cache of JPEG thumbnails stored under the library's
+/// `.photovault` directory. Files are named after the source's SHA-256, so
+/// moved or renamed photos and byte-identical duplicates share thumbnails.
+#[derive(Debug, Clone)]
+pub struct ThumbnailCache {
+    cache_dir: PathBuf,
+}
+
+impl ThumbnailCache {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
+    }
+
+    /// The cache for a library rooted at `library_root`.
+    pub fn for_library(library_root: &Path) -> Self {
+        Self::new(library_root.join(CONFIG_DIR_NAME).join("thumbnails"))
+    }
+
+    pub fn thumbnail_path(&self, content_hash: &str, size: u32) -> PathBuf {
+        self.cache_dir
+            .join(&content_hash[..2])
+            .join(format!("{}_{}.jpg", content_hash, size))
+    }
+
+    /// Returns the cached thumbnail for `photo_id`, if one has been generated.
+    pub async fn get(
+        &self,
+        pool: &SqlitePool,
+        photo_id: i64,
+        size: u32,
+    ) -> Result<Option<PathBuf>> {
+        let path: Option<String> =
+            sqlx::query_scalar("SELECT path FROM thumbnails WHERE photo_id = ? AND size = ?")
+                .bind(photo_id)
+                .bind(size)
+                .fetch_optional(pool)
+                .await?;
+        Ok(path.map(PathBuf::from).filter(|path| path.exists()))
+    }
+
+    /// Generates any missing thumbnails for `photo` and records them.
+    pub async fn generate(&self, pool: &SqlitePool, photo: &Photo) -> Result<()> {
+        let source = PathBuf::from(&photo.path);
+        let content_hash = match &photo.file_hash {
+            Some(hash) => hash.clone(),
+            None => {
+                let hash = DuplicateDetector::hash_file(&source).await?;
+                DuplicateDetector::cache_hash(pool, photo.id, hash.clone()).await?;
+                hash
+            }
+        };
+
+        let targets: Vec<(u32, PathBuf)> = THUMBNAIL_SIZES
+            .iter()
+            .map(|&size| (size, self.thumbnail_path(&content_hash, size)))
+            .collect();
+        let missing: Vec<(u32, PathBuf)> = targets
+            .iter()
+            .filter(|(_, path)| !path.exists())
+            .cloned()
+            .collect();
+
+        if !missing.is_empty() {
+            let orientation = photo.orientation;
+            tokio::task::spawn_blocking(move || render(&source, orientation, &missing)).await??;
+        }
+
+        for (size, path) in targets {
+            sqlx::query(
+                "INSERT OR REPLACE INTO thumbnails (photo_id, size, content_hash, path) VALUES (?, ?, ?, ?)",
+            )
+            .bind(photo.id)
+            .bind(size)
+            .bind(&content_hash)
+            .bind(path.to_str())
+            .execute(pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the cache in step with a catalog change that has just been
+    /// committed to `pool`.
+    pub async fn handle_operation(&self, pool: &SqlitePool, op: &Operation) -> Result<()> {
+        let path = match op {
+            Operation::AddPhoto { photo } | Operation::SyncPhoto { photo } => {
+                PathBuf::from(&photo.path)
+            }
+            Operation::Move { to, .. } => to.clone(),
+            Operation::Rename { path, new_name } => path.with_file_name(new_name),
+            Operation::Delete { .. } => return self.evict_orphans(pool).await,
+            _ => return Ok(()),
+        };
+
+        let photo = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE path = ?")
+            .bind(path.to_str())
+            .fetch_optional(pool)
+            .await?;
+        match photo {
+            Some(photo) => self.generate(pool, &photo).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Drops thumbnail rows whose photo is gone and deletes cached files that
+    /// no remaining photo refers to.
+    pub async fn evict_orphans(&self, pool: &SqlitePool) -> Result<()> {
+        let orphaned: Vec<String> = sqlx::query_scalar(
+            "DELETE FROM thumbnails WHERE photo_id NOT IN (SELECT id FROM photos) RETURNING path",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for path in orphaned {
+            let still_used: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM thumbnails WHERE path = ?")
+                    .bind(&path)
+                    .fetch_one(pool)
+                    .await?;
+            if still_used == 0 {
+                let _ = tokio::fs::remove_file(&path).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+enum ThumbnailJob {
+    Update(Operation),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Applies committed catalog changes to a `ThumbnailCache` on a background
+/// task, in the order they were made, so decoding photos never holds up the
+/// sync engine. The task is started by the first change queued.
+#[derive(Debug)]
+pub struct ThumbnailUpdater {
+    cache: ThumbnailCache,
+    jobs: Option<mpsc::UnboundedSender<ThumbnailJob>>,
+}
+
+impl ThumbnailUpdater {
+    pub fn new(cache: ThumbnailCache) -> Self {
+        Self { cache, jobs: None }
+    }
+
+    pub fn cache(&self) -> &ThumbnailCache {
+        &self.cache
+    }
+
+    /// Queues `op`, just committed to `pool`, for `ThumbnailCache::handle_operation`.
+    pub fn queue(&mut self, pool: &SqlitePool, op: &Operation) {
+        self.send(pool, ThumbnailJob::Update(op.clone()));
+    }
+
+    /// Waits until every change queued so far has been applied.
+    pub async fn flush(&mut self, pool: &SqlitePool) {
+        let (done, flushed) = oneshot::channel();
+        self.send(pool, ThumbnailJob::Flush(done));
+        let _ = flushed.await;
+    }
+
+    fn send(&mut self, pool: &SqlitePool, job: ThumbnailJob) {
+        let jobs = self
+            .jobs
+            .get_or_insert_with(|| spawn_updates(self.cache.clone(), pool.clone()));
+        let _ = jobs.send(job);
+    }
+}
+
+/// Thumbnails are a disposable cache, and the media protocol renders missing
+/// ones on request, so failures are only logged.
+fn spawn_updates(cache: ThumbnailCache, pool: SqlitePool) -> mpsc::UnboundedSender<ThumbnailJob> {
+    let (sender, mut jobs) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(job) = jobs.recv().await {
+            match job {
+                ThumbnailJob::Update(op) => {
+                    if let Err(e) = cache.handle_operation(&pool, &op).await {
+                        eprintln!("Failed to update thumbnails: {}", e);
+                    }
+                }
+                ThumbnailJob::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    });
+    sender
+}
+
+/// Decodes `source` once and writes a JPEG thumbnail for each requested size.
+fn render(source: &Path, orientation: Option<i64>, targets: &[(u32, PathBuf)]) -> Result<()> {
+    let handler = formats::handler_for(source)
+        .ok_or_else(|| anyhow!("Unsupported file type: {}", source.display()))?;
+    let image = apply_orientation(handler.preview(source)?, orientation);
+
+    for (size, path) in targets {
+        let resized = if image.width().max(image.height()) > *size {
+            image.thumbnail(*size, *size)
+        } else {
+            image.clone()
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_jpeg(&DynamicImage::ImageRgb8(resized.to_rgb8()), path)?;
+    }
+    Ok(())
+}
+
+/// Writes `image` to `path` through a partial file renamed into place, so an
+/// interrupted write never leaves a truncated thumbnail that looks cached.
+fn write_jpeg(image: &DynamicImage, path: &Path) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    let mut file = std::fs::File::create(&partial)?;
+    let written = image.write_to(&mut file, ImageOutputFormat::Jpeg(THUMBNAIL_QUALITY));
+    drop(file);
+    let result = match written {
+        Ok(()) => std::fs::rename(&partial, path).map_err(anyhow::Error::from),
+        Err(e) => Err(e.into()),
+    };
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Rotates/flips the decoded pixels so thumbnails display upright for every
+/// EXIF orientation value.
+pub(crate) fn apply_orientation(image: DynamicImage, orientation: Option<i64>) -> DynamicImage {
+    match orientation {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => image,
+    }
+}
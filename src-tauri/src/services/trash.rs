@@ -0,0 +1,250 @@
+use crate::models::operation::Operation;
+use crate::models::photo::Photo;
+use crate::services::config::{self, CONFIG_DIR_NAME, CONFIG_FILE_NAME};
+use crate::services::organizer::move_file;
+use crate::services::scanner::{ScanFailure, SCAN_BATCH_SIZE};
+use crate::services::stack;
+use crate::services::sync_engine::{AlreadyCommitted, SyncEngine};
+use crate::AppState;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Days a deleted photo stays in the trash unless `AppConfig` says otherwise.
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+/// How often photos past their retention period are purged.
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// What a trash, restore or purge did.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrashSummary {
+    pub photos: u32,
+    /// Combined size of those photos' files.
+    pub bytes: i64,
+    pub failed: Vec<ScanFailure>,
+}
+
+/// Where deleted photos on the drive at `root` are kept until they're purged.
+pub fn trash_dir(root: &Path) -> PathBuf {
+    root.join(CONFIG_DIR_NAME).join("trash")
+}
+
+/// Trashed photos, most recently deleted first.
+pub async fn list_trash(pool: &SqlitePool) -> Result<Vec<Photo>> {
+    let photos = sqlx::query_as::<_, Photo>(
+        "SELECT * FROM photos WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, path",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(photos)
+}
+
+/// Moves the photos with `photo_ids` into the trash of the drive at `root`.
+///
+/// Each file goes to a folder named after its photo id, so files deleted from
+/// different folders under the same name don't collide. The catalog row is
+/// kept, flagged as deleted, and the backup copy is moved into the backup
+/// drive's trash. Photos already in the trash are skipped.
+pub async fn trash_photos(
+    engine: &mut SyncEngine,
+    root: &Path,
+    photo_ids: &[i64],
+) -> Result<TrashSummary> {
+    let mut moves = Vec::new();
+    for &photo_id in photo_ids {
+        let photo = engine.get_photo_by_id(photo_id).await?;
+        if photo.deleted_at.is_some() {
+            continue;
+        }
+        if !Path::new(&photo.path).starts_with(root) {
+            return Err(anyhow!("{} is outside the library", photo.path));
+        }
+        let to = trash_dir(root)
+            .join(photo.id.to_string())
+            .join(&photo.filename);
+        moves.push((photo, to));
+    }
+
+    let mut summary = TrashSummary::default();
+    move_and_catalog(engine, moves, &mut summary, |path, trash_path| {
+        Operation::Trash { path, trash_path }
+    })
+    .await?;
+    Ok(summary)
+}
+
+/// Moves trashed photos with `photo_ids` back to where they were deleted from.
+///
+/// A photo whose old path has been taken since is reported and left in the
+/// trash. Restored files are stacked again with the files they belong with.
+pub async fn restore_photos(engine: &mut SyncEngine, photo_ids: &[i64]) -> Result<TrashSummary> {
+    let mut moves = Vec::new();
+    for &photo_id in photo_ids {
+        let photo = engine.get_photo_by_id(photo_id).await?;
+        if let Some(trashed_from) = &photo.trashed_from {
+            let to = PathBuf::from(trashed_from);
+            moves.push((photo, to));
+        }
+    }
+
+    let folders: Vec<PathBuf> = moves
+        .iter()
+        .filter_map(|(photo, _)| Path::new(&photo.path).parent().map(Path::to_path_buf))
+        .collect();
+    let mut summary = TrashSummary::default();
+    let restored = move_and_catalog(engine, moves, &mut summary, |trash_path, path| {
+        Operation::Restore { trash_path, path }
+    })
+    .await?;
+    // Only folders emptied by the restore go
+    for folder in folders {
+        let _ = tokio::fs::remove_dir(folder).await;
+    }
+    let stacks = stack::plan_stacks(&engine.primary_db, &restored).await?;
+    engine.execute_batch(&stacks).await?;
+    Ok(summary)
+}
+
+/// Permanently deletes everything in the trash.
+pub async fn empty_trash(engine: &mut SyncEngine) -> Result<TrashSummary> {
+    let photos = list_trash(&engine.primary_db).await?;
+    purge(engine, photos).await
+}
+
+/// Permanently deletes photos that have been in the trash for at least
+/// `retention_days`.
+pub async fn purge_expired(engine: &mut SyncEngine, retention_days: u32) -> Result<TrashSummary> {
+    let photos = sqlx::query_as::<_, Photo>(
+        "SELECT * FROM photos WHERE deleted_at <= datetime('now', ?) ORDER BY deleted_at",
+    )
+    .bind(format!("-{} days", retention_days))
+    .fetch_all(&engine.primary_db)
+    .await?;
+    purge(engine, photos).await
+}
+
+/// Moves each photo's file to its paired path and catalogs the moves with
+/// `operation(from, to)`, in batches of `SCAN_BATCH_SIZE`.
+///
+/// Files that can't be moved are reported and skipped. If cataloguing a batch
+/// fails, the error is returned and that batch's files are moved back, unless
+/// the catalog had already committed their moves. Returns the new paths of
+/// the files moved.
+async fn move_and_catalog(
+    engine: &mut SyncEngine,
+    moves: Vec<(Photo, PathBuf)>,
+    summary: &mut TrashSummary,
+    operation: impl Fn(PathBuf, PathBuf) -> Operation,
+) -> Result<Vec<PathBuf>> {
+    let mut destinations = Vec::new();
+    for batch in moves.chunks(SCAN_BATCH_SIZE) {
+        let mut moved = Vec::new();
+        for (photo, to) in batch {
+            let from = PathBuf::from(&photo.path);
+            match move_file(&from, to).await {
+                Ok(()) => moved.push((photo, from, to)),
+                Err(e) => summary.failed.push(ScanFailure {
+                    path: from,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        let ops: Vec<Operation> = moved
+            .iter()
+            .map(|(_, from, to)| operation(from.clone(), to.to_path_buf()))
+            .collect();
+        if let Err(e) = engine.execute_batch(&ops).await {
+            // Once committed, the catalog already points at the new paths
+            if !e.is::<AlreadyCommitted>() {
+                for (_, from, to) in moved.into_iter().rev() {
+                    if let Err(e) = move_file(to, &from).await {
+                        eprintln!(
+                            "Failed to restore {} to {}: {}",
+                            to.display(),
+                            from.display(),
+                            e
+                        );
+                    }
+                }
+            }
+            return Err(e);
+        }
+        for (photo, _, to) in moved {
+            summary.photos += 1;
+            summary.bytes += photo.file_size.unwrap_or(0);
+            destinations.push(to.to_path_buf());
+        }
+    }
+    Ok(destinations)
+}
+
+/// Deletes trashed photos' files and catalog rows, along with the folders the
+/// trash kept them in.
+async fn purge(engine: &mut SyncEngine, photos: Vec<Photo>) -> Result<TrashSummary> {
+    let mut summary = TrashSummary::default();
+    for batch in photos.chunks(SCAN_BATCH_SIZE) {
+        let mut ops = Vec::new();
+        for photo in batch {
+            let path = PathBuf::from(&photo.path);
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    summary.failed.push(ScanFailure {
+                        path,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(folder) = path.parent() {
+                let _ = tokio::fs::remove_dir(folder).await;
+            }
+            summary.photos += 1;
+            summary.bytes += photo.file_size.unwrap_or(0);
+            ops.push(Operation::Delete { path });
+        }
+        engine.execute_batch(&ops).await?;
+    }
+    Ok(summary)
+}
+
+/// Starts a background task that purges photos past the retention period in
+/// `AppConfig::trash_retention_days`, once at startup and then every
+/// `PURGE_INTERVAL`.
+pub fn spawn(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = purge_due(&app).await {
+                eprintln!("Trash purge error: {}", e);
+            }
+            tokio::time::sleep(PURGE_INTERVAL).await;
+        }
+    });
+}
+
+async fn purge_due(app: &AppHandle) -> Result<()> {
+    let config_path = config::get_app_config_dir()?.join(CONFIG_FILE_NAME);
+    let config = config::load_config_from_path(&config_path).await?;
+    let retention_days = config
+        .trash_retention_days
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
+
+    let state = app.state::<AppState>();
+    let mut sync_engine = state.sync_engine.lock().await;
+    let Some(sync_engine) = sync_engine.as_mut() else {
+        return Ok(());
+    };
+    let summary = purge_expired(sync_engine, retention_days).await?;
+    if summary.photos > 0 || !summary.failed.is_empty() {
+        println!(
+            "Purged {} photos from the trash, {} failed",
+            summary.photos,
+            summary.failed.len()
+        );
+    }
+    Ok(())
+}
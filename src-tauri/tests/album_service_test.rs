@@ -1,4 +1,6 @@
 use photovault::db::manager::DatabaseManager;
+use photovault::models::album::{Album, AlbumEntry};
+use photovault::models::filter::FilterCriteria;
 use photovault::services::album::AlbumService;
 use photovault::services::sync_engine::SyncEngine;
 use sqlx::pool::Pool;
@@ -17,11 +19,25 @@ async fn setup_test_db(db_name: &str) -> Pool<Sqlite> {
     pool
 }
 
+/// The regular albums among those `get_albums` lists.
+async fn regular_albums(album_service: &AlbumService<'_>) -> Vec<Album> {
+    album_service
+        .get_albums()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter_map(|entry| match entry {
+            AlbumEntry::Album(album) => Some(album),
+            AlbumEntry::Smart(_) => None,
+        })
+        .collect()
+}
+
 #[tokio::test]
 async fn test_create_album() {
     let pool = setup_test_db("test_create_album.db").await;
     let sync_engine = Arc::new(Mutex::new(Some(SyncEngine::new(pool, None))));
-    let album_service = AlbumService::new(sync_engine.clone());
+    let album_service = AlbumService::new(&sync_engine);
 
     let album_name = "Test Album".to_string();
     album_service
@@ -29,18 +45,46 @@ async fn test_create_album() {
         .await
         .unwrap();
 
-    let albums = album_service.get_albums().await.unwrap();
+    let albums = regular_albums(&album_service).await;
     assert_eq!(albums.len(), 1);
     assert_eq!(albums[0].name, album_name);
 }
 
+#[tokio::test]
+async fn test_get_albums_lists_smart_albums_after_albums() {
+    let pool = setup_test_db("test_get_albums_lists_smart_albums.db").await;
+    let sync_engine = Arc::new(Mutex::new(Some(SyncEngine::new(pool, None))));
+    let album_service = AlbumService::new(&sync_engine);
+
+    album_service
+        .create_smart_album(
+            "Wide".to_string(),
+            FilterCriteria {
+                min_width: Some(1000),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    album_service
+        .create_album("Trip".to_string())
+        .await
+        .unwrap();
+
+    let albums = album_service.get_albums().await.unwrap();
+    assert!(matches!(&albums[..], [
+        AlbumEntry::Album(album),
+        AlbumEntry::Smart(smart_album),
+    ] if album.name == "Trip" && smart_album.name == "Wide" && smart_album.photo_count == 0));
+}
+
 use std::fs;
 
 #[tokio::test]
 async fn test_add_photos_to_album() {
     let pool = setup_test_db("test_add_photos_to_album.db").await;
     let sync_engine = Arc::new(Mutex::new(Some(SyncEngine::new(pool, None))));
-    let album_service = AlbumService::new(sync_engine.clone());
+    let album_service = AlbumService::new(&sync_engine);
 
     // Create an album
     let album_name = "Test Album".to_string();
@@ -48,7 +92,7 @@ async fn test_add_photos_to_album() {
         .create_album(album_name.clone())
         .await
         .unwrap();
-    let albums = album_service.get_albums().await.unwrap();
+    let albums = regular_albums(&album_service).await;
     let album_id = albums[0].id;
 
     // Create a dummy file
@@ -65,6 +109,7 @@ async fn test_add_photos_to_album() {
         width: Some(100),
         height: Some(100),
         format: "jpeg".to_string(),
+        ..Default::default()
     };
     {
         let mut sync_engine_locked = sync_engine.lock().await;
@@ -113,7 +158,7 @@ async fn test_add_photos_to_album() {
 async fn test_delete_album() {
     let pool = setup_test_db("test_delete_album.db").await;
     let sync_engine = Arc::new(Mutex::new(Some(SyncEngine::new(pool, None))));
-    let album_service = AlbumService::new(sync_engine.clone());
+    let album_service = AlbumService::new(&sync_engine);
 
     // Create an album
     let album_name = "Test Album".to_string();
@@ -121,7 +166,7 @@ async fn test_delete_album() {
         .create_album(album_name.clone())
         .await
         .unwrap();
-    let albums = album_service.get_albums().await.unwrap();
+    let albums = regular_albums(&album_service).await;
     assert_eq!(albums.len(), 1);
     let album_id = albums[0].id;
 
@@ -130,5 +175,5 @@ async fn test_delete_album() {
 
     // Verify
     let albums = album_service.get_albums().await.unwrap();
-    assert_eq!(albums.len(), 0);
+    assert!(albums.is_empty());
 }
@@ -0,0 +1,128 @@
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::services::backup_monitor::{drain_queue, update_backup, BackupChange};
+use photovault::services::sync_engine::SyncEngine;
+use tempfile::tempdir;
+use tokio::sync::Mutex;
+
+async fn create_album(engine: &mut SyncEngine, name: &str) {
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: name.to_string(),
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_reconnected_backup_is_attached_and_drained() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&primary.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool, None);
+    create_album(&mut engine, "Trips").await;
+    create_album(&mut engine, "Family").await;
+
+    assert_eq!(
+        update_backup(&mut engine, Some(backup.path()), false)
+            .await
+            .unwrap(),
+        BackupChange::Unchanged
+    );
+    assert_eq!(
+        update_backup(&mut engine, Some(backup.path()), true)
+            .await
+            .unwrap(),
+        BackupChange::Reconnected
+    );
+    assert!(engine.has_backup());
+    assert_eq!(
+        update_backup(&mut engine, Some(backup.path()), true)
+            .await
+            .unwrap(),
+        BackupChange::Unchanged
+    );
+    let backup_pool = engine.backup_db().unwrap().clone();
+
+    let engine = Mutex::new(Some(engine));
+    let mut progress = Vec::new();
+    let result = drain_queue(&engine, |p| progress.push((p.flushed, p.remaining))).await;
+    assert_eq!((result.flushed, result.remaining), (2, 0));
+    assert!(result.error.is_none());
+    assert_eq!(progress, vec![(1, 1), (2, 0)]);
+
+    let backup_albums: Vec<String> = sqlx::query_scalar("SELECT name FROM albums ORDER BY id")
+        .fetch_all(&backup_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        backup_albums,
+        vec!["Trips".to_string(), "Family".to_string()]
+    );
+
+    let mut engine = engine.lock().await;
+    let engine = engine.as_mut().unwrap();
+    assert_eq!(
+        update_backup(engine, Some(backup.path()), false)
+            .await
+            .unwrap(),
+        BackupChange::Disconnected
+    );
+    assert!(!engine.has_backup());
+}
+
+#[tokio::test]
+async fn test_drain_stops_at_a_failing_operation() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&primary.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+
+    // Replaying "Trips" on the backup fails until this row is gone
+    sqlx::query("INSERT INTO albums (name) VALUES ('Trips')")
+        .execute(&backup_pool)
+        .await
+        .unwrap();
+
+    let mut engine = SyncEngine::new(pool, None);
+    create_album(&mut engine, "Trips").await;
+    create_album(&mut engine, "Family").await;
+    let engine = Mutex::new(Some(engine));
+
+    // Nothing to drain into while the backup is away
+    let result = drain_queue(&engine, |_| {}).await;
+    assert_eq!((result.flushed, result.remaining), (0, 2));
+    assert!(result.error.is_none());
+
+    engine
+        .lock()
+        .await
+        .as_mut()
+        .unwrap()
+        .set_backup_db(Some(backup_pool.clone()));
+    let mut progress = Vec::new();
+    let result = drain_queue(&engine, |p| progress.push(p.flushed)).await;
+    assert_eq!((result.flushed, result.remaining), (0, 2));
+    assert!(result.error.is_some());
+    assert!(progress.is_empty());
+    {
+        let engine = engine.lock().await;
+        let queued = engine.as_ref().unwrap().queued_operations();
+        assert_eq!(queued[0].attempts, 1);
+        assert_eq!(queued[0].last_error, result.error);
+    }
+
+    sqlx::query("DELETE FROM albums")
+        .execute(&backup_pool)
+        .await
+        .unwrap();
+    let result = drain_queue(&engine, |_| {}).await;
+    assert_eq!((result.flushed, result.remaining), (2, 0));
+    assert!(result.error.is_none());
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use common::{add, break_backup_mirror, id_of, photo_file};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::batch_rename::{apply_rename, plan_rename, RenameTemplate};
+use photovault::services::sync_engine::SyncEngine;
+use sqlx::SqlitePool;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+/// A new file at `path`, taken `minutes` after a fixed time.
+fn taken(path: &Path, minutes: i64) -> Photo {
+    Photo {
+        date_taken: Some(
+            Utc.with_ymd_and_hms(2024, 3, 7, 9, 0, 0).unwrap() + Duration::minutes(minutes),
+        ),
+        date_taken_offset: Some(0),
+        ..photo_file(path)
+    }
+}
+
+async fn catalogued(pool: &SqlitePool) -> Vec<String> {
+    sqlx::query_scalar("SELECT filename FROM photos ORDER BY filename")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[test]
+fn test_rename_templates() {
+    let photo = Photo {
+        path: "/photos/IMG_0001.JPG".to_string(),
+        camera_make: Some("Canon".to_string()),
+        camera_model: Some("EOS R5".to_string()),
+        date_taken: Some(Utc.with_ymd_and_hms(2024, 3, 7, 23, 30, 0).unwrap()),
+        date_taken_offset: Some(60),
+        ..Default::default()
+    };
+    let template = RenameTemplate::parse("{date:%Y%m%d}_{camera}_{seq:04}{ext}").unwrap();
+    assert_eq!(template.render(&photo, 7), "20240308_EOS R5_0007.JPG");
+    let template = RenameTemplate::parse("{date} {name} ({seq}){ext}").unwrap();
+    assert_eq!(template.render(&photo, 12), "2024-03-08 IMG_0001 (12).JPG");
+
+    let unknown = Photo {
+        camera_model: Some("Model/II".to_string()),
+        ..photo.clone()
+    };
+    let template = RenameTemplate::parse("{camera}").unwrap();
+    assert_eq!(template.render(&unknown, 1), "Model-II");
+    assert_eq!(template.render(&Photo::default(), 1), "Unknown");
+
+    for invalid in ["{lens}", "{seq:x}", "{date:%Q}", "trip/{name}", "{name", ""] {
+        assert!(RenameTemplate::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn test_batch_rename_numbers_by_capture_time_and_follows_stacks() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    let a = add(&mut engine, taken(&path("a.png"), 2)).await;
+    let b = add(&mut engine, taken(&path("b.png"), 3)).await;
+    let c = add(&mut engine, taken(&path("c.png"), 1)).await;
+    let raw = add(&mut engine, taken(&path("b.dng"), 3)).await;
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("b.png"), path("b.dng")],
+            representative: path("b.png"),
+        })
+        .await
+        .unwrap();
+
+    let template = RenameTemplate::parse("trip_{seq:02}{ext}").unwrap();
+    let plan = plan_rename(&pool, &[a, b, c], &template).await.unwrap();
+    assert!(plan.collisions.is_empty());
+    let planned: Vec<(i64, PathBuf)> = plan
+        .renames
+        .iter()
+        .map(|rename| (rename.photo_id, rename.to.clone()))
+        .collect();
+    assert_eq!(
+        planned,
+        vec![
+            (c, path("trip_01.png")),
+            (a, path("trip_02.png")),
+            (b, path("trip_03.png")),
+            (raw, path("trip_03.dng")),
+        ]
+    );
+    // Previewing touches nothing
+    assert!(path("a.png").exists());
+
+    apply_rename(&mut engine, &plan).await.unwrap();
+    for name in ["trip_01.png", "trip_02.png", "trip_03.png", "trip_03.dng"] {
+        assert!(path(name).exists(), "{}", name);
+    }
+    assert_eq!(
+        catalogued(&pool).await,
+        vec!["trip_01.png", "trip_02.png", "trip_03.dng", "trip_03.png"]
+    );
+}
+
+#[tokio::test]
+async fn test_batch_rename_orders_chains_and_reports_collisions() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+    let template = RenameTemplate::parse("{seq}{ext}").unwrap();
+
+    // new.png -> 1.png -> 2.png -> 3.png only works back to front
+    let new = add(&mut engine, taken(&path("new.png"), 1)).await;
+    let one = add(&mut engine, taken(&path("1.png"), 2)).await;
+    let two = add(&mut engine, taken(&path("2.png"), 3)).await;
+    let plan = plan_rename(&pool, &[one, two, new], &template)
+        .await
+        .unwrap();
+    assert!(plan.collisions.is_empty());
+    let order: Vec<&Path> = plan.renames.iter().map(|r| r.to.as_path()).collect();
+    assert_eq!(order, vec![path("3.png"), path("2.png"), path("1.png")]);
+    apply_rename(&mut engine, &plan).await.unwrap();
+    assert_eq!(catalogued(&pool).await, vec!["1.png", "2.png", "3.png"]);
+
+    // Swapping two names can't be done one file at a time
+    let first = id_of(&pool, &path("1.png")).await;
+    let second = id_of(&pool, &path("2.png")).await;
+    sqlx::query("UPDATE photos SET date_taken_offset = 600 WHERE id = ?")
+        .bind(first)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let plan = plan_rename(&pool, &[first, second], &template)
+        .await
+        .unwrap();
+    assert!(plan.renames.is_empty());
+    assert_eq!(plan.collisions.len(), 2);
+    assert!(apply_rename(&mut engine, &plan).await.is_err());
+
+    // Names taken by files outside the batch, and names shared within it
+    fs::write(path("taken.png"), b"not catalogued").unwrap();
+    let fixed = RenameTemplate::parse("taken{ext}").unwrap();
+    let plan = plan_rename(&pool, &[first], &fixed).await.unwrap();
+    assert_eq!(plan.collisions[0].target, path("taken.png"));
+    let plan = plan_rename(
+        &pool,
+        &[first, second],
+        &RenameTemplate::parse("same{ext}").unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(plan.collisions.len(), 1);
+    assert_eq!(plan.collisions[0].sources.len(), 2);
+    assert!(plan.renames.is_empty());
+}
+
+#[tokio::test]
+async fn test_failed_batch_rename_restores_renamed_files() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    let a = add(&mut engine, taken(&path("a.png"), 1)).await;
+    let b = add(&mut engine, taken(&path("b.png"), 2)).await;
+    let template = RenameTemplate::parse("renamed_{seq}{ext}").unwrap();
+    let plan = plan_rename(&pool, &[a, b], &template).await.unwrap();
+    assert_eq!(plan.renames.len(), 2);
+
+    // Something takes the second name between preview and apply
+    fs::write(path("renamed_2.png"), b"appeared").unwrap();
+    assert!(apply_rename(&mut engine, &plan).await.is_err());
+
+    assert!(path("a.png").exists());
+    assert!(!path("renamed_1.png").exists());
+    assert_eq!(fs::read(path("renamed_2.png")).unwrap(), b"appeared");
+    assert_eq!(catalogued(&pool).await, vec!["a.png", "b.png"]);
+}
+
+#[tokio::test]
+async fn test_batch_rename_keeps_renames_the_catalog_committed() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), Some(backup_pool));
+    let path = |name: &str| library.path().join(name);
+
+    let a = add(&mut engine, taken(&path("a.png"), 1)).await;
+    let template = RenameTemplate::parse("renamed_{seq}{ext}").unwrap();
+    let plan = plan_rename(&pool, &[a], &template).await.unwrap();
+
+    break_backup_mirror(&engine).await;
+    assert!(apply_rename(&mut engine, &plan).await.is_err());
+    // The file keeps the name the catalog now has
+    assert!(path("renamed_1.png").exists());
+    assert!(!path("a.png").exists());
+    assert_eq!(catalogued(&pool).await, vec!["renamed_1.png"]);
+}
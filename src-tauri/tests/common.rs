@@ -1,5 +1,13 @@
+// Each test binary compiles this module and uses only some of its helpers
+#![allow(dead_code)]
+
 use photovault::db::manager::DatabaseManager;
-use sqlx::{Pool, Sqlite};
+use photovault::models::photo::Photo;
+use photovault::services::formats::handler_for;
+use photovault::services::sync_engine::SyncEngine;
+use sqlx::{Pool, Sqlite, SqlitePool};
+use std::fs;
+use std::path::Path;
 
 /// Creates a new, anonymous, in-memory database pool for testing.
 pub async fn create_in_memory_db_pool() -> Pool<Sqlite> {
@@ -7,3 +15,63 @@ pub async fn create_in_memory_db_pool() -> Pool<Sqlite> {
     let db_path = std::path::Path::new(":memory:");
     DatabaseManager::create_pool(db_path).await.unwrap()
 }
+
+/// An uncatalogued photo at `path`, named after it and with the format its
+/// extension implies. The file itself needn't exist.
+pub fn photo(path: impl AsRef<Path>) -> Photo {
+    let path = path.as_ref();
+    Photo {
+        path: path.to_str().unwrap().to_string(),
+        filename: path.file_name().unwrap().to_str().unwrap().to_string(),
+        format: handler_for(path)
+            .map_or("", |handler| handler.name())
+            .to_string(),
+        ..Default::default()
+    }
+}
+
+/// Writes a file at `path` whose content is its own path, so no two files
+/// are identical, and returns a photo for it.
+pub fn photo_file(path: &Path) -> Photo {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, path.to_str().unwrap()).unwrap();
+    Photo {
+        file_size: Some(path.to_str().unwrap().len() as i64),
+        ..photo(path)
+    }
+}
+
+/// Catalogs `photo` and returns its id.
+pub async fn add(engine: &mut SyncEngine, photo: Photo) -> i64 {
+    let path = photo.path.clone();
+    engine.add_photo(photo).await.unwrap();
+    id_of(&engine.primary_db, path).await
+}
+
+/// The catalog id of the photo at `path`.
+pub async fn id_of(pool: &SqlitePool, path: impl AsRef<Path>) -> i64 {
+    sqlx::query_scalar("SELECT id FROM photos WHERE path = ?")
+        .bind(path.as_ref().to_str())
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+/// Makes mirroring to `engine`'s backup fail, and recording that failure on
+/// the primary fail too, so operations error after the primary commits.
+pub async fn break_backup_mirror(engine: &SyncEngine) {
+    sqlx::query(
+        "CREATE TRIGGER refuse_operations BEFORE INSERT ON sync_operations
+         BEGIN SELECT RAISE(ABORT, 'backup refused the operation'); END",
+    )
+    .execute(engine.backup_db().unwrap())
+    .await
+    .unwrap();
+    sqlx::query(
+        "CREATE TRIGGER refuse_status BEFORE UPDATE ON sync_operations
+         BEGIN SELECT RAISE(ABORT, 'sync queue is read-only'); END",
+    )
+    .execute(&engine.primary_db)
+    .await
+    .unwrap();
+}
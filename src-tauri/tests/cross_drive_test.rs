@@ -0,0 +1,181 @@
+mod common;
+
+use photovault::db::manager::DatabaseManager;
+use photovault::models::photo::{modified_millis, Photo};
+use photovault::services::cross_drive::{compare_drives, CrossDriveGroup, CrossDriveKind, Drive};
+use photovault::services::file_mirror::FileMirror;
+use photovault::services::sync_engine::SyncEngine;
+use sqlx::SqlitePool;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::sync::Mutex;
+
+/// Writes `bytes` to `location` and catalogs it in `pool` under `path`.
+async fn catalog(pool: &SqlitePool, path: &Path, location: &Path, bytes: &[u8]) {
+    fs::create_dir_all(location.parent().unwrap()).unwrap();
+    fs::write(location, bytes).unwrap();
+    let metadata = fs::metadata(location).unwrap();
+    let photo = Photo {
+        file_size: Some(metadata.len() as i64),
+        file_mtime: modified_millis(&metadata),
+        ..common::photo(path)
+    };
+    SyncEngine::new(pool.clone(), None)
+        .add_photo(photo)
+        .await
+        .unwrap();
+}
+
+fn summary(groups: &[CrossDriveGroup], kind: CrossDriveKind) -> Vec<Vec<(Drive, PathBuf)>> {
+    let mut found: Vec<Vec<(Drive, PathBuf)>> = groups
+        .iter()
+        .filter(|group| group.kind == kind)
+        .map(|group| {
+            group
+                .copies
+                .iter()
+                .map(|copy| (copy.drive, copy.path.clone()))
+                .collect()
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+#[tokio::test]
+async fn test_compare_drives_finds_relocated_diverged_and_orphaned_files() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let primary = |name: &str| library.path().join(name);
+    let backup_path = |name: &str| backup.path().join(name);
+
+    for (name, bytes) in [
+        ("same.jpg", &b"same"[..]),
+        ("sizes.jpg", b"short"),
+        ("start.jpg", b"AAAA-content"),
+        ("new/moved.jpg", b"moved content"),
+        ("fresh.jpg", b"twelve bytes"),
+    ] {
+        catalog(&pool, &primary(name), &primary(name), bytes).await;
+    }
+    for (name, bytes) in [
+        ("same.jpg", &b"same"[..]),
+        ("sizes.jpg", b"much longer"),
+        ("start.jpg", b"BBBB-content"),
+        ("old/moved.jpg", b"moved content"),
+        ("gone.jpg", b"only on the backup"),
+        ("stale.jpg", b"twelve BYTES"),
+    ] {
+        catalog(&backup_pool, &primary(name), &backup_path(name), bytes).await;
+    }
+    // Left to reconcile, as its file is gone
+    catalog(&pool, &primary("lost.jpg"), &primary("lost.jpg"), b"lost").await;
+    fs::remove_file(primary("lost.jpg")).unwrap();
+
+    let engine = Mutex::new(Some(
+        SyncEngine::new(pool.clone(), Some(backup_pool.clone())).with_file_mirror(FileMirror::new(
+            library.path().to_path_buf(),
+            backup.path().to_path_buf(),
+        )),
+    ));
+    let groups = compare_drives(&engine, false).await.unwrap();
+    assert_eq!(
+        summary(&groups, CrossDriveKind::Diverged),
+        vec![
+            vec![
+                (Drive::Primary, primary("sizes.jpg")),
+                (Drive::Backup, backup_path("sizes.jpg"))
+            ],
+            vec![
+                (Drive::Primary, primary("start.jpg")),
+                (Drive::Backup, backup_path("start.jpg"))
+            ],
+        ]
+    );
+    assert_eq!(
+        summary(&groups, CrossDriveKind::Relocated),
+        vec![vec![
+            (Drive::Primary, primary("new/moved.jpg")),
+            (Drive::Backup, backup_path("old/moved.jpg"))
+        ]]
+    );
+    let relocated = groups
+        .iter()
+        .find(|group| group.kind == CrossDriveKind::Relocated)
+        .unwrap();
+    assert!(relocated.hash.is_some());
+    assert!(relocated.copies[0].photo_id.is_some());
+    assert_eq!(
+        summary(&groups, CrossDriveKind::Orphaned),
+        vec![
+            vec![(Drive::Primary, primary("fresh.jpg"))],
+            vec![(Drive::Backup, backup_path("gone.jpg"))],
+            vec![(Drive::Backup, backup_path("stale.jpg"))],
+        ]
+    );
+
+    // Hashes are saved, and only files something could match are read
+    let hashed = |pool: &SqlitePool| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_scalar::<_, String>(
+                "SELECT filename FROM photos WHERE file_hash IS NOT NULL ORDER BY filename",
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+        }
+    };
+    assert_eq!(
+        hashed(&pool).await,
+        vec!["fresh.jpg", "moved.jpg", "same.jpg", "start.jpg"]
+    );
+    assert_eq!(
+        hashed(&backup_pool).await,
+        vec!["moved.jpg", "same.jpg", "stale.jpg", "start.jpg"]
+    );
+
+    // Saved hashes are trusted until the file changes, or hashes are verified
+    let rewrite = |path: PathBuf, bytes: &[u8], later: u64| {
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, bytes).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(later))
+            .unwrap();
+    };
+    rewrite(backup_path("same.jpg"), b"SAME", 0);
+    rewrite(primary("start.jpg"), b"BBBB-content", 1);
+    let groups = compare_drives(&engine, false).await.unwrap();
+    assert_eq!(
+        summary(&groups, CrossDriveKind::Diverged),
+        vec![vec![
+            (Drive::Primary, primary("sizes.jpg")),
+            (Drive::Backup, backup_path("sizes.jpg"))
+        ]]
+    );
+    let groups = compare_drives(&engine, true).await.unwrap();
+    assert_eq!(
+        summary(&groups, CrossDriveKind::Diverged),
+        vec![
+            vec![
+                (Drive::Primary, primary("same.jpg")),
+                (Drive::Backup, backup_path("same.jpg"))
+            ],
+            vec![
+                (Drive::Primary, primary("sizes.jpg")),
+                (Drive::Backup, backup_path("sizes.jpg"))
+            ],
+        ]
+    );
+}
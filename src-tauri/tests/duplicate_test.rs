@@ -0,0 +1,211 @@
+mod common;
+
+use common::add;
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::duplicate::{
+    difference_hash, DuplicateDetector, HashStage, DEFAULT_MAX_DISTANCE, PARTIAL_HASH_BYTES,
+};
+use photovault::services::sync_engine::SyncEngine;
+use std::collections::HashMap;
+use tempfile::tempdir;
+
+/// Soft blobs of colour, so the picture survives scaling and JPEG.
+fn picture() -> RgbImage {
+    RgbImage::from_fn(400, 300, |x, y| {
+        let wave = (x as f64 / 40.0).sin() * (y as f64 / 30.0).cos();
+        let value = (128.0 + 100.0 * wave) as u8;
+        Rgb([value, value / 2, 255 - value])
+    })
+}
+
+/// Fades from white to black, left to right.
+fn other_picture() -> RgbImage {
+    RgbImage::from_fn(400, 300, |x, _| {
+        let value = 255 - (x * 255 / 400) as u8;
+        Rgb([value, value, value])
+    })
+}
+
+#[tokio::test]
+async fn test_similar_photos_include_resized_copies() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    let picture = picture();
+    picture.save(path("original.png")).unwrap();
+    std::fs::copy(path("original.png"), path("copy.png")).unwrap();
+    imageops::resize(&picture, 160, 120, imageops::FilterType::Triangle)
+        .save(path("small.jpg"))
+        .unwrap();
+    other_picture().save(path("other.png")).unwrap();
+    let original = add(
+        &mut engine,
+        Photo::new_from_path(path("original.png")).unwrap(),
+    )
+    .await;
+    let copy = add(&mut engine, Photo::new_from_path(path("copy.png")).unwrap()).await;
+    let small = add(
+        &mut engine,
+        Photo::new_from_path(path("small.jpg")).unwrap(),
+    )
+    .await;
+    let other = add(
+        &mut engine,
+        Photo::new_from_path(path("other.png")).unwrap(),
+    )
+    .await;
+
+    let exact = DuplicateDetector::find_duplicates(&pool).await.unwrap();
+    assert_eq!(exact.len(), 1);
+    let mut ids: Vec<i64> = exact[0].photos.iter().map(|photo| photo.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![original, copy]);
+    assert_eq!(exact[0].similarity, 1.0);
+
+    let similar = DuplicateDetector::find_similar(&pool, DEFAULT_MAX_DISTANCE)
+        .await
+        .unwrap();
+    assert_eq!(similar.len(), 1);
+    let ids: Vec<i64> = similar[0].photos.iter().map(|photo| photo.id).collect();
+    assert_eq!(ids, vec![original, copy, small]);
+    assert!(similar[0].similarity > 0.8);
+    let hashed: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM photos WHERE perceptual_hash IS NOT NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(hashed, 4);
+
+    // The other picture is nowhere near
+    let distance = (difference_hash(&DynamicImage::ImageRgb8(picture))
+        ^ difference_hash(&DynamicImage::ImageRgb8(other_picture())))
+    .count_ones();
+    assert!(distance > 2 * DEFAULT_MAX_DISTANCE);
+    assert!(similar
+        .iter()
+        .all(|group| group.photos.iter().all(|photo| photo.id != other)));
+
+    // Only identical images are left at no tolerance
+    let identical = DuplicateDetector::find_similar(&pool, 0).await.unwrap();
+    assert!(identical
+        .iter()
+        .all(|group| group.similarity == 1.0 && group.photos.len() >= 2));
+}
+
+#[tokio::test]
+async fn test_similar_photos_leave_out_stacked_pairs() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    // A RAW previews as the JPEG it was shot with
+    picture().save(path("IMG_1.png")).unwrap();
+    picture().save(path("IMG_1.jpg")).unwrap();
+    for name in ["IMG_1.png", "IMG_1.jpg"] {
+        add(&mut engine, Photo::new_from_path(path(name)).unwrap()).await;
+    }
+    assert_eq!(
+        DuplicateDetector::find_similar(&pool, DEFAULT_MAX_DISTANCE)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("IMG_1.png"), path("IMG_1.jpg")],
+            representative: path("IMG_1.jpg"),
+        })
+        .await
+        .unwrap();
+    assert!(DuplicateDetector::find_similar(&pool, DEFAULT_MAX_DISTANCE)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_duplicate_scan_only_reads_what_it_must() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    let size = 3 * PARTIAL_HASH_BYTES as usize;
+    let content = vec![7u8; size];
+    let mut middle = content.clone();
+    middle[size / 2] = 8;
+    let mut start = content.clone();
+    start[0] = 8;
+    let files = [
+        ("a.png", content.clone()),
+        ("b.png", content.clone()),
+        ("middle.png", middle),
+        ("start.png", start),
+        ("unique.png", vec![7u8; size + 1]),
+    ];
+    let mut ids = HashMap::new();
+    for (name, bytes) in files {
+        std::fs::write(path(name), &bytes).unwrap();
+        let photo = Photo {
+            file_size: Some(bytes.len() as i64),
+            ..common::photo(path(name))
+        };
+        ids.insert(name, add(&mut engine, photo).await);
+    }
+
+    let mut reports = Vec::new();
+    let groups = DuplicateDetector::find_duplicates_with_progress(&pool, |progress| {
+        reports.push((progress.stage, progress.hashed, progress.total))
+    })
+    .await
+    .unwrap();
+    assert_eq!(groups.len(), 1);
+    let grouped: Vec<i64> = groups[0].photos.iter().map(|photo| photo.id).collect();
+    assert_eq!(grouped, vec![ids["a.png"], ids["b.png"]]);
+    assert_eq!(groups[0].size, 2 * size as i64);
+    assert_eq!(
+        reports,
+        vec![(HashStage::Partial, 4, 4), (HashStage::Full, 3, 3)]
+    );
+
+    let hashes: HashMap<String, (Option<String>, Option<String>)> =
+        sqlx::query_as::<_, (String, Option<String>, Option<String>)>(
+            "SELECT filename, partial_hash, file_hash FROM photos",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(name, partial, full)| (name, (partial, full)))
+        .collect();
+    // Its size sets it apart, so it's never opened
+    assert_eq!(hashes["unique.png"], (None, None));
+    // Its first bytes set it apart
+    assert!(hashes["start.png"].0.is_some() && hashes["start.png"].1.is_none());
+    assert!(hashes["middle.png"].1.is_some());
+    assert_ne!(hashes["middle.png"].1, hashes["a.png"].1);
+
+    // Saved hashes are reused, so a second run reads nothing
+    let mut reports = Vec::new();
+    let again = DuplicateDetector::find_duplicates_with_progress(&pool, |progress| {
+        reports.push(progress.total)
+    })
+    .await
+    .unwrap();
+    assert_eq!(again.len(), 1);
+    assert_eq!(reports, vec![0, 0]);
+}
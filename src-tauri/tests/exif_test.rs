@@ -0,0 +1,145 @@
+use chrono::{TimeZone, Utc};
+use image::{ImageOutputFormat, RgbImage};
+use photovault::models::photo::Photo;
+use photovault::services::exif::read_exif;
+use std::io::Cursor;
+use tempfile::tempdir;
+
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+
+/// Serialises a little-endian TIFF IFD located at `offset`, placing values
+/// larger than four bytes directly after the entry table.
+fn build_ifd(offset: u32, entries: &[(u16, u16, u32, Vec<u8>)]) -> Vec<u8> {
+    let table_len = 2 + entries.len() as u32 * 12 + 4;
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+
+    table.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, kind, count, value) in entries {
+        table.extend_from_slice(&tag.to_le_bytes());
+        table.extend_from_slice(&kind.to_le_bytes());
+        table.extend_from_slice(&count.to_le_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            table.extend_from_slice(&inline);
+        } else {
+            let value_offset = offset + table_len + data.len() as u32;
+            table.extend_from_slice(&value_offset.to_le_bytes());
+            data.extend_from_slice(value);
+        }
+    }
+    table.extend_from_slice(&0u32.to_le_bytes());
+    table.extend_from_slice(&data);
+    table
+}
+
+fn ascii(value: &str) -> (u32, Vec<u8>) {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    (bytes.len() as u32, bytes)
+}
+
+fn rational(num: u32, denom: u32) -> Vec<u8> {
+    [num.to_le_bytes(), denom.to_le_bytes()].concat()
+}
+
+/// Builds a JPEG carrying an APP1 EXIF segment with typical camera fields.
+fn jpeg_with_exif() -> Vec<u8> {
+    let (make_len, make) = ascii("FUJIFILM");
+    let (model_len, model) = ascii("X100V");
+    let (date_len, date) = ascii("2023:06:15 14:30:05");
+    let (offset_len, offset) = ascii("+02:00");
+
+    let ifd0_entries = |exif_offset: u32| {
+        vec![
+            (0x010f, ASCII, make_len, make.clone()),
+            (0x0110, ASCII, model_len, model.clone()),
+            (0x0112, SHORT, 1, 6u16.to_le_bytes().to_vec()),
+            (0x8769, LONG, 1, exif_offset.to_le_bytes().to_vec()),
+        ]
+    };
+    let ifd0_len = build_ifd(8, &ifd0_entries(0)).len() as u32;
+    let exif_offset = 8 + ifd0_len;
+
+    let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+    tiff.extend(build_ifd(8, &ifd0_entries(exif_offset)));
+    tiff.extend(build_ifd(
+        exif_offset,
+        &[
+            (0x829a, RATIONAL, 1, rational(1, 250)),
+            (0x829d, RATIONAL, 1, rational(28, 10)),
+            (0x8827, SHORT, 1, 400u16.to_le_bytes().to_vec()),
+            (0x9003, ASCII, date_len, date),
+            (0x9011, ASCII, offset_len, offset),
+            (0x920a, RATIONAL, 1, rational(230, 10)),
+        ],
+    ));
+
+    let mut segment = b"Exif\0\0".to_vec();
+    segment.extend(tiff);
+
+    let mut encoded = Cursor::new(Vec::new());
+    RgbImage::new(8, 8)
+        .write_to(&mut encoded, ImageOutputFormat::Jpeg(90))
+        .unwrap();
+    let encoded = encoded.into_inner();
+
+    let mut jpeg = encoded[..2].to_vec();
+    jpeg.extend_from_slice(&[0xff, 0xe1]);
+    jpeg.extend_from_slice(&((segment.len() + 2) as u16).to_be_bytes());
+    jpeg.extend(segment);
+    jpeg.extend_from_slice(&encoded[2..]);
+    jpeg
+}
+
+#[test]
+fn test_read_exif_from_jpeg() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("DSCF0001.jpg");
+    std::fs::write(&path, jpeg_with_exif()).unwrap();
+
+    let exif = read_exif(&path).unwrap();
+    assert_eq!(
+        exif.date_taken,
+        Some(Utc.with_ymd_and_hms(2023, 6, 15, 12, 30, 5).unwrap())
+    );
+    assert_eq!(exif.date_taken_offset, Some(120));
+    assert_eq!(exif.camera_make.as_deref(), Some("FUJIFILM"));
+    assert_eq!(exif.camera_model.as_deref(), Some("X100V"));
+    assert_eq!(exif.iso, Some(400));
+    assert_eq!(exif.aperture, Some(2.8));
+    assert_eq!(exif.exposure_time, Some(0.004));
+    assert_eq!(exif.focal_length, Some(23.0));
+    assert_eq!(exif.orientation, Some(6));
+}
+
+#[test]
+fn test_photo_without_exif_has_no_capture_metadata() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("plain.png");
+    RgbImage::new(4, 4).save(&path).unwrap();
+
+    let photo = Photo::new_from_path(path).unwrap();
+    assert_eq!(photo.width, Some(4));
+    assert!(photo.date_taken.is_none());
+    assert!(photo.camera_model.is_none());
+}
+
+#[test]
+fn test_photo_from_path_reads_exif() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("DSCF0002.jpg");
+    std::fs::write(&path, jpeg_with_exif()).unwrap();
+
+    let photo = Photo::new_from_path(path).unwrap();
+    assert_eq!(photo.width, Some(8));
+    assert_eq!(photo.camera_model.as_deref(), Some("X100V"));
+    assert_eq!(
+        photo.date_taken,
+        Some(Utc.with_ymd_and_hms(2023, 6, 15, 12, 30, 5).unwrap())
+    );
+}
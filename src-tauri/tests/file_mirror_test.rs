@@ -0,0 +1,121 @@
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::file_mirror::FileMirror;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn photo_at(path: &Path) -> Box<Photo> {
+    Box::new(Photo {
+        path: path.to_str().unwrap().to_string(),
+        filename: path.file_name().unwrap().to_str().unwrap().to_string(),
+        format: "Jpeg".to_string(),
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn test_add_photo_copies_file_with_relative_layout() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mirror = FileMirror::new(primary.path().to_path_buf(), backup.path().to_path_buf());
+
+    let source = primary.path().join("2024/06/beach.jpg");
+    fs::create_dir_all(source.parent().unwrap()).unwrap();
+    fs::write(&source, b"beach bytes").unwrap();
+
+    mirror
+        .apply(&Operation::AddPhoto {
+            photo: photo_at(&source),
+        })
+        .await
+        .unwrap();
+
+    let copied = backup.path().join("2024/06/beach.jpg");
+    assert_eq!(fs::read(&copied).unwrap(), b"beach bytes");
+    assert!(!backup
+        .path()
+        .join("2024/06/beach.jpg.photovault-partial")
+        .exists());
+}
+
+#[tokio::test]
+async fn test_move_rename_and_delete_follow_primary() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mirror = FileMirror::new(primary.path().to_path_buf(), backup.path().to_path_buf());
+
+    let original = primary.path().join("inbox/a.jpg");
+    fs::create_dir_all(original.parent().unwrap()).unwrap();
+    fs::write(&original, b"a").unwrap();
+    mirror
+        .apply(&Operation::AddPhoto {
+            photo: photo_at(&original),
+        })
+        .await
+        .unwrap();
+
+    let moved = primary.path().join("sorted/a.jpg");
+    fs::create_dir_all(moved.parent().unwrap()).unwrap();
+    fs::rename(&original, &moved).unwrap();
+    mirror
+        .apply(&Operation::Move {
+            from: original.clone(),
+            to: moved.clone(),
+        })
+        .await
+        .unwrap();
+    assert!(!backup.path().join("inbox/a.jpg").exists());
+    assert!(backup.path().join("sorted/a.jpg").exists());
+
+    fs::rename(&moved, moved.with_file_name("b.jpg")).unwrap();
+    mirror
+        .apply(&Operation::Rename {
+            path: moved.clone(),
+            new_name: "b.jpg".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(backup.path().join("sorted/b.jpg").exists());
+
+    mirror
+        .apply(&Operation::Delete {
+            path: moved.with_file_name("b.jpg"),
+        })
+        .await
+        .unwrap();
+    assert!(!backup.path().join("sorted/b.jpg").exists());
+}
+
+#[tokio::test]
+async fn test_move_recopies_when_backup_copy_is_missing() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mirror = FileMirror::new(primary.path().to_path_buf(), backup.path().to_path_buf());
+
+    let moved = primary.path().join("b.jpg");
+    fs::write(&moved, b"b").unwrap();
+    mirror
+        .apply(&Operation::Move {
+            from: primary.path().join("a.jpg"),
+            to: moved,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(fs::read(backup.path().join("b.jpg")).unwrap(), b"b");
+}
+
+#[test]
+fn test_backup_path_rejects_paths_outside_primary() {
+    let mirror = FileMirror::new(PathBuf::from("/photos"), PathBuf::from("/backup"));
+    assert_eq!(
+        mirror
+            .backup_path(&PathBuf::from("/photos/x/y.jpg"))
+            .unwrap(),
+        PathBuf::from("/backup/x/y.jpg")
+    );
+    assert!(mirror
+        .backup_path(&PathBuf::from("/elsewhere/y.jpg"))
+        .is_err());
+}
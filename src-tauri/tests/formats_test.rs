@@ -0,0 +1,372 @@
+use chrono::{TimeZone, Utc};
+use image::{GenericImageView, ImageFormat, RgbImage};
+use photovault::models::photo::{MediaType, Photo};
+use photovault::services::formats::{handler_for, FormatRegistry, NoPreview};
+use std::io::Cursor;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn iso_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(kind);
+    data.extend_from_slice(body);
+    data
+}
+
+/// The boxes a HEIC writer puts in front of the image data, with the primary
+/// item's `ispe` property listed after an unrelated one.
+fn heic_file(width: u32, height: u32) -> Vec<u8> {
+    let pitm = iso_box(b"pitm", &[0, 0, 0, 0, 0, 1]);
+    let mut ispe = vec![0; 4];
+    ispe.extend_from_slice(&width.to_be_bytes());
+    ispe.extend_from_slice(&height.to_be_bytes());
+    let ipco = [
+        iso_box(b"colr", b"nclx\0\0\0\0\0\0\0"),
+        iso_box(b"ispe", &ispe),
+    ]
+    .concat();
+    // Item 2 (the thumbnail) has property 1; item 1 has properties 1 and 2
+    let ipma = [0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 1, 0x01, 0, 1, 2, 0x81, 0x02];
+    let iprp = iso_box(
+        b"iprp",
+        &[iso_box(b"ipco", &ipco), iso_box(b"ipma", &ipma)].concat(),
+    );
+    let meta = iso_box(b"meta", &[vec![0; 4], pitm, iprp].concat());
+    [
+        iso_box(b"ftyp", b"heic\0\0\0\0mif1heic"),
+        meta,
+        iso_box(b"mdat", &[0; 16]),
+    ]
+    .concat()
+}
+
+/// A HEIC whose item 2 is a JPEG thumbnail of the HEVC-coded item 1, stored
+/// in `mdat` and located through `iloc`.
+fn heic_with_thumbnail(thumbnail: &[u8]) -> Vec<u8> {
+    let infe = |id: u16, kind: &[u8; 4]| {
+        let body = [&[2, 0, 0, 0][..], &id.to_be_bytes(), &[0, 0], kind, b"\0"].concat();
+        iso_box(b"infe", &body)
+    };
+    let iinf = iso_box(
+        b"iinf",
+        &[vec![0, 0, 0, 0, 0, 2], infe(1, b"hvc1"), infe(2, b"jpeg")].concat(),
+    );
+    let ftyp = iso_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+    let meta = |offset: u32| {
+        let mut iloc = vec![0, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 2, 0, 0, 0, 1];
+        iloc.extend_from_slice(&offset.to_be_bytes());
+        iloc.extend_from_slice(&(thumbnail.len() as u32).to_be_bytes());
+        iso_box(
+            b"meta",
+            &[vec![0; 4], iinf.clone(), iso_box(b"iloc", &iloc)].concat(),
+        )
+    };
+    // The thumbnail starts right after the `mdat` header
+    let offset = (ftyp.len() + meta(0).len() + 8) as u32;
+    [ftyp.clone(), meta(offset), iso_box(b"mdat", thumbnail)].concat()
+}
+
+fn full_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    iso_box(kind, &[&[0, 0, 0, 0], body].concat())
+}
+
+fn be32(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+/// A video track as a phone writes it: the stored size, rotated a quarter
+/// turn by the `tkhd` matrix. Sample tables are only filled in for Motion
+/// JPEG, whose first frame sits at `frame`.
+fn video_trak(codec: &[u8; 4], width: u32, height: u32, frame: Option<(u32, u32)>) -> Vec<u8> {
+    let matrix = [0, 0x0001_0000, 0, 0xffff_0000, 0, 0, 0, 0, 0x4000_0000];
+    let tkhd = full_box(
+        b"tkhd",
+        &[
+            be32(&[0, 0, 1, 0, 0, 0, 0, 0, 0]),
+            be32(&matrix),
+            be32(&[width << 16, height << 16]),
+        ]
+        .concat(),
+    );
+    let hdlr = full_box(
+        b"hdlr",
+        &[be32(&[0]), b"vide".to_vec(), vec![0; 12]].concat(),
+    );
+    let mut entry = vec![0u8; 24];
+    entry.extend_from_slice(&(width as u16).to_be_bytes());
+    entry.extend_from_slice(&(height as u16).to_be_bytes());
+    entry.extend_from_slice(&[0; 50]);
+    let stsd = full_box(b"stsd", &[be32(&[1]), iso_box(codec, &entry)].concat());
+    let mut stbl = stsd;
+    if let Some((offset, length)) = frame {
+        stbl.extend(full_box(b"stsz", &be32(&[0, 1, length])));
+        stbl.extend(full_box(b"stco", &be32(&[1, offset])));
+    }
+    let minf = iso_box(b"minf", &iso_box(b"stbl", &stbl));
+    let mdia = iso_box(b"mdia", &[hdlr, minf].concat());
+    iso_box(b"trak", &[tkhd, mdia].concat())
+}
+
+/// A `moov` box with a sound track ahead of the video track, for a movie
+/// created at 2024-06-01T12:00:00Z and 12.345 seconds long.
+fn moov(trak: Vec<u8>, cover: Option<&[u8]>) -> Vec<u8> {
+    // Seconds since 1904-01-01
+    let created = 1_717_243_200 + 2_082_844_800;
+    let mvhd = full_box(
+        b"mvhd",
+        &[be32(&[created, created, 1000, 12_345]), vec![0; 80]].concat(),
+    );
+    let sound = iso_box(
+        b"trak",
+        &iso_box(
+            b"mdia",
+            &full_box(
+                b"hdlr",
+                &[be32(&[0]), b"soun".to_vec(), vec![0; 12]].concat(),
+            ),
+        ),
+    );
+    let mut moov = [mvhd, sound, trak].concat();
+    if let Some(cover) = cover {
+        let data = iso_box(b"data", &[be32(&[14, 0]), cover.to_vec()].concat());
+        let ilst = iso_box(b"ilst", &iso_box(b"covr", &data));
+        let hdlr = full_box(
+            b"hdlr",
+            &[be32(&[0]), b"mdir".to_vec(), vec![0; 12]].concat(),
+        );
+        moov.extend(iso_box(b"udta", &full_box(b"meta", &[hdlr, ilst].concat())));
+    }
+    iso_box(b"moov", &moov)
+}
+
+fn mp4_file(moov: Vec<u8>) -> Vec<u8> {
+    [iso_box(b"ftyp", b"isom\0\0\0\0isommp42"), moov].concat()
+}
+
+fn jpeg(width: u32, height: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    RgbImage::from_pixel(width, height, image::Rgb([200, 40, 40]))
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+        .unwrap();
+    data
+}
+
+/// A little-endian DNG-like TIFF: IFD0 is a small JPEG preview, its SubIFD is
+/// the full-size sensor image stored as lossless JPEG.
+fn raw_file(preview: &[u8]) -> Vec<u8> {
+    // SOI, then a lossless (SOF3) frame header a standard decoder can't read
+    let sensor = [
+        0xff, 0xd8, 0xff, 0xc3, 0x00, 0x0b, 8, 0, 4, 0, 4, 1, 1, 0x11, 0,
+    ]
+    .iter()
+    .copied()
+    .chain(std::iter::repeat_n(0, preview.len() * 2))
+    .collect::<Vec<u8>>();
+
+    let entry = |tag: u16, kind: u16, value: u32| {
+        let mut data = tag.to_le_bytes().to_vec();
+        data.extend_from_slice(&kind.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&value.to_le_bytes());
+        data
+    };
+    let ifd = |entries: Vec<Vec<u8>>| {
+        let mut data = (entries.len() as u16).to_le_bytes().to_vec();
+        data.extend(entries.concat());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data
+    };
+
+    let ifd0_offset = 8u32;
+    let ifd_len = |count: u32| 2 + count * 12 + 4;
+    let sub_ifd_offset = ifd0_offset + ifd_len(6);
+    let preview_offset = sub_ifd_offset + ifd_len(6);
+    let sensor_offset = preview_offset + preview.len() as u32;
+
+    let ifd0 = ifd(vec![
+        entry(0x00fe, 4, 1),
+        entry(0x0100, 4, 160),
+        entry(0x0101, 4, 120),
+        entry(0x014a, 13, sub_ifd_offset),
+        entry(0x0201, 4, preview_offset),
+        entry(0x0202, 4, preview.len() as u32),
+    ]);
+    let sub_ifd = ifd(vec![
+        entry(0x00fe, 4, 0),
+        entry(0x0100, 4, 6000),
+        entry(0x0101, 4, 4000),
+        entry(0x0103, 3, 7),
+        entry(0x0111, 4, sensor_offset),
+        entry(0x0117, 4, sensor.len() as u32),
+    ]);
+
+    let mut data = b"II".to_vec();
+    data.extend_from_slice(&42u16.to_le_bytes());
+    data.extend_from_slice(&ifd0_offset.to_le_bytes());
+    data.extend(ifd0);
+    data.extend(sub_ifd);
+    data.extend_from_slice(preview);
+    data.extend(sensor);
+    data
+}
+
+#[test]
+fn test_heic_dimensions_come_from_the_primary_item() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("IMG_0001.HEIC");
+    std::fs::write(&path, heic_file(4032, 3024)).unwrap();
+
+    let handler = handler_for(&path).unwrap();
+    assert_eq!(handler.name(), "Heic");
+    assert_eq!(handler.dimensions(&path).unwrap(), (4032, 3024));
+    assert!(handler.preview(&path).unwrap_err().is::<NoPreview>());
+
+    let photo = Photo::new_from_path(path).unwrap();
+    assert_eq!(photo.format, "Heic");
+    assert_eq!((photo.width, photo.height), (Some(4032), Some(3024)));
+}
+
+#[test]
+fn test_heic_box_sizes_that_overflow_are_rejected() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("IMG_0003.HEIC");
+    let mut free = 1u32.to_be_bytes().to_vec();
+    free.extend_from_slice(b"free");
+    free.extend_from_slice(&u64::MAX.to_be_bytes());
+    std::fs::write(
+        &path,
+        [iso_box(b"ftyp", b"heic\0\0\0\0mif1heic"), free].concat(),
+    )
+    .unwrap();
+
+    let error = handler_for(&path).unwrap().dimensions(&path).unwrap_err();
+    assert_eq!(error.to_string(), "Bad box size");
+}
+
+#[test]
+fn test_heic_preview_comes_from_the_embedded_thumbnail() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("IMG_0002.HEIC");
+    std::fs::write(&path, heic_with_thumbnail(&jpeg(32, 24))).unwrap();
+
+    let preview = handler_for(&path).unwrap().preview(&path).unwrap();
+    assert_eq!(preview.dimensions(), (32, 24));
+}
+
+#[test]
+fn test_raw_uses_sensor_size_and_embedded_preview() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("DSC_0001.dng");
+    std::fs::write(&path, raw_file(&jpeg(160, 120))).unwrap();
+
+    let handler = handler_for(&path).unwrap();
+    assert_eq!(handler.name(), "Dng");
+    assert_eq!(handler.dimensions(&path).unwrap(), (6000, 4000));
+    // The larger lossless sensor stream is skipped in favour of the preview
+    assert_eq!(handler.preview(&path).unwrap().dimensions(), (160, 120));
+
+    std::fs::write(&path, b"not a tiff").unwrap();
+    assert!(handler.dimensions(&path).is_err());
+}
+
+#[test]
+fn test_registry_only_supports_enabled_formats() {
+    let all = FormatRegistry::default();
+    assert!(all.is_supported(Path::new("a.JPG")));
+    assert!(all.is_supported(Path::new("a.webp")));
+    assert!(all.is_supported(Path::new("a.cr2")));
+    assert!(!all.is_supported(Path::new("a.txt")));
+    assert!(!all.is_supported(Path::new("jpg")));
+
+    let enabled = vec!["jpeg".to_string(), "Heic".to_string(), "Bogus".to_string()];
+    let some = FormatRegistry::new(Some(&enabled));
+    assert_eq!(some.handlers().len(), 2);
+    assert!(some.is_enabled("JPEG"));
+    assert!(some.is_supported(Path::new("a.jpeg")));
+    assert!(some.is_supported(Path::new("a.heif")));
+    assert!(!some.is_supported(Path::new("a.png")));
+    assert!(!some.is_supported(Path::new("a.nef")));
+
+    assert!(FormatRegistry::new(Some(&[])).handlers().is_empty());
+}
+
+#[test]
+fn test_video_metadata_comes_from_the_container() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("VID_0001.mp4");
+    std::fs::write(
+        &path,
+        mp4_file(moov(video_trak(b"hvc1", 1920, 1080, None), None)),
+    )
+    .unwrap();
+
+    let handler = handler_for(&path).unwrap();
+    assert_eq!(handler.name(), "Mp4");
+    let info = handler.video_info(&path).unwrap().unwrap();
+    // Stored landscape, displayed portrait
+    assert_eq!((info.width, info.height), (1080, 1920));
+    assert_eq!(info.duration_ms, Some(12_345));
+    assert_eq!(info.codec.as_deref(), Some("HEVC"));
+    assert!(handler.preview(&path).is_err());
+
+    let photo = Photo::new_from_path(path).unwrap();
+    assert_eq!(photo.media_type, MediaType::Video);
+    assert_eq!((photo.width, photo.height), (Some(1080), Some(1920)));
+    assert_eq!(photo.duration_ms, Some(12_345));
+    assert_eq!(photo.video_codec.as_deref(), Some("HEVC"));
+    assert_eq!(
+        photo.date_taken,
+        Some(Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap())
+    );
+
+    let still = dir.path().join("still.png");
+    RgbImage::new(4, 4).save(&still).unwrap();
+    assert!(handler_for(&still)
+        .unwrap()
+        .video_info(&still)
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        Photo::new_from_path(still).unwrap().media_type,
+        MediaType::Photo
+    );
+}
+
+#[test]
+fn test_video_posters_use_cover_art_or_the_first_jpeg_frame() {
+    let dir = tempdir().unwrap();
+
+    let mut cover = Vec::new();
+    RgbImage::new(300, 300)
+        .write_to(&mut Cursor::new(&mut cover), ImageFormat::Png)
+        .unwrap();
+    let with_cover = dir.path().join("clip.m4v");
+    std::fs::write(
+        &with_cover,
+        mp4_file(moov(video_trak(b"avc1", 640, 480, None), Some(&cover))),
+    )
+    .unwrap();
+    let handler = handler_for(&with_cover).unwrap();
+    assert_eq!(
+        handler.preview(&with_cover).unwrap().dimensions(),
+        (300, 300)
+    );
+
+    // Motion JPEG: the first frame lives in an `mdat` ahead of `moov`
+    let frame = jpeg(64, 48);
+    let ftyp = iso_box(b"ftyp", b"qt  \0\0\0\0qt  ");
+    let offset = (ftyp.len() + 8) as u32;
+    let trak = video_trak(b"jpeg", 64, 48, Some((offset, frame.len() as u32)));
+    let mjpeg = dir.path().join("CAMERA.MOV");
+    std::fs::write(
+        &mjpeg,
+        [ftyp, iso_box(b"mdat", &frame), moov(trak, None)].concat(),
+    )
+    .unwrap();
+
+    let handler = handler_for(&mjpeg).unwrap();
+    assert_eq!(handler.name(), "Mov");
+    let info = handler.video_info(&mjpeg).unwrap().unwrap();
+    assert_eq!(info.codec.as_deref(), Some("Motion JPEG"));
+    assert_eq!(handler.preview(&mjpeg).unwrap().dimensions(), (64, 48));
+}
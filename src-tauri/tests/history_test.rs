@@ -0,0 +1,357 @@
+mod common;
+
+use common::{add, break_backup_mirror, photo_file};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::services::file_mirror::FileMirror;
+use photovault::services::history::{list_history, redo, undo_last};
+use photovault::services::sync_engine::SyncEngine;
+use photovault::services::trash::{empty_trash, trash_photos};
+use sqlx::SqlitePool;
+use std::fs;
+use tempfile::{tempdir, TempDir};
+
+async fn path_of(pool: &SqlitePool, id: i64) -> String {
+    sqlx::query_scalar("SELECT path FROM photos WHERE id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn engine(library: &TempDir, backup: &TempDir) -> SyncEngine {
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    SyncEngine::new(pool, Some(backup_pool)).with_file_mirror(FileMirror::new(
+        library.path().to_path_buf(),
+        backup.path().to_path_buf(),
+    ))
+}
+
+#[tokio::test]
+async fn test_undo_and_redo_move_and_rename_on_both_drives() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mut engine = engine(&library, &backup).await;
+    let pool = engine.primary_db.clone();
+    let path = |name: &str| library.path().join(name);
+    let backup_path = |name: &str| backup.path().join(name);
+
+    let id = add(&mut engine, photo_file(&path("a.jpg"))).await;
+    fs::create_dir_all(path("trip")).unwrap();
+
+    fs::rename(path("a.jpg"), path("trip/a.jpg")).unwrap();
+    engine
+        .record_action("Move 1 photos", async |engine| {
+            engine
+                .execute_operation(&Operation::Move {
+                    from: path("a.jpg"),
+                    to: path("trip/a.jpg"),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+    fs::rename(path("trip/a.jpg"), path("trip/b.jpg")).unwrap();
+    engine
+        .record_action("Rename to b.jpg", async |engine| {
+            engine
+                .execute_operation(&Operation::Rename {
+                    path: path("trip/a.jpg"),
+                    new_name: "b.jpg".to_string(),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+    assert!(backup_path("trip/b.jpg").exists());
+
+    let undone = undo_last(&mut engine).await.unwrap().unwrap();
+    assert_eq!(undone.label, "Rename to b.jpg");
+    assert!(path("trip/a.jpg").exists());
+    assert!(backup_path("trip/a.jpg").exists());
+    assert!(!backup_path("trip/b.jpg").exists());
+    assert_eq!(
+        path_of(&pool, id).await,
+        path("trip/a.jpg").to_str().unwrap()
+    );
+
+    let undone = undo_last(&mut engine).await.unwrap().unwrap();
+    assert_eq!(undone.label, "Move 1 photos");
+    assert!(path("a.jpg").exists());
+    assert!(backup_path("a.jpg").exists());
+    assert_eq!(path_of(&pool, id).await, path("a.jpg").to_str().unwrap());
+    assert!(undo_last(&mut engine).await.unwrap().is_none());
+
+    // Redone oldest first
+    let redone = redo(&mut engine).await.unwrap().unwrap();
+    assert_eq!(redone.label, "Move 1 photos");
+    assert!(path("trip/a.jpg").exists());
+    assert!(backup_path("trip/a.jpg").exists());
+    let history = list_history(&pool, 10).await.unwrap();
+    assert_eq!(
+        history
+            .iter()
+            .map(|entry| (entry.label.as_str(), entry.undone))
+            .collect::<Vec<_>>(),
+        vec![("Rename to b.jpg", true), ("Move 1 photos", false)]
+    );
+
+    // A new action drops what's left to redo
+    engine
+        .record_action("Create album Trip", async |engine| {
+            engine
+                .execute_operation(&Operation::CreateAlbum {
+                    name: "Trip".to_string(),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+    assert!(redo(&mut engine).await.unwrap().is_none());
+    assert_eq!(list_history(&pool, 10).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_undo_keeps_moves_committed_before_mirroring_failed() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mut engine = engine(&library, &backup).await;
+    let pool = engine.primary_db.clone();
+    let path = |name: &str| library.path().join(name);
+
+    let id = add(&mut engine, photo_file(&path("a.jpg"))).await;
+    fs::rename(path("a.jpg"), path("b.jpg")).unwrap();
+    engine
+        .record_action("Move 1 photos", async |engine| {
+            engine
+                .execute_operation(&Operation::Move {
+                    from: path("a.jpg"),
+                    to: path("b.jpg"),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+    break_backup_mirror(&engine).await;
+    let undone = undo_last(&mut engine).await.unwrap().unwrap();
+    assert!(undone.undone);
+    // The file stays where the catalog now says it is
+    assert!(path("a.jpg").exists());
+    assert!(!path("b.jpg").exists());
+    assert_eq!(path_of(&pool, id).await, path("a.jpg").to_str().unwrap());
+}
+
+#[tokio::test]
+async fn test_undo_create_album_leaves_other_backup_albums_alone() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mut engine = engine(&library, &backup).await;
+    let pool = engine.primary_db.clone();
+    let backup_pool = engine.backup_db().unwrap().clone();
+
+    // The backup numbers its albums differently, as after a create there was
+    // set aside as failed
+    sqlx::query("INSERT INTO albums (name) VALUES ('Family')")
+        .execute(&backup_pool)
+        .await
+        .unwrap();
+    engine
+        .record_action("Create album Trip", async |engine| {
+            engine
+                .execute_operation(&Operation::CreateAlbum {
+                    name: "Trip".to_string(),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+    undo_last(&mut engine).await.unwrap().unwrap();
+    let albums: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM albums")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(albums, 0);
+    // The backup refuses the delete rather than removing Family
+    let names: Vec<String> = sqlx::query_scalar("SELECT name FROM albums ORDER BY name")
+        .fetch_all(&backup_pool)
+        .await
+        .unwrap();
+    assert_eq!(names, vec!["Family", "Trip"]);
+    assert_eq!(engine.queue_len(), 1);
+}
+
+#[tokio::test]
+async fn test_undo_brings_back_trashed_stacks_and_deleted_albums() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mut engine = engine(&library, &backup).await;
+    let pool = engine.primary_db.clone();
+    let path = |name: &str| library.path().join(name);
+
+    let jpeg = add(&mut engine, photo_file(&path("IMG_1.jpg"))).await;
+    let raw = add(&mut engine, photo_file(&path("IMG_1.cr2"))).await;
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("IMG_1.jpg"), path("IMG_1.cr2")],
+            representative: path("IMG_1.jpg"),
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Trip".to_string(),
+        })
+        .await
+        .unwrap();
+    let album_id: i64 = sqlx::query_scalar("SELECT id FROM albums")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    for photo_id in [jpeg, raw] {
+        engine
+            .execute_operation(&Operation::AddToAlbum { photo_id, album_id })
+            .await
+            .unwrap();
+    }
+    // Only actions started by the user are recorded
+    assert!(list_history(&pool, 10).await.unwrap().is_empty());
+
+    engine
+        .record_action("Delete album", async |engine| {
+            engine
+                .execute_operation(&Operation::DeleteAlbum {
+                    album_id,
+                    name: None,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+    undo_last(&mut engine).await.unwrap().unwrap();
+    let members: Vec<i64> = sqlx::query_scalar(
+        "SELECT photo_id FROM photo_albums WHERE album_id = ? ORDER BY photo_id",
+    )
+    .bind(album_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(members, vec![jpeg, raw]);
+
+    // A deleted album whose name was taken since comes back renamed
+    engine
+        .record_action("Delete album", async |engine| {
+            engine
+                .execute_operation(&Operation::DeleteAlbum {
+                    album_id,
+                    name: None,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Trip".to_string(),
+        })
+        .await
+        .unwrap();
+    undo_last(&mut engine).await.unwrap().unwrap();
+    for pool in [&pool, engine.backup_db().unwrap()] {
+        let albums: Vec<(i64, String)> =
+            sqlx::query_as("SELECT id, name FROM albums ORDER BY name")
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        assert_eq!(albums.len(), 2);
+        assert_eq!(albums[1], (album_id, "Trip (2)".to_string()));
+    }
+
+    engine
+        .record_action("Delete 2 photos", async |engine| {
+            trash_photos(engine, library.path(), &[jpeg, raw]).await
+        })
+        .await
+        .unwrap();
+    assert!(!path("IMG_1.jpg").exists());
+
+    undo_last(&mut engine).await.unwrap().unwrap();
+    let rows: Vec<(String, Option<i64>, Option<String>)> = sqlx::query_as(
+        "SELECT path, stack_id, deleted_at FROM photos WHERE id IN (?, ?) ORDER BY id",
+    )
+    .bind(jpeg)
+    .bind(raw)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(rows[0].0, path("IMG_1.jpg").to_str().unwrap());
+    assert_eq!(rows[1].0, path("IMG_1.cr2").to_str().unwrap());
+    assert!(rows[0].1.is_some() && rows[0].1 == rows[1].1);
+    assert!(rows.iter().all(|row| row.2.is_none()));
+    assert!(path("IMG_1.cr2").exists());
+    let representative: i64 = sqlx::query_scalar("SELECT representative_id FROM stacks")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(representative, jpeg);
+    assert!(!library
+        .path()
+        .join(".photovault/trash")
+        .join(jpeg.to_string())
+        .exists());
+
+    // Redoing puts them back in the trash
+    redo(&mut engine).await.unwrap().unwrap();
+    assert!(!path("IMG_1.jpg").exists());
+    let trashed: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM photos WHERE deleted_at IS NOT NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(trashed, 2);
+}
+
+#[tokio::test]
+async fn test_undo_skips_deletions_whose_trash_was_emptied() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let mut engine = engine(&library, &backup).await;
+    let pool = engine.primary_db.clone();
+    let path = |name: &str| library.path().join(name);
+
+    add(&mut engine, photo_file(&path("a.jpg"))).await;
+    let b = add(&mut engine, photo_file(&path("b.jpg"))).await;
+    fs::rename(path("a.jpg"), path("c.jpg")).unwrap();
+    engine
+        .record_action("Rename to c.jpg", async |engine| {
+            engine
+                .execute_operation(&Operation::Rename {
+                    path: path("a.jpg"),
+                    new_name: "c.jpg".to_string(),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+    engine
+        .record_action("Delete 1 photos", async |engine| {
+            trash_photos(engine, library.path(), &[b]).await
+        })
+        .await
+        .unwrap();
+    empty_trash(&mut engine).await.unwrap();
+
+    let undone = undo_last(&mut engine).await.unwrap().unwrap();
+    assert_eq!(undone.label, "Rename to c.jpg");
+    assert!(path("a.jpg").exists());
+    let history = list_history(&pool, 10).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert!(undo_last(&mut engine).await.unwrap().is_none());
+}
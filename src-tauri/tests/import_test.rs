@@ -0,0 +1,218 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use image::RgbImage;
+use photovault::db::manager::DatabaseManager;
+use photovault::models::photo::Photo;
+use photovault::services::file_mirror::FileMirror;
+use photovault::services::importer::{
+    capture_time, import_folder, PathTemplate, DEFAULT_IMPORT_TEMPLATE,
+};
+use photovault::services::sync_engine::SyncEngine;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+use tokio::sync::Mutex;
+
+fn noon(year: i32, month: u32, day: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap()
+}
+
+fn save_png(path: &Path, size: u32) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    RgbImage::new(size, size).save(path).unwrap();
+}
+
+#[test]
+fn test_templates_render_capture_dates() {
+    let template = PathTemplate::parse(DEFAULT_IMPORT_TEMPLATE).unwrap();
+    assert_eq!(
+        template.render(noon(2024, 3, 7), "IMG_0001.JPG"),
+        PathBuf::from("2024/03/2024-03-07_IMG_0001.JPG")
+    );
+    let template = PathTemplate::parse("Imports/{year}-{month}-{day} {original_name}").unwrap();
+    assert_eq!(
+        template.render(noon(2024, 11, 23), "a.png"),
+        PathBuf::from("Imports/2024-11-23 a.png")
+    );
+
+    for invalid in [
+        "{year}/{camera}",
+        "{year/{original_name}",
+        "{year}}/{original_name}",
+        "../{original_name}",
+        "/photos/{original_name}",
+        "",
+    ] {
+        assert!(PathTemplate::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_capture_time_uses_the_cameras_clock() {
+    let photo = Photo {
+        date_taken: Some(Utc.with_ymd_and_hms(2024, 1, 1, 2, 0, 0).unwrap()),
+        date_taken_offset: Some(-300),
+        ..Default::default()
+    };
+    assert_eq!(
+        capture_time(&photo),
+        NaiveDate::from_ymd_opt(2023, 12, 31)
+            .unwrap()
+            .and_hms_opt(21, 0, 0)
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_import_copies_new_files_and_skips_known_ones() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let card = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), Some(backup_pool)).with_file_mirror(
+        FileMirror::new(library.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+
+    // Catalogued without a hash, like photos found by a scan
+    let known = library.path().join("old").join("known.png");
+    save_png(&known, 3);
+    engine
+        .add_photo(Photo::new_from_path(known.clone()).unwrap())
+        .await
+        .unwrap();
+    let engine = Mutex::new(Some(engine));
+
+    // Taken in mid-2020 as far as the file system knows
+    let taken = SystemTime::UNIX_EPOCH + Duration::from_secs(1_592_222_400);
+    let source = |name: &str| card.path().join("DCIM").join(name);
+    save_png(&source("a.png"), 4);
+    save_png(&source("b.png"), 5);
+    save_png(&source("copy_of_b.png"), 5);
+    save_png(&source("known.png"), 3);
+    fs::write(source("notes.txt"), "not a photo").unwrap();
+    for name in ["a.png", "b.png", "copy_of_b.png", "known.png"] {
+        fs::File::options()
+            .write(true)
+            .open(source(name))
+            .unwrap()
+            .set_modified(taken)
+            .unwrap();
+    }
+    // An uncatalogued file already sits where `a.png` would go
+    save_png(&library.path().join("2020").join("a.png"), 6);
+
+    let template = PathTemplate::parse("{year}/{original_name}").unwrap();
+    let summary = import_folder(
+        &engine,
+        card.path(),
+        library.path(),
+        &template,
+        &AtomicBool::new(false),
+        |_| {},
+    )
+    .await
+    .unwrap();
+
+    let imported = |name: &str| library.path().join("2020").join(name);
+    assert_eq!(
+        summary.imported,
+        vec![imported("a_1.png"), imported("b.png")]
+    );
+    assert_eq!(
+        summary.duplicates,
+        vec![source("copy_of_b.png"), source("known.png")]
+    );
+    assert!(summary.failed.is_empty());
+    assert!(!summary.cancelled);
+
+    let photo = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE path = ?")
+        .bind(imported("a_1.png").to_str())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(photo.filename, "a_1.png");
+    assert_eq!(photo.width, Some(4));
+    assert!(photo.file_hash.is_some());
+    assert_eq!(
+        fs::metadata(imported("a_1.png"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+        taken
+    );
+    // The backup drive follows
+    assert!(backup.path().join("2020").join("a_1.png").exists());
+    assert!(backup.path().join("2020").join("b.png").exists());
+    // The source is left untouched
+    assert!(source("a.png").exists());
+
+    // Importing the card again finds everything already in the library
+    let summary = import_folder(
+        &engine,
+        card.path(),
+        library.path(),
+        &template,
+        &AtomicBool::new(false),
+        |_| {},
+    )
+    .await
+    .unwrap();
+    assert!(summary.imported.is_empty());
+    assert_eq!(summary.duplicates.len(), 4);
+
+    assert!(import_folder(
+        &engine,
+        &library.path().join("old"),
+        library.path(),
+        &template,
+        &AtomicBool::new(false),
+        |_| {},
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_import_removes_copies_it_cannot_catalog() {
+    let library = tempdir().unwrap();
+    let card = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    // The catalog refuses every new photo
+    sqlx::query(
+        "CREATE TRIGGER reject_photos BEFORE INSERT ON photos BEGIN SELECT RAISE(ABORT, 'catalog is read-only'); END",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let engine = Mutex::new(Some(SyncEngine::new(pool, None)));
+    save_png(&card.path().join("a.png"), 4);
+
+    let template = PathTemplate::parse("{original_name}").unwrap();
+    let summary = import_folder(
+        &engine,
+        card.path(),
+        library.path(),
+        &template,
+        &AtomicBool::new(false),
+        |_| {},
+    )
+    .await
+    .unwrap();
+
+    assert!(summary.imported.is_empty());
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].path, card.path().join("a.png"));
+    assert!(summary.failed[0].error.contains("read-only"));
+    assert!(!library.path().join("a.png").exists());
+}
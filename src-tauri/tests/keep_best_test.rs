@@ -0,0 +1,222 @@
+mod common;
+
+use common::{add, photo_file};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::keep_best::{
+    keep_best, recommend, KeepCriterion, KeepPolicy, KeepRecommendation,
+};
+use photovault::services::sync_engine::SyncEngine;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// A new file at `path` whose size grows with its `width`.
+fn sized(path: &Path, width: i64) -> Photo {
+    Photo {
+        file_size: Some(width * 10),
+        width: Some(width),
+        height: Some(width * 3 / 4),
+        ..photo_file(path)
+    }
+}
+
+#[tokio::test]
+async fn test_keepers_follow_the_policy() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    let jpeg = add(&mut engine, sized(&path("trip/IMG_1.jpg"), 4000)).await;
+    let raw = add(&mut engine, sized(&path("trip/IMG_1.dng"), 4000)).await;
+    let small = add(&mut engine, sized(&path("trip/IMG_2.jpg"), 1600)).await;
+    let shared = add(&mut engine, sized(&path("WhatsApp/IMG_2.jpg"), 2000)).await;
+    let first = add(&mut engine, sized(&path("a/IMG_3.jpg"), 1000)).await;
+    let second = add(&mut engine, sized(&path("b/IMG_3.jpg"), 1000)).await;
+    let groups = vec![
+        vec![jpeg, raw],
+        vec![small, shared],
+        vec![second, first],
+        // Only one photo left, so nothing to choose
+        vec![jpeg, 9999],
+    ];
+
+    let recommendations = recommend(&pool, &groups, &KeepPolicy::default())
+        .await
+        .unwrap();
+    assert_eq!(recommendations.len(), 3);
+    assert_eq!(recommendations[0].keeper_id, raw);
+    assert_eq!(recommendations[0].remove_ids, vec![jpeg]);
+    assert_eq!(recommendations[0].decided_by, Some(KeepCriterion::Format));
+    assert_eq!(recommendations[1].keeper_id, shared);
+    assert_eq!(
+        recommendations[1].decided_by,
+        Some(KeepCriterion::Resolution)
+    );
+    assert_eq!(recommendations[2].keeper_id, first);
+    assert_eq!(recommendations[2].decided_by, None);
+
+    let policy = KeepPolicy {
+        avoid_paths: vec!["whatsapp".to_string()],
+        ..Default::default()
+    };
+    let recommendations = recommend(&pool, &groups[1..2], &policy).await.unwrap();
+    assert_eq!(recommendations[0].keeper_id, small);
+    assert_eq!(recommendations[0].remove_ids, vec![shared]);
+    assert_eq!(recommendations[0].decided_by, Some(KeepCriterion::Path));
+}
+
+#[tokio::test]
+async fn test_keep_best_merges_tags_and_albums_onto_the_keeper() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    let keeper = add(&mut engine, sized(&path("trip/IMG_1.jpg"), 4000)).await;
+    let copy = add(&mut engine, sized(&path("WhatsApp/IMG_1.jpg"), 1600)).await;
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Trip".to_string(),
+        })
+        .await
+        .unwrap();
+    let album_id: i64 = sqlx::query_scalar("SELECT id FROM albums")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    for (photo_id, tag_name) in [(keeper, "Beach"), (copy, "Beach"), (copy, "Family")] {
+        engine
+            .execute_operation(&Operation::AddTag {
+                photo_id,
+                tag_name: tag_name.to_string(),
+            })
+            .await
+            .unwrap();
+    }
+    engine
+        .execute_operation(&Operation::AddToAlbum {
+            photo_id: copy,
+            album_id,
+        })
+        .await
+        .unwrap();
+
+    let recommendations = recommend(&pool, &[vec![keeper, copy]], &KeepPolicy::default())
+        .await
+        .unwrap();
+    assert_eq!(recommendations[0].keeper_id, keeper);
+    let summary = keep_best(&mut engine, library.path(), &recommendations)
+        .await
+        .unwrap();
+    assert_eq!(summary.tags_merged, 1);
+    assert_eq!(summary.albums_merged, 1);
+    assert_eq!(summary.removed.photos, 1);
+    assert!(!path("WhatsApp/IMG_1.jpg").exists());
+    assert!(path("trip/IMG_1.jpg").exists());
+
+    let tags: Vec<String> = sqlx::query_scalar(
+        "SELECT t.name FROM tags t JOIN photo_tags pt ON pt.tag_id = t.id
+         WHERE pt.photo_id = ? ORDER BY t.name",
+    )
+    .bind(keeper)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(tags, vec!["Beach", "Family"]);
+    let albums: Vec<i64> =
+        sqlx::query_scalar("SELECT album_id FROM photo_albums WHERE photo_id = ?")
+            .bind(keeper)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(albums, vec![album_id]);
+
+    // A photo can't be both kept and removed
+    let mut conflicting = recommendations.clone();
+    conflicting[0].remove_ids.push(keeper);
+    assert!(keep_best(&mut engine, library.path(), &conflicting)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_keep_best_checks_recommendations_against_the_catalog() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+    let keep = |keeper_id: i64, remove_ids: Vec<i64>| KeepRecommendation {
+        keeper_id,
+        remove_ids,
+        decided_by: None,
+    };
+
+    let a = add(&mut engine, photo_file(&path("a.jpg"))).await;
+    let b = add(&mut engine, photo_file(&path("b.jpg"))).await;
+    let c = add(&mut engine, photo_file(&path("c.jpg"))).await;
+    let jpeg = add(&mut engine, photo_file(&path("IMG_1.jpg"))).await;
+    let raw = add(&mut engine, photo_file(&path("IMG_1.cr2"))).await;
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("IMG_1.jpg"), path("IMG_1.cr2")],
+            representative: path("IMG_1.jpg"),
+        })
+        .await
+        .unwrap();
+
+    // One set's keeper can't be removed by another, nor a keeper's stack
+    for recommendations in [
+        vec![keep(a, vec![b]), keep(c, vec![a])],
+        vec![keep(jpeg, vec![raw])],
+    ] {
+        assert!(keep_best(&mut engine, library.path(), &recommendations)
+            .await
+            .is_err());
+    }
+    assert!(path("b.jpg").exists() && path("IMG_1.cr2").exists());
+
+    // Removing part of a stack removes all of it
+    let summary = keep_best(&mut engine, library.path(), &[keep(a, vec![raw])])
+        .await
+        .unwrap();
+    assert_eq!(summary.removed.photos, 2);
+    assert!(!path("IMG_1.jpg").exists());
+
+    // A keeper already in the trash is refused
+    assert!(
+        keep_best(&mut engine, library.path(), &[keep(jpeg, vec![b])])
+            .await
+            .is_err()
+    );
+    assert!(path("b.jpg").exists());
+
+    // A copy outside the library is refused before its tags are merged
+    let elsewhere = tempdir().unwrap();
+    let outside = add(&mut engine, photo_file(&elsewhere.path().join("b.jpg"))).await;
+    engine
+        .execute_operation(&Operation::AddTag {
+            photo_id: outside,
+            tag_name: "Beach".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(
+        keep_best(&mut engine, library.path(), &[keep(b, vec![outside])])
+            .await
+            .is_err()
+    );
+    let merged: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM photo_tags WHERE photo_id = ?")
+        .bind(b)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(merged, 0);
+}
@@ -0,0 +1,169 @@
+use image::RgbImage;
+use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode};
+use notify::{Event, EventKind};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::formats::FormatRegistry;
+use photovault::services::library_watcher::{
+    apply_changes, plan_operations, FileChange, PendingChanges,
+};
+use photovault::services::sync_engine::SyncEngine;
+use std::path::PathBuf;
+use tempfile::tempdir;
+use tokio::sync::Mutex;
+
+fn event(kind: EventKind, paths: &[&str]) -> Event {
+    paths
+        .iter()
+        .fold(Event::new(kind), |event, path| event.add_path(path.into()))
+}
+
+#[test]
+fn test_pending_changes_pair_renames_and_coalesce() {
+    let mut pending = PendingChanges::default();
+    assert!(pending.ready_at().is_none());
+
+    pending.record(&event(EventKind::Create(CreateKind::File), &["/lib/a.jpg"]));
+    pending.record(&event(
+        EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+        &["/lib/a.jpg"],
+    ));
+    pending.record(&event(
+        EventKind::Create(CreateKind::File),
+        &["/lib/.photovault/thumbnails/ab/ab_256.jpg"],
+    ));
+    pending.record(
+        &event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+            &["/lib/b.jpg"],
+        )
+        .set_tracker(7),
+    );
+    pending.record(
+        &event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)),
+            &["/lib/c.jpg"],
+        )
+        .set_tracker(7),
+    );
+    pending.record(
+        &event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+            &["/lib/d.jpg"],
+        )
+        .set_tracker(8),
+    );
+    pending.record(&event(EventKind::Remove(RemoveKind::File), &["/lib/a.jpg"]));
+    assert!(pending.ready_at().is_some());
+
+    assert_eq!(
+        pending.take(),
+        vec![
+            FileChange::Renamed {
+                from: "/lib/b.jpg".into(),
+                to: "/lib/c.jpg".into(),
+            },
+            FileChange::Changed("/lib/a.jpg".into()),
+            // Moved out of the watched tree
+            FileChange::Changed("/lib/d.jpg".into()),
+        ]
+    );
+    assert!(pending.ready_at().is_none());
+}
+
+#[tokio::test]
+async fn test_plan_operations_follows_the_file_system() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    std::fs::create_dir_all(path("album")).unwrap();
+    for name in ["kept.png", "renamed.png", "deleted.png", "album/inner.png"] {
+        RgbImage::new(4, 4).save(path(name)).unwrap();
+        engine
+            .add_photo(Photo::new_from_path(path(name)).unwrap())
+            .await
+            .unwrap();
+    }
+
+    RgbImage::new(4, 4).save(path("new.png")).unwrap();
+    std::fs::rename(path("renamed.png"), path("now.png")).unwrap();
+    std::fs::remove_file(path("deleted.png")).unwrap();
+    std::fs::rename(path("album"), path("trip")).unwrap();
+
+    let changes = vec![
+        FileChange::Changed(path("kept.png")),
+        FileChange::Changed(path("new.png")),
+        FileChange::Renamed {
+            from: path("renamed.png"),
+            to: path("now.png"),
+        },
+        FileChange::Changed(path("deleted.png")),
+        FileChange::Renamed {
+            from: path("album"),
+            to: path("trip"),
+        },
+        FileChange::Changed(path("new.png")),
+    ];
+    let plan = plan_operations(&pool, &FormatRegistry::default(), &changes)
+        .await
+        .unwrap();
+
+    let described: Vec<(&str, PathBuf)> = plan
+        .operations
+        .iter()
+        .map(|op| match op {
+            Operation::AddPhoto { photo } => ("add", PathBuf::from(&photo.path)),
+            Operation::Move { to, .. } => ("move", to.clone()),
+            other => panic!("unexpected operation {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        described,
+        vec![
+            ("add", path("new.png")),
+            ("move", path("now.png")),
+            ("move", path("trip/inner.png")),
+        ]
+    );
+    // A vanished file is flagged, not deleted from the catalog
+    assert_eq!(plan.missing, vec![path("deleted.png")]);
+    assert!(plan.found.is_empty());
+
+    let engine = Mutex::new(Some(engine));
+    assert!(apply_changes(&engine, &changes).await.unwrap());
+    {
+        let engine = engine.lock().await;
+        let engine = engine.as_ref().unwrap();
+        let missing: Vec<String> = engine
+            .get_missing_photos()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|photo| photo.path)
+            .collect();
+        assert_eq!(missing, vec![path("deleted.png").to_string_lossy()]);
+        // and kept out of the gallery
+        let shown = engine.get_photos(10, 0).await.unwrap();
+        assert_eq!(shown.len(), 4);
+        assert!(shown.iter().all(|photo| photo.path != missing[0]));
+    }
+
+    // Once applied, the same changes are a no-op
+    assert!(!apply_changes(&engine, &changes).await.unwrap());
+
+    // Restoring the file clears the flag
+    RgbImage::new(4, 4).save(path("deleted.png")).unwrap();
+    let restored = [FileChange::Changed(path("deleted.png"))];
+    assert!(apply_changes(&engine, &restored).await.unwrap());
+    let missing: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM photos WHERE missing_since IS NOT NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(missing, 0);
+}
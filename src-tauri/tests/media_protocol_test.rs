@@ -0,0 +1,232 @@
+use image::RgbImage;
+use photovault::db::manager::DatabaseManager;
+use photovault::models::photo::Photo;
+use photovault::services::media_protocol::{self, parse_range, ByteRange, MediaRequest};
+use photovault::services::sync_engine::SyncEngine;
+use photovault::services::thumbnail::ThumbnailCache;
+use tauri::http::{header, Request, StatusCode, Uri};
+use tempfile::tempdir;
+
+fn uri(s: &str) -> Uri {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_parse_media_request() {
+    let thumb = Some(MediaRequest::Thumbnail {
+        photo_id: 42,
+        size: 256,
+    });
+    assert_eq!(
+        MediaRequest::parse(&uri("photovault://thumb/42/256")),
+        thumb
+    );
+    assert_eq!(
+        MediaRequest::parse(&uri("photovault://localhost/thumb%2F42%2F256")),
+        thumb
+    );
+    assert_eq!(
+        MediaRequest::parse(&uri("http://photovault.localhost/thumb/42/256")),
+        thumb
+    );
+    assert_eq!(
+        MediaRequest::parse(&uri("photovault://localhost/original/7")),
+        Some(MediaRequest::Original { photo_id: 7 })
+    );
+    assert_eq!(MediaRequest::parse(&uri("photovault://thumb/42")), None);
+    assert_eq!(MediaRequest::parse(&uri("photovault://original/abc")), None);
+}
+
+#[test]
+fn test_parse_range() {
+    let range = |start, end| Some(ByteRange::Satisfiable { start, end });
+    assert_eq!(parse_range("bytes=0-99", 1000), range(0, 99));
+    assert_eq!(parse_range("bytes=500-", 1000), range(500, 999));
+    assert_eq!(parse_range("bytes=-100", 1000), range(900, 999));
+    assert_eq!(parse_range("bytes=-5000", 1000), range(0, 999));
+    assert_eq!(parse_range("bytes=900-5000", 1000), range(900, 999));
+    assert_eq!(
+        parse_range("bytes=1000-", 1000),
+        Some(ByteRange::Unsatisfiable)
+    );
+    assert_eq!(
+        parse_range("bytes=-0", 1000),
+        Some(ByteRange::Unsatisfiable)
+    );
+    assert_eq!(parse_range("bytes=5-1", 1000), None);
+    assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+    assert_eq!(parse_range("items=0-1", 1000), None);
+}
+
+#[tokio::test]
+async fn test_respond_serves_thumbnails_and_ranges() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    // Added without a cache so the protocol has to generate the thumbnail
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = library.path().join("photo.png");
+    RgbImage::new(600, 300).save(&path).unwrap();
+    engine
+        .add_photo(Photo::new_from_path(path.clone()).unwrap())
+        .await
+        .unwrap();
+    let id = engine.get_photos(1, 0).await.unwrap()[0].id;
+    let cache = ThumbnailCache::for_library(library.path());
+    let original = std::fs::read(&path).unwrap();
+
+    let get = |uri: String, headers: &[(header::HeaderName, &str)]| {
+        let mut request = Request::get(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        request.body(Vec::new()).unwrap()
+    };
+
+    let response = media_protocol::respond(
+        &pool,
+        Some(&cache),
+        &get(format!("photovault://thumb/{}/256", id), &[]),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/jpeg");
+    let thumbnail = image::load_from_memory(response.body()).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+
+    let response = media_protocol::respond(
+        &pool,
+        Some(&cache),
+        &get(
+            format!("photovault://original/{}", id),
+            &[(header::RANGE, "bytes=10-19")],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes 10-19/{}", original.len()).as_str()
+    );
+    assert_eq!(response.body().as_slice(), &original[10..20]);
+
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let response = media_protocol::respond(
+        &pool,
+        Some(&cache),
+        &get(
+            format!("photovault://original/{}", id),
+            &[(header::IF_NONE_MATCH, &etag)],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = media_protocol::respond(
+        &pool,
+        Some(&cache),
+        &get(
+            format!("photovault://original/{}", id),
+            &[(header::RANGE, "bytes=999999-")],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+    let response = media_protocol::respond(
+        &pool,
+        Some(&cache),
+        &get(format!("photovault://original/{}", id + 1), &[]),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_respond_caps_video_chunks_and_tracks_edits() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = library.path().join("clip.mp4");
+    let len = media_protocol::MAX_RANGE_LEN + 10;
+    std::fs::write(&path, vec![7u8; len as usize]).unwrap();
+    engine
+        .add_photo(Photo {
+            path: path.to_string_lossy().into_owned(),
+            filename: "clip.mp4".to_string(),
+            format: "Mp4".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let id = engine.get_photos(1, 0).await.unwrap()[0].id;
+    let original = |range: Option<&str>| {
+        let mut request = Request::get(format!("photovault://original/{}", id));
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        request.body(Vec::new()).unwrap()
+    };
+
+    // A whole-file request only gets the first chunk
+    let response = media_protocol::respond(&pool, None, &original(None)).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.body().len() as u64, media_protocol::MAX_RANGE_LEN);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes 0-{}/{}", media_protocol::MAX_RANGE_LEN - 1, len).as_str()
+    );
+    let etag = response.headers()[header::ETAG].clone();
+
+    let response = media_protocol::respond(&pool, None, &original(Some("bytes=5-"))).await;
+    assert_eq!(response.body().len() as u64, media_protocol::MAX_RANGE_LEN);
+    let response = media_protocol::respond(
+        &pool,
+        None,
+        &original(Some(&format!("bytes={}-", media_protocol::MAX_RANGE_LEN))),
+    )
+    .await;
+    assert_eq!(response.body().len(), 10);
+
+    // Rewriting the file in place changes its ETag
+    std::fs::write(&path, vec![7u8; 10]).unwrap();
+    let response = media_protocol::respond(&pool, None, &original(None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[header::ETAG], etag);
+}
+
+#[tokio::test]
+async fn test_respond_rejects_thumbnails_it_cannot_decode() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    // A HEIF `meta` box with no thumbnail items
+    let path = library.path().join("IMG_0001.HEIC");
+    std::fs::write(&path, [0, 0, 0, 12, b'm', b'e', b't', b'a', 0, 0, 0, 0]).unwrap();
+    engine
+        .add_photo(Photo {
+            path: path.to_string_lossy().into_owned(),
+            filename: "IMG_0001.HEIC".to_string(),
+            format: "Heic".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let id = engine.get_photos(1, 0).await.unwrap()[0].id;
+    let cache = ThumbnailCache::for_library(library.path());
+
+    let request = Request::get(format!("photovault://thumb/{}/256", id))
+        .body(Vec::new())
+        .unwrap();
+    let response = media_protocol::respond(&pool, Some(&cache), &request).await;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
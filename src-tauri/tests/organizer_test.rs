@@ -0,0 +1,189 @@
+mod common;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use common::{add, photo_file};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::file_mirror::FileMirror;
+use photovault::services::organizer::{apply_organize, plan_organize, OrganizeRule, RuleMatch};
+use photovault::services::sync_engine::SyncEngine;
+use photovault::services::tag::TagService;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+/// A new file at `path` taken on `date`.
+fn taken_on(path: &Path, date: (i32, u32, u32)) -> Photo {
+    Photo {
+        date_taken: Some(
+            Utc.with_ymd_and_hms(date.0, date.1, date.2, 12, 0, 0)
+                .unwrap(),
+        ),
+        date_taken_offset: Some(0),
+        ..photo_file(path)
+    }
+}
+
+fn rules() -> Vec<OrganizeRule> {
+    let rule = |matches, folder: &str| OrganizeRule {
+        matches,
+        folder: folder.to_string(),
+    };
+    vec![
+        rule(
+            RuleMatch::Tag {
+                name: "Work".to_string(),
+            },
+            "Work/{year}",
+        ),
+        rule(
+            RuleMatch::Camera {
+                camera: "iphone".to_string(),
+            },
+            "Phone/{year}/{month}",
+        ),
+        rule(
+            RuleMatch::Format {
+                format: "png".to_string(),
+            },
+            "Screenshots/",
+        ),
+        rule(
+            RuleMatch::Taken {
+                from: NaiveDate::from_ymd_opt(2024, 1, 1),
+                to: None,
+            },
+            "{year}/{month}",
+        ),
+    ]
+}
+
+#[tokio::test]
+async fn test_organizer_plans_and_moves_by_rule() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), Some(backup_pool)).with_file_mirror(
+        FileMirror::new(library.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+    let path = |name: &str| library.path().join(name);
+
+    let work = add(&mut engine, taken_on(&path("dl/work.jpg"), (2023, 5, 1))).await;
+    TagService::add_tag(&pool, work, "Work".to_string())
+        .await
+        .unwrap();
+    let phone = Photo {
+        camera_make: Some("Apple".to_string()),
+        camera_model: Some("iPhone 15".to_string()),
+        ..taken_on(&path("dl/phone.jpg"), (2023, 12, 25))
+    };
+    add(&mut engine, phone).await;
+    add(&mut engine, taken_on(&path("dl/screen.png"), (2024, 2, 2))).await;
+    add(&mut engine, taken_on(&path("dl/IMG_1.jpg"), (2024, 3, 7))).await;
+    add(&mut engine, taken_on(&path("dl/IMG_1.cr2"), (2024, 3, 7))).await;
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("dl/IMG_1.jpg"), path("dl/IMG_1.cr2")],
+            representative: path("dl/IMG_1.jpg"),
+        })
+        .await
+        .unwrap();
+    add(
+        &mut engine,
+        taken_on(&path("2024/03/in_place.jpg"), (2024, 3, 1)),
+    )
+    .await;
+    add(&mut engine, taken_on(&path("dl/old.jpg"), (2019, 8, 1))).await;
+    // Takes the stack's name in its folder without being catalogued
+    fs::write(path("2024/03/IMG_1.jpg"), b"someone else").unwrap();
+
+    let plan = plan_organize(&pool, library.path(), &rules())
+        .await
+        .unwrap();
+    let moves: Vec<(PathBuf, PathBuf, usize)> = plan
+        .moves
+        .iter()
+        .map(|planned| (planned.from.clone(), planned.to.clone(), planned.rule))
+        .collect();
+    assert_eq!(
+        moves,
+        vec![
+            (path("dl/IMG_1.jpg"), path("2024/03/IMG_1_1.jpg"), 3),
+            (path("dl/IMG_1.cr2"), path("2024/03/IMG_1_1.cr2"), 3),
+            (path("dl/phone.jpg"), path("Phone/2023/12/phone.jpg"), 1),
+            (path("dl/screen.png"), path("Screenshots/screen.png"), 2),
+            (path("dl/work.jpg"), path("Work/2023/work.jpg"), 0),
+        ]
+    );
+    assert_eq!((plan.unchanged, plan.unmatched), (1, 1));
+    // Planning moves nothing
+    assert!(path("dl/work.jpg").exists());
+
+    let summary = apply_organize(&mut engine, &plan).await.unwrap();
+    assert_eq!(summary.moved, 5);
+    assert!(summary.failed.is_empty());
+    for (from, to, _) in &moves {
+        assert!(!from.exists());
+        assert!(to.exists());
+        let relative = to.strip_prefix(library.path()).unwrap();
+        assert!(backup.path().join(relative).exists(), "{:?}", relative);
+    }
+    let renamed: String = sqlx::query_scalar("SELECT filename FROM photos WHERE path = ?")
+        .bind(path("2024/03/IMG_1_1.cr2").to_str())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(renamed, "IMG_1_1.cr2");
+    assert_eq!(
+        fs::read(path("2024/03/IMG_1.jpg")).unwrap(),
+        b"someone else"
+    );
+
+    let plan = plan_organize(&pool, library.path(), &rules())
+        .await
+        .unwrap();
+    assert!(plan.moves.is_empty());
+    assert_eq!((plan.unchanged, plan.unmatched), (6, 1));
+}
+
+#[tokio::test]
+async fn test_organizer_skips_files_whose_target_appeared() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    add(&mut engine, taken_on(&path("a.jpg"), (2024, 1, 1))).await;
+    add(&mut engine, taken_on(&path("b.jpg"), (2024, 1, 1))).await;
+    let rules = vec![OrganizeRule {
+        matches: RuleMatch::All,
+        folder: "{date}".to_string(),
+    }];
+    let plan = plan_organize(&pool, library.path(), &rules).await.unwrap();
+    assert_eq!(plan.moves.len(), 2);
+
+    fs::create_dir_all(path("2024-01-01")).unwrap();
+    fs::write(path("2024-01-01/b.jpg"), b"appeared").unwrap();
+    let summary = apply_organize(&mut engine, &plan).await.unwrap();
+    assert_eq!(summary.moved, 1);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].path, path("b.jpg"));
+    assert!(path("b.jpg").exists());
+    assert!(path("2024-01-01/a.jpg").exists());
+
+    for invalid in ["../outside", "/absolute", "{camera}"] {
+        let rules = vec![OrganizeRule {
+            matches: RuleMatch::All,
+            folder: invalid.to_string(),
+        }];
+        assert!(plan_organize(&pool, library.path(), &rules).await.is_err());
+    }
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{add, photo};
+use photovault::models::operation::Operation;
+use photovault::models::photo::{MediaType, Photo};
+use photovault::services::query::{parse, query_photos, Expr, ParseError, Term};
+use photovault::services::sync_engine::SyncEngine;
+
+async fn tag(engine: &mut SyncEngine, photo_id: i64, tag_name: &str) {
+    engine
+        .execute_operation(&Operation::AddTag {
+            photo_id,
+            tag_name: tag_name.to_string(),
+        })
+        .await
+        .unwrap();
+}
+
+/// The ids of the photos matching `query`, in id order.
+async fn ids(engine: &SyncEngine, query: &str) -> Vec<i64> {
+    let mut ids: Vec<i64> = query_photos(&engine.primary_db, query)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|photo| photo.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+fn parse_error(query: &str) -> (String, &str) {
+    let ParseError {
+        message,
+        start,
+        end,
+    } = parse(query).unwrap_err();
+    let chars: Vec<(usize, char)> = query.char_indices().collect();
+    let byte = |index: usize| chars.get(index).map_or(query.len(), |&(byte, _)| byte);
+    (message, &query[byte(start)..byte(end)])
+}
+
+#[test]
+fn test_parse_groups_or_looser_than_and() {
+    let expr =
+        parse("tag:beach -tag:work (album:Trips OR album:\"Family 2023\") width>=4000").unwrap();
+    let tag = |name: &str| Expr::Term(Term::Tag(name.to_string()));
+    let album = |name: &str| Expr::Term(Term::Album(name.to_string()));
+    assert_eq!(
+        expr,
+        Expr::And(vec![
+            tag("beach"),
+            Expr::Not(Box::new(tag("work"))),
+            Expr::Or(vec![album("Trips"), album("Family 2023")]),
+            Expr::Term(Term::Number {
+                column: "width",
+                min: Some(4000),
+                max: None,
+            }),
+        ])
+    );
+
+    assert_eq!(
+        parse("date:2023-06..2023-08 OR type:video").unwrap(),
+        Expr::Or(vec![
+            Expr::Term(Term::Date {
+                from: Some(Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap()),
+                until: Some(Utc.with_ymd_and_hms(2023, 9, 1, 0, 0, 0).unwrap()),
+            }),
+            Expr::Term(Term::MediaType(MediaType::Video)),
+        ])
+    );
+    assert_eq!(
+        parse("date<=2024-02-29").unwrap(),
+        Expr::Term(Term::Date {
+            from: None,
+            until: Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()),
+        })
+    );
+}
+
+#[test]
+fn test_parse_errors_point_at_the_offending_token() {
+    let cases = [
+        ("tag:beach colour:red", "Unknown field", "colour"),
+        ("tag>=beach", "can only be matched with ':'", ">="),
+        ("tag: beach", "Expected a value after 'tag:'", "tag:"),
+        ("date:2023-13", "Expected a date", "2023-13"),
+        (
+            "date:2023-08..2023-06",
+            "ends before it starts",
+            "2023-08..2023-06",
+        ),
+        ("width>4k", "Expected a whole number", "4k"),
+        (
+            "width>9223372036854775807",
+            "Number out of range",
+            "9223372036854775807",
+        ),
+        (
+            "iso<-9223372036854775808",
+            "Number out of range",
+            "-9223372036854775808",
+        ),
+        ("width>=1..2", "Ranges can only follow", "1..2"),
+        ("type:gif", "Expected 'photo' or 'video'", "gif"),
+        ("camera:\"X100V", "Unclosed quote", "\"X100V"),
+        ("(album:Trips OR album:Family", "Unclosed '('", "("),
+        ("tag:a)", "Unmatched ')'", ")"),
+        ("tag:a OR", "Expected a term after 'OR'", "OR"),
+        ("OR tag:a", "Expected a term before 'OR'", "OR"),
+        ("tag:a -", "Expected a term after '-'", "-"),
+        ("café ()", "Empty parentheses", "()"),
+    ];
+    for (query, message, token) in cases {
+        let (actual_message, actual_token) = parse_error(query);
+        assert!(
+            actual_message.contains(message),
+            "{}: {}",
+            query,
+            actual_message
+        );
+        assert_eq!(actual_token, token, "{}", query);
+    }
+    assert_eq!(parse_error("  ").0, "Nothing to search for");
+}
+
+#[tokio::test]
+async fn test_query_photos() {
+    let pool = common::create_in_memory_db_pool().await;
+    let mut engine = SyncEngine::new(pool, None);
+
+    let beach = add(
+        &mut engine,
+        Photo {
+            date_taken: Some(Utc.with_ymd_and_hms(2023, 7, 14, 18, 0, 0).unwrap()),
+            width: Some(6240),
+            camera_make: Some("FUJIFILM".to_string()),
+            camera_model: Some("X100V".to_string()),
+            caption: Some("Sunset at the pier".to_string()),
+            ..photo("/library/beach.jpg")
+        },
+    )
+    .await;
+    let office = add(
+        &mut engine,
+        Photo {
+            date_taken: Some(Utc.with_ymd_and_hms(2023, 9, 1, 9, 0, 0).unwrap()),
+            width: Some(4000),
+            camera_model: Some("iPhone 14".to_string()),
+            ..photo("/library/office_100%.jpg")
+        },
+    )
+    .await;
+    let clip = add(
+        &mut engine,
+        Photo {
+            media_type: MediaType::Video,
+            ..photo("/library/clip.mov")
+        },
+    )
+    .await;
+    tag(&mut engine, beach, "Beach").await;
+    tag(&mut engine, office, "work").await;
+    tag(&mut engine, clip, "beach").await;
+    tag(&mut engine, clip, "work").await;
+
+    assert_eq!(ids(&engine, "tag:beach").await, vec![beach, clip]);
+    assert_eq!(ids(&engine, "tag:beach -tag:work").await, vec![beach]);
+    assert_eq!(ids(&engine, "camera:\"x100v\"").await, vec![beach]);
+    assert_eq!(ids(&engine, "date:2023-06..2023-08").await, vec![beach]);
+    assert_eq!(ids(&engine, "date>2023-07").await, vec![office]);
+    assert_eq!(ids(&engine, "width>=4000").await, vec![beach, office]);
+    assert_eq!(ids(&engine, "width>4000").await, vec![beach]);
+    // Photos without a width are among those not at least 4000 wide
+    assert_eq!(ids(&engine, "-width>=4000").await, vec![clip]);
+    assert_eq!(
+        ids(&engine, "type:video OR camera:iphone").await,
+        vec![office, clip]
+    );
+    assert_eq!(
+        ids(&engine, "(tag:beach OR tag:work) NOT type:video").await,
+        vec![beach, office]
+    );
+    // Full-text words and LIKE wildcards taken literally
+    assert_eq!(ids(&engine, "sunset tag:beach").await, vec![beach]);
+    assert_eq!(ids(&engine, "filename:100%").await, vec![office]);
+    assert_eq!(ids(&engine, "filename:_").await, vec![office]);
+
+    // Trashed photos are left out
+    engine
+        .execute_operation(&Operation::Trash {
+            path: "/library/beach.jpg".into(),
+            trash_path: "/library/.trash/beach.jpg".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(ids(&engine, "tag:beach").await, vec![clip]);
+}
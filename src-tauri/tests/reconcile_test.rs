@@ -0,0 +1,142 @@
+use image::RgbImage;
+use photovault::db::manager::DatabaseManager;
+use photovault::models::photo::Photo;
+use photovault::services::file_mirror::FileMirror;
+use photovault::services::reconcile::{reconcile, repair, Discrepancy};
+use photovault::services::sync_engine::SyncEngine;
+use std::fs;
+use tempfile::tempdir;
+use tokio::sync::Mutex;
+
+#[tokio::test]
+async fn test_reconcile_reports_and_repairs_differences() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let primary_pool = DatabaseManager::create_pool(&primary.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(primary_pool, Some(backup_pool)).with_file_mirror(
+        FileMirror::new(primary.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+
+    let kept = primary.path().join("kept.png");
+    RgbImage::new(4, 4).save(&kept).unwrap();
+    engine
+        .add_photo(Photo::new_from_path(kept.clone()).unwrap())
+        .await
+        .unwrap();
+    let engine = Mutex::new(Some(engine));
+
+    let report = reconcile(&engine, true).await.unwrap();
+    assert!(report.is_in_sync, "{:?}", report.discrepancies);
+
+    // Lose the backup copy, leave a stray file on the backup and add an
+    // uncatalogued photo on the primary.
+    fs::remove_file(backup.path().join("kept.png")).unwrap();
+    RgbImage::new(2, 2)
+        .save(backup.path().join("stray.png"))
+        .unwrap();
+    let fresh = primary.path().join("fresh.png");
+    RgbImage::new(3, 3).save(&fresh).unwrap();
+
+    let report = reconcile(&engine, false).await.unwrap();
+    assert!(!report.is_in_sync);
+    assert!(report
+        .discrepancies
+        .contains(&Discrepancy::MissingOnBackup { path: kept.clone() }));
+    assert!(report.discrepancies.contains(&Discrepancy::ExtraOnBackup {
+        path: primary.path().join("stray.png"),
+    }));
+    assert!(report.discrepancies.contains(&Discrepancy::Uncatalogued {
+        path: fresh.clone()
+    }));
+
+    repair(&engine, &report.discrepancies).await.unwrap();
+
+    let report = reconcile(&engine, true).await.unwrap();
+    assert!(report.is_in_sync, "{:?}", report.discrepancies);
+    assert!(backup.path().join("kept.png").exists());
+    assert!(backup.path().join("fresh.png").exists());
+    assert!(!backup.path().join("stray.png").exists());
+}
+
+#[tokio::test]
+async fn test_repair_restores_missing_primary_file_from_backup() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let primary_pool = DatabaseManager::create_pool(&primary.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(primary_pool, Some(backup_pool)).with_file_mirror(
+        FileMirror::new(primary.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+
+    let path = primary.path().join("lost.png");
+    RgbImage::new(4, 4).save(&path).unwrap();
+    engine
+        .add_photo(Photo::new_from_path(path.clone()).unwrap())
+        .await
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    let engine = Mutex::new(Some(engine));
+
+    let report = reconcile(&engine, false).await.unwrap();
+    assert_eq!(
+        report.discrepancies,
+        vec![Discrepancy::MissingOnPrimary {
+            path: path.clone(),
+            on_backup: true,
+        }]
+    );
+
+    repair(&engine, &report.discrepancies).await.unwrap();
+    assert!(path.exists());
+    assert!(reconcile(&engine, true).await.unwrap().is_in_sync);
+}
+
+#[tokio::test]
+async fn test_repair_recatalogues_photo_only_the_backup_catalog_lists() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let primary_pool = DatabaseManager::create_pool(&primary.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(primary_pool.clone(), Some(backup_pool)).with_file_mirror(
+        FileMirror::new(primary.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+
+    let path = primary.path().join("dropped.png");
+    RgbImage::new(4, 4).save(&path).unwrap();
+    engine
+        .add_photo(Photo::new_from_path(path.clone()).unwrap())
+        .await
+        .unwrap();
+    let engine = Mutex::new(Some(engine));
+
+    // The primary catalog loses its row; the file and the backup's row remain
+    sqlx::query("DELETE FROM photos WHERE path = ?")
+        .bind(path.to_str())
+        .execute(&primary_pool)
+        .await
+        .unwrap();
+
+    let report = reconcile(&engine, false).await.unwrap();
+    assert_eq!(
+        report.discrepancies,
+        vec![Discrepancy::Uncatalogued { path: path.clone() }]
+    );
+
+    repair(&engine, &report.discrepancies).await.unwrap();
+    let report = reconcile(&engine, true).await.unwrap();
+    assert!(report.is_in_sync, "{:?}", report.discrepancies);
+    assert!(backup.path().join("dropped.png").exists());
+}
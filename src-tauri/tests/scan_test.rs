@@ -0,0 +1,147 @@
+use image::RgbImage;
+use photovault::db::manager::DatabaseManager;
+use photovault::models::photo::Photo;
+use photovault::services::scanner::{self, ScanSummary};
+use photovault::services::sync_engine::SyncEngine;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use tempfile::tempdir;
+use tokio::sync::Mutex;
+
+async fn scan(engine: &Mutex<Option<SyncEngine>>, root: &Path) -> ScanSummary {
+    scanner::scan_library(engine, root, &AtomicBool::new(false), |_| {})
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_rescan_skips_unchanged_and_tracks_missing_files() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let engine = Mutex::new(Some(SyncEngine::new(pool.clone(), None)));
+    let root = library.path();
+
+    let keep = library.path().join("keep.png");
+    let edit = library.path().join("nested").join("edit.png");
+    let gone = library.path().join("gone.png");
+    std::fs::create_dir_all(edit.parent().unwrap()).unwrap();
+    for path in [&keep, &edit, &gone] {
+        RgbImage::new(4, 4).save(path).unwrap();
+    }
+
+    let summary = scan(&engine, root).await;
+    assert_eq!(
+        (summary.added, summary.updated, summary.unchanged),
+        (3, 0, 0)
+    );
+    assert!(summary.missing.is_empty());
+
+    RgbImage::new(8, 6).save(&edit).unwrap();
+    std::fs::remove_file(&gone).unwrap();
+
+    let summary = scan(&engine, root).await;
+    assert_eq!(
+        (summary.added, summary.updated, summary.unchanged),
+        (0, 1, 1)
+    );
+    assert_eq!(summary.missing, vec![gone.clone()]);
+
+    let photo = |path: PathBuf| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE path = ?")
+                .bind(path.to_str())
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        }
+    };
+    let edited = photo(edit).await;
+    assert_eq!((edited.width, edited.height), (Some(8), Some(6)));
+    assert!(photo(gone.clone()).await.missing_since.is_some());
+    assert!(photo(keep).await.missing_since.is_none());
+
+    RgbImage::new(4, 4).save(&gone).unwrap();
+    let summary = scan(&engine, root).await;
+    assert!(summary.missing.is_empty());
+    assert!(photo(gone).await.missing_since.is_none());
+
+    let photos: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM photos")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(photos, 3);
+}
+
+#[tokio::test]
+async fn test_scan_reports_failures_progress_and_cancellation() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let engine = Mutex::new(Some(SyncEngine::new(pool.clone(), None)));
+
+    RgbImage::new(4, 4)
+        .save(library.path().join("good.png"))
+        .unwrap();
+    let broken = library.path().join("broken.jpg");
+    std::fs::write(&broken, b"not a jpeg").unwrap();
+
+    let mut reports = Vec::new();
+    let summary = scanner::scan_library(&engine, library.path(), &AtomicBool::new(false), |p| {
+        reports.push(p.clone())
+    })
+    .await
+    .unwrap();
+    assert_eq!(summary.added, 1);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].path, broken);
+    assert!(!summary.cancelled);
+    let last = reports.last().unwrap();
+    assert_eq!((last.discovered, last.processed, last.failed), (2, 1, 1));
+
+    // A cancelled scan applies nothing and doesn't flag unseen photos as missing
+    std::fs::remove_file(library.path().join("good.png")).unwrap();
+    RgbImage::new(4, 4)
+        .save(library.path().join("new.png"))
+        .unwrap();
+    let summary = scanner::scan_library(&engine, library.path(), &AtomicBool::new(true), |_| {})
+        .await
+        .unwrap();
+    assert!(summary.cancelled);
+    assert_eq!(summary.added, 0);
+    assert!(summary.missing.is_empty());
+    let missing: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM photos WHERE missing_since IS NOT NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(missing, 0);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_scan_reports_paths_that_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let engine = Mutex::new(Some(SyncEngine::new(pool.clone(), None)));
+
+    RgbImage::new(4, 4)
+        .save(library.path().join("good.png"))
+        .unwrap();
+    let odd = library.path().join(OsStr::from_bytes(b"caf\xe9.png"));
+    std::fs::copy(library.path().join("good.png"), &odd).unwrap();
+
+    let summary = scan(&engine, library.path()).await;
+    assert_eq!(summary.added, 1);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].path, odd);
+    assert!(Photo::new_from_path(odd).is_err());
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use common::{add, photo};
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::filter::{filter_photos, FilterCriteria};
+use photovault::services::search::{match_expression, search, SnippetPart};
+use photovault::services::sync_engine::SyncEngine;
+use std::path::PathBuf;
+
+async fn ids(engine: &SyncEngine, query: &str) -> Vec<i64> {
+    search(&engine.primary_db, query, 50)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|result| result.photo.id)
+        .collect()
+}
+
+#[test]
+fn test_match_expression_quotes_words_as_prefixes() {
+    assert_eq!(
+        match_expression("bea  \"x100\" OR -work").as_deref(),
+        Some("\"bea\"* \"x100\"* \"OR\"* \"-work\"*")
+    );
+    assert_eq!(match_expression("  \"\" "), None);
+}
+
+#[tokio::test]
+async fn test_search_index_follows_operations() {
+    let pool = common::create_in_memory_db_pool().await;
+    let mut engine = SyncEngine::new(pool, None);
+
+    let beach = add(
+        &mut engine,
+        Photo {
+            camera_make: Some("FUJIFILM".to_string()),
+            camera_model: Some("X100V".to_string()),
+            caption: Some("Sunset over the pier".to_string()),
+            ..photo("/library/2023/Summer/IMG_0001.jpg")
+        },
+    )
+    .await;
+    let office = add(&mut engine, photo("/library/2023/Work/IMG_0002.jpg")).await;
+
+    // Every indexed field, by prefix, ignoring case and accents
+    assert_eq!(ids(&engine, "img_000").await.len(), 2);
+    assert_eq!(ids(&engine, "summ").await, vec![beach]);
+    assert_eq!(ids(&engine, "x100").await, vec![beach]);
+    assert_eq!(ids(&engine, "fuji sunset").await, vec![beach]);
+    assert_eq!(ids(&engine, "pier work").await, Vec::<i64>::new());
+
+    engine
+        .execute_operation(&Operation::AddTag {
+            photo_id: office,
+            tag_name: "Café".to_string(),
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Holidays".to_string(),
+        })
+        .await
+        .unwrap();
+    let album_id: i64 = sqlx::query_scalar("SELECT id FROM albums WHERE name = 'Holidays'")
+        .fetch_one(&engine.primary_db)
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::AddToAlbum {
+            photo_id: beach,
+            album_id,
+        })
+        .await
+        .unwrap();
+    assert_eq!(ids(&engine, "cafe").await, vec![office]);
+    assert_eq!(ids(&engine, "holi").await, vec![beach]);
+
+    // A tag counts for more than the same word in a folder name
+    engine
+        .execute_operation(&Operation::AddTag {
+            photo_id: beach,
+            tag_name: "work".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(ids(&engine, "work").await, vec![beach, office]);
+
+    engine
+        .execute_operation(&Operation::RemoveTag {
+            photo_id: office,
+            tag_name: "Café".to_string(),
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::DeleteAlbum {
+            album_id,
+            name: None,
+        })
+        .await
+        .unwrap();
+    assert!(ids(&engine, "cafe").await.is_empty());
+    assert!(ids(&engine, "holidays").await.is_empty());
+
+    engine
+        .execute_operation(&Operation::Move {
+            from: PathBuf::from("/library/2023/Work/IMG_0002.jpg"),
+            to: PathBuf::from("/library/Archive/scan.jpg"),
+        })
+        .await
+        .unwrap();
+    assert_eq!(ids(&engine, "archive scan").await, vec![office]);
+    assert!(ids(&engine, "img_0002").await.is_empty());
+
+    // Trashed photos can't be found until they're restored
+    engine
+        .execute_operation(&Operation::Trash {
+            path: PathBuf::from("/library/Archive/scan.jpg"),
+            trash_path: PathBuf::from("/library/.trash/scan.jpg"),
+        })
+        .await
+        .unwrap();
+    assert!(ids(&engine, "scan").await.is_empty());
+    engine
+        .execute_operation(&Operation::Restore {
+            trash_path: PathBuf::from("/library/.trash/scan.jpg"),
+            path: PathBuf::from("/library/Archive/scan.jpg"),
+        })
+        .await
+        .unwrap();
+    assert_eq!(ids(&engine, "scan").await, vec![office]);
+
+    engine
+        .execute_operation(&Operation::Delete {
+            path: PathBuf::from("/library/Archive/scan.jpg"),
+        })
+        .await
+        .unwrap();
+    assert!(ids(&engine, "scan").await.is_empty());
+
+    // Filters match the same way
+    let criteria = FilterCriteria {
+        query: Some("sunset".to_string()),
+        ..Default::default()
+    };
+    let photos = filter_photos(&engine.primary_db, criteria).await.unwrap();
+    assert_eq!(photos.len(), 1);
+    assert_eq!(photos[0].id, beach);
+}
+
+#[tokio::test]
+async fn test_search_highlights_matches() {
+    let pool = common::create_in_memory_db_pool().await;
+    let mut engine = SyncEngine::new(pool, None);
+    add(
+        &mut engine,
+        Photo {
+            caption: Some("Sunset over the <b>pier</b>".to_string()),
+            ..photo("/library/IMG_0001.jpg")
+        },
+    )
+    .await;
+
+    let results = search(&engine.primary_db, "sun", 10).await.unwrap();
+    assert_eq!(results.len(), 1);
+    let part = |text: &str, matched| SnippetPart {
+        text: text.to_string(),
+        matched,
+    };
+    assert_eq!(
+        results[0].snippet,
+        vec![part("Sunset", true), part(" over the <b>pier</b>", false)]
+    );
+}
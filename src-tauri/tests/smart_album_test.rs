@@ -0,0 +1,251 @@
+mod common;
+
+use common::{add, photo};
+use photovault::models::filter::FilterCriteria;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::filter::validate;
+use photovault::services::history::{redo, undo_last};
+use photovault::services::sync_engine::SyncEngine;
+
+fn criteria(expression: &str) -> FilterCriteria {
+    FilterCriteria {
+        expression: Some(expression.to_string()),
+        ..Default::default()
+    }
+}
+
+/// The name and photo count of each album, then of each smart album, marked
+/// with `*`.
+async fn albums(engine: &SyncEngine) -> Vec<(String, i64)> {
+    let mut albums: Vec<(String, i64)> = engine
+        .get_albums()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|album| (album.name, album.photo_count))
+        .collect();
+    albums.extend(
+        engine
+            .get_smart_albums()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|smart_album| (format!("{}*", smart_album.name), smart_album.photo_count)),
+    );
+    albums
+}
+
+#[tokio::test]
+async fn test_smart_albums_are_evaluated_live_and_mirrored() {
+    let pool = common::create_in_memory_db_pool().await;
+    let backup_pool = common::create_in_memory_db_pool().await;
+    let mut engine = SyncEngine::new(pool, Some(backup_pool.clone()));
+
+    let beach = add(
+        &mut engine,
+        Photo {
+            width: Some(6000),
+            ..photo("/library/beach.jpg")
+        },
+    )
+    .await;
+    let office = add(
+        &mut engine,
+        Photo {
+            width: Some(3000),
+            ..photo("/library/office.jpg")
+        },
+    )
+    .await;
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Trips".to_string(),
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::AddToAlbum {
+            photo_id: beach,
+            album_id: 1,
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::CreateSmartAlbum {
+            name: "Large".to_string(),
+            criteria: FilterCriteria {
+                min_width: Some(4000),
+                ..Default::default()
+            },
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::CreateSmartAlbum {
+            name: "Work".to_string(),
+            criteria: criteria("tag:work OR album:Trips"),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        albums(&engine).await,
+        vec![
+            ("Trips".to_string(), 1),
+            ("Large*".to_string(), 1),
+            ("Work*".to_string(), 1)
+        ]
+    );
+
+    // Membership follows the library
+    engine
+        .execute_operation(&Operation::AddTag {
+            photo_id: office,
+            tag_name: "work".to_string(),
+        })
+        .await
+        .unwrap();
+    let photos = engine.get_photos_by_smart_album_id(2).await.unwrap();
+    let mut ids: Vec<i64> = photos.iter().map(|photo| photo.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![beach, office]);
+
+    engine
+        .execute_operation(&Operation::UpdateSmartAlbum {
+            smart_album_id: 1,
+            name: "Small".to_string(),
+            criteria: criteria("width<4000"),
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::Trash {
+            path: "/library/office.jpg".into(),
+            trash_path: "/library/.trash/office.jpg".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        albums(&engine).await,
+        vec![
+            ("Trips".to_string(), 1),
+            ("Small*".to_string(), 0),
+            ("Work*".to_string(), 1)
+        ]
+    );
+
+    let mirrored: Vec<(i64, String, String)> =
+        sqlx::query_as("SELECT id, name, criteria FROM smart_albums ORDER BY id")
+            .fetch_all(&backup_pool)
+            .await
+            .unwrap();
+    assert_eq!(mirrored.len(), 2);
+    assert_eq!(mirrored[0].1, "Small");
+    assert_eq!(
+        serde_json::from_str::<FilterCriteria>(&mirrored[0].2).unwrap(),
+        criteria("width<4000")
+    );
+}
+
+#[tokio::test]
+async fn test_undo_smart_album_changes() {
+    let pool = common::create_in_memory_db_pool().await;
+    let mut engine = SyncEngine::new(pool, None);
+    add(
+        &mut engine,
+        Photo {
+            width: Some(6000),
+            ..photo("/library/beach.jpg")
+        },
+    )
+    .await;
+
+    engine
+        .record_action("Create smart album Large", async |engine| {
+            engine
+                .execute_operation(&Operation::CreateSmartAlbum {
+                    name: "Large".to_string(),
+                    criteria: criteria("width>=4000"),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+    engine
+        .record_action("Edit smart album Huge", async |engine| {
+            engine
+                .execute_operation(&Operation::UpdateSmartAlbum {
+                    smart_album_id: 1,
+                    name: "Huge".to_string(),
+                    criteria: criteria("width>=8000"),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+    engine
+        .record_action("Delete smart album", async |engine| {
+            engine
+                .execute_operation(&Operation::DeleteSmartAlbum {
+                    smart_album_id: 1,
+                    name: None,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+    assert!(albums(&engine).await.is_empty());
+
+    undo_last(&mut engine).await.unwrap();
+    assert_eq!(albums(&engine).await, vec![("Huge*".to_string(), 0)]);
+    undo_last(&mut engine).await.unwrap();
+    assert_eq!(albums(&engine).await, vec![("Large*".to_string(), 1)]);
+    undo_last(&mut engine).await.unwrap();
+    assert!(albums(&engine).await.is_empty());
+    redo(&mut engine).await.unwrap();
+    assert_eq!(albums(&engine).await, vec![("Large*".to_string(), 1)]);
+    // Recreated under a new id, which undoing it deletes
+    undo_last(&mut engine).await.unwrap();
+    assert!(albums(&engine).await.is_empty());
+    redo(&mut engine).await.unwrap();
+
+    // A deleted smart album whose name was taken since comes back renamed
+    let large = engine.get_smart_albums().await.unwrap()[0].id;
+    engine
+        .record_action("Delete smart album", async |engine| {
+            engine
+                .execute_operation(&Operation::DeleteSmartAlbum {
+                    smart_album_id: large,
+                    name: None,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::CreateSmartAlbum {
+            name: "Large".to_string(),
+            criteria: criteria("width>=5000"),
+        })
+        .await
+        .unwrap();
+    undo_last(&mut engine).await.unwrap();
+    assert_eq!(
+        albums(&engine).await,
+        vec![("Large*".to_string(), 1), ("Large (2)*".to_string(), 1)]
+    );
+    let restored = engine.get_smart_albums().await.unwrap();
+    assert_eq!(restored[1].id, large);
+    assert_eq!(restored[1].criteria, criteria("width>=4000"));
+}
+
+#[test]
+fn test_validate_rejects_expressions_that_dont_parse() {
+    assert!(validate(&criteria("tag:beach width>=4000")).is_ok());
+    assert!(validate(&FilterCriteria::default()).is_ok());
+    let error = validate(&criteria("tag:beach colour:red")).unwrap_err();
+    assert_eq!((error.start, error.end), (10, 16));
+}
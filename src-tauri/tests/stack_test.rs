@@ -0,0 +1,229 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::{MediaType, Photo};
+use photovault::services::stack::{self, member_file_name, plan_stacks};
+use photovault::services::sync_engine::SyncEngine;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn taken() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+fn photo(path: &Path, format: &str, date_taken: Option<DateTime<Utc>>) -> Photo {
+    Photo {
+        path: path.to_str().unwrap().to_string(),
+        filename: path.file_name().unwrap().to_str().unwrap().to_string(),
+        format: format.to_string(),
+        date_taken,
+        date_taken_offset: Some(120),
+        ..Default::default()
+    }
+}
+
+fn video(path: &Path, content_identifier: Option<&str>) -> Photo {
+    Photo {
+        media_type: MediaType::Video,
+        date_taken_offset: None,
+        content_identifier: content_identifier.map(String::from),
+        ..photo(path, "Mov", Some(taken() - Duration::seconds(1)))
+    }
+}
+
+async fn id_of(pool: &sqlx::SqlitePool, path: &Path) -> i64 {
+    sqlx::query_scalar("SELECT id FROM photos WHERE path = ?")
+        .bind(path.to_str())
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+fn stacked_paths(ops: &[Operation]) -> Vec<(Vec<String>, String)> {
+    let name = |path: &PathBuf| path.file_name().unwrap().to_str().unwrap().to_string();
+    ops.iter()
+        .map(|op| match op {
+            Operation::CreateStack {
+                paths,
+                representative,
+            } => (paths.iter().map(name).collect(), name(representative)),
+            other => panic!("unexpected operation {:?}", other),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_new_files_are_paired_into_stacks() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    let mut live_still = photo(&path("IMG_0002.HEIC"), "Heic", Some(taken()));
+    live_still.content_identifier = Some("A1B2".to_string());
+    let photos = vec![
+        // RAW+JPEG, shown as the JPEG
+        photo(&path("IMG_0001.CR2"), "Cr2", Some(taken())),
+        photo(&path("IMG_0001.JPG"), "Jpeg", Some(taken())),
+        // A Live Photo, paired by its content identifier despite the names
+        live_still,
+        video(&path("IMG_E0002.MOV"), Some("A1B2")),
+        // Same name, shot an hour apart
+        photo(&path("IMG_0003.JPG"), "Jpeg", Some(taken())),
+        photo(
+            &path("IMG_0003.NEF"),
+            "Nef",
+            Some(taken() + Duration::hours(1)),
+        ),
+        // Two stills never stack with each other
+        photo(&path("IMG_0004.jpg"), "Jpeg", Some(taken())),
+        photo(&path("IMG_0004.png"), "Png", Some(taken())),
+        // A video without an identifier pairs by name
+        photo(&path("IMG_0005.JPG"), "Jpeg", Some(taken())),
+        video(&path("IMG_0005.MOV"), None),
+    ];
+    let added: Vec<PathBuf> = photos.iter().map(|p| PathBuf::from(&p.path)).collect();
+    for photo in photos {
+        engine.add_photo(photo).await.unwrap();
+    }
+
+    assert!(plan_stacks(&pool, &[]).await.unwrap().is_empty());
+    let ops = plan_stacks(&pool, &added).await.unwrap();
+    assert_eq!(
+        stacked_paths(&ops),
+        vec![
+            (
+                vec!["IMG_0001.JPG".to_string(), "IMG_0001.CR2".to_string()],
+                "IMG_0001.JPG".to_string()
+            ),
+            (
+                vec!["IMG_0002.HEIC".to_string(), "IMG_E0002.MOV".to_string()],
+                "IMG_0002.HEIC".to_string()
+            ),
+            (
+                vec!["IMG_0005.JPG".to_string(), "IMG_0005.MOV".to_string()],
+                "IMG_0005.JPG".to_string()
+            ),
+        ]
+    );
+    engine.execute_batch(&ops).await.unwrap();
+    // Planning is idempotent once the stacks exist
+    assert!(plan_stacks(&pool, &added).await.unwrap().is_empty());
+
+    // A RAW added later joins the existing stack
+    engine
+        .add_photo(photo(&path("IMG_0005.DNG"), "Dng", Some(taken())))
+        .await
+        .unwrap();
+    let ops = plan_stacks(&pool, &[path("IMG_0005.DNG")]).await.unwrap();
+    assert_eq!(
+        stacked_paths(&ops),
+        vec![(
+            vec![
+                "IMG_0005.JPG".to_string(),
+                "IMG_0005.DNG".to_string(),
+                "IMG_0005.MOV".to_string()
+            ],
+            "IMG_0005.JPG".to_string()
+        )]
+    );
+    engine.execute_batch(&ops).await.unwrap();
+    let stacks: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM stacks")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stacks, 3);
+}
+
+#[tokio::test]
+async fn test_stacks_show_their_representative_and_dissolve() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), None);
+    let path = |name: &str| library.path().join(name);
+
+    for (name, format) in [("a.cr2", "Cr2"), ("a.jpg", "Jpeg"), ("b.jpg", "Jpeg")] {
+        engine
+            .add_photo(photo(&path(name), format, Some(taken())))
+            .await
+            .unwrap();
+    }
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("a.cr2"), path("a.jpg")],
+            representative: path("a.cr2"),
+        })
+        .await
+        .unwrap();
+
+    let mut shown: Vec<String> = engine
+        .get_photos(10, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| p.filename)
+        .collect();
+    shown.sort();
+    assert_eq!(shown, vec!["a.cr2", "b.jpg"]);
+
+    let members: Vec<String> = stack::members(&pool, id_of(&pool, &path("a.jpg")).await)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| p.filename)
+        .collect();
+    assert_eq!(members, vec!["a.cr2", "a.jpg"]);
+    let selected = [
+        id_of(&pool, &path("a.jpg")).await,
+        id_of(&pool, &path("b.jpg")).await,
+        id_of(&pool, &path("a.cr2")).await,
+    ];
+    assert_eq!(stack::expand(&pool, &selected).await.unwrap().len(), 3);
+
+    // Deleting the representative leaves a stack of one, which is dissolved
+    engine
+        .execute_operation(&Operation::Delete {
+            path: path("a.cr2"),
+        })
+        .await
+        .unwrap();
+    let remaining = engine.get_photos(10, 0).await.unwrap();
+    assert_eq!(remaining.len(), 2);
+    assert!(remaining.iter().all(|p| p.stack_id.is_none()));
+    let stacks: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM stacks")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stacks, 0);
+
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("a.jpg"), path("b.jpg")],
+            representative: path("b.jpg"),
+        })
+        .await
+        .unwrap();
+    assert_eq!(engine.get_photos(10, 0).await.unwrap().len(), 1);
+    engine
+        .execute_operation(&Operation::Unstack {
+            path: path("a.jpg"),
+        })
+        .await
+        .unwrap();
+    assert_eq!(engine.get_photos(10, 0).await.unwrap().len(), 2);
+}
+
+#[test]
+fn test_stack_members_keep_their_extension_on_rename() {
+    let raw = Path::new("/photos/IMG_1234.CR2");
+    assert_eq!(member_file_name(raw, "Beach.jpg"), "Beach.CR2");
+    assert_eq!(member_file_name(raw, "Beach"), "Beach.CR2");
+    assert_eq!(
+        member_file_name(Path::new("/photos/README"), "Beach.jpg"),
+        "Beach"
+    );
+}
@@ -0,0 +1,216 @@
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::services::sync_engine::{SyncEngine, MAX_SYNC_ATTEMPTS};
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_queue_survives_restart_and_flushes_in_order() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let primary_db = primary.path().join("photovault.db");
+
+    {
+        let pool = DatabaseManager::create_pool(&primary_db).await.unwrap();
+        let mut engine = SyncEngine::new(pool, None);
+        for name in ["Trips", "Family"] {
+            engine
+                .execute_operation(&Operation::CreateAlbum {
+                    name: name.to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(engine.queue_len(), 2);
+    }
+
+    let primary_pool = DatabaseManager::create_pool(&primary_db).await.unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(primary_pool.clone(), Some(backup_pool.clone()));
+    engine.load_queue().await.unwrap();
+    assert_eq!(engine.queue_len(), 2);
+
+    engine.flush_queue().await.unwrap();
+    assert_eq!(engine.queue_len(), 0);
+
+    let backup_albums: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, name FROM albums ORDER BY id")
+            .fetch_all(&backup_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        backup_albums,
+        vec![(1, "Trips".to_string()), (2, "Family".to_string())]
+    );
+
+    let pending: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM sync_operations WHERE status = 'pending'")
+            .fetch_one(&primary_pool)
+            .await
+            .unwrap();
+    assert_eq!(pending, 0);
+}
+
+#[tokio::test]
+async fn test_failed_mirror_is_recorded_and_retried() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let primary_pool = DatabaseManager::create_pool(&primary.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+
+    // The album exists on the backup only, so replaying the insert there fails
+    sqlx::query("INSERT INTO albums (name) VALUES ('Trips')")
+        .execute(&backup_pool)
+        .await
+        .unwrap();
+
+    let mut engine = SyncEngine::new(primary_pool.clone(), Some(backup_pool.clone()));
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Trips".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(engine.queue_len(), 1);
+    assert_eq!(engine.queued_operations()[0].attempts, 1);
+    assert!(engine.queued_operations()[0].last_error.is_some());
+
+    let (attempts, last_error): (i64, Option<String>) =
+        sqlx::query_as("SELECT attempts, last_error FROM sync_operations WHERE status = 'pending'")
+            .fetch_one(&primary_pool)
+            .await
+            .unwrap();
+    assert_eq!(attempts, 1);
+    assert!(last_error.is_some());
+
+    sqlx::query("DELETE FROM albums")
+        .execute(&backup_pool)
+        .await
+        .unwrap();
+    engine.flush_queue().await.unwrap();
+    assert_eq!(engine.queue_len(), 0);
+}
+
+#[tokio::test]
+async fn test_new_operations_wait_behind_queued_ones() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let primary_pool = DatabaseManager::create_pool(&primary.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+
+    let mut engine = SyncEngine::new(primary_pool, None);
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Trips".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // The backup is back, but "Trips" hasn't been flushed to it yet
+    engine.set_backup_db(Some(backup_pool.clone()));
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Family".to_string(),
+        })
+        .await
+        .unwrap();
+    engine
+        .execute_batch(&[
+            Operation::CreateAlbum {
+                name: "Pets".to_string(),
+            },
+            Operation::CreateAlbum {
+                name: "Garden".to_string(),
+            },
+        ])
+        .await
+        .unwrap();
+    assert_eq!(engine.queue_len(), 4);
+    let backup_albums: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM albums")
+        .fetch_one(&backup_pool)
+        .await
+        .unwrap();
+    assert_eq!(backup_albums, 0);
+
+    engine.flush_queue().await.unwrap();
+    let backup_albums: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, name FROM albums ORDER BY id")
+            .fetch_all(&backup_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        backup_albums,
+        vec![
+            (1, "Trips".to_string()),
+            (2, "Family".to_string()),
+            (3, "Pets".to_string()),
+            (4, "Garden".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_operation_failing_every_attempt_is_set_aside() {
+    let primary = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let primary_db = primary.path().join("photovault.db");
+    let primary_pool = DatabaseManager::create_pool(&primary_db).await.unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+
+    // Replaying "Trips" on the backup always fails
+    sqlx::query("INSERT INTO albums (name) VALUES ('Trips')")
+        .execute(&backup_pool)
+        .await
+        .unwrap();
+
+    let mut engine = SyncEngine::new(primary_pool.clone(), Some(backup_pool.clone()));
+    for name in ["Trips", "Family"] {
+        engine
+            .execute_operation(&Operation::CreateAlbum {
+                name: name.to_string(),
+            })
+            .await
+            .unwrap();
+    }
+    assert_eq!(engine.queue_len(), 2);
+
+    for _ in 1..MAX_SYNC_ATTEMPTS - 1 {
+        assert!(engine.flush_next().await.is_err());
+    }
+    assert_eq!(
+        engine.queued_operations()[0].attempts,
+        MAX_SYNC_ATTEMPTS - 1
+    );
+    assert!(engine.failed_operations().is_empty());
+
+    // The last attempt sets it aside and the rest of the queue follows
+    engine.flush_queue().await.unwrap();
+    assert_eq!(engine.queue_len(), 0);
+    assert_eq!(engine.failed_operations().len(), 1);
+    assert_eq!(engine.failed_operations()[0].attempts, MAX_SYNC_ATTEMPTS);
+    let backup_albums: Vec<String> = sqlx::query_scalar("SELECT name FROM albums ORDER BY id")
+        .fetch_all(&backup_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        backup_albums,
+        vec!["Trips".to_string(), "Family".to_string()]
+    );
+
+    // Still reported after a restart, but not retried
+    let mut engine = SyncEngine::new(primary_pool, Some(backup_pool));
+    engine.load_queue().await.unwrap();
+    assert_eq!(engine.queue_len(), 0);
+    assert_eq!(engine.failed_operations().len(), 1);
+}
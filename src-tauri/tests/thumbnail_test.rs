@@ -0,0 +1,65 @@
+use image::{GenericImageView, RgbImage};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::sync_engine::SyncEngine;
+use photovault::services::thumbnail::{ThumbnailCache, THUMBNAIL_SIZES};
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_thumbnails_follow_photo_lifecycle() {
+    let library = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let cache = ThumbnailCache::for_library(library.path());
+    let mut engine = SyncEngine::new(pool.clone(), None).with_thumbnail_cache(cache.clone());
+
+    let path = library.path().join("wide.png");
+    RgbImage::new(2000, 1000).save(&path).unwrap();
+    engine
+        .add_photo(Photo::new_from_path(path.clone()).unwrap())
+        .await
+        .unwrap();
+    engine.wait_for_thumbnails().await;
+    let photo = engine.get_photos(1, 0).await.unwrap().remove(0);
+    assert!(photo.file_hash.is_some());
+
+    let small = cache.get(&pool, photo.id, 256).await.unwrap().unwrap();
+    assert_eq!(image::open(&small).unwrap().dimensions(), (256, 128));
+    for &size in THUMBNAIL_SIZES {
+        assert!(cache.get(&pool, photo.id, size).await.unwrap().is_some());
+    }
+    // Thumbnails are renamed into place, so no partial files are left behind
+    let written: Vec<_> = std::fs::read_dir(small.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(written.len(), THUMBNAIL_SIZES.len());
+    assert!(written
+        .iter()
+        .all(|path| path.extension().unwrap() == "jpg"));
+
+    let moved = library.path().join("moved.png");
+    std::fs::rename(&path, &moved).unwrap();
+    engine
+        .execute_operation(&Operation::Move {
+            from: path,
+            to: moved.clone(),
+        })
+        .await
+        .unwrap();
+    engine.wait_for_thumbnails().await;
+    assert_eq!(
+        cache.get(&pool, photo.id, 256).await.unwrap(),
+        Some(small.clone())
+    );
+
+    engine
+        .execute_operation(&Operation::Delete { path: moved })
+        .await
+        .unwrap();
+    engine.wait_for_thumbnails().await;
+    assert!(cache.get(&pool, photo.id, 256).await.unwrap().is_none());
+    assert!(!small.exists());
+}
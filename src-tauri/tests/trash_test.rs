@@ -0,0 +1,199 @@
+mod common;
+
+use common::{add, break_backup_mirror, photo_file};
+use photovault::db::manager::DatabaseManager;
+use photovault::models::operation::Operation;
+use photovault::models::photo::Photo;
+use photovault::services::file_mirror::FileMirror;
+use photovault::services::sync_engine::SyncEngine;
+use photovault::services::tag::TagService;
+use photovault::services::trash::{
+    empty_trash, list_trash, purge_expired, restore_photos, trash_dir, trash_photos,
+};
+use sqlx::SqlitePool;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+async fn photo(pool: &SqlitePool, id: i64) -> Option<Photo> {
+    sqlx::query_as("SELECT * FROM photos WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_trash_and_restore_follow_the_backup_and_stacks() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), Some(backup_pool)).with_file_mirror(
+        FileMirror::new(library.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+    let path = |name: &str| library.path().join(name);
+    let backup_path = |name: &Path| {
+        backup
+            .path()
+            .join(name.strip_prefix(library.path()).unwrap())
+    };
+
+    let single = add(&mut engine, photo_file(&path("trip/a.jpg"))).await;
+    let jpeg = add(&mut engine, photo_file(&path("trip/IMG_1.jpg"))).await;
+    let raw = add(&mut engine, photo_file(&path("trip/IMG_1.cr2"))).await;
+    engine
+        .execute_operation(&Operation::CreateStack {
+            paths: vec![path("trip/IMG_1.jpg"), path("trip/IMG_1.cr2")],
+            representative: path("trip/IMG_1.jpg"),
+        })
+        .await
+        .unwrap();
+
+    let summary = trash_photos(&mut engine, library.path(), &[single, jpeg, raw])
+        .await
+        .unwrap();
+    assert_eq!(summary.photos, 3);
+    assert!(summary.failed.is_empty());
+    let trashed = trash_dir(library.path())
+        .join(single.to_string())
+        .join("a.jpg");
+    assert!(!path("trip/a.jpg").exists());
+    assert!(trashed.exists());
+    assert!(!backup_path(&path("trip/a.jpg")).exists());
+    assert!(backup_path(&trashed).exists());
+
+    let row = photo(&pool, single).await.unwrap();
+    assert_eq!(row.path, trashed.to_str().unwrap());
+    assert_eq!(row.trashed_from.as_deref(), path("trip/a.jpg").to_str());
+    assert!(row.deleted_at.is_some());
+    assert!(engine.get_photos(100, 0).await.unwrap().is_empty());
+    assert_eq!(list_trash(&pool).await.unwrap().len(), 3);
+
+    // Trashing again is a no-op
+    let summary = trash_photos(&mut engine, library.path(), &[single])
+        .await
+        .unwrap();
+    assert_eq!(summary.photos, 0);
+
+    // The old name has been taken by a new file in the meantime
+    fs::write(path("trip/a.jpg"), b"new").unwrap();
+    let summary = restore_photos(&mut engine, &[single, jpeg, raw])
+        .await
+        .unwrap();
+    assert_eq!(summary.photos, 2);
+    assert_eq!(summary.failed.len(), 1);
+    assert!(trashed.exists());
+    assert_eq!(fs::read(path("trip/a.jpg")).unwrap(), b"new");
+
+    for id in [jpeg, raw] {
+        let row = photo(&pool, id).await.unwrap();
+        assert!(Path::new(&row.path).exists());
+        assert!(backup_path(Path::new(&row.path)).exists());
+        assert_eq!((row.deleted_at, row.trashed_from), (None, None));
+        assert!(row.stack_id.is_some());
+    }
+    assert!(!trash_dir(library.path()).join(jpeg.to_string()).exists());
+    let shown = engine.get_photos(100, 0).await.unwrap();
+    assert_eq!(shown.len(), 1);
+    assert_eq!(shown[0].id, jpeg);
+}
+
+#[tokio::test]
+async fn test_retention_and_empty_trash_delete_for_good() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), Some(backup_pool)).with_file_mirror(
+        FileMirror::new(library.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+    let path = |name: &str| library.path().join(name);
+
+    let old = add(&mut engine, photo_file(&path("old.jpg"))).await;
+    let recent = add(&mut engine, photo_file(&path("recent.jpg"))).await;
+    engine
+        .execute_operation(&Operation::CreateAlbum {
+            name: "Trip".to_string(),
+        })
+        .await
+        .unwrap();
+    let album_id: i64 = sqlx::query_scalar("SELECT id FROM albums")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    engine
+        .execute_operation(&Operation::AddToAlbum {
+            photo_id: old,
+            album_id,
+        })
+        .await
+        .unwrap();
+    TagService::add_tag(&pool, old, "Beach".to_string())
+        .await
+        .unwrap();
+    trash_photos(&mut engine, library.path(), &[old, recent])
+        .await
+        .unwrap();
+    let old_trashed = photo(&pool, old).await.unwrap().path;
+    sqlx::query("UPDATE photos SET deleted_at = datetime('now', '-40 days') WHERE id = ?")
+        .bind(old)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let summary = purge_expired(&mut engine, 30).await.unwrap();
+    assert_eq!(summary.photos, 1);
+    assert!(photo(&pool, old).await.is_none());
+    assert!(!Path::new(&old_trashed).exists());
+    assert!(!trash_dir(library.path()).join(old.to_string()).exists());
+    let relative = Path::new(&old_trashed)
+        .strip_prefix(library.path())
+        .unwrap();
+    assert!(!backup.path().join(relative).exists());
+    let memberships: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM photo_albums")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(memberships, 0);
+    assert!(photo(&pool, recent).await.is_some());
+
+    let summary = empty_trash(&mut engine).await.unwrap();
+    assert_eq!(summary.photos, 1);
+    assert!(list_trash(&pool).await.unwrap().is_empty());
+    assert_eq!(fs::read_dir(trash_dir(library.path())).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn test_trash_keeps_files_the_catalog_committed() {
+    let library = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let pool = DatabaseManager::create_pool(&library.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let backup_pool = DatabaseManager::create_pool(&backup.path().join("photovault.db"))
+        .await
+        .unwrap();
+    let mut engine = SyncEngine::new(pool.clone(), Some(backup_pool)).with_file_mirror(
+        FileMirror::new(library.path().to_path_buf(), backup.path().to_path_buf()),
+    );
+    let id = add(&mut engine, photo_file(&library.path().join("a.jpg"))).await;
+
+    break_backup_mirror(&engine).await;
+    assert!(trash_photos(&mut engine, library.path(), &[id])
+        .await
+        .is_err());
+    // The file stays in the trash, where the catalog now says it is
+    let trashed = photo(&pool, id).await.unwrap();
+    assert!(trashed.deleted_at.is_some());
+    assert!(Path::new(&trashed.path).exists());
+    assert!(!library.path().join("a.jpg").exists());
+}
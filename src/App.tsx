@@ -1,28 +1,41 @@
 import React, { useState } from "react";
 import Library from "./pages/Library";
 import SettingsPage from "./pages/Settings";
+import TrashPage from "./pages/Trash";
+import MissingPage from "./pages/Missing";
+import HistoryPage from "./pages/History";
 import { AlbumManager } from "./components/AlbumManager";
 import { AlbumView } from "./pages/AlbumView";
+import { AlbumSelection } from "./models/album";
 import StatusBar from "./components/StatusBar";
 import { Button } from "./components/ui/button";
 import { Toaster } from "sonner";
 import "./index.css";
 import { DuplicateInspector } from "./components/DuplicateInspector";
 
-type View = "library" | "settings" | "albums" | "duplicates";
+type View =
+  | "library"
+  | "settings"
+  | "albums"
+  | "duplicates"
+  | "trash"
+  | "missing"
+  | "history";
 
 function App() {
   const [view, setView] = useState<View>("library");
-  const [selectedAlbumId, setSelectedAlbumId] = useState<number | null>(null);
+  const [selectedAlbum, setSelectedAlbum] = useState<AlbumSelection | null>(
+    null,
+  );
 
-  const handleAlbumSelect = (albumId: number) => {
-    setSelectedAlbumId(albumId);
+  const handleAlbumSelect = (selection: AlbumSelection) => {
+    setSelectedAlbum(selection);
     setView("library"); // Or a new 'album-details' view
   };
 
   const renderView = () => {
-    if (selectedAlbumId) {
-      return <AlbumView albumId={selectedAlbumId} />;
+    if (selectedAlbum) {
+      return <AlbumView selection={selectedAlbum} />;
     }
     switch (view) {
       case "settings":
@@ -31,6 +44,12 @@ function App() {
         return <AlbumManager onAlbumSelect={handleAlbumSelect} />;
       case "duplicates":
         return <DuplicateInspector />;
+      case "trash":
+        return <TrashPage />;
+      case "missing":
+        return <MissingPage />;
+      case "history":
+        return <HistoryPage />;
       case "library":
       default:
         return <Library />;
@@ -46,7 +65,7 @@ function App() {
             variant={view === "library" ? "secondary" : "ghost"}
             onClick={() => {
               setView("library");
-              setSelectedAlbumId(null);
+              setSelectedAlbum(null);
             }}
           >
             Library
@@ -55,7 +74,7 @@ function App() {
             variant={view === "albums" ? "secondary" : "ghost"}
             onClick={() => {
               setView("albums");
-              setSelectedAlbumId(null);
+              setSelectedAlbum(null);
             }}
           >
             Albums
@@ -64,16 +83,43 @@ function App() {
             variant={view === "duplicates" ? "secondary" : "ghost"}
             onClick={() => {
               setView("duplicates");
-              setSelectedAlbumId(null);
+              setSelectedAlbum(null);
             }}
           >
             Duplicates
           </Button>
+          <Button
+            variant={view === "trash" ? "secondary" : "ghost"}
+            onClick={() => {
+              setView("trash");
+              setSelectedAlbum(null);
+            }}
+          >
+            Trash
+          </Button>
+          <Button
+            variant={view === "missing" ? "secondary" : "ghost"}
+            onClick={() => {
+              setView("missing");
+              setSelectedAlbum(null);
+            }}
+          >
+            Missing
+          </Button>
+          <Button
+            variant={view === "history" ? "secondary" : "ghost"}
+            onClick={() => {
+              setView("history");
+              setSelectedAlbum(null);
+            }}
+          >
+            History
+          </Button>
           <Button
             variant={view === "settings" ? "secondary" : "ghost"}
             onClick={() => {
               setView("settings");
-              setSelectedAlbumId(null);
+              setSelectedAlbum(null);
             }}
           >
             Settings
@@ -1,21 +1,24 @@
 import { useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
-
-interface Album {
-  id: number;
-  name: string;
-}
+import {
+  Album,
+  AlbumEntry,
+  AlbumSelection,
+  SmartAlbum,
+} from "../models/album";
 
 interface AlbumManagerProps {
-  onAlbumSelect: (albumId: number) => void;
+  onAlbumSelect: (selection: AlbumSelection) => void;
 }
 
 export function AlbumManager({ onAlbumSelect }: AlbumManagerProps) {
   const queryClient = useQueryClient();
   const [albumName, setAlbumName] = useState("");
+  const [smartAlbumName, setSmartAlbumName] = useState("");
+  const [expression, setExpression] = useState("");
 
-  const { data: albums, isLoading } = useQuery<Album[]>({
+  const { data: entries, isLoading } = useQuery<AlbumEntry[]>({
     queryKey: ["albums"],
     queryFn: () => invoke("get_albums"),
   });
@@ -34,13 +37,87 @@ export function AlbumManager({ onAlbumSelect }: AlbumManagerProps) {
     }
   };
 
+  const createSmartAlbumMutation = useMutation({
+    mutationFn: ({ name, expression }: { name: string; expression: string }) =>
+      invoke("create_smart_album", { name, criteria: { expression } }),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ["albums"] });
+      setSmartAlbumName("");
+      setExpression("");
+    },
+  });
+
+  const handleCreateSmartAlbum = () => {
+    if (smartAlbumName.trim() && expression.trim()) {
+      createSmartAlbumMutation.mutate({ name: smartAlbumName, expression });
+    }
+  };
+
   const deleteAlbumMutation = useMutation({
-    mutationFn: (albumId: number) => invoke("delete_album", { albumId }),
+    mutationFn: (album: Album) => invoke("delete_album", { albumId: album.id }),
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: ["albums"] });
     },
   });
 
+  const deleteSmartAlbumMutation = useMutation({
+    mutationFn: (smartAlbum: SmartAlbum) =>
+      invoke("delete_smart_album", { smartAlbumId: smartAlbum.id }),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ["albums"] });
+    },
+  });
+
+  const renderAlbum = (album: Album) => (
+    <li
+      key={`album-${album.id}`}
+      className="p-2 border-b flex justify-between items-center"
+    >
+      <span
+        onClick={() => onAlbumSelect({ kind: "album", album })}
+        className="cursor-pointer hover:underline"
+      >
+        {album.name}
+        <span className="ml-2 text-sm text-gray-500">{album.photo_count}</span>
+      </span>
+      <button
+        onClick={() => deleteAlbumMutation.mutate(album)}
+        disabled={deleteAlbumMutation.isPending}
+        className="bg-red-500 text-white p-1 rounded"
+      >
+        Delete
+      </button>
+    </li>
+  );
+
+  const renderSmartAlbum = (smartAlbum: SmartAlbum) => (
+    <li
+      key={`smart-${smartAlbum.id}`}
+      className="p-2 border-b flex justify-between items-center"
+    >
+      <span
+        onClick={() => onAlbumSelect({ kind: "smart", album: smartAlbum })}
+        className="cursor-pointer hover:underline"
+        title={smartAlbum.criteria.expression ?? undefined}
+      >
+        {smartAlbum.name}
+        <span className="ml-2 text-xs rounded bg-purple-100 px-1.5 py-0.5 text-purple-700">
+          Smart
+        </span>
+        <span className="ml-2 text-sm text-gray-500">
+          {smartAlbum.photo_count}
+        </span>
+      </span>
+      <button
+        onClick={() => deleteSmartAlbumMutation.mutate(smartAlbum)}
+        disabled={deleteSmartAlbumMutation.isPending}
+        className="bg-red-500 text-white p-1 rounded"
+      >
+        Delete
+      </button>
+    </li>
+  );
+
   return (
     <div className="p-4">
       <h2 className="text-lg font-bold mb-4">Album Management</h2>
@@ -60,30 +137,47 @@ export function AlbumManager({ onAlbumSelect }: AlbumManagerProps) {
           {createAlbumMutation.isPending ? "Creating..." : "Create Album"}
         </button>
       </div>
+      <div className="mb-4">
+        <div className="flex gap-2 mb-1">
+          <input
+            type="text"
+            value={smartAlbumName}
+            onChange={(e) => setSmartAlbumName(e.target.value)}
+            placeholder="New smart album name"
+            className="border p-2 rounded w-1/3"
+          />
+          <input
+            type="text"
+            value={expression}
+            onChange={(e) => setExpression(e.target.value)}
+            placeholder="tag:beach -tag:work date:2023-06..2023-08"
+            className="border p-2 rounded flex-1 font-mono"
+          />
+          <button
+            onClick={handleCreateSmartAlbum}
+            disabled={createSmartAlbumMutation.isPending}
+            className="bg-blue-500 text-white p-2 rounded"
+          >
+            {createSmartAlbumMutation.isPending
+              ? "Creating..."
+              : "Create Smart Album"}
+          </button>
+        </div>
+        {createSmartAlbumMutation.isError && (
+          <p className="text-sm text-red-600">
+            {String(createSmartAlbumMutation.error)}
+          </p>
+        )}
+      </div>
       {isLoading ? (
         <p>Loading albums...</p>
       ) : (
         <ul>
-          {albums?.map((album) => (
-            <li
-              key={album.id}
-              className="p-2 border-b flex justify-between items-center"
-            >
-              <span
-                onClick={() => onAlbumSelect(album.id)}
-                className="cursor-pointer hover:underline"
-              >
-                {album.name}
-              </span>
-              <button
-                onClick={() => deleteAlbumMutation.mutate(album.id)}
-                disabled={deleteAlbumMutation.isPending}
-                className="bg-red-500 text-white p-1 rounded"
-              >
-                Delete
-              </button>
-            </li>
-          ))}
+          {entries?.map((entry) =>
+            entry.kind === "album"
+              ? renderAlbum(entry.album)
+              : renderSmartAlbum(entry.album),
+          )}
         </ul>
       )}
     </div>
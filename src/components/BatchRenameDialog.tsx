@@ -0,0 +1,148 @@
+import React, { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useQueryClient } from "@tanstack/react-query";
+import { Button } from "@/components/ui/button";
+import {
+  Dialog,
+  DialogContent,
+  DialogHeader,
+  DialogFooter,
+  DialogTitle,
+  DialogDescription,
+} from "@/components/ui/dialog";
+
+interface PlannedRename {
+  photo_id: number;
+  from: string;
+  to: string;
+}
+
+interface RenameCollision {
+  target: string;
+  sources: string[];
+}
+
+interface RenamePlan {
+  renames: PlannedRename[];
+  collisions: RenameCollision[];
+}
+
+interface BatchRenameDialogProps {
+  photoIds: number[];
+  isOpen: boolean;
+  onClose: () => void;
+}
+
+const DEFAULT_TEMPLATE = "{date:%Y%m%d}_{camera}_{seq:04}{ext}";
+
+const fileName = (path: string) => path.split(/[\\/]/).pop() ?? path;
+
+const BatchRenameDialog: React.FC<BatchRenameDialogProps> = ({
+  photoIds,
+  isOpen,
+  onClose,
+}) => {
+  const queryClient = useQueryClient();
+  const [template, setTemplate] = useState(DEFAULT_TEMPLATE);
+  const [plan, setPlan] = useState<RenamePlan | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const [isRenaming, setIsRenaming] = useState(false);
+
+  useEffect(() => {
+    if (!isOpen) {
+      return;
+    }
+    let current = true;
+    invoke<RenamePlan>("preview_batch_rename", { photoIds, template })
+      .then((plan) => {
+        if (current) {
+          setPlan(plan);
+          setError(null);
+        }
+      })
+      .catch((err) => {
+        if (current) {
+          setPlan(null);
+          setError(String(err));
+        }
+      });
+    return () => {
+      current = false;
+    };
+  }, [isOpen, photoIds, template]);
+
+  const handleRename = async () => {
+    setIsRenaming(true);
+    try {
+      await invoke("batch_rename", { photoIds, template });
+      queryClient.invalidateQueries({ queryKey: ["photos"] });
+      onClose();
+    } catch (err) {
+      setError(String(err));
+    } finally {
+      setIsRenaming(false);
+    }
+  };
+
+  return (
+    <Dialog open={isOpen} onOpenChange={(open) => !open && onClose()}>
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Rename {photoIds.length} photos</DialogTitle>
+          <DialogDescription>
+            Placeholders: {"{date}"} or {"{date:%Y%m%d}"}, {"{camera}"},{" "}
+            {"{seq}"} or {"{seq:04}"}, {"{name}"} and {"{ext}"}. Stacked files
+            keep their own extensions.
+          </DialogDescription>
+        </DialogHeader>
+
+        <input
+          type="text"
+          className="w-full p-2 border rounded-md bg-background"
+          value={template}
+          onChange={(e) => setTemplate(e.target.value)}
+        />
+
+        {plan && (
+          <div className="max-h-64 overflow-y-auto text-sm">
+            {plan.collisions.map((collision) => (
+              <p key={collision.target} className="text-red-500">
+                {collision.sources.map(fileName).join(", ")} →{" "}
+                {fileName(collision.target)}: name already taken
+              </p>
+            ))}
+            {plan.renames.map((rename) => (
+              <p key={rename.from}>
+                {fileName(rename.from)} → {fileName(rename.to)}
+              </p>
+            ))}
+            {plan.renames.length === 0 && plan.collisions.length === 0 && (
+              <p className="text-muted-foreground">No names change.</p>
+            )}
+          </div>
+        )}
+
+        {error && <p className="text-sm text-red-500">{error}</p>}
+
+        <DialogFooter>
+          <Button variant="ghost" onClick={onClose}>
+            Cancel
+          </Button>
+          <Button
+            onClick={handleRename}
+            disabled={
+              isRenaming ||
+              !plan ||
+              plan.renames.length === 0 ||
+              plan.collisions.length > 0
+            }
+          >
+            {isRenaming ? "Renaming..." : "Rename"}
+          </Button>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+};
+
+export default BatchRenameDialog;
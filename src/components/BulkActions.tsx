@@ -2,10 +2,12 @@ import React from "react";
 import { useMutation, useQueryClient } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/core";
 import { Button } from "./ui/button";
+import BatchRenameDialog from "./BatchRenameDialog";
+import { Album } from "../models/album";
 
 interface BulkActionsProps {
   selectedPhotoIds: number[];
-  albums: { id: number; name: string }[];
+  albums: Album[];
 }
 
 export function BulkActions({ selectedPhotoIds, albums }: BulkActionsProps) {
@@ -13,6 +15,7 @@ export function BulkActions({ selectedPhotoIds, albums }: BulkActionsProps) {
   const [selectedAlbumId, setSelectedAlbumId] = React.useState<number | null>(
     null,
   );
+  const [isRenameOpen, setIsRenameOpen] = React.useState(false);
 
   const addToAlbumMutation = useMutation({
     mutationFn: ({
@@ -28,6 +31,16 @@ export function BulkActions({ selectedPhotoIds, albums }: BulkActionsProps) {
     },
   });
 
+  const stackMutation = useMutation({
+    mutationFn: (photoIds: number[]) =>
+      photoIds.length > 1
+        ? invoke("create_stack", { photoIds })
+        : invoke("unstack_photo", { photoId: photoIds[0] }),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ["photos"] });
+    },
+  });
+
   const handleAddToAlbum = () => {
     if (selectedAlbumId && selectedPhotoIds.length > 0) {
       addToAlbumMutation.mutate({
@@ -65,7 +78,22 @@ export function BulkActions({ selectedPhotoIds, albums }: BulkActionsProps) {
         >
           {addToAlbumMutation.isPending ? "Adding..." : "Add to Album"}
         </Button>
+        <Button
+          variant="outline"
+          onClick={() => stackMutation.mutate(selectedPhotoIds)}
+          disabled={stackMutation.isPending}
+        >
+          {selectedPhotoIds.length > 1 ? "Stack" : "Unstack"}
+        </Button>
+        <Button variant="outline" onClick={() => setIsRenameOpen(true)}>
+          Rename…
+        </Button>
       </div>
+      <BatchRenameDialog
+        photoIds={selectedPhotoIds}
+        isOpen={isRenameOpen}
+        onClose={() => setIsRenameOpen(false)}
+      />
     </div>
   );
 }
@@ -0,0 +1,86 @@
+import { invoke } from "@tauri-apps/api/core";
+import { useState } from "react";
+import { Button } from "./ui/button";
+import { Checkbox } from "@/components/ui/checkbox";
+
+interface DriveCopy {
+  drive: "primary" | "backup";
+  path: string;
+  size: number;
+  photo_id: number | null;
+}
+
+interface CrossDriveGroup {
+  kind: "relocated" | "diverged" | "orphaned";
+  hash: string | null;
+  copies: DriveCopy[];
+  size: number;
+}
+
+const KIND_LABELS: Record<CrossDriveGroup["kind"], string> = {
+  relocated: "Same file, different paths",
+  diverged: "Content differs between drives",
+  orphaned: "Only on one drive",
+};
+
+export function CrossDriveReport() {
+  const [groups, setGroups] = useState<CrossDriveGroup[] | null>(null);
+  const [verifyHashes, setVerifyHashes] = useState(false);
+  const [comparing, setComparing] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  const handleCompare = async () => {
+    setError(null);
+    setComparing(true);
+    try {
+      setGroups(
+        await invoke<CrossDriveGroup[]>("find_cross_drive_duplicates", {
+          verifyHashes,
+        }),
+      );
+    } catch (err) {
+      setError(String(err));
+    } finally {
+      setComparing(false);
+    }
+  };
+
+  return (
+    <div>
+      <div className="flex items-center gap-4 mb-4">
+        <label className="flex items-center gap-2 text-sm">
+          <Checkbox
+            checked={verifyHashes}
+            onCheckedChange={(checked) => setVerifyHashes(checked === true)}
+          />
+          Re-hash every file (slower)
+        </label>
+        <Button onClick={handleCompare} disabled={comparing}>
+          {comparing ? "Comparing..." : "Compare Drives"}
+        </Button>
+      </div>
+      {error && <p className="mb-2 text-sm text-red-500">{error}</p>}
+      {groups &&
+        (groups.length === 0 ? (
+          <p>Both drives hold the same files.</p>
+        ) : (
+          groups.map((group, index) => (
+            <div key={index} className="mb-4 p-4 border rounded-lg">
+              <h2 className="font-semibold mb-2">
+                {KIND_LABELS[group.kind]} -{" "}
+                {(group.size / 1024 / 1024).toFixed(2)} MB
+              </h2>
+              <ul className="text-sm">
+                {group.copies.map((copy) => (
+                  <li key={`${copy.drive}:${copy.path}`}>
+                    <span className="font-medium capitalize">{copy.drive}</span>
+                    : {copy.path}
+                  </li>
+                ))}
+              </ul>
+            </div>
+          ))
+        ))}
+    </div>
+  );
+}
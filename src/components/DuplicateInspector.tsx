@@ -1,7 +1,10 @@
 import { invoke } from "@tauri-apps/api/tauri";
-import { useEffect, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { useCallback, useEffect, useState } from "react";
 import { Button } from "./ui/button";
 import { Checkbox } from "@/components/ui/checkbox";
+import { thumbnailUrl } from "@/lib/media";
+import { CrossDriveReport } from "./CrossDriveReport";
 
 interface Photo {
   id: number;
@@ -14,17 +17,72 @@ interface DuplicateGroup {
   hash: string;
   photos: Photo[];
   size: number;
+  similarity: number;
+}
+
+type Mode = "exact" | "similar" | "drives";
+
+interface HashProgress {
+  stage: "partial" | "full";
+  hashed: number;
+  total: number;
+}
+
+interface KeepRecommendation {
+  keeper_id: number;
+  remove_ids: number[];
+  decided_by: string | null;
+}
+
+interface KeepSummary {
+  tags_merged: number;
+  albums_merged: number;
+  removed: { photos: number; bytes: number };
 }
 
 export function DuplicateInspector() {
   const [duplicateGroups, setDuplicateGroups] = useState<DuplicateGroup[]>([]);
   const [selectedPhotos, setSelectedPhotos] = useState<number[]>([]);
   const [error, setError] = useState<string | null>(null);
+  const [mode, setMode] = useState<Mode>("exact");
+  // Differing perceptual hash bits, out of 64
+  const [maxDistance, setMaxDistance] = useState(10);
+  const [progress, setProgress] = useState<HashProgress | null>(null);
+  const [recommendations, setRecommendations] = useState<
+    KeepRecommendation[]
+  >([]);
+
+  const loadGroups = useCallback(
+    () =>
+      (mode === "drives"
+        ? // Compared on demand by CrossDriveReport, as it reads both drives
+          Promise.resolve([])
+        : mode === "exact"
+          ? invoke<DuplicateGroup[]>("find_duplicates")
+          : invoke<DuplicateGroup[]>("find_similar_photos", { maxDistance })
+      ).then(setDuplicateGroups),
+    [mode, maxDistance],
+  );
+
+  const recommendationFor = (group: DuplicateGroup) =>
+    recommendations.find((recommendation) =>
+      group.photos.some((photo) => photo.id === recommendation.keeper_id),
+    );
 
   useEffect(() => {
-    invoke<DuplicateGroup[]>("find_duplicates")
-      .then(setDuplicateGroups)
-      .catch((err) => setError(err.toString()));
+    setRecommendations([]);
+    loadGroups()
+      .catch((err) => setError(err.toString()))
+      .finally(() => setProgress(null));
+  }, [loadGroups]);
+
+  useEffect(() => {
+    const unlisten = listen<HashProgress>("duplicate-progress", (event) =>
+      setProgress(event.payload),
+    );
+    return () => {
+      unlisten.then((stop) => stop());
+    };
   }, []);
 
   const handleSelectPhoto = (photoId: number) => {
@@ -41,16 +99,57 @@ export function DuplicateInspector() {
         photoIds: selectedPhotos,
       });
       alert(
-        `Successfully deleted photos and freed ${(spaceFreed / 1024 / 1024).toFixed(2)} MB`,
+        `Moved photos to the trash. Emptying it frees ${(spaceFreed / 1024 / 1024).toFixed(2)} MB`,
       );
       // Refresh the list of duplicates
-      invoke<DuplicateGroup[]>("find_duplicates").then(setDuplicateGroups);
+      loadGroups();
       setSelectedPhotos([]);
     } catch (err) {
       setError((err as Error).toString());
     }
   };
 
+  const handleRecommend = async () => {
+    try {
+      const result = await invoke<KeepRecommendation[]>("recommend_keepers", {
+        photoGroups: duplicateGroups.map((group) =>
+          group.photos.map((photo) => photo.id),
+        ),
+      });
+      setRecommendations(result);
+      setSelectedPhotos(result.flatMap((recommendation) => recommendation.remove_ids));
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  const handleKeepBest = async () => {
+    // Photos picked by hand since the recommendation are left alone
+    const chosen = recommendations
+      .map((recommendation) => ({
+        ...recommendation,
+        remove_ids: recommendation.remove_ids.filter((id) =>
+          selectedPhotos.includes(id),
+        ),
+      }))
+      .filter((recommendation) => recommendation.remove_ids.length > 0);
+    try {
+      const summary = await invoke<KeepSummary>("keep_best_copies", {
+        recommendations: chosen,
+      });
+      alert(
+        `Moved ${summary.removed.photos} copies to the trash, keeping ` +
+          `${summary.tags_merged} tags and ${summary.albums_merged} album memberships. ` +
+          `Emptying it frees ${(summary.removed.bytes / 1024 / 1024).toFixed(2)} MB`,
+      );
+      setRecommendations([]);
+      setSelectedPhotos([]);
+      loadGroups();
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
   const totalSelectedSize = duplicateGroups
     .flatMap((group) => group.photos)
     .filter((photo) => selectedPhotos.includes(photo.id))
@@ -60,14 +159,75 @@ export function DuplicateInspector() {
     return <div className="text-red-500">Error: {error}</div>;
   }
 
+  const modeButtons = (
+    <>
+      <Button
+        variant={mode === "exact" ? "secondary" : "ghost"}
+        onClick={() => setMode("exact")}
+      >
+        Identical
+      </Button>
+      <Button
+        variant={mode === "similar" ? "secondary" : "ghost"}
+        onClick={() => setMode("similar")}
+      >
+        Similar
+      </Button>
+      <Button
+        variant={mode === "drives" ? "secondary" : "ghost"}
+        onClick={() => setMode("drives")}
+      >
+        Across Drives
+      </Button>
+    </>
+  );
+
+  if (mode === "drives") {
+    return (
+      <div className="p-4">
+        <div className="flex justify-between items-center mb-4">
+          <h1 className="text-2xl font-bold">Duplicate Photos</h1>
+          <div className="flex items-center gap-4">{modeButtons}</div>
+        </div>
+        <CrossDriveReport />
+      </div>
+    );
+  }
+
   return (
     <div className="p-4">
       <div className="flex justify-between items-center mb-4">
         <h1 className="text-2xl font-bold">Duplicate Photos</h1>
         <div className="flex items-center gap-4">
+          {modeButtons}
+          {mode === "similar" && (
+            <label className="flex items-center gap-2 text-sm">
+              Tolerance
+              <input
+                type="range"
+                min={0}
+                max={24}
+                value={maxDistance}
+                onChange={(e) => setMaxDistance(Number(e.target.value))}
+              />
+            </label>
+          )}
           <span>
             {`Selected: ${(totalSelectedSize / 1024 / 1024).toFixed(2)} MB`}
           </span>
+          <Button
+            variant="outline"
+            onClick={handleRecommend}
+            disabled={duplicateGroups.length === 0}
+          >
+            Select Recommended
+          </Button>
+          <Button
+            onClick={handleKeepBest}
+            disabled={recommendations.length === 0}
+          >
+            Keep Best
+          </Button>
           <Button
             onClick={handleDeleteSelected}
             disabled={selectedPhotos.length === 0}
@@ -76,6 +236,14 @@ export function DuplicateInspector() {
           </Button>
         </div>
       </div>
+      {progress && progress.hashed < progress.total && (
+        <p className="mb-2 text-sm">
+          {progress.stage === "partial"
+            ? "Comparing files of the same size"
+            : "Checking possible duplicates"}
+          : {progress.hashed} of {progress.total}
+        </p>
+      )}
       {duplicateGroups.length === 0 ? (
         <p>No duplicates found.</p>
       ) : (
@@ -84,12 +252,14 @@ export function DuplicateInspector() {
             <div key={group.hash} className="mb-8 p-4 border rounded-lg">
               <h2 className="text-xl font-semibold mb-2">
                 Duplicate Set - Save {(group.size / 1024 / 1024).toFixed(2)} MB
+                {group.similarity < 1 &&
+                  ` - ${Math.round(group.similarity * 100)}% similar`}
               </h2>
               <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 gap-4">
                 {group.photos.map((photo) => (
                   <div key={photo.id} className="relative">
                     <img
-                      src={`https://asset.localhost/${photo.path}`}
+                      src={thumbnailUrl(photo.id, 1024)}
                       alt={photo.filename}
                       className="w-full h-auto rounded-md"
                     />
@@ -100,6 +270,13 @@ export function DuplicateInspector() {
                       />
                     </div>
                     <p className="mt-2 text-sm text-center">{photo.filename}</p>
+                    {recommendationFor(group)?.keeper_id === photo.id && (
+                      <p className="text-xs text-center text-green-600">
+                        Keep
+                        {recommendationFor(group)?.decided_by &&
+                          ` (${recommendationFor(group)?.decided_by?.replace("_", " ")})`}
+                      </p>
+                    )}
                   </div>
                 ))}
               </div>
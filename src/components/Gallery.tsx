@@ -1,5 +1,6 @@
 import React from "react";
 import { Photo } from "../models/photo";
+import { formatDuration, thumbnailUrl } from "../lib/media";
 
 interface GalleryProps {
   photos: Photo[];
@@ -27,12 +28,30 @@ const Gallery: React.FC<GalleryProps> = ({
       {photos.map((photo) => (
         <div key={photo.id} className="relative group">
           <div
-            className={`bg-gray-200 dark:bg-gray-700 aspect-square rounded-md group-hover:opacity-80 ${
+            className={`bg-gray-200 dark:bg-gray-700 aspect-square rounded-md overflow-hidden group-hover:opacity-80 ${
               selectedPhotoIds?.includes(photo.id) ? "ring-2 ring-blue-500" : ""
             }`}
             onClick={() => handlePhotoSelection(photo.id)}
           >
-            <p className="text-white">{photo.filename}</p>
+            <img
+              src={thumbnailUrl(photo.id)}
+              alt={photo.filename}
+              loading="lazy"
+              className="w-full h-full object-cover"
+            />
+            {photo.stack_id != null && (
+              <span
+                className="absolute top-2 right-2 rounded bg-black/70 px-1.5 py-0.5 text-xs text-white"
+                title="Stack"
+              >
+                ⧉
+              </span>
+            )}
+            {photo.media_type === "video" && (
+              <span className="absolute bottom-2 right-2 rounded bg-black/70 px-1.5 py-0.5 text-xs text-white">
+                ▶ {photo.duration_ms != null ? formatDuration(photo.duration_ms) : ""}
+              </span>
+            )}
           </div>
           {selectedPhotoIds && (
             <input
@@ -0,0 +1,117 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { open } from "@tauri-apps/plugin-dialog";
+import { useQueryClient } from "@tanstack/react-query";
+import { Button } from "./ui/button";
+
+interface ImportProgress {
+  discovered: number;
+  imported: number;
+  duplicates: number;
+  failed: number;
+}
+
+interface ImportFailure {
+  path: string;
+  error: string;
+}
+
+interface ImportSummary {
+  imported: string[];
+  duplicates: string[];
+  failed: ImportFailure[];
+  stacked: number;
+  cancelled: boolean;
+}
+
+interface ImportFinished {
+  summary: ImportSummary | null;
+  error: string | null;
+}
+
+export function ImportPanel() {
+  const queryClient = useQueryClient();
+  const [importing, setImporting] = useState(false);
+  const [progress, setProgress] = useState<ImportProgress | null>(null);
+  const [result, setResult] = useState<ImportFinished | null>(null);
+
+  useEffect(() => {
+    const unlisteners = [
+      listen<ImportProgress>("import-progress", (event) =>
+        setProgress(event.payload),
+      ),
+      listen<ImportFinished>("import-finished", (event) => {
+        setImporting(false);
+        setProgress(null);
+        setResult(event.payload);
+        queryClient.invalidateQueries({ queryKey: ["photos"] });
+      }),
+    ];
+    return () => {
+      unlisteners.forEach((unlisten) => unlisten.then((fn) => fn()));
+    };
+  }, [queryClient]);
+
+  const startImport = async () => {
+    const source = await open({
+      directory: true,
+      multiple: false,
+      title: "Import photos from",
+    });
+    if (typeof source !== "string") {
+      return;
+    }
+    setResult(null);
+    invoke("import_folder", { source })
+      .then(() => setImporting(true))
+      .catch((error) => setResult({ summary: null, error: String(error) }));
+  };
+
+  const summary = result?.summary;
+
+  return (
+    <div className="p-4 border-b text-sm">
+      <div className="flex items-center gap-2">
+        {importing ? (
+          <Button variant="outline" onClick={() => invoke("cancel_import")}>
+            Cancel import
+          </Button>
+        ) : (
+          <Button onClick={startImport}>Import photos…</Button>
+        )}
+        {progress && (
+          <span>
+            {progress.discovered} found, {progress.imported} imported,{" "}
+            {progress.duplicates} already in library, {progress.failed} failed
+          </span>
+        )}
+      </div>
+      {result?.error && <p className="text-red-500">{result.error}</p>}
+      {summary && (
+        <div className="mt-2">
+          <p>
+            {summary.cancelled ? "Import cancelled" : "Import complete"}:{" "}
+            {summary.imported.length} imported, {summary.duplicates.length}{" "}
+            already in library
+            {summary.stacked > 0 && `, ${summary.stacked} stacked`}
+          </p>
+          {summary.failed.length > 0 && (
+            <details>
+              <summary>
+                {summary.failed.length} files could not be imported
+              </summary>
+              <ul>
+                {summary.failed.map((failure) => (
+                  <li key={failure.path}>
+                    {failure.path}: {failure.error}
+                  </li>
+                ))}
+              </ul>
+            </details>
+          )}
+        </div>
+      )}
+    </div>
+  );
+}
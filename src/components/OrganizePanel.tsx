@@ -0,0 +1,306 @@
+import { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useQuery, useQueryClient } from "@tanstack/react-query";
+import { Button } from "@/components/ui/button";
+import {
+  Dialog,
+  DialogContent,
+  DialogHeader,
+  DialogFooter,
+  DialogTitle,
+  DialogDescription,
+} from "@/components/ui/dialog";
+import { Album, AlbumEntry, regularAlbums } from "../models/album";
+
+type RuleMatch =
+  | { type: "all" }
+  | { type: "taken"; from: string | null; to: string | null }
+  | { type: "camera"; camera: string }
+  | { type: "tag"; name: string }
+  | { type: "album"; album_id: number }
+  | { type: "format"; format: string };
+
+interface OrganizeRule {
+  matches: RuleMatch;
+  folder: string;
+}
+
+interface PlannedMove {
+  photo_id: number;
+  from: string;
+  to: string;
+  rule: number;
+}
+
+interface OrganizePlan {
+  moves: PlannedMove[];
+  unchanged: number;
+  unmatched: number;
+}
+
+interface OrganizeSummary {
+  moved: number;
+  failed: { path: string; error: string }[];
+}
+
+const DEFAULT_RULES: OrganizeRule[] = [
+  { matches: { type: "all" }, folder: "{year}/{month}" },
+];
+
+const emptyMatch = (type: RuleMatch["type"]): RuleMatch => {
+  switch (type) {
+    case "all":
+      return { type };
+    case "taken":
+      return { type, from: null, to: null };
+    case "camera":
+      return { type, camera: "" };
+    case "tag":
+      return { type, name: "" };
+    case "album":
+      return { type, album_id: 0 };
+    case "format":
+      return { type, format: "" };
+  }
+};
+
+export function OrganizePanel() {
+  const queryClient = useQueryClient();
+  const [isOpen, setIsOpen] = useState(false);
+  const [rules, setRules] = useState<OrganizeRule[]>(DEFAULT_RULES);
+  const [plan, setPlan] = useState<OrganizePlan | null>(null);
+  const [summary, setSummary] = useState<OrganizeSummary | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const [isOrganizing, setIsOrganizing] = useState(false);
+
+  const { data: albums } = useQuery<AlbumEntry[], Error, Album[]>({
+    queryKey: ["albums"],
+    queryFn: () => invoke("get_albums"),
+    select: regularAlbums,
+  });
+
+  const updateRule = (index: number, rule: OrganizeRule) => {
+    setPlan(null);
+    setRules(rules.map((current, i) => (i === index ? rule : current)));
+  };
+
+  const handlePreview = async () => {
+    setError(null);
+    try {
+      setPlan(await invoke<OrganizePlan>("preview_organize", { rules }));
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  const handleOrganize = async () => {
+    setIsOrganizing(true);
+    setError(null);
+    try {
+      setSummary(await invoke<OrganizeSummary>("organize_library", { rules }));
+      setPlan(null);
+      setIsOpen(false);
+      queryClient.invalidateQueries({ queryKey: ["photos"] });
+    } catch (err) {
+      setError(String(err));
+    } finally {
+      setIsOrganizing(false);
+    }
+  };
+
+  const matchInput = (rule: OrganizeRule, index: number) => {
+    const { matches } = rule;
+    const update = (matches: RuleMatch) =>
+      updateRule(index, { ...rule, matches });
+    switch (matches.type) {
+      case "all":
+        return null;
+      case "taken":
+        return (
+          <>
+            <input
+              type="date"
+              className="border p-1 rounded"
+              value={matches.from ?? ""}
+              onChange={(e) =>
+                update({ ...matches, from: e.target.value || null })
+              }
+            />
+            <input
+              type="date"
+              className="border p-1 rounded"
+              value={matches.to ?? ""}
+              onChange={(e) =>
+                update({ ...matches, to: e.target.value || null })
+              }
+            />
+          </>
+        );
+      case "camera":
+        return (
+          <input
+            className="border p-1 rounded"
+            placeholder="Camera"
+            value={matches.camera}
+            onChange={(e) => update({ ...matches, camera: e.target.value })}
+          />
+        );
+      case "tag":
+        return (
+          <input
+            className="border p-1 rounded"
+            placeholder="Tag"
+            value={matches.name}
+            onChange={(e) => update({ ...matches, name: e.target.value })}
+          />
+        );
+      case "album":
+        return (
+          <select
+            className="border p-1 rounded"
+            value={matches.album_id}
+            onChange={(e) =>
+              update({ ...matches, album_id: Number(e.target.value) })
+            }
+          >
+            <option value={0} disabled>
+              Select an album
+            </option>
+            {albums?.map((album) => (
+              <option key={album.id} value={album.id}>
+                {album.name}
+              </option>
+            ))}
+          </select>
+        );
+      case "format":
+        return (
+          <input
+            className="border p-1 rounded"
+            placeholder="Format, e.g. Png"
+            value={matches.format}
+            onChange={(e) => update({ ...matches, format: e.target.value })}
+          />
+        );
+    }
+  };
+
+  return (
+    <div className="p-4 border-b text-sm">
+      <Button
+        variant="outline"
+        onClick={() => {
+          setSummary(null);
+          setIsOpen(true);
+        }}
+      >
+        Organize library…
+      </Button>
+      {summary && (
+        <p className="mt-2">
+          Moved {summary.moved} files
+          {summary.failed.length > 0 &&
+            `, ${summary.failed.length} could not be moved`}
+        </p>
+      )}
+
+      <Dialog open={isOpen} onOpenChange={setIsOpen}>
+        <DialogContent>
+          <DialogHeader>
+            <DialogTitle>Organize library</DialogTitle>
+            <DialogDescription>
+              Each photo goes to the folder of the first rule it matches.
+              Folders can use {"{year}"}, {"{month}"}, {"{day}"} and{" "}
+              {"{date}"}. Photos no rule matches stay where they are.
+            </DialogDescription>
+          </DialogHeader>
+
+          <div className="space-y-2">
+            {rules.map((rule, index) => (
+              <div key={index} className="flex items-center gap-2">
+                <select
+                  className="border p-1 rounded"
+                  value={rule.matches.type}
+                  onChange={(e) =>
+                    updateRule(index, {
+                      ...rule,
+                      matches: emptyMatch(e.target.value as RuleMatch["type"]),
+                    })
+                  }
+                >
+                  <option value="all">All photos</option>
+                  <option value="taken">Taken between</option>
+                  <option value="camera">Camera</option>
+                  <option value="tag">Tag</option>
+                  <option value="album">Album</option>
+                  <option value="format">Format</option>
+                </select>
+                {matchInput(rule, index)}
+                <span>→</span>
+                <input
+                  className="flex-1 border p-1 rounded"
+                  value={rule.folder}
+                  onChange={(e) =>
+                    updateRule(index, { ...rule, folder: e.target.value })
+                  }
+                />
+                <Button
+                  variant="ghost"
+                  onClick={() => {
+                    setPlan(null);
+                    setRules(rules.filter((_, i) => i !== index));
+                  }}
+                >
+                  Remove
+                </Button>
+              </div>
+            ))}
+            <Button
+              variant="outline"
+              onClick={() => {
+                setPlan(null);
+                setRules([
+                  ...rules,
+                  { matches: { type: "all" }, folder: "{year}/{month}" },
+                ]);
+              }}
+            >
+              Add rule
+            </Button>
+          </div>
+
+          {plan && (
+            <div className="max-h-64 overflow-y-auto text-sm">
+              <p>
+                {plan.moves.length} to move, {plan.unchanged} already in
+                place, {plan.unmatched} not matched
+              </p>
+              {plan.moves.map((move) => (
+                <p key={move.from}>
+                  {move.from} → {move.to}
+                </p>
+              ))}
+            </div>
+          )}
+
+          {error && <p className="text-sm text-red-500">{error}</p>}
+
+          <DialogFooter>
+            <Button variant="ghost" onClick={() => setIsOpen(false)}>
+              Cancel
+            </Button>
+            <Button variant="outline" onClick={handlePreview}>
+              Preview
+            </Button>
+            <Button
+              onClick={handleOrganize}
+              disabled={isOrganizing || !plan || plan.moves.length === 0}
+            >
+              {isOrganizing ? "Moving..." : "Move files"}
+            </Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
+    </div>
+  );
+}
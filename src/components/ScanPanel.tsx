@@ -0,0 +1,108 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useQueryClient } from "@tanstack/react-query";
+import { Button } from "./ui/button";
+
+interface ScanProgress {
+  discovered: number;
+  processed: number;
+  skipped: number;
+  failed: number;
+}
+
+interface ScanFailure {
+  path: string;
+  error: string;
+}
+
+interface ScanSummary {
+  added: number;
+  updated: number;
+  unchanged: number;
+  missing: string[];
+  failed: ScanFailure[];
+  stacked: number;
+  cancelled: boolean;
+}
+
+interface ScanFinished {
+  summary: ScanSummary | null;
+  error: string | null;
+}
+
+export function ScanPanel() {
+  const queryClient = useQueryClient();
+  const [scanning, setScanning] = useState(false);
+  const [progress, setProgress] = useState<ScanProgress | null>(null);
+  const [result, setResult] = useState<ScanFinished | null>(null);
+
+  useEffect(() => {
+    const unlisteners = [
+      listen<ScanProgress>("scan-progress", (event) =>
+        setProgress(event.payload),
+      ),
+      listen<ScanFinished>("scan-finished", (event) => {
+        setScanning(false);
+        setProgress(null);
+        setResult(event.payload);
+        queryClient.invalidateQueries({ queryKey: ["photos"] });
+      }),
+    ];
+    return () => {
+      unlisteners.forEach((unlisten) => unlisten.then((fn) => fn()));
+    };
+  }, [queryClient]);
+
+  const startScan = () => {
+    setResult(null);
+    invoke("scan_library")
+      .then(() => setScanning(true))
+      .catch((error) => setResult({ summary: null, error: String(error) }));
+  };
+
+  const summary = result?.summary;
+
+  return (
+    <div className="p-4 border-b text-sm">
+      <div className="flex items-center gap-2">
+        {scanning ? (
+          <Button variant="outline" onClick={() => invoke("cancel_scan")}>
+            Cancel scan
+          </Button>
+        ) : (
+          <Button onClick={startScan}>Scan library</Button>
+        )}
+        {progress && (
+          <span>
+            {progress.discovered} found, {progress.processed} read,{" "}
+            {progress.skipped} unchanged, {progress.failed} failed
+          </span>
+        )}
+      </div>
+      {result?.error && <p className="text-red-500">{result.error}</p>}
+      {summary && (
+        <div className="mt-2">
+          <p>
+            {summary.cancelled ? "Scan cancelled" : "Scan complete"}:{" "}
+            {summary.added} added, {summary.updated} updated,{" "}
+            {summary.unchanged} unchanged, {summary.missing.length} missing
+            {summary.stacked > 0 && `, ${summary.stacked} stacked`}
+          </p>
+          {summary.failed.length > 0 && (
+            <details>
+              <summary>{summary.failed.length} files could not be read</summary>
+              <ul>
+                {summary.failed.map((failure) => (
+                  <li key={failure.path}>
+                    {failure.path}: {failure.error}
+                  </li>
+                ))}
+              </ul>
+            </details>
+          )}
+        </div>
+      )}
+    </div>
+  );
+}
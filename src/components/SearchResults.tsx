@@ -0,0 +1,93 @@
+import React from "react";
+import { QueryPhotosError, SearchResult } from "../models/search";
+import { thumbnailUrl } from "../lib/media";
+
+interface SearchResultsProps {
+  results: SearchResult[];
+  selectedPhotoIds: number[];
+  setSelectedPhotoIds: React.Dispatch<React.SetStateAction<number[]>>;
+}
+
+export const SearchResults: React.FC<SearchResultsProps> = ({
+  results,
+  selectedPhotoIds,
+  setSelectedPhotoIds,
+}) => {
+  const toggle = (photoId: number) =>
+    setSelectedPhotoIds((prev) =>
+      prev.includes(photoId)
+        ? prev.filter((id) => id !== photoId)
+        : [...prev, photoId],
+    );
+
+  if (results.length === 0) {
+    return <p className="text-sm text-gray-500">No matching photos.</p>;
+  }
+
+  return (
+    <ul className="space-y-2">
+      {results.map(({ photo, snippet }) => (
+        <li
+          key={photo.id}
+          onClick={() => toggle(photo.id)}
+          className={`flex items-center gap-3 rounded-md p-2 cursor-pointer hover:bg-gray-100 dark:hover:bg-gray-800 ${
+            selectedPhotoIds.includes(photo.id) ? "ring-2 ring-blue-500" : ""
+          }`}
+        >
+          <img
+            src={thumbnailUrl(photo.id)}
+            alt={photo.filename}
+            loading="lazy"
+            className="w-16 h-16 rounded object-cover bg-gray-200 dark:bg-gray-700"
+          />
+          <div className="min-w-0">
+            <p className="font-medium truncate">{photo.filename}</p>
+            <p className="text-sm text-gray-600 dark:text-gray-400 truncate">
+              {snippet.map((part, i) =>
+                part.matched ? (
+                  <mark key={i} className="bg-yellow-200 dark:bg-yellow-700">
+                    {part.text}
+                  </mark>
+                ) : (
+                  <span key={i}>{part.text}</span>
+                ),
+              )}
+            </p>
+          </div>
+        </li>
+      ))}
+    </ul>
+  );
+};
+
+interface QueryErrorMessageProps {
+  query: string;
+  error: QueryPhotosError;
+}
+
+/** Shows why a query failed, underlining the part of it at fault. */
+export const QueryErrorMessage: React.FC<QueryErrorMessageProps> = ({
+  query,
+  error,
+}) => {
+  if (error.kind !== "syntax") {
+    return <p className="text-sm text-red-600">{error.message}</p>;
+  }
+  // Offsets count characters, not UTF-16 code units
+  const chars = Array.from(query);
+  const before = chars.slice(0, error.start).join("");
+  const culprit = chars.slice(error.start, error.end).join("");
+  const after = chars.slice(error.end).join("");
+  return (
+    <div className="text-sm text-red-600 space-y-1">
+      <p className="font-mono whitespace-pre-wrap text-gray-700 dark:text-gray-300">
+        {before}
+        <span className="underline decoration-wavy decoration-red-600">
+          {culprit || " "}
+        </span>
+        {after}
+      </p>
+      <p>{error.message}</p>
+    </div>
+  );
+};
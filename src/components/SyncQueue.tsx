@@ -1,12 +1,36 @@
 import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+interface FailedOperation {
+  id: number;
+  // Serialized `Operation`, e.g. `{ "Move": { "from": ..., "to": ... } }`
+  operation: Record<string, unknown>;
+  attempts: number;
+  last_error: string | null;
+}
 
 interface QueueStatus {
   pending_operations: number;
+  failed_operations: FailedOperation[];
+}
+
+interface SyncProgress {
+  flushed: number;
+  remaining: number;
+}
+
+interface SyncResult {
+  flushed: number;
+  remaining: number;
+  error: string | null;
 }
 
 export function SyncQueue() {
   const [status, setStatus] = useState<QueueStatus | null>(null);
+  const [backupConnected, setBackupConnected] = useState<boolean | null>(null);
+  const [progress, setProgress] = useState<SyncProgress | null>(null);
+  const [lastResult, setLastResult] = useState<SyncResult | null>(null);
 
   useEffect(() => {
     const interval = setInterval(() => {
@@ -14,7 +38,23 @@ export function SyncQueue() {
         .then(setStatus)
         .catch(console.error);
     }, 1000);
-    return () => clearInterval(interval);
+
+    const unlisteners = [
+      listen("backup-connected", () => setBackupConnected(true)),
+      listen("backup-disconnected", () => setBackupConnected(false)),
+      listen<SyncProgress>("sync-progress", (event) =>
+        setProgress(event.payload),
+      ),
+      listen<SyncResult>("sync-result", (event) => {
+        setProgress(null);
+        setLastResult(event.payload);
+      }),
+    ];
+
+    return () => {
+      clearInterval(interval);
+      unlisteners.forEach((unlisten) => unlisten.then((fn) => fn()));
+    };
   }, []);
 
   if (!status) {
@@ -24,6 +64,33 @@ export function SyncQueue() {
   return (
     <div>
       <p>Pending operations: {status.pending_operations}</p>
+      {backupConnected === false && <p>Backup drive disconnected</p>}
+      {progress && (
+        <p>
+          Syncing to backup: {progress.flushed} done, {progress.remaining}{" "}
+          remaining
+        </p>
+      )}
+      {lastResult && !progress && (
+        <p>
+          Last sync: {lastResult.flushed} operations copied
+          {lastResult.error && ` (stopped: ${lastResult.error})`}
+        </p>
+      )}
+      {status.failed_operations.length > 0 && (
+        <div>
+          <p>Operations that could not be copied to the backup:</p>
+          <ul>
+            {status.failed_operations.map((failed) => (
+              <li key={failed.id}>
+                {Object.keys(failed.operation)[0]} failed after{" "}
+                {failed.attempts} attempts
+                {failed.last_error && `: ${failed.last_error}`}
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
     </div>
   );
 }
@@ -0,0 +1,24 @@
+import { convertFileSrc } from "@tauri-apps/api/core";
+
+const SCHEME = "photovault";
+
+export type ThumbnailSize = 256 | 1024;
+
+export function thumbnailUrl(photoId: number, size: ThumbnailSize = 256) {
+  return convertFileSrc(`thumb/${photoId}/${size}`, SCHEME);
+}
+
+export function originalUrl(photoId: number) {
+  return convertFileSrc(`original/${photoId}`, SCHEME);
+}
+
+/** Formats a video duration as `m:ss`, or `h:mm:ss` for long clips. */
+export function formatDuration(durationMs: number) {
+  const total = Math.round(durationMs / 1000);
+  const hours = Math.floor(total / 3600);
+  const minutes = Math.floor((total % 3600) / 60);
+  const seconds = String(total % 60).padStart(2, "0");
+  return hours > 0
+    ? `${hours}:${String(minutes).padStart(2, "0")}:${seconds}`
+    : `${minutes}:${seconds}`;
+}
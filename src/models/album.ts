@@ -1,4 +1,40 @@
+import { MediaType } from "./photo";
+
+/** Mirrors `FilterCriteria`; `expression` uses the advanced search language. */
+export interface FilterCriteria {
+  date_from?: string | null;
+  date_to?: string | null;
+  min_width?: number | null;
+  min_height?: number | null;
+  tags?: number[] | null;
+  albums?: number[] | null;
+  query?: string | null;
+  media_type?: MediaType | null;
+  expression?: string | null;
+}
+
 export interface Album {
   id: number;
   name: string;
+  photo_count: number;
+}
+
+/** Holds the photos matching `criteria`; numbered separately from albums. */
+export interface SmartAlbum {
+  id: number;
+  name: string;
+  criteria: FilterCriteria;
+  photo_count: number;
 }
+
+/** Mirrors `AlbumEntry`: one entry of `get_albums`, of either kind. */
+export type AlbumEntry =
+  | { kind: "album"; album: Album }
+  | { kind: "smart"; album: SmartAlbum };
+
+/** An album or smart album picked from the album list. */
+export type AlbumSelection = AlbumEntry;
+
+/** The regular albums among `get_albums`' entries. */
+export const regularAlbums = (entries: AlbumEntry[]): Album[] =>
+  entries.flatMap((entry) => (entry.kind === "album" ? [entry.album] : []));
@@ -1,3 +1,5 @@
+export type MediaType = "photo" | "video";
+
 export interface Photo {
   id: number;
   path: string;
@@ -7,4 +9,25 @@ export interface Photo {
   width: number | null;
   height: number | null;
   format: string;
+  file_hash: string | null;
+  perceptual_hash: string | null;
+  date_taken_offset: number | null;
+  camera_make: string | null;
+  camera_model: string | null;
+  lens_model: string | null;
+  iso: number | null;
+  aperture: number | null;
+  exposure_time: number | null;
+  focal_length: number | null;
+  orientation: number | null;
+  file_mtime: number | null;
+  missing_since: string | null;
+  media_type: MediaType;
+  duration_ms: number | null;
+  video_codec: string | null;
+  stack_id: number | null;
+  content_identifier: string | null;
+  caption: string | null;
+  deleted_at: string | null;
+  trashed_from: string | null;
 }
@@ -0,0 +1,17 @@
+import { Photo } from "./photo";
+
+export interface SnippetPart {
+  text: string;
+  matched: boolean;
+}
+
+export interface SearchResult {
+  photo: Photo;
+  snippet: SnippetPart[];
+  score: number;
+}
+
+/** Why `query_photos` failed; `start` and `end` count characters of the query. */
+export type QueryPhotosError =
+  | { kind: "syntax"; message: string; start: number; end: number }
+  | { kind: "failed"; message: string };
@@ -1,17 +1,26 @@
 import React from "react";
 import { useQuery } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/core";
+import { AlbumSelection } from "../models/album";
 import { Photo } from "../models/photo";
 import Gallery from "../components/Gallery";
 
 interface AlbumViewProps {
-  albumId: number;
+  selection: AlbumSelection;
 }
 
-export function AlbumView({ albumId }: AlbumViewProps) {
+export function AlbumView({ selection }: AlbumViewProps) {
+  const { album } = selection;
+  // Smart albums are evaluated each time they're opened
   const { data: photos, isLoading } = useQuery<Photo[]>({
-    queryKey: ["album", albumId],
-    queryFn: () => invoke("get_photos_by_album", { albumId }),
+    queryKey:
+      selection.kind === "smart"
+        ? ["photos", "smart-album", album.id]
+        : ["album", album.id],
+    queryFn: () =>
+      selection.kind === "smart"
+        ? invoke("get_photos_by_smart_album", { smartAlbumId: album.id })
+        : invoke("get_photos_by_album", { albumId: album.id }),
   });
 
   if (isLoading) {
@@ -20,7 +29,7 @@ export function AlbumView({ albumId }: AlbumViewProps) {
 
   return (
     <div className="p-4">
-      <h2 className="text-lg font-bold mb-4">Album</h2>
+      <h2 className="text-lg font-bold mb-4">{album.name}</h2>
       <Gallery photos={photos || []} />
     </div>
   );
@@ -0,0 +1,89 @@
+import React, { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useQuery, useQueryClient } from "@tanstack/react-query";
+import { Button } from "@/components/ui/button";
+
+interface HistoryEntry {
+  id: number;
+  label: string;
+  undone: boolean;
+  created_at: string;
+}
+
+const HistoryPage: React.FC = () => {
+  const queryClient = useQueryClient();
+  const [message, setMessage] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  const { data: entries } = useQuery<HistoryEntry[]>({
+    queryKey: ["history"],
+    queryFn: () => invoke("get_history"),
+  });
+
+  const canUndo = entries?.some((entry) => !entry.undone) ?? false;
+  const canRedo = entries?.some((entry) => entry.undone) ?? false;
+
+  const run = async (command: "undo_last" | "redo") => {
+    setError(null);
+    const undo = command === "undo_last";
+    try {
+      const entry = await invoke<HistoryEntry | null>(command);
+      setMessage(
+        entry
+          ? `${undo ? "Undid" : "Redid"} "${entry.label}"`
+          : `Nothing to ${undo ? "undo" : "redo"}`,
+      );
+      // Any view may show what was changed
+      queryClient.invalidateQueries();
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  return (
+    <div className="p-4">
+      <div className="flex justify-between items-center mb-4">
+        <h1 className="text-2xl font-bold">History</h1>
+        <div className="flex gap-2">
+          <Button
+            variant="outline"
+            onClick={() => run("undo_last")}
+            disabled={!canUndo}
+          >
+            Undo
+          </Button>
+          <Button
+            variant="outline"
+            onClick={() => run("redo")}
+            disabled={!canRedo}
+          >
+            Redo
+          </Button>
+        </div>
+      </div>
+      {message && <p className="mb-2 text-sm">{message}</p>}
+      {error && <p className="mb-2 text-sm text-red-500">{error}</p>}
+      {!entries?.length ? (
+        <p>Nothing to undo yet.</p>
+      ) : (
+        <ul className="divide-y">
+          {entries.map((entry) => (
+            <li
+              key={entry.id}
+              className={`flex justify-between py-2 ${
+                entry.undone ? "text-muted-foreground line-through" : ""
+              }`}
+            >
+              <span>{entry.label}</span>
+              <span className="text-sm">
+                {new Date(entry.created_at).toLocaleString()}
+              </span>
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+};
+
+export default HistoryPage;
@@ -1,11 +1,24 @@
-import React, { useState } from "react";
+import React, { useEffect, useState } from "react";
 import Sidebar from "../components/Sidebar";
 import Gallery from "../components/Gallery";
 import { BulkActions } from "../components/BulkActions";
-import { useInfiniteQuery, useQuery } from "@tanstack/react-query";
+import { ScanPanel } from "../components/ScanPanel";
+import { ImportPanel } from "../components/ImportPanel";
+import { OrganizePanel } from "../components/OrganizePanel";
+import {
+  QueryErrorMessage,
+  SearchResults,
+} from "../components/SearchResults";
+import {
+  useInfiniteQuery,
+  useQuery,
+  useQueryClient,
+} from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/tauri";
-import { Album } from "../models/album";
+import { listen } from "@tauri-apps/api/event";
+import { Album, AlbumEntry, regularAlbums } from "../models/album";
 import { Photo } from "../models/photo";
+import { QueryPhotosError, SearchResult } from "../models/search";
 
 const fetchPhotos = async ({ pageParam = 0 }) => {
   const photos: Photo[] = await invoke("get_photos", {
@@ -17,10 +30,42 @@ const fetchPhotos = async ({ pageParam = 0 }) => {
 
 const Library: React.FC = () => {
   const [selectedPhotoIds, setSelectedPhotoIds] = useState<number[]>([]);
+  const [search, setSearch] = useState("");
+  const searchQuery = search.trim();
+  // Advanced queries use the query language, e.g. `tag:beach width>=4000`
+  const [advanced, setAdvanced] = useState(false);
+  const queryClient = useQueryClient();
 
-  const { data: albums } = useQuery<Album[]>({
+  // The library watcher reports changes made to the primary drive by other tools
+  useEffect(() => {
+    const unlisten = listen("library-changed", () =>
+      queryClient.invalidateQueries({ queryKey: ["photos"] }),
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [queryClient]);
+
+  const { data: albums } = useQuery<AlbumEntry[], Error, Album[]>({
     queryKey: ["albums"],
     queryFn: () => invoke("get_albums"),
+    select: regularAlbums,
+  });
+
+  const { data: searchResults } = useQuery<SearchResult[]>({
+    queryKey: ["photos", "search", searchQuery],
+    queryFn: () => invoke("search_library", { query: searchQuery }),
+    enabled: searchQuery !== "" && !advanced,
+  });
+
+  const { data: queryResults, error: queryError } = useQuery<
+    Photo[],
+    QueryPhotosError
+  >({
+    queryKey: ["photos", "query", searchQuery],
+    queryFn: () => invoke("query_photos", { query: searchQuery }),
+    enabled: searchQuery !== "" && advanced,
+    retry: false,
   });
 
   const {
@@ -45,14 +90,60 @@ const Library: React.FC = () => {
       <div className="flex flex-grow overflow-hidden">
         <Sidebar />
         <main className="flex-1 p-4 overflow-y-auto">
-          {isLoading && <p>Loading photos...</p>}
-          {isError && <p>Error loading photos.</p>}
-          <Gallery
-            photos={allPhotos}
-            selectedPhotoIds={selectedPhotoIds}
-            setSelectedPhotoIds={setSelectedPhotoIds}
-          />
-          {hasNextPage && (
+          <ScanPanel />
+          <ImportPanel />
+          <OrganizePanel />
+          <div className="flex items-center gap-3 mb-4">
+            <input
+              type="search"
+              value={search}
+              onChange={(e) => setSearch(e.target.value)}
+              placeholder={
+                advanced
+                  ? 'tag:beach -tag:work camera:"X100V" date:2023-06..2023-08'
+                  : "Search filenames, folders, tags, albums, captions, cameras"
+              }
+              className={`flex-1 px-3 py-2 rounded border border-gray-300 dark:border-gray-600 dark:bg-gray-800 ${
+                advanced ? "font-mono" : ""
+              }`}
+            />
+            <label className="flex items-center gap-1 text-sm">
+              <input
+                type="checkbox"
+                checked={advanced}
+                onChange={(e) => setAdvanced(e.target.checked)}
+              />
+              Advanced
+            </label>
+          </div>
+          {searchQuery !== "" && advanced ? (
+            queryError ? (
+              <QueryErrorMessage query={searchQuery} error={queryError} />
+            ) : (
+              <Gallery
+                photos={queryResults || []}
+                selectedPhotoIds={selectedPhotoIds}
+                setSelectedPhotoIds={setSelectedPhotoIds}
+              />
+            )
+          ) : searchQuery !== "" ? (
+            <SearchResults
+              results={searchResults || []}
+              selectedPhotoIds={selectedPhotoIds}
+              setSelectedPhotoIds={setSelectedPhotoIds}
+            />
+          ) : (
+            <>
+              {isLoading && <p>Loading photos...</p>}
+              {isError && <p>Error loading photos.</p>}
+              <Gallery
+                photos={allPhotos}
+                selectedPhotoIds={selectedPhotoIds}
+                setSelectedPhotoIds={setSelectedPhotoIds}
+              />
+            </>
+          )}
+          {searchQuery === "" && hasNextPage && (
             <button
               onClick={() => fetchNextPage()}
               disabled={isFetchingNextPage}
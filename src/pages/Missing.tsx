@@ -0,0 +1,38 @@
+import React from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useQuery } from "@tanstack/react-query";
+import { Photo } from "../models/photo";
+
+const MissingPage: React.FC = () => {
+  const { data: photos } = useQuery<Photo[]>({
+    queryKey: ["missing"],
+    queryFn: () => invoke("get_missing_photos"),
+  });
+
+  return (
+    <div className="p-4">
+      <h1 className="text-2xl font-bold mb-2">Missing</h1>
+      <p className="mb-4 text-sm text-muted-foreground">
+        These photos were removed from the primary drive outside PhotoVault.
+        Their backup copies are kept; put a file back in place to bring it back
+        into the library.
+      </p>
+      {!photos?.length ? (
+        <p>No photos are missing.</p>
+      ) : (
+        <ul className="divide-y">
+          {photos.map((photo) => (
+            <li key={photo.id} className="py-2">
+              <p className="text-sm truncate">{photo.path}</p>
+              <p className="text-xs text-muted-foreground">
+                Missing since {photo.missing_since?.slice(0, 10)}
+              </p>
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+};
+
+export default MissingPage;
@@ -6,13 +6,28 @@ import DriveSetupModal from "@/components/DriveSetupModal";
 interface AppConfig {
   primary_drive: string | null;
   backup_drive: string | null;
+  watch_primary_drive: boolean;
+  import_template: string | null;
+  trash_retention_days: number | null;
+}
+
+const DEFAULT_IMPORT_TEMPLATE = "{year}/{month}/{date}_{original_name}";
+const DEFAULT_TRASH_RETENTION_DAYS = 30;
+
+interface FormatInfo {
+  name: string;
+  extensions: string[];
+  enabled: boolean;
 }
 
 const SettingsPage: React.FC = () => {
   const [config, setConfig] = useState<AppConfig | null>(null);
+  const [formats, setFormats] = useState<FormatInfo[]>([]);
   const [isLoading, setIsLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
   const [isModalOpen, setIsModalOpen] = useState(false);
+  const [importTemplate, setImportTemplate] = useState("");
+  const [retentionDays, setRetentionDays] = useState("");
 
   const fetchConfig = async () => {
     setIsLoading(true);
@@ -20,6 +35,9 @@ const SettingsPage: React.FC = () => {
     try {
       const result = await invoke<AppConfig>("get_config");
       setConfig(result);
+      setImportTemplate(result.import_template ?? "");
+      setRetentionDays(result.trash_retention_days?.toString() ?? "");
+      setFormats(await invoke<FormatInfo[]>("get_formats"));
       // If this is the first run (no config), open the setup modal automatically
       if (!result.primary_drive || !result.backup_drive) {
         setIsModalOpen(true);
@@ -35,6 +53,47 @@ const SettingsPage: React.FC = () => {
     fetchConfig();
   }, []);
 
+  const handleWatchChange = async (enabled: boolean) => {
+    try {
+      await invoke("set_watch_primary_drive", { enabled });
+      fetchConfig();
+    } catch (err) {
+      setError(err as string);
+    }
+  };
+
+  const handleFormatChange = async (name: string, enabled: boolean) => {
+    const enabledFormats = formats
+      .filter((format) => (format.name === name ? enabled : format.enabled))
+      .map((format) => format.name);
+    try {
+      await invoke("set_enabled_formats", { formats: enabledFormats });
+      fetchConfig();
+    } catch (err) {
+      setError(err as string);
+    }
+  };
+
+  const handleImportTemplateSave = async () => {
+    try {
+      await invoke("set_import_template", { template: importTemplate });
+      fetchConfig();
+    } catch (err) {
+      setError(err as string);
+    }
+  };
+
+  const handleRetentionSave = async () => {
+    try {
+      await invoke("set_trash_retention_days", {
+        days: retentionDays.trim() ? Number(retentionDays) : null,
+      });
+      fetchConfig();
+    } catch (err) {
+      setError(err as string);
+    }
+  };
+
   const handleConfigSaved = () => {
     fetchConfig(); // Re-fetch the config to display the new paths
   };
@@ -63,6 +122,16 @@ const SettingsPage: React.FC = () => {
                   {config.backup_drive || "Not set"}
                 </p>
               </div>
+              <label className="flex items-center gap-2">
+                <input
+                  type="checkbox"
+                  checked={config.watch_primary_drive}
+                  onChange={(e) => handleWatchChange(e.target.checked)}
+                />
+                <span className="font-medium">
+                  Watch the primary drive for new and changed photos
+                </span>
+              </label>
             </div>
           </div>
         )}
@@ -70,6 +139,67 @@ const SettingsPage: React.FC = () => {
         <Button onClick={() => setIsModalOpen(true)}>Configure Drives</Button>
       </div>
 
+      <div className="space-y-4 mt-8">
+        <h2 className="text-xl font-semibold">File Formats</h2>
+        <p className="text-sm text-muted-foreground">
+          Scans and the library watcher only pick up files in enabled formats.
+        </p>
+        <div className="p-4 border rounded-md bg-muted/50 grid grid-cols-2 gap-2">
+          {formats.map((format) => (
+            <label key={format.name} className="flex items-center gap-2">
+              <input
+                type="checkbox"
+                checked={format.enabled}
+                onChange={(e) => handleFormatChange(format.name, e.target.checked)}
+              />
+              <span className="font-medium">{format.name}</span>
+              <span className="text-sm text-muted-foreground">
+                {format.extensions.map((ext) => `.${ext}`).join(", ")}
+              </span>
+            </label>
+          ))}
+        </div>
+      </div>
+
+      <div className="space-y-4 mt-8">
+        <h2 className="text-xl font-semibold">Import</h2>
+        <p className="text-sm text-muted-foreground">
+          Where imported files are placed on the primary drive. Available
+          placeholders: {"{year}"}, {"{month}"}, {"{day}"}, {"{date}"} and{" "}
+          {"{original_name}"}.
+        </p>
+        <div className="flex items-center gap-2">
+          <input
+            type="text"
+            className="flex-1 p-2 border rounded-md bg-background"
+            placeholder={DEFAULT_IMPORT_TEMPLATE}
+            value={importTemplate}
+            onChange={(e) => setImportTemplate(e.target.value)}
+          />
+          <Button onClick={handleImportTemplateSave}>Save</Button>
+        </div>
+      </div>
+
+      <div className="space-y-4 mt-8">
+        <h2 className="text-xl font-semibold">Trash</h2>
+        <p className="text-sm text-muted-foreground">
+          Deleted photos stay in the trash for this many days before they are
+          removed for good.
+        </p>
+        <div className="flex items-center gap-2">
+          <input
+            type="number"
+            min={1}
+            className="w-32 p-2 border rounded-md bg-background"
+            placeholder={String(DEFAULT_TRASH_RETENTION_DAYS)}
+            value={retentionDays}
+            onChange={(e) => setRetentionDays(e.target.value)}
+          />
+          <span>days</span>
+          <Button onClick={handleRetentionSave}>Save</Button>
+        </div>
+      </div>
+
       <DriveSetupModal
         isOpen={isModalOpen}
         onClose={() => setIsModalOpen(false)}
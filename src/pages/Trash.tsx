@@ -0,0 +1,124 @@
+import React, { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useQuery, useQueryClient } from "@tanstack/react-query";
+import { Button } from "@/components/ui/button";
+import { thumbnailUrl } from "@/lib/media";
+import { Photo } from "../models/photo";
+
+interface TrashSummary {
+  photos: number;
+  bytes: number;
+  failed: { path: string; error: string }[];
+}
+
+const TrashPage: React.FC = () => {
+  const queryClient = useQueryClient();
+  const [selected, setSelected] = useState<number[]>([]);
+  const [message, setMessage] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  const { data: photos } = useQuery<Photo[]>({
+    queryKey: ["trash"],
+    queryFn: () => invoke("list_trash"),
+  });
+
+  const refresh = () => {
+    setSelected([]);
+    queryClient.invalidateQueries({ queryKey: ["trash"] });
+    queryClient.invalidateQueries({ queryKey: ["photos"] });
+  };
+
+  const toggle = (photoId: number) =>
+    setSelected((prev) =>
+      prev.includes(photoId)
+        ? prev.filter((id) => id !== photoId)
+        : [...prev, photoId],
+    );
+
+  const handleRestore = async () => {
+    setError(null);
+    try {
+      const summary = await invoke<TrashSummary>("restore_from_trash", {
+        photoIds: selected,
+      });
+      setMessage(
+        `Restored ${summary.photos} photos` +
+          (summary.failed.length > 0
+            ? `, ${summary.failed.length} could not be restored`
+            : ""),
+      );
+      refresh();
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  const handleEmpty = async () => {
+    if (!confirm("Permanently delete everything in the trash?")) {
+      return;
+    }
+    setError(null);
+    try {
+      const summary = await invoke<TrashSummary>("empty_trash");
+      setMessage(
+        `Deleted ${summary.photos} photos and freed ${(summary.bytes / 1024 / 1024).toFixed(2)} MB`,
+      );
+      refresh();
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  return (
+    <div className="p-4">
+      <div className="flex justify-between items-center mb-4">
+        <h1 className="text-2xl font-bold">Trash</h1>
+        <div className="flex gap-2">
+          <Button
+            variant="outline"
+            onClick={handleRestore}
+            disabled={selected.length === 0}
+          >
+            Restore Selected
+          </Button>
+          <Button onClick={handleEmpty} disabled={!photos?.length}>
+            Empty Trash
+          </Button>
+        </div>
+      </div>
+      {message && <p className="mb-2 text-sm">{message}</p>}
+      {error && <p className="mb-2 text-sm text-red-500">{error}</p>}
+      {!photos?.length ? (
+        <p>The trash is empty.</p>
+      ) : (
+        <div className="grid grid-cols-2 md:grid-cols-4 lg:grid-cols-6 gap-4">
+          {photos.map((photo) => (
+            <div
+              key={photo.id}
+              className={`cursor-pointer rounded-md border-2 ${
+                selected.includes(photo.id)
+                  ? "border-blue-500"
+                  : "border-transparent"
+              }`}
+              onClick={() => toggle(photo.id)}
+            >
+              <img
+                src={thumbnailUrl(photo.id)}
+                alt={photo.filename}
+                className="w-full h-auto rounded-md"
+              />
+              <p className="mt-1 text-sm text-center truncate">
+                {photo.filename}
+              </p>
+              <p className="text-xs text-center text-muted-foreground">
+                Deleted {photo.deleted_at?.slice(0, 10)}
+              </p>
+            </div>
+          ))}
+        </div>
+      )}
+    </div>
+  );
+};
+
+export default TrashPage;